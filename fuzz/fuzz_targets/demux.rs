@@ -0,0 +1,41 @@
+//! Feeds arbitrary bytes to the demuxer, which parses them with libwebm's mkvparser.
+//!
+//! Run with `cargo fuzz run demux corpus/demux seeds` from the `fuzz` directory.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use webm::demux::Demuxer;
+
+/// Frames past this many are not read, so that inputs with huge numbers of tiny blocks don't time out.
+const MAX_FRAMES: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut demuxer) = Demuxer::new(Cursor::new(data)) else {
+        return;
+    };
+
+    // Everything that comes out of libwebm gets touched, so that bad pointers and lengths show up
+    let info = demuxer.info();
+    let _ = (info.doc_type.len(), info.muxing_app.as_deref(), info.writing_app.as_deref(), info.title.as_deref());
+    for track in demuxer.tracks() {
+        let _ = (track.codec_id.len(), track.codec_private.iter().sum::<u8>(), track.name.as_deref());
+    }
+    for (name, value) in demuxer.tags() {
+        let _ = (name.len(), value.len());
+    }
+    for chapter in demuxer.chapters() {
+        let _ = (chapter.string_uid.as_deref(), chapter.title.as_deref(), chapter.language.as_deref());
+    }
+    let _ = demuxer.cue_points();
+
+    for frame in demuxer.by_ref().take(MAX_FRAMES) {
+        let Ok(frame) = frame else {
+            break;
+        };
+        let _ = frame.data.iter().sum::<u8>();
+    }
+    let _ = demuxer.into_inner();
+});
@@ -0,0 +1,42 @@
+//! Walks arbitrary bytes with the EBML reader, reading every payload, under tight limits.
+//!
+//! Run with `cargo fuzz run ebml corpus/ebml seeds` from the `fuzz` directory.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use webm::ebml::{Children, ElementType, Limits, Reader};
+use webm::mux::Error;
+
+fn walk(reader: &mut Reader<Cursor<&[u8]>>, mut children: Children) -> Result<(), Error> {
+    while let Some(element) = reader.next_child(&mut children)? {
+        match element.id.element_type() {
+            Some(ElementType::Master) | None => {
+                if let Ok(children) = reader.children(&element) {
+                    walk(reader, children)?;
+                }
+            },
+            Some(ElementType::Uint) => drop(reader.read_uint(&element)),
+            Some(ElementType::Int | ElementType::Date) => drop(reader.read_int(&element)),
+            Some(ElementType::Float) => drop(reader.read_float(&element)),
+            Some(ElementType::String | ElementType::Utf8) => drop(reader.read_string(&element)),
+            Some(ElementType::Binary) => drop(reader.read_binary(&element)),
+        }
+    }
+    Ok(())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_element_size: 1 << 16,
+        max_depth: 16,
+    };
+    let mut reader = Reader::with_limits(Cursor::new(data), limits);
+    let top_level = reader.top_level();
+    match walk(&mut reader, top_level) {
+        Ok(()) | Err(Error::InvalidData(_)) => {},
+        Err(err) => panic!("reading from memory can't fail with {err}"),
+    }
+});
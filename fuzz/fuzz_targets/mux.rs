@@ -0,0 +1,287 @@
+//! Makes arbitrary sequences of `SegmentBuilder` and `Segment` calls, including ones that should fail, and parses
+//! back what was written.
+//!
+//! Run with `cargo fuzz run mux` from the `fuzz` directory.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use webm::demux::Demuxer;
+use webm::mux::{
+    AudioCodecId, AudioTrack, ColorRange, ColorSubsampling, DocType, SegmentBuilder, TrackNum, VideoCodecId,
+    VideoTrack, Writer,
+};
+
+#[derive(Arbitrary, Debug)]
+enum VideoCodec {
+    Vp8,
+    Vp9,
+    Av1,
+    Other(String),
+}
+
+#[derive(Arbitrary, Debug)]
+enum AudioCodec {
+    Opus,
+    Vorbis,
+    Flac,
+    PcmIntLittleEndian,
+    PcmFloat,
+    Other(String),
+}
+
+/// A track to pass to a call, picked from the handles returned so far or given as a raw track number.
+#[derive(Arbitrary, Debug)]
+enum TrackRef {
+    Video(u8),
+    Audio(u8),
+    Raw(TrackNum),
+}
+
+#[derive(Arbitrary, Debug)]
+enum BuilderCall {
+    SetDocType(bool),
+    SetWritingApp(String),
+    AddTag(String, String),
+    SetKeyframeValidation(bool),
+    SetMaxClusterDuration(u64),
+    SetMaxClusterSize(u64),
+    SetCues(bool),
+    AddVideoTrack {
+        width: u32,
+        height: u32,
+        codec: VideoCodec,
+        number: Option<TrackNum>,
+    },
+    AddAudioTrack {
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec: AudioCodec,
+        number: Option<TrackNum>,
+    },
+    SetCodecPrivate(TrackRef, Vec<u8>),
+    SetCodecDelay(TrackRef, u64, u64),
+    SetColor(u8, u8, u8, u8, u8),
+    SetTrackName(TrackRef, String),
+    SetTrackLanguage(TrackRef, String),
+}
+
+#[derive(Arbitrary, Debug)]
+enum SegmentCall {
+    AddFrame {
+        track: TrackRef,
+        data: Vec<u8>,
+        timestamp_ns: u64,
+        keyframe: bool,
+    },
+    AddFrameWithDiscardPadding {
+        track: TrackRef,
+        data: Vec<u8>,
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    },
+    AddFrameAuto {
+        track: TrackRef,
+        data: Vec<u8>,
+        timestamp_ns: u64,
+    },
+    SetOpusStart(u8, u64),
+    AddOpusPacket(u8, Vec<u8>),
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    seekable: bool,
+    builder_calls: Vec<BuilderCall>,
+    segment_calls: Vec<SegmentCall>,
+    duration: Option<u64>,
+}
+
+/// Calls `$call` with the track `$track` refers to, as a `VideoTrack`, `AudioTrack` or raw `TrackNum`. Indices past
+/// the handles returned so far are wrapped around, and used as raw track numbers if there are none.
+macro_rules! with_track {
+    ($videos:expr, $audios:expr, $track:expr, |$handle:ident| $call:expr) => {
+        match *$track {
+            TrackRef::Video(index) if !$videos.is_empty() => {
+                let $handle = $videos[usize::from(index) % $videos.len()];
+                $call
+            },
+            TrackRef::Audio(index) if !$audios.is_empty() => {
+                let $handle = $audios[usize::from(index) % $audios.len()];
+                $call
+            },
+            TrackRef::Video(number) | TrackRef::Audio(number) => {
+                let $handle = TrackNum::from(number);
+                $call
+            },
+            TrackRef::Raw(number) => {
+                let $handle = number;
+                $call
+            },
+        }
+    };
+}
+
+fn run(input: &Input) -> Option<Vec<u8>> {
+    let writer = if input.seekable {
+        Writer::new(Cursor::new(Vec::new()))
+    } else {
+        Writer::new_non_seek(Cursor::new(Vec::new()))
+    };
+
+    // The builder is consumed by every call, so the first one that fails ends the run
+    let mut builder = SegmentBuilder::new(writer).ok()?;
+    let mut videos: Vec<VideoTrack> = Vec::new();
+    let mut audios: Vec<AudioTrack> = Vec::new();
+    for call in &input.builder_calls {
+        builder = match call {
+            BuilderCall::SetDocType(matroska) => {
+                builder.set_doc_type(if *matroska { DocType::Matroska } else { DocType::WebM })
+            },
+            BuilderCall::SetWritingApp(name) => builder.set_writing_app(name),
+            BuilderCall::AddTag(name, value) => builder.add_tag(name, value),
+            BuilderCall::SetKeyframeValidation(enabled) => Ok(builder.set_keyframe_validation(*enabled)),
+            BuilderCall::SetMaxClusterDuration(duration_ns) => builder.set_max_cluster_duration(*duration_ns),
+            BuilderCall::SetMaxClusterSize(size) => builder.set_max_cluster_size(*size),
+            BuilderCall::SetCues(enabled) => builder.set_cues(*enabled),
+            BuilderCall::AddVideoTrack {
+                width,
+                height,
+                codec,
+                number,
+            } => {
+                let result = match codec {
+                    VideoCodec::Vp8 => builder.add_video_track(*width, *height, VideoCodecId::VP8, *number),
+                    VideoCodec::Vp9 => builder.add_video_track(*width, *height, VideoCodecId::VP9, *number),
+                    VideoCodec::Av1 => builder.add_video_track(*width, *height, VideoCodecId::AV1, *number),
+                    VideoCodec::Other(codec_id) => {
+                        builder.add_video_track_with_codec_id(*width, *height, codec_id, *number)
+                    },
+                };
+                result.map(|(builder, track)| {
+                    videos.push(track);
+                    builder
+                })
+            },
+            BuilderCall::AddAudioTrack {
+                sample_rate,
+                channels,
+                bit_depth,
+                codec,
+                number,
+            } => {
+                let (sample_rate, channels, bit_depth, number) = (*sample_rate, *channels, *bit_depth, *number);
+                let codec = match codec {
+                    AudioCodec::Opus => Ok(AudioCodecId::Opus),
+                    AudioCodec::Vorbis => Ok(AudioCodecId::Vorbis),
+                    AudioCodec::Flac => Ok(AudioCodecId::Flac),
+                    AudioCodec::PcmIntLittleEndian => Ok(AudioCodecId::PcmIntLittleEndian),
+                    AudioCodec::PcmFloat => Ok(AudioCodecId::PcmFloat),
+                    AudioCodec::Other(codec_id) => Err(codec_id),
+                };
+                let result = match codec {
+                    Ok(codec) => builder.add_audio_track(sample_rate, channels, bit_depth, codec, number),
+                    Err(codec_id) => {
+                        builder.add_audio_track_with_codec_id(sample_rate, channels, bit_depth, codec_id, number)
+                    },
+                };
+                result.map(|(builder, track)| {
+                    audios.push(track);
+                    builder
+                })
+            },
+            BuilderCall::SetCodecPrivate(track, data) => {
+                with_track!(videos, audios, track, |handle| builder.set_codec_private(handle, data))
+            },
+            BuilderCall::SetCodecDelay(track, codec_delay_ns, seek_pre_roll_ns) => {
+                with_track!(videos, audios, track, |handle| {
+                    builder.set_codec_delay(handle, *codec_delay_ns, *seek_pre_roll_ns)
+                })
+            },
+            BuilderCall::SetColor(index, bit_depth, chroma_horizontal, chroma_vertical, range) => {
+                let Some(&track) = videos.get(usize::from(*index)) else {
+                    continue;
+                };
+                let subsampling = ColorSubsampling {
+                    chroma_horizontal: *chroma_horizontal,
+                    chroma_vertical: *chroma_vertical,
+                };
+                let range = match range % 3 {
+                    0 => ColorRange::Unspecified,
+                    1 => ColorRange::Broadcast,
+                    _ => ColorRange::Full,
+                };
+                builder.set_color(track, *bit_depth, subsampling, range)
+            },
+            BuilderCall::SetTrackName(track, name) => {
+                with_track!(videos, audios, track, |handle| builder.set_track_name(handle, name))
+            },
+            BuilderCall::SetTrackLanguage(track, language) => {
+                with_track!(videos, audios, track, |handle| builder.set_track_language(handle, language))
+            },
+        }
+        .ok()?;
+    }
+
+    // Failing segment calls leave the segment usable, so these all go through
+    let mut segment = builder.build();
+    for call in &input.segment_calls {
+        let _ = match call {
+            SegmentCall::AddFrame {
+                track,
+                data,
+                timestamp_ns,
+                keyframe,
+            } => with_track!(videos, audios, track, |handle| {
+                segment.add_frame(handle, data, *timestamp_ns, *keyframe)
+            }),
+            SegmentCall::AddFrameWithDiscardPadding {
+                track,
+                data,
+                timestamp_ns,
+                keyframe,
+                discard_padding_ns,
+            } => with_track!(videos, audios, track, |handle| {
+                segment.add_frame_with_discard_padding(handle, data, *timestamp_ns, *keyframe, *discard_padding_ns)
+            }),
+            SegmentCall::AddFrameAuto {
+                track,
+                data,
+                timestamp_ns,
+            } => with_track!(videos, audios, track, |handle| {
+                segment.add_frame_auto(handle, data, *timestamp_ns).map(|_| ())
+            }),
+            SegmentCall::SetOpusStart(index, timestamp_ns) => match audios.get(usize::from(*index)) {
+                Some(&track) => segment.set_opus_start(track, *timestamp_ns),
+                None => continue,
+            },
+            SegmentCall::AddOpusPacket(index, packet) => match audios.get(usize::from(*index)) {
+                Some(&track) => segment.add_opus_packet(track, packet).map(|_| ()),
+                None => continue,
+            },
+        };
+    }
+
+    let writer = segment.finalize(input.duration).unwrap_or_else(|writer| writer);
+    Some(writer.into_inner().into_inner())
+}
+
+fuzz_target!(|input: Input| {
+    let Some(output) = run(&input) else {
+        return;
+    };
+
+    // Whatever the muxer wrote must be safe to parse again
+    if let Ok(demuxer) = Demuxer::new(Cursor::new(output)) {
+        for frame in demuxer.take(1000) {
+            if frame.is_err() {
+                break;
+            }
+        }
+    }
+});
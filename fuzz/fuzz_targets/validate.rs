@@ -0,0 +1,19 @@
+//! Feeds arbitrary bytes to the structural validator, in both modes.
+//!
+//! Run with `cargo fuzz run validate corpus/validate seeds` from the `fuzz` directory.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use webm::validate::{validate, Mode};
+
+fuzz_target!(|data: &[u8]| {
+    for mode in [Mode::File, Mode::Live] {
+        let violations = validate(Cursor::new(data), mode).expect("reading from memory can't fail");
+        for violation in violations {
+            let _ = violation.to_string();
+        }
+    }
+});
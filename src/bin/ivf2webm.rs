@@ -0,0 +1,35 @@
+//! Wraps raw encoder output in IVF format (as written by `vpxenc` or `aomenc`) into a WebM file.
+//!
+//! Usage: `ivf2webm <input.ivf> <output.webm>`
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+use webm::mux::Writer;
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let [input, output] = args.as_slice() else {
+        eprintln!("Usage: ivf2webm <input.ivf> <output.webm>");
+        return ExitCode::from(2);
+    };
+
+    let result = File::open(input)
+        .map_err(|err| format!("Could not open {}: {err}", input.to_string_lossy()))
+        .and_then(|input| {
+            let output = File::create(output)
+                .map_err(|err| format!("Could not create {}: {err}", output.to_string_lossy()))?;
+            let writer = webm::ivf::remux_to_webm(BufReader::new(input), Writer::new(BufWriter::new(output)))
+                .map_err(|err| format!("Could not remux: {err}"))?;
+            writer.into_inner().flush().map_err(|err| format!("Could not write output: {err}"))
+        });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,347 @@
+//! Prints what a WebM file contains: its EBML header, segment info, tracks with their color and HDR metadata,
+//! chapters, tags and cue points, and optionally every block.
+//!
+//! Usage: `webm-info [--blocks] [--json] <input.webm>`
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::process::ExitCode;
+
+use webm::demux::{ColorMetadata, Demuxer, TrackKind, VideoColor};
+use webm::mux::Error;
+
+const USAGE: &str = "Usage: webm-info [--blocks] [--json] <input.webm>";
+
+/// A value in the report, which is printed either as JSON or as indented text.
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    List(Vec<Value>),
+    Map(Vec<(&'static str, Value)>),
+}
+
+impl Value {
+    fn number(value: impl ToString) -> Self {
+        Self::Number(value.to_string())
+    }
+
+    /// Like [`Value::number`], but non-finite values become [`Value::Null`], as JSON has no syntax for them.
+    fn float<T: Copy + Into<f64> + ToString>(value: T) -> Self {
+        if value.into().is_finite() {
+            Self::number(value)
+        } else {
+            Self::Null
+        }
+    }
+
+    fn optional<T>(value: Option<T>, to_value: impl FnOnce(T) -> Value) -> Self {
+        value.map_or(Self::Null, to_value)
+    }
+
+    fn string(value: impl Into<String>) -> Self {
+        Self::String(value.into())
+    }
+
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Self::Null => out.push_str("null"),
+            Self::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Self::Number(value) => out.push_str(value),
+            Self::String(value) => write_json_string(value, out),
+            Self::List(values) => {
+                out.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    value.write_json(out);
+                }
+                out.push(']');
+            },
+            Self::Map(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_string(key, out);
+                    out.push(':');
+                    value.write_json(out);
+                }
+                out.push('}');
+            },
+        }
+    }
+
+    /// Writes the entries of a map as `key: value` lines, leaving out missing values.
+    fn write_text(&self, indent: usize, out: &mut String) {
+        let Self::Map(entries) = self else {
+            return;
+        };
+
+        for (key, value) in entries {
+            let pad = "  ".repeat(indent);
+            match value {
+                Self::Null => {},
+                Self::Bool(value) => _ = writeln!(out, "{pad}{key}: {}", if *value { "yes" } else { "no" }),
+                Self::Number(value) | Self::String(value) => _ = writeln!(out, "{pad}{key}: {value}"),
+                Self::Map(_) => {
+                    _ = writeln!(out, "{pad}{key}:");
+                    value.write_text(indent + 1, out);
+                },
+                Self::List(values) if values.is_empty() => {},
+                Self::List(values) => {
+                    _ = writeln!(out, "{pad}{key}:");
+                    for (i, value) in values.iter().enumerate() {
+                        match value {
+                            Self::Map(_) => {
+                                _ = writeln!(out, "{pad}  [{i}]");
+                                value.write_text(indent + 2, out);
+                            },
+                            Self::Number(value) | Self::String(value) => _ = writeln!(out, "{pad}  {value}"),
+                            _ => {},
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn write_json_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => _ = write!(out, "\\u{:04x}", c as u32),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn color_value(color: Option<VideoColor>, metadata: Option<ColorMetadata>) -> Value {
+    if color.is_none() && metadata.is_none() {
+        return Value::Null;
+    }
+
+    let mut entries = Vec::new();
+    if let Some(color) = color {
+        entries.extend([
+            ("bit_depth", Value::number(color.bit_depth)),
+            ("chroma_subsampling_horizontal", Value::number(color.subsampling.chroma_horizontal)),
+            ("chroma_subsampling_vertical", Value::number(color.subsampling.chroma_vertical)),
+            ("range", Value::string(format!("{:?}", color.range))),
+        ]);
+    }
+    if let Some(metadata) = metadata {
+        let chromaticity = |point: Option<(f32, f32)>| {
+            Value::optional(point, |(x, y)| Value::List(vec![Value::float(x), Value::float(y)]))
+        };
+        entries.extend([
+            ("matrix_coefficients", Value::optional(metadata.matrix_coefficients, Value::number)),
+            ("transfer_characteristics", Value::optional(metadata.transfer_characteristics, Value::number)),
+            ("primaries", Value::optional(metadata.primaries, Value::number)),
+            ("chroma_siting_horizontal", Value::optional(metadata.chroma_siting.0, Value::number)),
+            ("chroma_siting_vertical", Value::optional(metadata.chroma_siting.1, Value::number)),
+            ("max_cll", Value::optional(metadata.max_cll, Value::number)),
+            ("max_fall", Value::optional(metadata.max_fall, Value::number)),
+            (
+                "mastering_metadata",
+                Value::optional(metadata.mastering, |mastering| {
+                    Value::Map(vec![
+                        ("red", chromaticity(mastering.red)),
+                        ("green", chromaticity(mastering.green)),
+                        ("blue", chromaticity(mastering.blue)),
+                        ("white_point", chromaticity(mastering.white_point)),
+                        ("luminance_max", Value::optional(mastering.luminance_max, Value::float)),
+                        ("luminance_min", Value::optional(mastering.luminance_min, Value::float)),
+                    ])
+                }),
+            ),
+        ]);
+    }
+    Value::Map(entries)
+}
+
+fn report<R: Read + Seek>(demuxer: &mut Demuxer<R>, blocks: bool) -> Result<Value, Error> {
+    let info = demuxer.info();
+    let header = info.ebml_header;
+    let ebml_header = Value::Map(vec![
+        ("version", Value::number(header.version)),
+        ("read_version", Value::number(header.read_version)),
+        ("max_id_length", Value::number(header.max_id_length)),
+        ("max_size_length", Value::number(header.max_size_length)),
+        ("doc_type", Value::string(&info.doc_type)),
+        ("doc_type_version", Value::number(header.doc_type_version)),
+        ("doc_type_read_version", Value::number(header.doc_type_read_version)),
+    ]);
+    let segment_info = Value::Map(vec![
+        ("timecode_scale", Value::number(info.timecode_scale)),
+        ("duration_ns", Value::optional(info.duration_ns, Value::number)),
+        ("title", Value::optional(info.title.as_deref(), Value::string)),
+        ("muxing_app", Value::optional(info.muxing_app.as_deref(), Value::string)),
+        ("writing_app", Value::optional(info.writing_app.as_deref(), Value::string)),
+    ]);
+
+    let tracks = demuxer.tracks().iter().map(|track| {
+        let mut entries = vec![
+            ("number", Value::number(track.number)),
+            ("codec_id", Value::string(&track.codec_id)),
+            ("codec_private_size", Value::number(track.codec_private.len())),
+            ("name", Value::optional(track.name.as_deref(), Value::string)),
+            ("language", Value::optional(track.language.as_deref(), Value::string)),
+            ("codec_delay_ns", Value::number(track.codec_delay_ns)),
+            ("seek_pre_roll_ns", Value::number(track.seek_pre_roll_ns)),
+        ];
+        match &track.kind {
+            TrackKind::Video(video) => entries.extend([
+                ("type", Value::string("video")),
+                ("width", Value::number(video.width)),
+                ("height", Value::number(video.height)),
+                ("display_width", Value::optional(video.display_size, |(width, _)| Value::number(width))),
+                ("display_height", Value::optional(video.display_size, |(_, height)| Value::number(height))),
+                ("frame_rate", Value::optional(video.frame_rate, Value::float)),
+                ("color", color_value(video.color, video.color_metadata)),
+            ]),
+            TrackKind::Audio(audio) => entries.extend([
+                ("type", Value::string("audio")),
+                ("sample_rate", Value::float(audio.sample_rate)),
+                ("channels", Value::number(audio.channels)),
+                ("bit_depth", Value::optional(audio.bit_depth, Value::number)),
+            ]),
+            _ => entries.push(("type", Value::string("other"))),
+        }
+        Value::Map(entries)
+    });
+    let tracks = Value::List(tracks.collect());
+
+    let chapters = demuxer.chapters().iter().map(|chapter| {
+        Value::Map(vec![
+            ("edition", Value::number(chapter.edition)),
+            ("uid", Value::number(chapter.uid)),
+            ("string_uid", Value::optional(chapter.string_uid.as_deref(), Value::string)),
+            ("start_ns", Value::number(chapter.start_ns)),
+            ("end_ns", Value::optional(chapter.end_ns, Value::number)),
+            ("title", Value::optional(chapter.title.as_deref(), Value::string)),
+            ("language", Value::optional(chapter.language.as_deref(), Value::string)),
+        ])
+    });
+    let chapters = Value::List(chapters.collect());
+
+    let tags = demuxer.tags().iter().map(|(name, value)| {
+        Value::Map(vec![("name", Value::string(name)), ("value", Value::string(value))])
+    });
+    let tags = Value::List(tags.collect());
+
+    let cue_points = demuxer.cue_points()?.into_iter().map(|cue_point| {
+        Value::Map(vec![
+            ("timestamp_ns", Value::number(cue_point.timestamp_ns)),
+            ("track", Value::number(cue_point.track)),
+            ("cluster_position", Value::number(cue_point.cluster_position)),
+            ("block_number", Value::number(cue_point.block_number)),
+        ])
+    });
+
+    let mut entries = vec![
+        ("ebml_header", ebml_header),
+        ("segment_info", segment_info),
+        ("tracks", tracks),
+        ("chapters", chapters),
+        ("tags", tags),
+        ("cue_points", Value::List(cue_points.collect())),
+    ];
+    if blocks {
+        let mut list = Vec::new();
+        while let Some(frame) = demuxer.next_frame()? {
+            list.push(Value::Map(vec![
+                ("track", Value::number(frame.track)),
+                ("timestamp_ns", Value::number(frame.timestamp_ns)),
+                ("size", Value::number(frame.data.len())),
+                ("keyframe", Value::Bool(frame.keyframe)),
+            ]));
+        }
+        entries.push(("blocks", Value::List(list)));
+    }
+    Ok(Value::Map(entries))
+}
+
+fn main() -> ExitCode {
+    let mut blocks = false;
+    let mut json = false;
+    let mut input = None;
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("--blocks") => blocks = true,
+            Some("--json") => json = true,
+            _ if input.is_none() => input = Some(arg),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::from(2);
+            },
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+
+    let result = File::open(&input)
+        .map_err(|err| format!("Could not open {}: {err}", input.to_string_lossy()))
+        .and_then(|input| {
+            let mut demuxer = Demuxer::new(BufReader::new(input)).map_err(|err| format!("Could not read: {err}"))?;
+            report(&mut demuxer, blocks).map_err(|err| format!("Could not read: {err}"))
+        });
+
+    match result {
+        Ok(report) => {
+            let mut out = String::new();
+            if json {
+                report.write_json(&mut out);
+                out.push('\n');
+            } else {
+                report.write_text(0, &mut out);
+            }
+            print!("{out}");
+            ExitCode::SUCCESS
+        },
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(value: &Value) -> String {
+        let mut out = String::new();
+        value.write_json(&mut out);
+        out
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let value = Value::string("\"quoted\" \\ line\nbreak\ttab\r\u{1}é");
+        assert_eq!(json(&value), r#""\"quoted\" \\ line\nbreak\ttab\r\u0001é""#);
+        assert_eq!(json(&Value::Map(vec![("a\"b", Value::Null)])), r#"{"a\"b":null}"#);
+    }
+
+    #[test]
+    fn writes_non_finite_numbers_as_null() {
+        let values = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, 29.97, -1.5];
+        let value = Value::List(values.into_iter().map(Value::float).collect());
+        assert_eq!(json(&value), "[null,null,null,29.97,-1.5]");
+        assert_eq!(json(&Value::List(vec![Value::float(f32::NAN), Value::float(0.68_f32)])), "[null,0.68]");
+        assert_eq!(json(&Value::number(u64::MAX)), "18446744073709551615");
+    }
+}
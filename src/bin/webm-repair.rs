@@ -0,0 +1,34 @@
+//! Makes a WebM file that was written live, such as a browser's `MediaRecorder` recording, seekable by writing the
+//! `Duration`, `Cues` and `SeekHead` it is missing.
+//!
+//! Usage: `webm-repair <input.webm> <output.webm>`
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let [input, output] = args.as_slice() else {
+        eprintln!("Usage: webm-repair <input.webm> <output.webm>");
+        return ExitCode::from(2);
+    };
+
+    let result = File::open(input)
+        .map_err(|err| format!("Could not open {}: {err}", input.to_string_lossy()))
+        .and_then(|input| {
+            let output = File::create(output)
+                .map_err(|err| format!("Could not create {}: {err}", output.to_string_lossy()))?;
+            let mut output = webm::repair::repair(BufReader::new(input), BufWriter::new(output))
+                .map_err(|err| format!("Could not repair: {err}"))?;
+            output.flush().map_err(|err| format!("Could not write output: {err}"))
+        });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        },
+    }
+}
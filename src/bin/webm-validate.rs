@@ -0,0 +1,57 @@
+//! Checks WebM files against the WebM specification, printing every violation found. Exits with 1 if any file is
+//! invalid, which makes it usable in CI.
+//!
+//! Usage: `webm-validate [--live] <input.webm>...`
+//!
+//! With `--live`, the `Segment` and `Cluster` elements may have unknown sizes, as in files written for streaming.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+
+use webm::validate::{validate, Mode};
+
+fn main() -> ExitCode {
+    let mut mode = Mode::File;
+    let mut inputs = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        match arg.to_str() {
+            Some("--live") => mode = Mode::Live,
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        eprintln!("Usage: webm-validate [--live] <input.webm>...");
+        return ExitCode::from(2);
+    }
+
+    let mut valid = true;
+    for input in inputs {
+        let name = input.to_string_lossy();
+        let result = File::open(&input)
+            .map_err(|err| format!("Could not open {name}: {err}"))
+            .and_then(|file| {
+                validate(BufReader::new(file), mode).map_err(|err| format!("Could not read {name}: {err}"))
+            });
+
+        match result {
+            Ok(violations) if violations.is_empty() => println!("{name}: OK"),
+            Ok(violations) => {
+                valid = false;
+                for violation in violations {
+                    println!("{name}: {violation}");
+                }
+            },
+            Err(message) => {
+                valid = false;
+                eprintln!("{message}");
+            },
+        }
+    }
+
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,597 @@
+use std::borrow::Cow;
+
+use super::bits::BitReader;
+use crate::mux::{ColorRange, ColorSubsampling, Error};
+
+pub(crate) const OBU_SEQUENCE_HEADER: u8 = 1;
+pub(crate) const OBU_TEMPORAL_DELIMITER: u8 = 2;
+pub(crate) const OBU_FRAME_HEADER: u8 = 3;
+pub(crate) const OBU_FRAME: u8 = 6;
+
+/// A single OBU (open bitstream unit) within AV1 data in the low overhead bitstream format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Obu<'a> {
+    pub(crate) obu_type: u8,
+
+    /// The OBU payload, without any header or size field
+    pub(crate) payload: &'a [u8],
+
+    /// The complete OBU as it appeared in the input
+    pub(crate) raw: &'a [u8],
+}
+
+/// Splits AV1 data in the low overhead bitstream format (as used in WebM blocks and IVF frames) into OBUs.
+pub(crate) fn obus(mut data: &[u8]) -> impl Iterator<Item = Result<Obu<'_>, Error>> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+
+        let obu = parse_obu(data);
+        match obu {
+            Ok(obu) => data = &data[obu.raw.len()..],
+            Err(_) => data = &[],
+        }
+        Some(obu)
+    })
+}
+
+fn parse_obu(data: &[u8]) -> Result<Obu<'_>, Error> {
+    const TRUNCATED: Error = Error::InvalidData("AV1 OBU is truncated");
+
+    let first = *data.first().ok_or(TRUNCATED)?;
+    if first & 0x80 != 0 {
+        return Err(Error::InvalidData("AV1 OBU forbidden bit is set"));
+    }
+    let has_extension = first & 0x04 != 0;
+    let has_size_field = first & 0x02 != 0;
+
+    let header_len = if has_extension { 2 } else { 1 };
+    let (payload_start, payload_len) = if has_size_field {
+        let (size, size_len) = read_leb128(data.get(header_len..).ok_or(TRUNCATED)?).ok_or(TRUNCATED)?;
+        let size = usize::try_from(size).map_err(|_| TRUNCATED)?;
+        (header_len + size_len, size)
+    } else {
+        // Without a size field, the OBU extends to the end of the data
+        (header_len, data.len().checked_sub(header_len).ok_or(TRUNCATED)?)
+    };
+
+    let end = payload_start.checked_add(payload_len).ok_or(TRUNCATED)?;
+    let raw = data.get(..end).ok_or(TRUNCATED)?;
+    Ok(Obu {
+        obu_type: first >> 3 & 0x0F,
+        payload: &raw[payload_start..],
+        raw,
+    })
+}
+
+/// Reads a `leb128()` value, returning it along with the number of bytes it took up.
+fn read_leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= u64::from(byte & 0x7F) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn write_leb128(mut value: u64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Removes all temporal delimiter OBUs from a temporal unit, which must not be stored in WebM blocks.
+///
+/// Only allocates if there is something to remove. This is done automatically by
+/// [`Segment::add_frame`](crate::mux::Segment::add_frame) for tracks added as [`VideoCodecId::AV1`].
+///
+/// [`VideoCodecId::AV1`]: crate::mux::VideoCodecId::AV1
+pub fn strip_temporal_delimiters(data: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    let obus = obus(data).collect::<Result<Vec<_>, _>>()?;
+    if !obus.iter().any(|obu| obu.obu_type == OBU_TEMPORAL_DELIMITER) {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    let stripped = obus
+        .iter()
+        .filter(|obu| obu.obu_type != OBU_TEMPORAL_DELIMITER)
+        .flat_map(|obu| obu.raw)
+        .copied()
+        .collect();
+    Ok(Cow::Owned(stripped))
+}
+
+/// Returns a temporal unit from a WebM block as it appears in the low overhead bitstream format (AV1 specification
+/// section 5), which starts every temporal unit with a temporal delimiter OBU. This is the reverse of
+/// [`strip_temporal_delimiters`].
+pub fn add_temporal_delimiter(data: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    if let Some(first) = obus(data).next() {
+        if first?.obu_type == OBU_TEMPORAL_DELIMITER {
+            return Ok(Cow::Borrowed(data));
+        }
+    }
+
+    let mut delimited = Vec::with_capacity(data.len() + 2);
+    delimited.extend_from_slice(&[OBU_TEMPORAL_DELIMITER << 3 | 0x02, 0]);
+    delimited.extend_from_slice(data);
+    Ok(Cow::Owned(delimited))
+}
+
+/// Converts a temporal unit from the low overhead bitstream format (as used in WebM blocks and IVF frames) into a
+/// `temporal_unit()` of the length-delimited format from Annex B of the AV1 specification.
+///
+/// Every OBU is stored without its size field, each frame and the OBUs that come with it are grouped into a
+/// `frame_unit()`, and a temporal delimiter is added if `data` doesn't start with one.
+pub fn to_annex_b(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let data = add_temporal_delimiter(data)?;
+
+    let mut frame_units: Vec<Vec<u8>> = Vec::new();
+    let mut frame_seen = false;
+    for obu in obus(&data) {
+        let obu = obu?;
+
+        // A new frame unit starts with every frame header, except that everything before the first one belongs to it
+        let starts_frame = matches!(obu.obu_type, OBU_FRAME_HEADER | OBU_FRAME);
+        if frame_units.is_empty() || (starts_frame && frame_seen) {
+            frame_units.push(Vec::new());
+        }
+        frame_seen |= starts_frame;
+
+        let header_len = if obu.raw[0] & 0x04 != 0 { 2 } else { 1 };
+        let frame_unit = frame_units.last_mut().unwrap();
+        write_leb128((header_len + obu.payload.len()) as u64, frame_unit);
+        frame_unit.push(obu.raw[0] & !0x02);
+        frame_unit.extend_from_slice(&obu.raw[1..header_len]);
+        frame_unit.extend_from_slice(obu.payload);
+    }
+
+    let mut temporal_unit = Vec::new();
+    for frame_unit in &frame_units {
+        write_leb128(frame_unit.len() as u64, &mut temporal_unit);
+        temporal_unit.extend_from_slice(frame_unit);
+    }
+    let mut out = Vec::with_capacity(temporal_unit.len() + 8);
+    write_leb128(temporal_unit.len() as u64, &mut out);
+    out.extend_from_slice(&temporal_unit);
+    Ok(out)
+}
+
+/// Returns the `configOBUs` of an `AV1CodecConfigurationRecord` (`av1C`), the `CodecPrivate` of AV1 tracks. These
+/// are the Sequence Header OBU and any metadata OBUs needed to decode the track, in the low overhead bitstream format.
+pub fn config_obus(codec_private: &[u8]) -> Result<&[u8], Error> {
+    match codec_private {
+        [0x81, _, _, _, config_obus @ ..] => Ok(config_obus),
+        _ => Err(Error::InvalidData("AV1 CodecPrivate is not a valid av1C record")),
+    }
+}
+
+/// Returns `true` if the temporal unit `data` is a keyframe in the WebM sense, i.e. it can be decoded on its own.
+///
+/// As per the Matroska AV1 mapping, this requires a Sequence Header OBU, followed by a shown frame of type
+/// `KEY_FRAME` as the first frame.
+pub fn is_keyframe(data: &[u8]) -> Result<bool, Error> {
+    let mut reduced_still_picture_header = None;
+    for obu in obus(data) {
+        let obu = obu?;
+        match obu.obu_type {
+            OBU_SEQUENCE_HEADER => {
+                // reduced_still_picture_header comes right after seq_profile and still_picture
+                let mut r = BitReader::new(obu.payload);
+                r.skip(4)?;
+                reduced_still_picture_header = Some(r.read_bool()?);
+            },
+            OBU_FRAME_HEADER | OBU_FRAME => {
+                let Some(reduced_still_picture_header) = reduced_still_picture_header else {
+                    return Ok(false);
+                };
+                if reduced_still_picture_header {
+                    return Ok(true);
+                }
+
+                let mut r = BitReader::new(obu.payload);
+                let show_existing_frame = r.read_bool()?;
+                let frame_type = r.read(2)?;
+                let show_frame = r.read_bool()?;
+                return Ok(!show_existing_frame && frame_type == 0 && show_frame);
+            },
+            _ => {},
+        }
+    }
+
+    Ok(false)
+}
+
+/// The parameters of an AV1 stream from its Sequence Header OBU, as needed to set up a WebM track.
+///
+/// Use [`SegmentBuilder::add_av1_track`] to add a track configured from it, with the `av1C` `CodecPrivate` data and
+/// color information filled in.
+///
+/// [`SegmentBuilder::add_av1_track`]: crate::mux::SegmentBuilder::add_av1_track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceHeader {
+    /// The profile, `seq_profile`: 0 (Main), 1 (High) or 2 (Professional).
+    pub profile: u8,
+
+    /// The level of the first operating point, `seq_level_idx[0]`.
+    pub level: u8,
+
+    /// The tier of the first operating point, `seq_tier[0]`.
+    pub tier: u8,
+
+    /// The number of bits per sample: 8, 10 or 12.
+    pub bit_depth: u8,
+
+    /// Whether there are no chroma planes.
+    pub monochrome: bool,
+
+    /// Whether chroma is subsampled horizontally.
+    pub subsampling_x: bool,
+
+    /// Whether chroma is subsampled vertically.
+    pub subsampling_y: bool,
+
+    /// Where chroma samples are located when subsampled in both directions, `chroma_sample_position`.
+    pub chroma_sample_position: u8,
+
+    /// Whether the full range of sample values is used, rather than the studio (broadcast) range.
+    pub full_color_range: bool,
+
+    /// The color primaries, as defined by ISO/IEC 23091-4 (2 means unspecified).
+    pub color_primaries: u8,
+
+    /// The transfer characteristics, as defined by ISO/IEC 23091-4 (2 means unspecified).
+    pub transfer_characteristics: u8,
+
+    /// The matrix coefficients, as defined by ISO/IEC 23091-4 (2 means unspecified).
+    pub matrix_coefficients: u8,
+
+    /// The maximum width of frames in the stream.
+    pub max_frame_width: u32,
+
+    /// The maximum height of frames in the stream.
+    pub max_frame_height: u32,
+
+    /// The payload of the Sequence Header OBU this was parsed from.
+    obu_payload: Vec<u8>,
+}
+
+impl SequenceHeader {
+    /// Finds and parses the Sequence Header OBU in `data`, which may be a complete temporal unit such as the
+    /// first keyframe out of an encoder.
+    pub fn from_obus(data: &[u8]) -> Result<Self, Error> {
+        for obu in obus(data) {
+            let obu = obu?;
+            if obu.obu_type == OBU_SEQUENCE_HEADER {
+                return Self::parse_payload(obu.payload);
+            }
+        }
+
+        Err(Error::InvalidData("AV1 sequence header OBU not found"))
+    }
+
+    /// Parses the payload of a Sequence Header OBU, i.e. without OBU header and size.
+    pub fn parse_payload(payload: &[u8]) -> Result<Self, Error> {
+        let mut r = BitReader::new(payload);
+
+        let profile = r.read(3)? as u8;
+        if profile > 2 {
+            return Err(Error::InvalidData("unsupported AV1 profile"));
+        }
+        let _still_picture = r.read_bool()?;
+        let reduced_still_picture_header = r.read_bool()?;
+
+        let level;
+        let mut tier = 0;
+        if reduced_still_picture_header {
+            level = r.read(5)? as u8;
+        } else {
+            let mut decoder_model_info_present = false;
+            let mut buffer_delay_length = 0;
+            if r.read_bool()? {
+                // timing_info()
+                r.skip(64)?;
+                if r.read_bool()? {
+                    r.read_uvlc()?;
+                }
+
+                decoder_model_info_present = r.read_bool()?;
+                if decoder_model_info_present {
+                    buffer_delay_length = r.read(5)? as usize + 1;
+                    r.skip(32 + 5 + 5)?;
+                }
+            }
+
+            let initial_display_delay_present = r.read_bool()?;
+            let operating_points = r.read(5)? + 1;
+            let mut first_level = None;
+            for _ in 0..operating_points {
+                r.skip(12)?;
+                let op_level = r.read(5)? as u8;
+                let op_tier = if op_level > 7 { r.read(1)? as u8 } else { 0 };
+                if first_level.is_none() {
+                    first_level = Some(op_level);
+                    tier = op_tier;
+                }
+
+                if decoder_model_info_present && r.read_bool()? {
+                    // operating_parameters_info()
+                    r.skip(2 * buffer_delay_length + 1)?;
+                }
+                if initial_display_delay_present && r.read_bool()? {
+                    r.skip(4)?;
+                }
+            }
+            level = first_level.unwrap_or_default();
+        }
+
+        let frame_width_bits = r.read(4)? + 1;
+        let frame_height_bits = r.read(4)? + 1;
+        let max_frame_width = r.read(frame_width_bits)? + 1;
+        let max_frame_height = r.read(frame_height_bits)? + 1;
+
+        if !reduced_still_picture_header && r.read_bool()? {
+            // delta_frame_id_length_minus_2 and additional_frame_id_length_minus_1
+            r.skip(7)?;
+        }
+
+        // use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+        r.skip(3)?;
+        if !reduced_still_picture_header {
+            // enable_interintra_compound, enable_masked_compound, enable_warped_motion, enable_dual_filter
+            r.skip(4)?;
+            let enable_order_hint = r.read_bool()?;
+            if enable_order_hint {
+                // enable_jnt_comp, enable_ref_frame_mvs
+                r.skip(2)?;
+            }
+
+            let seq_force_screen_content_tools = if r.read_bool()? { 2 } else { r.read(1)? };
+            if seq_force_screen_content_tools > 0 {
+                let seq_choose_integer_mv = r.read_bool()?;
+                if !seq_choose_integer_mv {
+                    r.skip(1)?;
+                }
+            }
+            if enable_order_hint {
+                r.skip(3)?;
+            }
+        }
+        // enable_superres, enable_cdef, enable_restoration
+        r.skip(3)?;
+
+        // color_config()
+        let high_bitdepth = r.read_bool()?;
+        let bit_depth = match (profile, high_bitdepth) {
+            (2, true) if r.read_bool()? => 12,
+            (_, true) => 10,
+            (_, false) => 8,
+        };
+        let monochrome = profile != 1 && r.read_bool()?;
+
+        let (color_primaries, transfer_characteristics, matrix_coefficients) = if r.read_bool()? {
+            (r.read(8)? as u8, r.read(8)? as u8, r.read(8)? as u8)
+        } else {
+            (2, 2, 2)
+        };
+
+        let full_color_range;
+        let (subsampling_x, subsampling_y);
+        let mut chroma_sample_position = 0;
+        if monochrome {
+            full_color_range = r.read_bool()?;
+            (subsampling_x, subsampling_y) = (true, true);
+        } else if (color_primaries, transfer_characteristics, matrix_coefficients) == (1, 13, 0) {
+            // sRGB
+            full_color_range = true;
+            (subsampling_x, subsampling_y) = (false, false);
+        } else {
+            full_color_range = r.read_bool()?;
+            (subsampling_x, subsampling_y) = match profile {
+                0 => (true, true),
+                1 => (false, false),
+                _ if bit_depth == 12 => {
+                    let subsampling_x = r.read_bool()?;
+                    (subsampling_x, subsampling_x && r.read_bool()?)
+                },
+                _ => (true, false),
+            };
+            if subsampling_x && subsampling_y {
+                chroma_sample_position = r.read(2)? as u8;
+            }
+        }
+
+        Ok(Self {
+            profile,
+            level,
+            tier,
+            bit_depth,
+            monochrome,
+            subsampling_x,
+            subsampling_y,
+            chroma_sample_position,
+            full_color_range,
+            color_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            max_frame_width,
+            max_frame_height,
+            obu_payload: payload.to_vec(),
+        })
+    }
+
+    /// Returns the chroma subsampling, for use with [`SegmentBuilder::set_color`].
+    ///
+    /// [`SegmentBuilder::set_color`]: crate::mux::SegmentBuilder::set_color
+    #[must_use]
+    pub fn color_subsampling(&self) -> ColorSubsampling {
+        ColorSubsampling {
+            chroma_horizontal: self.subsampling_x.into(),
+            chroma_vertical: self.subsampling_y.into(),
+        }
+    }
+
+    /// Returns the color range, for use with [`SegmentBuilder::set_color`].
+    ///
+    /// [`SegmentBuilder::set_color`]: crate::mux::SegmentBuilder::set_color
+    #[must_use]
+    pub fn color_range(&self) -> ColorRange {
+        if self.full_color_range {
+            ColorRange::Full
+        } else {
+            ColorRange::Broadcast
+        }
+    }
+
+    /// Builds the `AV1CodecConfigurationRecord` (`av1C`) that is the `CodecPrivate` of AV1 tracks, including the
+    /// Sequence Header OBU.
+    #[must_use]
+    pub fn to_codec_private(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(4 + 2 + 8 + self.obu_payload.len());
+        data.push(0x81); // marker and version
+        data.push(self.profile << 5 | self.level & 0x1F);
+        data.push(
+            (self.tier & 1) << 7
+                | u8::from(self.bit_depth > 8) << 6
+                | u8::from(self.bit_depth == 12) << 5
+                | u8::from(self.monochrome) << 4
+                | u8::from(self.subsampling_x) << 3
+                | u8::from(self.subsampling_y) << 2
+                | self.chroma_sample_position & 0x03,
+        );
+        data.push(0); // no initial presentation delay
+
+        // The sequence header OBU, always with a size field
+        data.push(OBU_SEQUENCE_HEADER << 3 | 0x02);
+        write_leb128(self.obu_payload.len() as u64, &mut data);
+        data.extend_from_slice(&self.obu_payload);
+
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sequence header OBU of a 352x288, 8-bit 4:2:0 stream (profile 0, level 4.0, main tier) with BT.709 color
+    const SEQUENCE_HEADER: [u8; 16] = [
+        0x0A, 0x0E, 0x00, 0x00, 0x00, 0x42, 0x22, 0xBF, 0x1F, 0x3F, 0xFE, 0x64, 0x04, 0x04, 0x04, 0x10,
+    ];
+    const TEMPORAL_DELIMITER: [u8; 2] = [0x12, 0x00];
+
+    #[test]
+    fn parses_sequence_header() {
+        let data = [TEMPORAL_DELIMITER.as_slice(), &SEQUENCE_HEADER, &[0x32, 0x02, 0xAB, 0xCD]].concat();
+        let header = SequenceHeader::from_obus(&data).unwrap();
+        assert_eq!(header.profile, 0);
+        assert_eq!(header.level, 8);
+        assert_eq!(header.tier, 0);
+        assert_eq!(header.bit_depth, 8);
+        assert!(!header.monochrome);
+        assert!(header.subsampling_x && header.subsampling_y);
+        assert_eq!(header.color_subsampling(), ColorSubsampling { chroma_horizontal: 1, chroma_vertical: 1 });
+        assert_eq!(header.color_range(), ColorRange::Broadcast);
+        assert_eq!((header.color_primaries, header.transfer_characteristics, header.matrix_coefficients), (1, 1, 1));
+        assert_eq!((header.max_frame_width, header.max_frame_height), (352, 288));
+
+        assert_eq!(
+            header.to_codec_private(),
+            [[0x81, 0x08, 0x0C, 0x00].as_slice(), &SEQUENCE_HEADER].concat()
+        );
+
+        assert!(SequenceHeader::from_obus(&TEMPORAL_DELIMITER).is_err());
+        assert!(SequenceHeader::from_obus(&SEQUENCE_HEADER[..8]).is_err());
+    }
+
+    #[test]
+    fn converts_bitstream_formats() {
+        let frame = [0x32, 0x02, 0xAB, 0xCD];
+        let frame_header = [0x1A, 0x01, 0x55];
+        let data = [SEQUENCE_HEADER.as_slice(), &frame, &frame_header].concat();
+
+        let delimited = add_temporal_delimiter(&data).unwrap();
+        assert_eq!(delimited, [TEMPORAL_DELIMITER.as_slice(), &data].concat());
+        assert!(matches!(add_temporal_delimiter(&delimited).unwrap(), Cow::Borrowed(_)));
+
+        let first_frame_unit = [&[0x01, 0x10, 0x0F, 0x08], &SEQUENCE_HEADER[2..], &[0x03, 0x30, 0xAB, 0xCD]].concat();
+        let second_frame_unit = [0x02, 0x18, 0x55];
+        let expected = [&[27, 22], first_frame_unit.as_slice(), &[3], &second_frame_unit].concat();
+        assert_eq!(to_annex_b(&data).unwrap(), expected);
+        assert_eq!(to_annex_b(&delimited).unwrap(), expected);
+
+        let codec_private = [[0x81, 0x08, 0x0C, 0x00].as_slice(), &SEQUENCE_HEADER].concat();
+        assert_eq!(config_obus(&codec_private).unwrap(), SEQUENCE_HEADER);
+        assert!(config_obus(&SEQUENCE_HEADER).is_err());
+    }
+
+    #[test]
+    fn parses_reduced_still_picture_header() {
+        // Profile 1, reduced header, level 2.0, 16x16 with 4-bit sizes, then 10-bit sRGB
+        let payload = [0x39, 0x0C, 0xFF, 0xC0, 0xC0, 0x43, 0x40, 0x08];
+        let header = SequenceHeader::parse_payload(&payload).unwrap();
+        assert_eq!((header.profile, header.level), (1, 4));
+        assert_eq!((header.max_frame_width, header.max_frame_height), (16, 16));
+        assert_eq!(header.bit_depth, 10);
+        assert!(!header.subsampling_x && !header.subsampling_y);
+        assert_eq!(header.color_range(), ColorRange::Full);
+        assert_eq!(header.to_codec_private()[..4], [0x81, 0x24, 0x40, 0x00]);
+    }
+
+    #[test]
+    fn detects_keyframes() {
+        // show_existing_frame = 0, frame_type = KEY_FRAME, show_frame = 1
+        let keyframe = [0x32, 0x02, 0x10, 0x00];
+        // show_existing_frame = 0, frame_type = INTER_FRAME, show_frame = 1
+        let inter_frame = [0x32, 0x02, 0x30, 0x00];
+        // show_existing_frame = 1
+        let shown_keyframe = [0x1A, 0x01, 0x80];
+
+        let temporal_unit = |obus: &[&[u8]]| [[TEMPORAL_DELIMITER.as_slice()].as_slice(), obus].concat().concat();
+        assert!(is_keyframe(&temporal_unit(&[&SEQUENCE_HEADER, &keyframe])).unwrap());
+        assert!(!is_keyframe(&temporal_unit(&[&keyframe])).unwrap());
+        assert!(!is_keyframe(&temporal_unit(&[&SEQUENCE_HEADER, &inter_frame])).unwrap());
+        assert!(!is_keyframe(&temporal_unit(&[&SEQUENCE_HEADER, &shown_keyframe, &keyframe])).unwrap());
+        assert!(!is_keyframe(&temporal_unit(&[&SEQUENCE_HEADER])).unwrap());
+        assert!(is_keyframe(&temporal_unit(&[&SEQUENCE_HEADER, &[0x32, 0x05, 0x10]])).is_err());
+
+        // Reduced still picture headers imply a keyframe
+        let reduced_sequence_header = [0x0A, 0x08, 0x39, 0x0C, 0xFF, 0xC0, 0xC0, 0x43, 0x40, 0x08];
+        assert!(is_keyframe(&[reduced_sequence_header.as_slice(), &[0x32, 0x01, 0xFF]].concat()).unwrap());
+    }
+
+    #[test]
+    fn strips_temporal_delimiters() {
+        let frame = [0x32, 0x03, 1, 2, 3];
+        let data = [TEMPORAL_DELIMITER.as_slice(), &frame].concat();
+        assert_eq!(strip_temporal_delimiters(&data).unwrap(), frame.as_slice());
+        assert!(matches!(strip_temporal_delimiters(&frame).unwrap(), Cow::Borrowed(_)));
+
+        // The last OBU may omit its size field
+        let unsized_frame = [0x30, 1, 2, 3];
+        let data = [TEMPORAL_DELIMITER.as_slice(), &unsized_frame].concat();
+        assert_eq!(strip_temporal_delimiters(&data).unwrap(), unsized_frame.as_slice());
+
+        assert!(strip_temporal_delimiters(&[0x32, 0x05, 1, 2]).is_err());
+        assert!(strip_temporal_delimiters(&[0x92, 0x00]).is_err());
+    }
+
+    #[test]
+    fn leb128() {
+        let mut data = Vec::new();
+        for value in [0, 127, 128, 300, u64::from(u32::MAX)] {
+            data.clear();
+            write_leb128(value, &mut data);
+            assert_eq!(read_leb128(&data), Some((value, data.len())));
+        }
+        assert_eq!(read_leb128(&[0x80, 0x80]), None);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::mux::Error;
+
+/// Reads big-endian bit fields, as found in video bitstream headers.
+#[derive(Debug, Clone)]
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+
+    /// Position of the next bit to read, counting from the most significant bit of `data[0]`
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Reads an unsigned number of `bits` bits, up to 32.
+    pub(crate) fn read(&mut self, bits: u32) -> Result<u32, Error> {
+        debug_assert!(bits <= 32);
+        if self.remaining() < bits as usize {
+            return Err(Error::InvalidData("bitstream header is truncated"));
+        }
+
+        let mut value = 0_u64;
+        for _ in 0..bits {
+            let bit = self.data[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            value = value << 1 | u64::from(bit);
+            self.pos += 1;
+        }
+        Ok(value as u32)
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Skips `bits` bits.
+    pub(crate) fn skip(&mut self, bits: usize) -> Result<(), Error> {
+        if self.remaining() < bits {
+            return Err(Error::InvalidData("bitstream header is truncated"));
+        }
+        self.pos += bits;
+        Ok(())
+    }
+
+    /// Reads a variable length unsigned number, as used in AV1 (`uvlc()`).
+    pub(crate) fn read_uvlc(&mut self) -> Result<u32, Error> {
+        let mut leading_zeros = 0;
+        while !self.read_bool()? {
+            leading_zeros += 1;
+        }
+        if leading_zeros >= 32 {
+            return Ok(u32::MAX);
+        }
+
+        let value = self.read(leading_zeros)?;
+        Ok(value + ((1_u64 << leading_zeros) - 1) as u32)
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_fields() {
+        let mut reader = BitReader::new(&[0b1010_0111, 0xFF, 0x00, 0x12, 0x34, 0x56]);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read(3).unwrap(), 0b010);
+        assert_eq!(reader.read(8).unwrap(), 0b0111_1111);
+        reader.skip(4).unwrap();
+        assert_eq!(reader.read(32).unwrap(), 0x0012_3456);
+        assert!(reader.read(1).is_err());
+    }
+
+    #[test]
+    fn reads_uvlc() {
+        // 1 => 0, 010 => 1, 011 => 2, 00100 => 3
+        let mut reader = BitReader::new(&[0b1010_0110, 0b0100_0000]);
+        assert_eq!(reader.read_uvlc().unwrap(), 0);
+        assert_eq!(reader.read_uvlc().unwrap(), 1);
+        assert_eq!(reader.read_uvlc().unwrap(), 2);
+        assert_eq!(reader.read_uvlc().unwrap(), 3);
+        assert!(reader.read_uvlc().is_err());
+    }
+}
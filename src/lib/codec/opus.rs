@@ -0,0 +1,339 @@
+use crate::mux::Error;
+
+const OPUS_HEAD_MAGIC: &[u8; 8] = b"OpusHead";
+
+/// The rate at which Opus packet durations and [`OpusHead::pre_skip`] are counted, regardless of the input sample
+/// rate.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// How long before a seek target decoding should start for the output to have converged, as recommended in
+/// RFC 7845. Written as the track's `SeekPreRoll`.
+pub(crate) const SEEK_PRE_ROLL_NS: u64 = 80_000_000;
+
+/// The longest duration a single packet may have, 120 ms
+const MAX_PACKET_SAMPLES: u32 = 5760;
+
+/// The Opus identification header (`OpusHead`), as defined in [RFC 7845, section 5.1].
+///
+/// In WebM, this is the `CodecPrivate` of Opus tracks. Use [`SegmentBuilder::set_opus_head`] to set it, which also
+/// checks it against the track's channel count.
+///
+/// [RFC 7845, section 5.1]: https://www.rfc-editor.org/rfc/rfc7845#section-5.1
+/// [`SegmentBuilder::set_opus_head`]: crate::mux::SegmentBuilder::set_opus_head
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpusHead {
+    /// The number of output channels. This must match the channel count of the track.
+    pub channels: u8,
+
+    /// The number of samples (at 48 kHz) to discard from the start of the decoder output.
+    pub pre_skip: u16,
+
+    /// The sample rate of the original input, for informational purposes only. Zero means unspecified.
+    pub input_sample_rate: u32,
+
+    /// The gain to apply to the decoder output, in dB as a Q7.8 fixed-point number.
+    pub output_gain: i16,
+
+    /// The channel mapping family. Family 0 is for mono or stereo in a single stream, family 1 for up to 8 channels in
+    /// Vorbis order, and family 255 for arbitrary, unidentified channels.
+    pub mapping_family: u8,
+
+    /// How channels are assigned to coded streams. Required for any mapping family other than 0, and forbidden for 0.
+    pub mapping_table: Option<ChannelMappingTable>,
+}
+
+/// The channel mapping table of an [`OpusHead`], describing how decoded streams map to output channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMappingTable {
+    /// The total number of streams in each packet.
+    pub stream_count: u8,
+
+    /// How many of the streams are coupled (stereo). These come first, and decode to two channels each.
+    pub coupled_count: u8,
+
+    /// For each output channel, the index of the decoded channel it comes from, or 255 for silence.
+    pub channel_mapping: Vec<u8>,
+}
+
+impl OpusHead {
+    /// Creates an [`OpusHead`] with the standard channel layout for `channels`, as produced by libopus.
+    ///
+    /// Mono and stereo use mapping family 0. Up to 8 channels use mapping family 1 with the Vorbis channel order.
+    /// For anything else, fill in [`OpusHead::mapping_table`] yourself.
+    pub fn new(channels: u8, pre_skip: u16, input_sample_rate: u32) -> Result<Self, Error> {
+        // Stream count, coupled stream count, and mapping for each surround layout, as used by libopus
+        let (mapping_family, mapping_table) = match channels {
+            1 | 2 => (0, None),
+            3..=8 => {
+                let (stream_count, coupled_count, channel_mapping): (u8, u8, &[u8]) = match channels {
+                    3 => (2, 1, &[0, 2, 1]),
+                    4 => (2, 2, &[0, 1, 2, 3]),
+                    5 => (3, 2, &[0, 4, 1, 2, 3]),
+                    6 => (4, 2, &[0, 4, 1, 2, 3, 5]),
+                    7 => (4, 3, &[0, 4, 1, 2, 3, 5, 6]),
+                    _ => (5, 3, &[0, 6, 1, 2, 3, 4, 5, 7]),
+                };
+                let table = ChannelMappingTable {
+                    stream_count,
+                    coupled_count,
+                    channel_mapping: channel_mapping.to_vec(),
+                };
+                (1, Some(table))
+            },
+            _ => return Err(Error::BadParam),
+        };
+
+        Ok(Self {
+            channels,
+            pre_skip,
+            input_sample_rate,
+            output_gain: 0,
+            mapping_family,
+            mapping_table,
+        })
+    }
+
+    /// Parses an `OpusHead` packet, such as the first packet of an Ogg Opus stream or existing `CodecPrivate` data.
+    ///
+    /// Any bytes after the header are ignored, as are minor version changes, per the specification.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let header = data.get(..19).ok_or(Error::InvalidData("OpusHead is truncated"))?;
+        if &header[..8] != OPUS_HEAD_MAGIC {
+            return Err(Error::InvalidData("OpusHead magic signature not found"));
+        }
+
+        // Only the major version (upper 4 bits) signals incompatible changes
+        if header[8] & 0xF0 != 0 {
+            return Err(Error::InvalidData("unsupported OpusHead version"));
+        }
+
+        let channels = header[9];
+        let mapping_family = header[18];
+        let mapping_table = if mapping_family == 0 {
+            None
+        } else {
+            let table = data
+                .get(19..21 + usize::from(channels))
+                .ok_or(Error::InvalidData("OpusHead channel mapping table is truncated"))?;
+            Some(ChannelMappingTable {
+                stream_count: table[0],
+                coupled_count: table[1],
+                channel_mapping: table[2..].to_vec(),
+            })
+        };
+
+        let head = Self {
+            channels,
+            pre_skip: u16::from_le_bytes([header[10], header[11]]),
+            input_sample_rate: u32::from_le_bytes([header[12], header[13], header[14], header[15]]),
+            output_gain: i16::from_le_bytes([header[16], header[17]]),
+            mapping_family,
+            mapping_table,
+        };
+        head.validate().map_err(|_| Error::InvalidData("OpusHead is inconsistent"))?;
+
+        Ok(head)
+    }
+
+    /// Checks that the channel count, mapping family and mapping table are consistent with each other.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.channels == 0 {
+            return Err(Error::BadParam);
+        }
+
+        match (self.mapping_family, &self.mapping_table) {
+            (0, None) if self.channels <= 2 => Ok(()),
+            (0, _) => Err(Error::BadParam),
+            (_, None) => Err(Error::BadParam),
+            (family, Some(table)) => {
+                let decoded_channels = u16::from(table.stream_count) + u16::from(table.coupled_count);
+                let valid = !(family == 1 && self.channels > 8)
+                    && table.stream_count > 0
+                    && table.coupled_count <= table.stream_count
+                    && decoded_channels <= 255
+                    && table.channel_mapping.len() == usize::from(self.channels)
+                    && table
+                        .channel_mapping
+                        .iter()
+                        .all(|&index| index == 255 || u16::from(index) < decoded_channels);
+
+                if valid {
+                    Ok(())
+                } else {
+                    Err(Error::BadParam)
+                }
+            },
+        }
+    }
+
+    /// Returns the pre-skip in nanoseconds, which is written as the track's `CodecDelay`.
+    #[must_use]
+    pub fn pre_skip_ns(&self) -> u64 {
+        samples_to_ns(self.pre_skip.into())
+    }
+
+    /// Serializes this header, e.g. for use as `CodecPrivate`. Fails if it is not [valid](OpusHead::validate).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        self.validate()?;
+
+        let mut data = Vec::with_capacity(21 + usize::from(self.channels));
+        data.extend_from_slice(OPUS_HEAD_MAGIC);
+        data.push(1); // version
+        data.push(self.channels);
+        data.extend_from_slice(&self.pre_skip.to_le_bytes());
+        data.extend_from_slice(&self.input_sample_rate.to_le_bytes());
+        data.extend_from_slice(&self.output_gain.to_le_bytes());
+        data.push(self.mapping_family);
+        if let Some(table) = &self.mapping_table {
+            data.push(table.stream_count);
+            data.push(table.coupled_count);
+            data.extend_from_slice(&table.channel_mapping);
+        }
+
+        Ok(data)
+    }
+}
+
+/// Returns the number of samples (at [`SAMPLE_RATE`]) in an Opus packet, i.e. the duration of its frames times
+/// their number, according to its TOC byte as described in [RFC 6716, section 3.1].
+///
+/// [RFC 6716, section 3.1]: https://www.rfc-editor.org/rfc/rfc6716#section-3.1
+pub fn packet_samples(packet: &[u8]) -> Result<u32, Error> {
+    let (&toc, rest) = packet.split_first().ok_or(Error::InvalidData("Opus packet is empty"))?;
+
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK: 10, 20, 40 or 60 ms
+        0..=11 => [480, 960, 1920, 2880][usize::from(config % 4)],
+        // Hybrid: 10 or 20 ms
+        12..=15 => [480, 960][usize::from(config % 2)],
+        // CELT: 2.5, 5, 10 or 20 ms
+        _ => [120, 240, 480, 960][usize::from(config % 4)],
+    };
+
+    let frame_count = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => {
+            let count = rest.first().ok_or(Error::InvalidData("Opus packet is truncated"))? & 0x3F;
+            if count == 0 {
+                return Err(Error::InvalidData("Opus packet has no frames"));
+            }
+            u32::from(count)
+        },
+    };
+
+    let samples = frame_samples * frame_count;
+    if samples > MAX_PACKET_SAMPLES {
+        return Err(Error::InvalidData("Opus packet is longer than 120 ms"));
+    }
+    Ok(samples)
+}
+
+/// Returns the duration of an Opus packet in nanoseconds. See [`packet_samples`].
+pub fn packet_duration_ns(packet: &[u8]) -> Result<u64, Error> {
+    Ok(samples_to_ns(packet_samples(packet)?.into()))
+}
+
+/// Converts a number of samples at [`SAMPLE_RATE`] to nanoseconds, rounding down.
+pub(crate) fn samples_to_ns(samples: u64) -> u64 {
+    (u128::from(samples) * 1_000_000_000 / u128::from(SAMPLE_RATE)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_round_trip() {
+        let head = OpusHead::new(2, 312, 44_100).unwrap();
+        let bytes = head.to_bytes().unwrap();
+        assert_eq!(
+            bytes,
+            [b'O', b'p', b'u', b's', b'H', b'e', b'a', b'd', 1, 2, 0x38, 0x01, 0x44, 0xAC, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(OpusHead::parse(&bytes).unwrap(), head);
+    }
+
+    #[test]
+    fn surround_round_trip() {
+        let mut head = OpusHead::new(6, 3840, 48_000).unwrap();
+        head.output_gain = -256;
+        assert_eq!(head.mapping_family, 1);
+
+        let bytes = head.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 27);
+        assert_eq!(&bytes[16..], [0x00, 0xFF, 1, 4, 2, 0, 4, 1, 2, 3, 5]);
+        assert_eq!(OpusHead::parse(&bytes).unwrap(), head);
+
+        // Trailing data is ignored
+        let mut padded = bytes.clone();
+        padded.extend_from_slice(&[0; 8]);
+        assert_eq!(OpusHead::parse(&padded).unwrap(), head);
+    }
+
+    #[test]
+    fn rejects_inconsistent_headers() {
+        assert!(OpusHead::new(0, 0, 0).is_err());
+        assert!(OpusHead::new(9, 0, 0).is_err());
+
+        let mut head = OpusHead::new(2, 0, 0).unwrap();
+        head.channels = 3;
+        assert!(head.to_bytes().is_err());
+
+        let mut head = OpusHead::new(4, 0, 0).unwrap();
+        head.mapping_table.as_mut().unwrap().channel_mapping.pop();
+        assert!(head.validate().is_err());
+
+        let mut head = OpusHead::new(4, 0, 0).unwrap();
+        head.mapping_table.as_mut().unwrap().channel_mapping[0] = 4;
+        assert!(head.validate().is_err());
+        head.mapping_table.as_mut().unwrap().channel_mapping[0] = 255;
+        assert!(head.validate().is_ok());
+
+        let mut head = OpusHead::new(4, 0, 0).unwrap();
+        head.mapping_table = None;
+        assert!(head.validate().is_err());
+    }
+
+    #[test]
+    fn packet_durations() {
+        // SILK 20 ms, one frame
+        assert_eq!(packet_samples(&[0x08]).unwrap(), 960);
+        // SILK 60 ms, two frames
+        assert_eq!(packet_samples(&[0x19, 0xAB]).unwrap(), 5760);
+        // Hybrid 10 ms, two frames of different sizes
+        assert_eq!(packet_samples(&[0x62, 0x01, 0xAB]).unwrap(), 960);
+        // CELT 2.5 ms, 6 frames (with VBR and padding flags set)
+        assert_eq!(packet_samples(&[0x83, 0xC6, 0x00]).unwrap(), 720);
+        // CELT 20 ms, one frame
+        assert_eq!(packet_samples(&[0xFC, 0xFF, 0xFE]).unwrap(), 960);
+        assert_eq!(packet_duration_ns(&[0xFC]).unwrap(), 20_000_000);
+        assert_eq!(packet_duration_ns(&[0x80]).unwrap(), 2_500_000);
+
+        assert!(packet_samples(&[]).is_err());
+        assert!(packet_samples(&[0x03]).is_err());
+        assert!(packet_samples(&[0x03, 0x00]).is_err());
+        // SILK 60 ms, three frames
+        assert!(packet_samples(&[0x1B, 0x03]).is_err());
+
+        assert_eq!(OpusHead::new(2, 312, 48_000).unwrap().pre_skip_ns(), 6_500_000);
+    }
+
+    #[test]
+    fn rejects_malformed_bytes() {
+        let bytes = OpusHead::new(5, 0, 0).unwrap().to_bytes().unwrap();
+        assert!(OpusHead::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(OpusHead::parse(&bytes[..18]).is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'o';
+        assert!(OpusHead::parse(&wrong_magic).is_err());
+
+        let mut future_version = bytes.clone();
+        future_version[8] = 0x10;
+        assert!(OpusHead::parse(&future_version).is_err());
+        future_version[8] = 0x0F;
+        assert!(OpusHead::parse(&future_version).is_ok());
+    }
+}
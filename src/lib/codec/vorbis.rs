@@ -0,0 +1,421 @@
+use crate::mux::Error;
+
+const VORBIS_MAGIC: &[u8; 6] = b"vorbis";
+const IDENTIFICATION_HEADER_TYPE: u8 = 1;
+const COMMENT_HEADER_TYPE: u8 = 3;
+const SETUP_HEADER_TYPE: u8 = 5;
+
+/// The three header packets a Vorbis stream starts with, as required in the `CodecPrivate` of Vorbis tracks.
+///
+/// These usually come from the first three packets of an Ogg Vorbis stream, or directly from an encoder such as
+/// libvorbis (`vorbis_analysis_headerout`). Use [`SegmentBuilder::add_vorbis_track`] to add a track configured from
+/// them.
+///
+/// [`SegmentBuilder::add_vorbis_track`]: crate::mux::SegmentBuilder::add_vorbis_track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VorbisHeaders {
+    identification: Vec<u8>,
+    comment: Vec<u8>,
+    setup: Vec<u8>,
+    info: VorbisIdentification,
+}
+
+/// The stream parameters from a Vorbis identification header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VorbisIdentification {
+    /// The number of audio channels.
+    pub channels: u8,
+
+    /// The sample rate, in Hz.
+    pub sample_rate: u32,
+
+    /// The maximum bitrate, in bits per second, or zero if unset.
+    pub bitrate_maximum: i32,
+
+    /// The nominal bitrate, in bits per second, or zero if unset.
+    pub bitrate_nominal: i32,
+
+    /// The minimum bitrate, in bits per second, or zero if unset.
+    pub bitrate_minimum: i32,
+
+    /// The short and long block sizes, in samples.
+    pub block_sizes: (u16, u16),
+}
+
+impl VorbisHeaders {
+    /// Checks and takes the identification, comment and setup header packets, in that order.
+    pub fn new(identification: &[u8], comment: &[u8], setup: &[u8]) -> Result<Self, Error> {
+        let info = VorbisIdentification::parse(identification)?;
+        check_header(comment, COMMENT_HEADER_TYPE, "Vorbis comment header not found")?;
+        check_header(setup, SETUP_HEADER_TYPE, "Vorbis setup header not found")?;
+
+        Ok(Self {
+            identification: identification.to_vec(),
+            comment: comment.to_vec(),
+            setup: setup.to_vec(),
+            info,
+        })
+    }
+
+    /// Splits existing Vorbis `CodecPrivate` data back into the three header packets.
+    pub fn from_codec_private(data: &[u8]) -> Result<Self, Error> {
+        match xiph_unlace(data)?.as_slice() {
+            [identification, comment, setup] => Self::new(identification, comment, setup),
+            _ => Err(Error::InvalidData("Vorbis CodecPrivate must contain exactly three packets")),
+        }
+    }
+
+    /// Returns the stream parameters from the identification header.
+    #[must_use]
+    pub fn info(&self) -> &VorbisIdentification {
+        &self.info
+    }
+
+    /// Returns the identification, comment and setup header packets, in that order.
+    #[must_use]
+    pub fn packets(&self) -> [&[u8]; 3] {
+        [&self.identification, &self.comment, &self.setup]
+    }
+
+    /// Assembles the `CodecPrivate` data for a Vorbis track, i.e. the three header packets in Xiph lacing.
+    #[must_use]
+    pub fn to_codec_private(&self) -> Vec<u8> {
+        xiph_lace(&self.packets())
+    }
+}
+
+impl VorbisIdentification {
+    /// Parses a Vorbis identification header packet.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        check_header(data, IDENTIFICATION_HEADER_TYPE, "Vorbis identification header not found")?;
+        let header = data.get(..30).ok_or(Error::InvalidData("Vorbis identification header is truncated"))?;
+
+        let u32_at = |offset: usize| {
+            let bytes = [header[offset], header[offset + 1], header[offset + 2], header[offset + 3]];
+            u32::from_le_bytes(bytes)
+        };
+        if u32_at(7) != 0 {
+            return Err(Error::InvalidData("unsupported Vorbis version"));
+        }
+
+        let channels = header[11];
+        let sample_rate = u32_at(12);
+        let block_size_exponents = (header[28] & 0x0F, header[28] >> 4);
+        let valid_exponents = (6..=13).contains(&block_size_exponents.0)
+            && (6..=13).contains(&block_size_exponents.1)
+            && block_size_exponents.0 <= block_size_exponents.1;
+        if channels == 0 || sample_rate == 0 || !valid_exponents || header[29] & 1 == 0 {
+            return Err(Error::InvalidData("Vorbis identification header is inconsistent"));
+        }
+
+        Ok(Self {
+            channels,
+            sample_rate,
+            bitrate_maximum: u32_at(16) as i32,
+            bitrate_nominal: u32_at(20) as i32,
+            bitrate_minimum: u32_at(24) as i32,
+            block_sizes: (1 << block_size_exponents.0, 1 << block_size_exponents.1),
+        })
+    }
+}
+
+/// Works out how many samples consecutive Vorbis audio packets decode to.
+///
+/// A Vorbis packet's duration depends on its own block size and that of the packet before it, and the block size
+/// depends on the packet's mode, which is described in the setup header. The first packet decodes to no samples.
+#[derive(Debug, Clone)]
+pub struct PacketDurations {
+    block_sizes: (u16, u16),
+
+    /// Whether each mode uses long blocks
+    mode_block_flags: Vec<bool>,
+    previous_block_size: Option<u16>,
+}
+
+impl PacketDurations {
+    /// Reads the modes from the setup header in `headers`.
+    pub fn new(headers: &VorbisHeaders) -> Result<Self, Error> {
+        Ok(Self {
+            block_sizes: headers.info.block_sizes,
+            mode_block_flags: mode_block_flags(&headers.setup)?,
+            previous_block_size: None,
+        })
+    }
+
+    /// Returns the number of samples `packet` decodes to, given the packets passed to this before it.
+    ///
+    /// Empty packets are allowed, and decode to nothing.
+    pub fn next_packet_samples(&mut self, packet: &[u8]) -> Result<u32, Error> {
+        let Some(&first) = packet.first() else {
+            return Ok(0);
+        };
+        if first & 1 != 0 {
+            return Err(Error::InvalidData("Vorbis audio packet expected"));
+        }
+
+        // The mode number follows the packet type bit, and takes at most 6 bits
+        let mode_bits = usize::BITS - (self.mode_block_flags.len() - 1).leading_zeros();
+        let mode = usize::from(first >> 1) & ((1 << mode_bits) - 1);
+        let long_block = *self.mode_block_flags.get(mode).ok_or(Error::InvalidData("Vorbis packet mode is invalid"))?;
+
+        let block_size = if long_block { self.block_sizes.1 } else { self.block_sizes.0 };
+        let samples = match self.previous_block_size.replace(block_size) {
+            Some(previous) => u32::from(previous) / 4 + u32::from(block_size) / 4,
+            None => 0,
+        };
+        Ok(samples)
+    }
+}
+
+/// Finds the block flag of each mode in a setup header.
+///
+/// Modes are at the very end of the header, but parsing everything before them would mean parsing all codebooks,
+/// floors, residues and mappings. Instead, this works backwards from the framing bit, taking the largest number of
+/// modes with zeroed window and transform types that is preceded by a matching mode count.
+fn mode_block_flags(setup: &[u8]) -> Result<Vec<bool>, Error> {
+    const MODE_BITS: usize = 41;
+    const INVALID: Error = Error::InvalidData("Vorbis setup header modes not found");
+
+    // Vorbis packs bits starting from the least significant one
+    let bits = |position: usize, count: usize| {
+        (0..count).fold(0_u32, |acc, i| {
+            let bit = position + i;
+            acc | u32::from(setup[bit / 8] >> (bit % 8) & 1) << i
+        })
+    };
+
+    let last_byte = match setup.last() {
+        Some(&byte) if byte != 0 => byte,
+        _ => return Err(INVALID),
+    };
+    let framing_bit = setup.len() * 8 - 1 - last_byte.leading_zeros() as usize;
+
+    let mut mode_count = None;
+    for count in 1..=64 {
+        let Some(modes_start) = framing_bit.checked_sub(count * MODE_BITS) else {
+            break;
+        };
+        // Window and transform types are always zero
+        if bits(modes_start + 1, 16) != 0 || bits(modes_start + 17, 16) != 0 {
+            break;
+        }
+        if modes_start >= 6 && bits(modes_start - 6, 6) as usize == count - 1 {
+            mode_count = Some(count);
+        }
+    }
+
+    let mode_count = mode_count.ok_or(INVALID)?;
+    let modes_start = framing_bit - mode_count * MODE_BITS;
+    Ok((0..mode_count).map(|mode| bits(modes_start + mode * MODE_BITS, 1) != 0).collect())
+}
+
+/// Makes sure `data` starts with the common Vorbis header prefix for the given packet type.
+fn check_header(data: &[u8], packet_type: u8, message: &'static str) -> Result<(), Error> {
+    match data {
+        [first, magic @ .., _] if *first == packet_type && magic.starts_with(VORBIS_MAGIC) => Ok(()),
+        _ => Err(Error::InvalidData(message)),
+    }
+}
+
+/// Joins `packets` using Xiph lacing, as used in the `CodecPrivate` of Vorbis (and Theora) tracks: the number of
+/// packets minus one, followed by the sizes of all but the last packet, followed by the packets themselves.
+///
+/// # Panics
+/// Panics if there are no packets, or more than 256 of them.
+#[must_use]
+pub fn xiph_lace(packets: &[&[u8]]) -> Vec<u8> {
+    let (_, sized) = packets.split_last().expect("at least one packet is needed for lacing");
+    let count = u8::try_from(sized.len()).expect("no more than 256 packets can be laced");
+
+    let total_len: usize = packets.iter().map(|packet| packet.len()).sum();
+    let mut data = Vec::with_capacity(1 + total_len / 255 + sized.len() + total_len);
+    data.push(count);
+    for packet in sized {
+        // Sizes are written as a run of 255s, terminated by a smaller byte
+        data.extend(std::iter::repeat_n(255, packet.len() / 255));
+        data.push((packet.len() % 255) as u8);
+    }
+    for packet in packets {
+        data.extend_from_slice(packet);
+    }
+
+    data
+}
+
+/// Splits data joined with [`xiph_lace`] back into its packets.
+pub fn xiph_unlace(data: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    const TRUNCATED: Error = Error::InvalidData("Xiph-laced data is truncated");
+
+    let (&count, mut rest) = data.split_first().ok_or(TRUNCATED)?;
+    let mut sizes = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let mut size = 0;
+        loop {
+            let (&byte, remaining) = rest.split_first().ok_or(TRUNCATED)?;
+            rest = remaining;
+            size += usize::from(byte);
+            if byte != 255 {
+                break;
+            }
+        }
+        sizes.push(size);
+    }
+
+    let mut packets = Vec::with_capacity(sizes.len() + 1);
+    for size in sizes {
+        if size > rest.len() {
+            return Err(TRUNCATED);
+        }
+        let (packet, remaining) = rest.split_at(size);
+        packets.push(packet);
+        rest = remaining;
+    }
+    packets.push(rest);
+
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identification_header(channels: u8, sample_rate: u32) -> Vec<u8> {
+        let mut data = vec![IDENTIFICATION_HEADER_TYPE];
+        data.extend_from_slice(VORBIS_MAGIC);
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.push(channels);
+        data.extend_from_slice(&sample_rate.to_le_bytes());
+        data.extend_from_slice(&0_i32.to_le_bytes());
+        data.extend_from_slice(&128_000_i32.to_le_bytes());
+        data.extend_from_slice(&(-1_i32).to_le_bytes());
+        data.push(0xB8);
+        data.push(1);
+        data
+    }
+
+    fn header(packet_type: u8, len: usize) -> Vec<u8> {
+        let mut data = vec![packet_type];
+        data.extend_from_slice(VORBIS_MAGIC);
+        data.resize(len, 0xAB);
+        data.push(1);
+        data
+    }
+
+    /// Builds a setup header ending with the given modes, as pairs of block flag and mapping number.
+    fn setup_header(modes: &[(bool, u8)]) -> Vec<u8> {
+        let mut bits = Vec::new();
+        let mut push = |value: u32, count: usize| bits.extend((0..count).map(|i| value >> i & 1 != 0));
+        push(modes.len() as u32 - 1, 6);
+        for &(block_flag, mapping) in modes {
+            push(block_flag.into(), 1);
+            push(0, 32);
+            push(mapping.into(), 8);
+        }
+        push(1, 1);
+
+        // Stand-in for the codebooks, floors, residues and mappings
+        let mut data = vec![SETUP_HEADER_TYPE];
+        data.extend_from_slice(VORBIS_MAGIC);
+        data.extend_from_slice(&[0x5A, 0xC3, 0x81]);
+        for byte in bits.chunks(8) {
+            data.push(byte.iter().rev().fold(0, |acc, &bit| acc << 1 | u8::from(bit)));
+        }
+        data
+    }
+
+    #[test]
+    fn parses_identification() {
+        let info = VorbisIdentification::parse(&identification_header(2, 44_100)).unwrap();
+        assert_eq!(
+            info,
+            VorbisIdentification {
+                channels: 2,
+                sample_rate: 44_100,
+                bitrate_maximum: 0,
+                bitrate_nominal: 128_000,
+                bitrate_minimum: -1,
+                block_sizes: (256, 2048),
+            }
+        );
+
+        assert!(VorbisIdentification::parse(&identification_header(0, 44_100)).is_err());
+        assert!(VorbisIdentification::parse(&identification_header(2, 0)).is_err());
+        assert!(VorbisIdentification::parse(&identification_header(2, 44_100)[..29]).is_err());
+
+        let mut bad_block_sizes = identification_header(2, 44_100);
+        bad_block_sizes[28] = 0x8B;
+        assert!(VorbisIdentification::parse(&bad_block_sizes).is_err());
+
+        let mut no_framing = identification_header(2, 44_100);
+        no_framing[29] = 0;
+        assert!(VorbisIdentification::parse(&no_framing).is_err());
+    }
+
+    #[test]
+    fn codec_private_round_trip() {
+        let identification = identification_header(6, 48_000);
+        let comment = header(COMMENT_HEADER_TYPE, 300);
+        let setup = header(SETUP_HEADER_TYPE, 4000);
+        let headers = VorbisHeaders::new(&identification, &comment, &setup).unwrap();
+        assert_eq!(headers.info().channels, 6);
+
+        let codec_private = headers.to_codec_private();
+        assert_eq!(&codec_private[..4], [2, 30, 255, 46]);
+        assert_eq!(codec_private.len(), 4 + 30 + 301 + 4001);
+        assert_eq!(VorbisHeaders::from_codec_private(&codec_private).unwrap(), headers);
+    }
+
+    #[test]
+    fn rejects_wrong_packets() {
+        let identification = identification_header(2, 48_000);
+        let comment = header(COMMENT_HEADER_TYPE, 20);
+        let setup = header(SETUP_HEADER_TYPE, 20);
+
+        assert!(VorbisHeaders::new(&comment, &identification, &setup).is_err());
+        assert!(VorbisHeaders::new(&identification, &setup, &comment).is_err());
+        assert!(VorbisHeaders::new(&identification, &comment, b"\x05vorbi").is_err());
+
+        let two_packets = xiph_lace(&[&identification, &comment]);
+        assert!(VorbisHeaders::from_codec_private(&two_packets).is_err());
+    }
+
+    #[test]
+    fn lacing() {
+        let long = [7; 510];
+        let packets: [&[u8]; 4] = [b"", &long, b"abc", b"last"];
+        let laced = xiph_lace(&packets);
+        assert_eq!(&laced[..5], [3, 0, 255, 255, 0]);
+        assert_eq!(laced.len(), 6 + 510 + 3 + 4);
+        assert_eq!(xiph_unlace(&laced).unwrap(), packets);
+
+        assert_eq!(xiph_unlace(&[0, 1, 2]).unwrap(), [&[1, 2]]);
+        assert!(xiph_unlace(&[]).is_err());
+        assert!(xiph_unlace(&[1, 255]).is_err());
+        assert!(xiph_unlace(&[1, 5, 1, 2]).is_err());
+    }
+    #[test]
+    fn packet_durations() {
+        // Block sizes are 256 and 2048
+        let identification = identification_header(2, 44_100);
+        let comment = header(COMMENT_HEADER_TYPE, 20);
+        let headers = |setup: &[u8]| VorbisHeaders::new(&identification, &comment, setup).unwrap();
+
+        let mut durations = PacketDurations::new(&headers(&setup_header(&[(false, 0), (true, 1)]))).unwrap();
+        let samples: Vec<_> =
+            [0x00, 0x02, 0x02, 0x00].iter().map(|&packet| durations.next_packet_samples(&[packet]).unwrap()).collect();
+        assert_eq!(samples, [0, 576, 1024, 576]);
+        assert_eq!(durations.next_packet_samples(&[]).unwrap(), 0);
+        assert!(durations.next_packet_samples(&[0x01]).is_err());
+
+        let mut durations = PacketDurations::new(&headers(&setup_header(&[(true, 0); 3]))).unwrap();
+        assert_eq!(durations.next_packet_samples(&[0x04]).unwrap(), 0);
+        assert_eq!(durations.next_packet_samples(&[0x02]).unwrap(), 1024);
+        assert!(durations.next_packet_samples(&[0x06]).is_err());
+
+        let mut durations = PacketDurations::new(&headers(&setup_header(&[(false, 0)]))).unwrap();
+        assert_eq!(durations.next_packet_samples(&[0xFE]).unwrap(), 0);
+        assert_eq!(durations.next_packet_samples(&[0xFE]).unwrap(), 128);
+
+        assert!(PacketDurations::new(&headers(&header(SETUP_HEADER_TYPE, 20))).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::mux::Error;
+
+const START_CODE: [u8; 3] = [0x9D, 0x01, 0x2A];
+
+/// Returns `true` if `frame` is a VP8 keyframe, based on its frame tag.
+///
+/// Keyframes must also carry the start code, or this fails with [`Error::InvalidData`].
+pub fn is_keyframe(frame: &[u8]) -> Result<bool, Error> {
+    let Some(tag) = frame.get(..3) else {
+        return Err(Error::InvalidData("VP8 frame is truncated"));
+    };
+    if tag[0] & 0x01 != 0 {
+        return Ok(false);
+    }
+
+    // Keyframes continue with a start code, and then the frame size
+    match frame.get(3..10) {
+        Some(header) if header[..3] == START_CODE => Ok(true),
+        Some(_) => Err(Error::InvalidData("VP8 keyframe start code not found")),
+        None => Err(Error::InvalidData("VP8 frame is truncated")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_keyframes() {
+        assert!(is_keyframe(&[0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01]).unwrap());
+        assert!(!is_keyframe(&[0x31, 0x05, 0x00, 0xAB]).unwrap());
+
+        assert!(is_keyframe(&[0x50, 0x42, 0x00, 0x9D, 0x01, 0x2B, 0x60, 0x01, 0x20, 0x01]).is_err());
+        assert!(is_keyframe(&[0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A]).is_err());
+        assert!(is_keyframe(&[0x31, 0x05]).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use super::bits::BitReader;
+use crate::mux::{ColorRange, ColorSubsampling, Error};
+
+const FRAME_MARKER: u32 = 2;
+const SYNC_CODE: u32 = 0x49_8342;
+
+/// IDs of the VP9 codec feature metadata elements stored in `CodecPrivate`
+const FEATURE_PROFILE: u8 = 1;
+const FEATURE_LEVEL: u8 = 2;
+const FEATURE_BIT_DEPTH: u8 = 3;
+const FEATURE_CHROMA_SUBSAMPLING: u8 = 4;
+
+/// The color space signaled in a VP9 keyframe header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Unknown,
+    Bt601,
+    Bt709,
+    Smpte170,
+    Smpte240,
+    Bt2020,
+    Reserved,
+    Srgb,
+}
+
+/// The stream parameters from the uncompressed header of a VP9 keyframe.
+///
+/// Use [`SegmentBuilder::add_vp9_track`] to add a track configured from it, with the `CodecPrivate` data and color
+/// information filled in.
+///
+/// [`SegmentBuilder::add_vp9_track`]: crate::mux::SegmentBuilder::add_vp9_track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyframeHeader {
+    /// The profile, 0 to 3.
+    pub profile: u8,
+
+    /// The number of bits per sample: 8, 10 or 12.
+    pub bit_depth: u8,
+
+    /// The color space.
+    pub color_space: ColorSpace,
+
+    /// Whether the full range of sample values is used, rather than the studio (broadcast) range.
+    pub full_color_range: bool,
+
+    /// Whether chroma is subsampled horizontally.
+    pub subsampling_x: bool,
+
+    /// Whether chroma is subsampled vertically.
+    pub subsampling_y: bool,
+
+    /// The frame width, in pixels.
+    pub width: u32,
+
+    /// The frame height, in pixels.
+    pub height: u32,
+}
+
+impl KeyframeHeader {
+    /// Parses the uncompressed header of a keyframe, such as the first frame out of an encoder. If `frame` is a
+    /// superframe, the first keyframe within it is used.
+    ///
+    /// Fails with [`Error::InvalidData`] if there is no keyframe.
+    pub fn parse(frame: &[u8]) -> Result<Self, Error> {
+        for frame in superframe_frames(frame) {
+            if let Some(header) = parse_frame(frame)? {
+                return Ok(header);
+            }
+        }
+
+        Err(Error::InvalidData("VP9 frame is not a keyframe"))
+    }
+
+    /// Returns the chroma subsampling, for use with [`SegmentBuilder::set_color`].
+    ///
+    /// [`SegmentBuilder::set_color`]: crate::mux::SegmentBuilder::set_color
+    #[must_use]
+    pub fn color_subsampling(&self) -> ColorSubsampling {
+        ColorSubsampling {
+            chroma_horizontal: self.subsampling_x.into(),
+            chroma_vertical: self.subsampling_y.into(),
+        }
+    }
+
+    /// Returns the color range, for use with [`SegmentBuilder::set_color`].
+    ///
+    /// [`SegmentBuilder::set_color`]: crate::mux::SegmentBuilder::set_color
+    #[must_use]
+    pub fn color_range(&self) -> ColorRange {
+        if self.full_color_range {
+            ColorRange::Full
+        } else {
+            ColorRange::Broadcast
+        }
+    }
+
+    /// Builds the `CodecPrivate` data for a VP9 track, i.e. the codec feature metadata with the profile, bit depth
+    /// and chroma subsampling, and the level if given.
+    ///
+    /// The level can't be derived from a single frame, so pass along whatever the encoder was configured with, in
+    /// tenths (e.g. 41 for level 4.1).
+    #[must_use]
+    pub fn to_codec_private(&self, level: Option<u8>) -> Vec<u8> {
+        // 4:2:0 is written as vertically sited (the libvpx default) as VP9 itself doesn't signal the position
+        let chroma_subsampling = match (self.subsampling_x, self.subsampling_y) {
+            (true, true) => 0,
+            (true, false) => 2,
+            (false, _) => 3,
+        };
+
+        let mut features = vec![(FEATURE_PROFILE, self.profile)];
+        features.extend(level.map(|level| (FEATURE_LEVEL, level)));
+        features.push((FEATURE_BIT_DEPTH, self.bit_depth));
+        features.push((FEATURE_CHROMA_SUBSAMPLING, chroma_subsampling));
+
+        features.into_iter().flat_map(|(id, value)| [id, 1, value]).collect()
+    }
+}
+
+/// Returns `true` if `frame` is a VP9 keyframe, based on its uncompressed header.
+///
+/// For superframes, this is determined by the first frame within, which is the one decoding starts with.
+pub fn is_keyframe(frame: &[u8]) -> Result<bool, Error> {
+    let first_frame = superframe_frames(frame)[0];
+    let mut r = BitReader::new(first_frame);
+    if parse_frame_start(&mut r)?.is_none() {
+        return Ok(false);
+    }
+
+    Ok(!r.read_bool()?)
+}
+
+/// Parses the uncompressed header of a single (non-super) frame, returning `None` if it is not a keyframe.
+fn parse_frame(frame: &[u8]) -> Result<Option<KeyframeHeader>, Error> {
+    let mut r = BitReader::new(frame);
+    let Some(profile) = parse_frame_start(&mut r)? else {
+        return Ok(None);
+    };
+    let keyframe = !r.read_bool()?;
+    if !keyframe {
+        return Ok(None);
+    }
+    // show_frame, error_resilient_mode
+    r.skip(2)?;
+    if r.read(24)? != SYNC_CODE {
+        return Err(Error::InvalidData("VP9 frame sync code not found"));
+    }
+
+    // color_config()
+    let bit_depth = match profile {
+        2 | 3 if r.read_bool()? => 12,
+        2 | 3 => 10,
+        _ => 8,
+    };
+    let color_space = match r.read(3)? {
+        0 => ColorSpace::Unknown,
+        1 => ColorSpace::Bt601,
+        2 => ColorSpace::Bt709,
+        3 => ColorSpace::Smpte170,
+        4 => ColorSpace::Smpte240,
+        5 => ColorSpace::Bt2020,
+        6 => ColorSpace::Reserved,
+        _ => ColorSpace::Srgb,
+    };
+
+    let full_color_range;
+    let (subsampling_x, subsampling_y);
+    if color_space == ColorSpace::Srgb {
+        if profile % 2 == 0 {
+            return Err(Error::InvalidData("VP9 profiles 0 and 2 do not support sRGB"));
+        }
+        full_color_range = true;
+        (subsampling_x, subsampling_y) = (false, false);
+        r.skip(1)?;
+    } else {
+        full_color_range = r.read_bool()?;
+        if profile % 2 == 1 {
+            (subsampling_x, subsampling_y) = (r.read_bool()?, r.read_bool()?);
+            r.skip(1)?;
+        } else {
+            (subsampling_x, subsampling_y) = (true, true);
+        }
+    }
+
+    let width = r.read(16)? + 1;
+    let height = r.read(16)? + 1;
+
+    Ok(Some(KeyframeHeader {
+        profile,
+        bit_depth,
+        color_space,
+        full_color_range,
+        subsampling_x,
+        subsampling_y,
+        width,
+        height,
+    }))
+}
+
+/// Reads the start of an uncompressed header, up to and including `show_existing_frame`. Returns the profile, or
+/// `None` if the frame only shows an existing one.
+fn parse_frame_start(r: &mut BitReader<'_>) -> Result<Option<u8>, Error> {
+    if r.read(2)? != FRAME_MARKER {
+        return Err(Error::InvalidData("VP9 frame marker not found"));
+    }
+
+    let profile_low_bit = r.read(1)?;
+    let profile = (r.read(1)? << 1 | profile_low_bit) as u8;
+    if profile == 3 {
+        r.skip(1)?;
+    }
+
+    let show_existing_frame = r.read_bool()?;
+    Ok((!show_existing_frame).then_some(profile))
+}
+
+/// Splits a VP9 superframe into the frames it contains. Anything that is not a valid superframe is returned as a
+/// single frame.
+pub(crate) fn superframe_frames(data: &[u8]) -> Vec<&[u8]> {
+    parse_superframe_index(data).unwrap_or_else(|| vec![data])
+}
+
+fn parse_superframe_index(data: &[u8]) -> Option<Vec<&[u8]>> {
+    let &marker = data.last()?;
+    if marker & 0xE0 != 0xC0 {
+        return None;
+    }
+
+    let frame_count = usize::from(marker & 0x07) + 1;
+    let size_len = usize::from(marker >> 3 & 0x03) + 1;
+    let index_len = 2 + size_len * frame_count;
+    let index_start = data.len().checked_sub(index_len)?;
+    if data[index_start] != marker {
+        return None;
+    }
+
+    let mut frames = Vec::with_capacity(frame_count);
+    let mut rest = &data[..index_start];
+    for size in data[index_start + 1..data.len() - 1].chunks_exact(size_len) {
+        let size = size.iter().rev().fold(0, |acc, &b| acc << 8 | usize::from(b));
+        if size > rest.len() {
+            return None;
+        }
+        let (frame, remaining) = rest.split_at(size);
+        frames.push(frame);
+        rest = remaining;
+    }
+
+    Some(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start of a 352x288 profile 0 keyframe in BT.601, followed by some compressed data.
+    const KEYFRAME: [u8; 12] = [0x82, 0x49, 0x83, 0x42, 0x20, 0x15, 0xF0, 0x11, 0xF0, 0x00, 0xAB, 0xCD];
+
+    /// The start of a 1280x720 profile 1 keyframe in BT.709, full range 4:4:4.
+    const PROFILE_1_KEYFRAME: [u8; 10] = [0xA2, 0x49, 0x83, 0x42, 0x50, 0x09, 0xFE, 0x05, 0x9E, 0x00];
+
+    /// An inter frame in profile 0.
+    const INTER_FRAME: [u8; 4] = [0x86, 0x00, 0x40, 0x00];
+
+    #[test]
+    fn parses_keyframes() {
+        let header = KeyframeHeader::parse(&KEYFRAME).unwrap();
+        assert_eq!(
+            header,
+            KeyframeHeader {
+                profile: 0,
+                bit_depth: 8,
+                color_space: ColorSpace::Bt601,
+                full_color_range: false,
+                subsampling_x: true,
+                subsampling_y: true,
+                width: 352,
+                height: 288,
+            }
+        );
+        assert_eq!(header.color_subsampling(), ColorSubsampling { chroma_horizontal: 1, chroma_vertical: 1 });
+        assert_eq!(header.color_range(), ColorRange::Broadcast);
+        assert_eq!(header.to_codec_private(Some(21)), [1, 1, 0, 2, 1, 21, 3, 1, 8, 4, 1, 0]);
+
+        let header = KeyframeHeader::parse(&PROFILE_1_KEYFRAME).unwrap();
+        assert_eq!((header.profile, header.bit_depth, header.color_space), (1, 8, ColorSpace::Bt709));
+        assert!(header.full_color_range && !header.subsampling_x && !header.subsampling_y);
+        assert_eq!((header.width, header.height), (1280, 720));
+        assert_eq!(header.to_codec_private(None), [1, 1, 1, 3, 1, 8, 4, 1, 3]);
+    }
+
+    #[test]
+    fn rejects_other_frames() {
+        assert!(KeyframeHeader::parse(&INTER_FRAME).is_err());
+        assert!(KeyframeHeader::parse(&KEYFRAME[..8]).is_err());
+        assert!(KeyframeHeader::parse(&[0x02, 0x49, 0x83, 0x42, 0x00]).is_err());
+
+        let mut bad_sync_code = KEYFRAME;
+        bad_sync_code[2] = 0;
+        assert!(KeyframeHeader::parse(&bad_sync_code).is_err());
+    }
+
+    #[test]
+    fn detects_keyframes() {
+        assert!(is_keyframe(&KEYFRAME).unwrap());
+        assert!(is_keyframe(&PROFILE_1_KEYFRAME).unwrap());
+        assert!(!is_keyframe(&INTER_FRAME).unwrap());
+
+        // show_existing_frame
+        assert!(!is_keyframe(&[0x88]).unwrap());
+        assert!(is_keyframe(&[0x02]).is_err());
+        assert!(is_keyframe(&[]).is_err());
+
+        let superframe = [KEYFRAME.as_slice(), &INTER_FRAME, &[0xC1, 12, 4, 0xC1]].concat();
+        assert!(is_keyframe(&superframe).unwrap());
+        let superframe = [INTER_FRAME.as_slice(), &KEYFRAME, &[0xC1, 4, 12, 0xC1]].concat();
+        assert!(!is_keyframe(&superframe).unwrap());
+    }
+
+    #[test]
+    fn splits_superframes() {
+        let superframe = [KEYFRAME.as_slice(), &INTER_FRAME, &[0xC9, 12, 0, 4, 0, 0xC9]].concat();
+        assert_eq!(superframe_frames(&superframe), [KEYFRAME.as_slice(), &INTER_FRAME]);
+        assert_eq!(KeyframeHeader::parse(&superframe).unwrap().width, 352);
+
+        // The keyframe doesn't need to come first
+        let superframe = [INTER_FRAME.as_slice(), &KEYFRAME, &[0xC1, 4, 12, 0xC1]].concat();
+        assert_eq!(KeyframeHeader::parse(&superframe).unwrap().height, 288);
+
+        // Inconsistent indices are treated as regular frames
+        let broken = [KEYFRAME.as_slice(), &[0xC1, 4, 12, 0xC0]].concat();
+        assert_eq!(superframe_frames(&broken), [broken.as_slice()]);
+        let broken = [KEYFRAME.as_slice(), &[0xC1, 4, 13, 0xC1]].concat();
+        assert_eq!(superframe_frames(&broken), [broken.as_slice()]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Joining WebM files with the same tracks into one, without re-encoding.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::Writer;
+//!
+//! let inputs = ["./part1.webm", "./part2.webm"].map(|path| BufReader::new(File::open(path).unwrap()));
+//! let output = Writer::new(File::create("./joined.webm").unwrap());
+//! webm::concat::concat(inputs, output).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use crate::codec::opus;
+use crate::demux::{Demuxer, TrackInfo, TrackKind};
+use crate::mux::{Error, SegmentTemplate, Writer};
+use crate::remux::{copy_track, doc_type};
+use crate::repair::{TrackEnd, TIMECODE_SCALE};
+
+/// Writes the frames of all WebM (or Matroska) files in `inputs` one after the other into a new file written to
+/// `writer`, and returns the writer once done.
+///
+/// The timestamps of each input are offset by the end of the input before it, which is where its last frame ends.
+/// For Opus, the `CodecDelay` at the start of every input after the first is discarded with a negative
+/// `DiscardPadding` on its first packet, so no extra audio is played where inputs meet.
+///
+/// All inputs must have the same video and audio tracks as the first: the same numbers, codecs, video dimensions,
+/// audio parameters, `CodecPrivate` and `CodecDelay`. Tracks that are neither video nor audio are left out. The
+/// output gets the tags, `DocType` and writing application of the first input.
+///
+/// Fails with [`Error::IncompatibleTracks`] if the tracks of an input don't match, or [`Error::BadParam`] if there
+/// are no inputs.
+pub fn concat<R: Read + Seek, W: Write>(
+    inputs: impl IntoIterator<Item = R>,
+    writer: Writer<W>,
+) -> Result<Writer<W>, Error> {
+    // Everything is checked before anything is written
+    let mut demuxers = inputs.into_iter().map(Demuxer::new).collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = demuxers.first() else {
+        return Err(Error::BadParam);
+    };
+    let tracks: Vec<_> = first.tracks().iter().filter(|track| !is_ignored(track)).cloned().collect();
+    if tracks.is_empty() {
+        return Err(Error::InvalidData("file has no video or audio tracks"));
+    }
+    for (input, demuxer) in demuxers.iter().enumerate().skip(1) {
+        let other: Vec<_> = demuxer.tracks().iter().filter(|track| !is_ignored(track)).cloned().collect();
+        check_compatible(&tracks, &other).map_err(|reason| Error::IncompatibleTracks { input, reason })?;
+    }
+
+    let info = first.info();
+    let mut template = SegmentTemplate::new().set_doc_type(doc_type(&info.doc_type))?;
+    if let Some(app_name) = &info.writing_app {
+        template = template.set_writing_app(app_name)?;
+    }
+    for (name, value) in first.tags() {
+        template = template.add_tag(name, value)?;
+    }
+    for track in &tracks {
+        template = copy_track(template, track)?;
+    }
+
+    let mut segment = template.build(writer)?;
+    let mut offset_ns = 0;
+    for (input, demuxer) in demuxers.iter_mut().enumerate() {
+        let mut track_ends: HashMap<_, TrackEnd> = HashMap::new();
+        while let Some(frame) = demuxer.next_frame()? {
+            let Some(track) = tracks.iter().find(|track| track.number == frame.track) else {
+                continue;
+            };
+
+            let duration_ns = match track.codec_id.as_str() {
+                "A_OPUS" => opus::packet_duration_ns(&frame.data).ok(),
+                _ => None,
+            };
+            let first_frame = !track_ends.contains_key(&frame.track);
+
+            // The decoder is primed again at the start of every input
+            let mut discard_padding_ns = frame.discard_padding_ns;
+            if let Some(duration_ns) = duration_ns.filter(|_| first_frame && input > 0) {
+                discard_padding_ns = -(track.codec_delay_ns.min(duration_ns) as i64);
+            }
+
+            segment.add_frame_with_discard_padding(
+                frame.track,
+                &frame.data,
+                frame.timestamp_ns + offset_ns,
+                frame.keyframe,
+                discard_padding_ns,
+            )?;
+
+            // Ends are worked out from the time played, which is shifted by the `CodecDelay`
+            let end = track_ends.entry(frame.track).or_default();
+            end.update(frame.timestamp_ns.saturating_sub(track.codec_delay_ns), duration_ns);
+        }
+        offset_ns += track_ends.values().map(TrackEnd::end_ns).max().unwrap_or(0);
+    }
+
+    segment
+        .finalize(Some(offset_ns.div_ceil(TIMECODE_SCALE)))
+        .map_err(|_| Error::Unknown)
+}
+
+/// Returns whether `track` is left out of the output, as it's neither video nor audio.
+fn is_ignored(track: &TrackInfo) -> bool {
+    matches!(track.kind, TrackKind::Other)
+}
+
+/// Checks whether frames of the `other` tracks can be written to the `first` tracks, returning what differs if not.
+fn check_compatible(first: &[TrackInfo], other: &[TrackInfo]) -> Result<(), &'static str> {
+    if first.len() != other.len() {
+        return Err("different number of tracks");
+    }
+
+    for (first, other) in first.iter().zip(other) {
+        if first.number != other.number {
+            return Err("different track numbers");
+        }
+        if first.codec_id != other.codec_id {
+            return Err("different codecs");
+        }
+        match (&first.kind, &other.kind) {
+            (TrackKind::Video(first), TrackKind::Video(other)) => {
+                if (first.width, first.height) != (other.width, other.height) {
+                    return Err("different video dimensions");
+                }
+            },
+            (TrackKind::Audio(first), TrackKind::Audio(other)) => {
+                if first != other {
+                    return Err("different sample rates, channel counts or bit depths");
+                }
+            },
+            _ => return Err("different track types"),
+        }
+        if first.codec_private != other.codec_private {
+            return Err("different CodecPrivate");
+        }
+        if (first.codec_delay_ns, first.seek_pre_roll_ns) != (other.codec_delay_ns, other.seek_pre_roll_ns) {
+            return Err("different CodecDelay or SeekPreRoll");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::demux::{AudioInfo, VideoInfo};
+    use crate::remux::tests::live_recording;
+    use crate::remux::{remux, RemuxOptions};
+
+    fn video_track(width: u32, codec_private: &[u8]) -> TrackInfo {
+        TrackInfo {
+            number: 1,
+            codec_id: "V_VP9".to_owned(),
+            codec_private: codec_private.to_vec(),
+            name: None,
+            language: None,
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            kind: TrackKind::Video(VideoInfo {
+                width,
+                height: 480,
+                display_size: None,
+                frame_rate: None,
+                color: None,
+                color_metadata: None,
+            }),
+        }
+    }
+
+    fn audio_track(channels: u32) -> TrackInfo {
+        TrackInfo {
+            number: 2,
+            codec_id: "A_OPUS".to_owned(),
+            codec_private: Vec::new(),
+            name: Some("Microphone".to_owned()),
+            language: None,
+            codec_delay_ns: 6_500_000,
+            seek_pre_roll_ns: 80_000_000,
+            kind: TrackKind::Audio(AudioInfo {
+                sample_rate: 48_000.0,
+                channels,
+                bit_depth: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn compatibility() {
+        let tracks = [video_track(640, &[1, 2]), audio_track(2)];
+        assert_eq!(check_compatible(&tracks, &tracks), Ok(()));
+
+        // Names don't matter
+        let mut renamed = tracks.clone();
+        renamed[1].name = None;
+        assert_eq!(check_compatible(&tracks, &renamed), Ok(()));
+
+        assert!(check_compatible(&tracks, &tracks[..1]).is_err());
+        assert!(check_compatible(&tracks, &[tracks[1].clone(), tracks[0].clone()]).is_err());
+        assert!(check_compatible(&tracks, &[video_track(320, &[1, 2]), audio_track(2)]).is_err());
+        assert!(check_compatible(&tracks, &[video_track(640, &[1, 3]), audio_track(2)]).is_err());
+        assert!(check_compatible(&tracks, &[video_track(640, &[1, 2]), audio_track(1)]).is_err());
+
+        let mut other_codec = tracks.clone();
+        other_codec[0].codec_id = "V_AV1".to_owned();
+        assert_eq!(check_compatible(&tracks, &other_codec), Err("different codecs"));
+    }
+
+    #[test]
+    fn concatenates_recordings() {
+        let inputs = [live_recording(), live_recording()].map(Cursor::new);
+        let output = concat(inputs, Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let mut demuxer = Demuxer::new(Cursor::new(output.into_inner().into_inner())).unwrap();
+        assert_eq!(demuxer.info().duration_ns, Some(1_200_000_000));
+
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(frames.len(), 90);
+        let second_video = frames.iter().filter(|frame| frame.track == 1).nth(15).unwrap();
+        assert_eq!((second_video.timestamp_ns, second_video.keyframe), (600_000_000, true));
+        let second_audio = frames.iter().filter(|frame| frame.track == 2).nth(30).unwrap();
+        assert_eq!((second_audio.timestamp_ns, second_audio.discard_padding_ns), (600_000_000, -6_500_000));
+
+        let video_only = remux(
+            Cursor::new(live_recording()),
+            Writer::new(Cursor::new(Vec::new())),
+            &RemuxOptions::new().drop_audio(),
+        )
+        .unwrap()
+        .into_inner();
+        let inputs = [Cursor::new(live_recording()), video_only];
+        let result = concat(inputs, Writer::new(Cursor::new(Vec::new())));
+        assert!(matches!(result, Err(Error::IncompatibleTracks { input: 1, .. })));
+    }
+}
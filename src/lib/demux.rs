@@ -0,0 +1,742 @@
+//! Reading WebM (and Matroska) files frame by frame, using libwebm's parser.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::demux::Demuxer;
+//!
+//! let input = BufReader::new(File::open("./my-cool-file.webm").unwrap());
+//! let mut demuxer = Demuxer::new(input).unwrap();
+//! for track in demuxer.tracks() {
+//!     println!("Track {}: {}", track.number, track.codec_id);
+//! }
+//! while let Some(frame) = demuxer.next_frame().unwrap() {
+//!     println!("{} bytes on track {} at {} ns", frame.data.len(), frame.track, frame.timestamp_ns);
+//! }
+//! ```
+
+use std::ffi::{c_char, c_void, CStr};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr::NonNull;
+
+use crate::ffi;
+use crate::ffi::demux::ResultCode;
+use crate::mux::{ColorRange, ColorSubsampling, Error, TrackNum};
+
+pub use crate::mux::{ColorMetadata, MasteringMetadata};
+
+/// RAII semantics for an FFI reader.
+struct OwnedReaderPtr {
+    reader: ffi::demux::ReaderNonNullPtr,
+}
+
+impl Drop for OwnedReaderPtr {
+    fn drop(&mut self) {
+        // SAFETY: Readers are only ever created by `Demuxer::new`, which hands ownership to this
+        unsafe {
+            ffi::demux::delete_reader(self.reader.as_ptr());
+        }
+    }
+}
+
+/// RAII semantics for a parsed FFI segment.
+struct OwnedSegmentPtr {
+    segment: ffi::demux::SegmentNonNullPtr,
+}
+
+impl Drop for OwnedSegmentPtr {
+    fn drop(&mut self) {
+        // SAFETY: Segments are only ever created by `Demuxer::new`, which hands ownership to this
+        unsafe {
+            ffi::demux::delete_segment(self.segment.as_ptr());
+        }
+    }
+}
+
+struct ReaderData<R> {
+    source: R,
+
+    /// Where `source` currently is, if known. Reads at that position don't need to seek first, which would
+    /// throw away the buffer of a `BufReader`.
+    position: Option<u64>,
+
+    /// The last error `source` returned, as libwebm only learns that reading failed
+    error: Option<io::Error>,
+    _marker: PhantomPinned,
+}
+
+impl<R: Read + Seek> ReaderData<R> {
+    fn read_at(&mut self, position: u64, buf: &mut [u8]) -> io::Result<()> {
+        if self.position != Some(position) {
+            self.position = None;
+            self.source.seek(SeekFrom::Start(position))?;
+        }
+
+        self.position = None;
+        self.source.read_exact(buf)?;
+        self.position = Some(position + buf.len() as u64);
+        Ok(())
+    }
+}
+
+/// Segment-level information about a file read by a [`Demuxer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// The `DocType` from the EBML header, such as `webm` or `matroska`.
+    pub doc_type: String,
+
+    /// The length of one timestamp unit in the file, in nanoseconds.
+    pub timecode_scale: u64,
+
+    /// The `Duration` of the segment in nanoseconds, if the file has one.
+    pub duration_ns: Option<u64>,
+
+    /// The name of the library the file was written with.
+    pub muxing_app: Option<String>,
+
+    /// The name of the application the file was written with.
+    pub writing_app: Option<String>,
+
+    /// The title of the segment.
+    pub title: Option<String>,
+
+    /// The versions and limits from the EBML header.
+    pub ebml_header: EbmlHeader,
+}
+
+/// The versions and limits from the EBML header of a file read by a [`Demuxer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EbmlHeader {
+    /// The version of EBML the file was written with, `EBMLVersion`.
+    pub version: u64,
+
+    /// The minimum version of EBML a reader needs to support, `EBMLReadVersion`.
+    pub read_version: u64,
+
+    /// The maximum length of element IDs in bytes, `EBMLMaxIDLength`.
+    pub max_id_length: u64,
+
+    /// The maximum length of element sizes in bytes, `EBMLMaxSizeLength`.
+    pub max_size_length: u64,
+
+    /// The version of the `DocType` the file was written with.
+    pub doc_type_version: u64,
+
+    /// The minimum version of the `DocType` a reader needs to support.
+    pub doc_type_read_version: u64,
+}
+
+/// Information about a track in a file read by a [`Demuxer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackInfo {
+    /// The track number, which [`Frame::track`] refers to.
+    pub number: TrackNum,
+
+    /// The Matroska codec ID, such as `V_VP9` or `A_OPUS`.
+    pub codec_id: String,
+
+    /// The `CodecPrivate` data, or an empty vector if there is none.
+    pub codec_private: Vec<u8>,
+
+    /// The human-readable track name.
+    pub name: Option<String>,
+
+    /// The track language, as a Matroska language code such as `eng`.
+    pub language: Option<String>,
+
+    /// The `CodecDelay`, in nanoseconds.
+    pub codec_delay_ns: u64,
+
+    /// The `SeekPreRoll`, in nanoseconds.
+    pub seek_pre_roll_ns: u64,
+
+    /// Whether this is a video or audio track, along with the parameters specific to that.
+    pub kind: TrackKind,
+}
+
+/// The type of a [`TrackInfo`], along with the parameters specific to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackKind {
+    Video(VideoInfo),
+    Audio(AudioInfo),
+
+    /// Any other kind of track, such as subtitles. These can't be written by this crate's muxer.
+    Other,
+}
+
+/// The parameters of a video track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VideoInfo {
+    /// The frame width, in pixels.
+    pub width: u32,
+
+    /// The frame height, in pixels.
+    pub height: u32,
+
+    /// The width and height to display frames at, if different from the frame size.
+    pub display_size: Option<(u32, u32)>,
+
+    /// The frame rate, if the file specifies it. This is only informational.
+    pub frame_rate: Option<f64>,
+
+    /// Color information, if the track has any.
+    pub color: Option<VideoColor>,
+
+    /// The color space and HDR metadata, if the track has any.
+    pub color_metadata: Option<ColorMetadata>,
+}
+
+/// The color information of a video track, in the form [`SegmentBuilder::set_color`] takes it.
+///
+/// [`SegmentBuilder::set_color`]: crate::mux::SegmentBuilder::set_color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoColor {
+    /// The number of bits per color channel, or zero if unspecified.
+    pub bit_depth: u8,
+
+    /// How chroma channels are subsampled.
+    pub subsampling: ColorSubsampling,
+
+    /// How the range of colors has been clipped.
+    pub range: ColorRange,
+}
+
+/// The parameters of an audio track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioInfo {
+    /// The sample rate, in Hz.
+    pub sample_rate: f64,
+
+    /// The number of channels.
+    pub channels: u32,
+
+    /// The number of bits per sample, if specified.
+    pub bit_depth: Option<u32>,
+}
+
+/// A chapter of a file read by a [`Demuxer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// The index of the `EditionEntry` the chapter is in.
+    pub edition: usize,
+
+    /// The `ChapterUID`.
+    pub uid: u64,
+
+    /// The `ChapterStringUID`, which WebVTT cue identifiers are stored as.
+    pub string_uid: Option<String>,
+
+    /// Where the chapter starts, in nanoseconds.
+    pub start_ns: u64,
+
+    /// Where the chapter ends, in nanoseconds, if the file says so.
+    pub end_ns: Option<u64>,
+
+    /// The first display string of the chapter.
+    pub title: Option<String>,
+
+    /// The language of [`Chapter::title`], as a Matroska language code such as `eng`.
+    pub language: Option<String>,
+}
+
+/// A point in the seek index (`Cues`) of a file read by a [`Demuxer`], for a single track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CuePoint {
+    /// The timestamp of the frame the cue point refers to, in nanoseconds.
+    pub timestamp_ns: u64,
+
+    /// The number of the track the cue point is for.
+    pub track: TrackNum,
+
+    /// Where the cluster containing the frame starts, relative to the start of the segment data.
+    pub cluster_position: u64,
+
+    /// The one-based number of the block within the cluster, or zero if unknown.
+    pub block_number: u64,
+}
+
+/// A single frame read by a [`Demuxer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The number of the track this frame belongs to.
+    pub track: TrackNum,
+
+    /// The timestamp of this frame, in nanoseconds.
+    ///
+    /// Frames stored together in a laced block all get the timestamp of the block.
+    pub timestamp_ns: u64,
+
+    /// Whether this frame is marked as a keyframe.
+    pub keyframe: bool,
+
+    /// How much audio should be discarded from the end of this frame after decoding, in nanoseconds, or zero.
+    /// See [`Segment::add_frame_with_discard_padding`](crate::mux::Segment::add_frame_with_discard_padding).
+    pub discard_padding_ns: i64,
+
+    /// The encoded frame.
+    pub data: Vec<u8>,
+}
+
+/// Reads the tracks and frames of a WebM or Matroska file.
+///
+/// The whole file is indexed on creation, so the source must support [`Seek`]. Frames are then read in the order
+/// they are stored in, which is the order they were written in.
+pub struct Demuxer<R: Read + Seek> {
+    // These are dropped in order: the segment refers to the reader, which refers to the reader data
+    segment: OwnedSegmentPtr,
+    _reader: OwnedReaderPtr,
+    reader_data: Pin<Box<ReaderData<R>>>,
+
+    info: SegmentInfo,
+    tracks: Vec<TrackInfo>,
+    tags: Vec<(String, String)>,
+    chapters: Vec<Chapter>,
+}
+
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// Thus, safety is only conditional on the source `R`, hence the `Send` bound on it.
+//
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl<R: Read + Seek + Send> Send for Demuxer<R> {}
+
+impl<R: Read + Seek> Demuxer<R> {
+    /// Parses the headers of the file in `source`, and indexes its clusters.
+    ///
+    /// Fails with [`Error::InvalidData`] if the file could not be parsed, or [`Error::Io`] if reading from `source`
+    /// failed.
+    pub fn new(mut source: R) -> Result<Self, Error> {
+        extern "C" fn read_fn<R: Read + Seek>(data: *mut c_void, position: u64, len: usize, buf: *mut u8) -> bool {
+            if buf.is_null() {
+                return false;
+            }
+            let data = unsafe { data.cast::<ReaderData<R>>().as_mut().unwrap() };
+            let buf = unsafe { std::slice::from_raw_parts_mut(buf, len) };
+
+            match data.read_at(position, buf) {
+                Ok(()) => true,
+                Err(err) => {
+                    data.error = Some(err);
+                    false
+                },
+            }
+        }
+
+        let length = source.seek(SeekFrom::End(0)).map_err(Error::Io)?;
+        let mut reader_data = Box::pin(ReaderData {
+            source,
+            position: Some(length),
+            error: None,
+            _marker: PhantomPinned,
+        });
+
+        // SAFETY: libwebm only accesses the reader data through this pointer while the demuxer is alive, and the
+        // data is pinned for that long.
+        let user_data = unsafe { reader_data.as_mut().get_unchecked_mut() as *mut ReaderData<R> };
+        let reader = unsafe { ffi::demux::new_reader(Some(read_fn::<R>), length, user_data.cast()) };
+        let reader = NonNull::new(reader)
+            .map(|reader| OwnedReaderPtr { reader })
+            .ok_or(Error::Unknown)?;
+
+        let mut segment = std::ptr::null_mut();
+        let result = unsafe { ffi::demux::new_segment(reader.reader.as_ptr(), &mut segment) };
+        let segment = match (result, NonNull::new(segment)) {
+            (ResultCode::Ok, Some(segment)) => OwnedSegmentPtr { segment },
+            (ResultCode::BadParam, _) => return Err(Error::BadParam),
+            _ => return Err(take_error(&mut reader_data, "WebM file could not be parsed")),
+        };
+
+        let info = unsafe { read_segment_info(segment.segment.as_ptr()) }?;
+        let tracks = unsafe { read_tracks(segment.segment.as_ptr()) };
+        let tags = unsafe { read_tags(segment.segment.as_ptr()) };
+        let chapters = unsafe { read_chapters(segment.segment.as_ptr()) };
+
+        Ok(Self {
+            segment,
+            _reader: reader,
+            reader_data,
+            info,
+            tracks,
+            tags,
+            chapters,
+        })
+    }
+
+    /// Returns segment-level information about the file.
+    #[must_use]
+    pub fn info(&self) -> &SegmentInfo {
+        &self.info
+    }
+
+    /// Returns all tracks in the file.
+    #[must_use]
+    pub fn tracks(&self) -> &[TrackInfo] {
+        &self.tracks
+    }
+
+    /// Returns the track with the given number, if there is one.
+    #[must_use]
+    pub fn track(&self, number: TrackNum) -> Option<&TrackInfo> {
+        self.tracks.iter().find(|track| track.number == number)
+    }
+
+    /// Returns the names and values of all `SimpleTag`s in the file, in order.
+    #[must_use]
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// Returns the chapters of all editions in the file, in order.
+    #[must_use]
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Returns the seek index of the file, with one entry per cue point and track, or an empty vector if the file
+    /// has no `Cues`.
+    ///
+    /// Files written live usually have no `Cues`. They are parsed on the first call, which may have to read ahead if
+    /// they come after the clusters, and later calls return the same cue points without parsing them again.
+    pub fn cue_points(&mut self) -> Result<Vec<CuePoint>, Error> {
+        let segment = self.segment.segment.as_ptr();
+        let count = unsafe { ffi::demux::get_cue_point_count(segment) };
+        let mut cue_points = Vec::new();
+        for index in 0..count {
+            let mut info = ffi::demux::CuePointInfo::default();
+            if unsafe { ffi::demux::get_cue_point(segment, index, &mut info) } != ResultCode::Ok {
+                return Err(take_error(&mut self.reader_data, "WebM cues could not be parsed"));
+            }
+            let (Ok(timestamp_ns), Ok(cluster_position)) =
+                (u64::try_from(info.timestamp_ns), u64::try_from(info.cluster_position))
+            else {
+                return Err(Error::InvalidData("WebM cue point is invalid"));
+            };
+
+            cue_points.push(CuePoint {
+                timestamp_ns,
+                track: info.track_number,
+                cluster_position,
+                block_number: u64::try_from(info.block_number).unwrap_or(0),
+            });
+        }
+        Ok(cue_points)
+    }
+
+    /// Reads the next frame, returning `None` at the end of the file.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, Error> {
+        let mut info = ffi::demux::FrameInfo::default();
+        let mut end_of_stream = false;
+        let result = unsafe { ffi::demux::next_frame(self.segment.segment.as_ptr(), &mut info, &mut end_of_stream) };
+        match result {
+            ResultCode::Ok if end_of_stream => return Ok(None),
+            ResultCode::Ok => {},
+            ResultCode::BadParam => return Err(Error::BadParam),
+            _ => return Err(take_error(&mut self.reader_data, "WebM cluster could not be parsed")),
+        }
+
+        let timestamp_ns =
+            u64::try_from(info.timestamp_ns).map_err(|_| Error::InvalidData("frame timestamp is negative"))?;
+        let (Ok(position), Ok(len)) = (u64::try_from(info.position), usize::try_from(info.length)) else {
+            return Err(Error::InvalidData("WebM frame is invalid"));
+        };
+
+        let mut data = vec![0; len];
+        // SAFETY: Only the reader data as a whole must stay in place, and libwebm is not using it right now
+        let reader_data = unsafe { self.reader_data.as_mut().get_unchecked_mut() };
+        reader_data.read_at(position, &mut data).map_err(Error::Io)?;
+
+        Ok(Some(Frame {
+            track: info.track_number,
+            timestamp_ns,
+            keyframe: info.keyframe,
+            discard_padding_ns: info.discard_padding_ns,
+            data,
+        }))
+    }
+
+    /// Returns the underlying source.
+    pub fn into_inner(self) -> R {
+        let Self {
+            segment,
+            _reader,
+            reader_data,
+            ..
+        } = self;
+        drop(segment);
+        drop(_reader);
+
+        // SAFETY: libwebm no longer holds a pointer to the reader data
+        unsafe { Pin::into_inner_unchecked(reader_data).source }
+    }
+}
+
+impl<R: Read + Seek> Iterator for Demuxer<R> {
+    type Item = Result<Frame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+impl<R: Read + Seek> std::fmt::Debug for Demuxer<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Demuxer")
+            .field("info", &self.info)
+            .field("tracks", &self.tracks)
+            .field("tags", &self.tags)
+            .field("chapters", &self.chapters)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns the I/O error that made libwebm fail, if there was one, or else [`Error::InvalidData`] with `message`.
+fn take_error<R>(reader_data: &mut Pin<Box<ReaderData<R>>>, message: &'static str) -> Error {
+    // SAFETY: Only the reader data as a whole must stay in place
+    let reader_data = unsafe { reader_data.as_mut().get_unchecked_mut() };
+    reader_data.error.take().map_or(Error::InvalidData(message), Error::Io)
+}
+
+/// Copies a string owned by libwebm, which may be missing.
+unsafe fn owned_string(ptr: *const c_char) -> Option<String> {
+    (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_string_lossy().into_owned())
+}
+
+unsafe fn read_segment_info(segment: ffi::demux::SegmentMutPtr) -> Result<SegmentInfo, Error> {
+    let mut info = std::mem::MaybeUninit::uninit();
+    if ffi::demux::get_segment_info(segment, info.as_mut_ptr()) != ResultCode::Ok {
+        return Err(Error::InvalidData("WebM segment info could not be parsed"));
+    }
+    let info = info.assume_init();
+
+    Ok(SegmentInfo {
+        doc_type: owned_string(info.doc_type).unwrap_or_default(),
+        timecode_scale: info.timecode_scale,
+        duration_ns: u64::try_from(info.duration_ns).ok(),
+        muxing_app: owned_string(info.muxing_app),
+        writing_app: owned_string(info.writing_app),
+        title: owned_string(info.title),
+        ebml_header: EbmlHeader {
+            version: info.ebml_version,
+            read_version: info.ebml_read_version,
+            max_id_length: info.ebml_max_id_length,
+            max_size_length: info.ebml_max_size_length,
+            doc_type_version: info.doc_type_version,
+            doc_type_read_version: info.doc_type_read_version,
+        },
+    })
+}
+
+unsafe fn read_tracks(segment: ffi::demux::SegmentMutPtr) -> Vec<TrackInfo> {
+    let count = ffi::demux::get_track_count(segment);
+    let mut tracks = Vec::new();
+    for index in 0..count {
+        let mut track = std::mem::MaybeUninit::uninit();
+        // Track entries that could not be parsed are left out
+        if ffi::demux::get_track(segment, index, track.as_mut_ptr()) != ResultCode::Ok {
+            continue;
+        }
+        let track = track.assume_init();
+
+        let kind = match track.track_type {
+            ffi::demux::VIDEO_TRACK_TYPE => {
+                let optional = |value: i64| u8::try_from(value).ok();
+                let color = [track.color_bits, track.color_sampling_horiz, track.color_sampling_vert, track.color_range]
+                    .iter()
+                    .any(|&value| value >= 0)
+                    .then(|| VideoColor {
+                        bit_depth: optional(track.color_bits).unwrap_or(0),
+                        subsampling: ColorSubsampling {
+                            chroma_horizontal: optional(track.color_sampling_horiz).unwrap_or(0),
+                            chroma_vertical: optional(track.color_sampling_vert).unwrap_or(0),
+                        },
+                        range: match track.color_range {
+                            1 => ColorRange::Broadcast,
+                            2 => ColorRange::Full,
+                            _ => ColorRange::Unspecified,
+                        },
+                    });
+
+                let width = u32::try_from(track.width).unwrap_or(0);
+                let height = u32::try_from(track.height).unwrap_or(0);
+                let display_size = (
+                    u32::try_from(track.display_width).unwrap_or(0),
+                    u32::try_from(track.display_height).unwrap_or(0),
+                );
+                TrackKind::Video(VideoInfo {
+                    width,
+                    height,
+                    display_size: (display_size != (0, 0) && display_size != (width, height)).then_some(display_size),
+                    frame_rate: (track.frame_rate > 0.0).then_some(track.frame_rate),
+                    color,
+                    color_metadata: track.has_color.then(|| read_color_metadata(&track)),
+                })
+            },
+            ffi::demux::AUDIO_TRACK_TYPE => TrackKind::Audio(AudioInfo {
+                sample_rate: track.sample_rate,
+                channels: u32::try_from(track.channels).unwrap_or(0),
+                bit_depth: u32::try_from(track.bit_depth).ok().filter(|&bit_depth| bit_depth != 0),
+            }),
+            _ => TrackKind::Other,
+        };
+
+        let codec_private = if track.codec_private.is_null() {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts(track.codec_private, track.codec_private_len).to_vec()
+        };
+
+        tracks.push(TrackInfo {
+            number: track.number,
+            codec_id: owned_string(track.codec_id).unwrap_or_default(),
+            codec_private,
+            name: owned_string(track.name),
+            language: owned_string(track.language),
+            codec_delay_ns: track.codec_delay_ns,
+            seek_pre_roll_ns: track.seek_pre_roll_ns,
+            kind,
+        });
+    }
+    tracks
+}
+
+fn read_color_metadata(track: &ffi::demux::TrackInfo) -> ColorMetadata {
+    let optional = |value: i64| u64::try_from(value).ok();
+    let optional_float = |value: f32| (value >= 0.0).then_some(value);
+    let chromaticity = |index: usize| {
+        let (x, y) = (track.mastering_chromaticity[2 * index], track.mastering_chromaticity[2 * index + 1]);
+        optional_float(x).zip(optional_float(y))
+    };
+
+    ColorMetadata {
+        matrix_coefficients: optional(track.color_matrix_coefficients),
+        transfer_characteristics: optional(track.color_transfer_characteristics),
+        primaries: optional(track.color_primaries),
+        chroma_siting: (optional(track.color_chroma_siting_horiz), optional(track.color_chroma_siting_vert)),
+        max_cll: optional(track.color_max_cll),
+        max_fall: optional(track.color_max_fall),
+        mastering: track.has_mastering_metadata.then(|| MasteringMetadata {
+            red: chromaticity(0),
+            green: chromaticity(1),
+            blue: chromaticity(2),
+            white_point: chromaticity(3),
+            luminance_max: optional_float(track.mastering_luminance_max),
+            luminance_min: optional_float(track.mastering_luminance_min),
+        }),
+    }
+}
+
+unsafe fn read_tags(segment: ffi::demux::SegmentMutPtr) -> Vec<(String, String)> {
+    let count = ffi::demux::get_simple_tag_count(segment);
+    let mut tags = Vec::new();
+    for index in 0..count {
+        let mut name = std::ptr::null();
+        let mut value = std::ptr::null();
+        if ffi::demux::get_simple_tag(segment, index, &mut name, &mut value) == ResultCode::Ok {
+            tags.push((owned_string(name).unwrap_or_default(), owned_string(value).unwrap_or_default()));
+        }
+    }
+    tags
+}
+
+unsafe fn read_chapters(segment: ffi::demux::SegmentMutPtr) -> Vec<Chapter> {
+    let count = ffi::demux::get_chapter_count(segment);
+    let mut chapters = Vec::new();
+    for index in 0..count {
+        let mut chapter = std::mem::MaybeUninit::uninit();
+        if ffi::demux::get_chapter(segment, index, chapter.as_mut_ptr()) != ResultCode::Ok {
+            continue;
+        }
+        let chapter = chapter.assume_init();
+
+        chapters.push(Chapter {
+            edition: usize::try_from(chapter.edition).unwrap_or(0),
+            uid: chapter.uid,
+            string_uid: owned_string(chapter.string_uid),
+            start_ns: u64::try_from(chapter.start_ns).unwrap_or(0),
+            end_ns: u64::try_from(chapter.end_ns).ok(),
+            title: owned_string(chapter.title),
+            language: owned_string(chapter.language),
+        });
+    }
+    chapters
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId, Writer};
+
+    const KEYFRAME: [u8; 12] = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01, 0xAB, 0xCD];
+    const INTER_FRAME: [u8; 5] = [0x31, 0x05, 0x00, 0xEF, 0x12];
+
+    #[test]
+    fn reads_muxed_file() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new())))
+            .unwrap()
+            .add_tag("TITLE", "Demuxing")
+            .unwrap();
+        let (builder, video) = builder.add_video_track(352, 288, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let builder = builder
+            .set_codec_private(audio, b"OpusHead\x01\x02\x38\x01\x80\xBB\0\0\0\0\0")
+            .unwrap()
+            .set_track_language(audio, "fin")
+            .unwrap();
+
+        let mut segment = builder.build();
+        segment.add_frame(video, &KEYFRAME, 0, true).unwrap();
+        segment.add_frame(audio, &[0xFC, 0x01], 0, true).unwrap();
+        segment.add_frame(video, &INTER_FRAME, 33_000_000, false).unwrap();
+        segment.add_frame_with_discard_padding(audio, &[0xFC, 0x02], 40_000_000, true, 5_000_000).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing unexpectedly failed")
+        };
+
+        let mut demuxer = Demuxer::new(writer.into_inner()).unwrap();
+        assert_eq!(demuxer.info().doc_type, "webm");
+        assert_eq!(demuxer.info().timecode_scale, 1_000_000);
+        assert_eq!(demuxer.tags(), [("TITLE".to_owned(), "Demuxing".to_owned())]);
+        assert_eq!((demuxer.info().ebml_header.version, demuxer.info().ebml_header.max_id_length), (1, 4));
+        assert!(demuxer.chapters().is_empty());
+
+        // Cues are written for the video track
+        let cue_points = demuxer.cue_points().unwrap();
+        assert!(!cue_points.is_empty());
+        assert_eq!((cue_points[0].timestamp_ns, cue_points[0].track), (0, video.into()));
+
+        let [video_info, audio_info] = demuxer.tracks() else {
+            panic!("Expected two tracks, got {:?}", demuxer.tracks())
+        };
+        assert_eq!(video_info.codec_id, "V_VP8");
+        assert!(matches!(video_info.kind, TrackKind::Video(VideoInfo { width: 352, height: 288, .. })));
+        assert_eq!(audio_info.codec_id, "A_OPUS");
+        assert_eq!(audio_info.language.as_deref(), Some("fin"));
+        assert!(matches!(audio_info.kind, TrackKind::Audio(AudioInfo { channels: 2, .. })));
+
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let summary: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.track, frame.timestamp_ns, frame.keyframe, frame.discard_padding_ns, frame.data.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (video.into(), 0, true, 0, KEYFRAME.len()),
+                (audio.into(), 0, true, 0, 2),
+                (video.into(), 33_000_000, false, 0, INTER_FRAME.len()),
+                (audio.into(), 40_000_000, true, 5_000_000, 2),
+            ]
+        );
+        assert_eq!(frames[2].data, INTER_FRAME);
+        assert!(demuxer.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(Demuxer::new(Cursor::new(b"not a WebM file".to_vec())), Err(Error::InvalidData(_))));
+        assert!(Demuxer::new(Cursor::new(Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,834 @@
+//! Reading the EBML structure of WebM and Matroska files, without going through libwebm.
+//!
+//! [`Reader`] walks elements one level at a time, only reading their payloads when asked to. Element sizes that are
+//! unknown, as in files written live, are resolved by looking for the first element that can't be inside them. As
+//! none of this runs C++ code, it is also available without the `libwebm` feature, and [`Limits`] bound the memory
+//! and recursion spent on untrusted input.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::ebml::{ElementId, Reader};
+//!
+//! let mut reader = Reader::new(BufReader::new(File::open("./my-cool-file.webm").unwrap()));
+//! let mut top_level = reader.top_level();
+//! while let Some(element) = reader.next_child(&mut top_level).unwrap() {
+//!     if element.id == ElementId::SEGMENT {
+//!         let mut children = reader.children(&element).unwrap();
+//!         while let Some(child) = reader.next_child(&mut children).unwrap() {
+//!             println!("{} at byte {}", child.id, child.position);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::mux::Error;
+
+/// The ID of an EBML element, including its length marker, as in `0x1A45DFA3` for the EBML header.
+///
+/// The elements WebM allows, as listed in libwebm's `webmids.h`, are available as constants. Other IDs can still be
+/// read, they just don't have a [name](ElementId::name) or [type](ElementId::element_type).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ElementId(pub u32);
+
+/// The type of an element's payload, which decides how it should be read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    /// Contains other elements, see [`Reader::children`].
+    Master,
+
+    /// See [`Reader::read_uint`].
+    Uint,
+
+    /// See [`Reader::read_int`].
+    Int,
+
+    /// See [`Reader::read_float`].
+    Float,
+
+    /// An ASCII string, see [`Reader::read_string`].
+    String,
+
+    /// A UTF-8 string, see [`Reader::read_string`].
+    Utf8,
+
+    /// Nanoseconds since 2001-01-01T00:00:00 UTC, as a signed integer. See [`Reader::read_int`].
+    Date,
+
+    /// See [`Reader::read_binary`].
+    Binary,
+}
+
+/// Defines the [`ElementId`] constants, along with their name, type and level. The level is how deeply an element
+/// is nested in the Matroska schema, starting at 0 for the EBML header and `Segment`, or `None` for global elements,
+/// which may appear at any level.
+macro_rules! element_ids {
+    ($($konst:ident = $id:literal, $name:literal, $element_type:ident, $level:expr;)*) => {
+        impl ElementId {
+            $(
+                #[doc = concat!("The `", $name, "` element.")]
+                pub const $konst: Self = Self($id);
+            )*
+
+            fn info(self) -> Option<(&'static str, ElementType, Option<u8>)> {
+                match self.0 {
+                    $($id => Some(($name, ElementType::$element_type, $level)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+element_ids! {
+    // EBML header and global elements
+    EBML = 0x1A45_DFA3, "EBML", Master, Some(0);
+    EBML_VERSION = 0x4286, "EBMLVersion", Uint, Some(1);
+    EBML_READ_VERSION = 0x42F7, "EBMLReadVersion", Uint, Some(1);
+    EBML_MAX_ID_LENGTH = 0x42F2, "EBMLMaxIDLength", Uint, Some(1);
+    EBML_MAX_SIZE_LENGTH = 0x42F3, "EBMLMaxSizeLength", Uint, Some(1);
+    DOC_TYPE = 0x4282, "DocType", String, Some(1);
+    DOC_TYPE_VERSION = 0x4287, "DocTypeVersion", Uint, Some(1);
+    DOC_TYPE_READ_VERSION = 0x4285, "DocTypeReadVersion", Uint, Some(1);
+    VOID = 0xEC, "Void", Binary, None;
+
+    SEGMENT = 0x1853_8067, "Segment", Master, Some(0);
+
+    // SeekHead
+    SEEK_HEAD = 0x114D_9B74, "SeekHead", Master, Some(1);
+    SEEK = 0x4DBB, "Seek", Master, Some(2);
+    SEEK_ID = 0x53AB, "SeekID", Binary, Some(3);
+    SEEK_POSITION = 0x53AC, "SeekPosition", Uint, Some(3);
+
+    // Info
+    INFO = 0x1549_A966, "Info", Master, Some(1);
+    TIMECODE_SCALE = 0x2A_D7B1, "TimecodeScale", Uint, Some(2);
+    DURATION = 0x4489, "Duration", Float, Some(2);
+    DATE_UTC = 0x4461, "DateUTC", Date, Some(2);
+    TITLE = 0x7BA9, "Title", Utf8, Some(2);
+    MUXING_APP = 0x4D80, "MuxingApp", Utf8, Some(2);
+    WRITING_APP = 0x5741, "WritingApp", Utf8, Some(2);
+
+    // Cluster
+    CLUSTER = 0x1F43_B675, "Cluster", Master, Some(1);
+    TIMECODE = 0xE7, "Timecode", Uint, Some(2);
+    PREV_SIZE = 0xAB, "PrevSize", Uint, Some(2);
+    SIMPLE_BLOCK = 0xA3, "SimpleBlock", Binary, Some(2);
+    BLOCK_GROUP = 0xA0, "BlockGroup", Master, Some(2);
+    BLOCK = 0xA1, "Block", Binary, Some(3);
+    BLOCK_DURATION = 0x9B, "BlockDuration", Uint, Some(3);
+    REFERENCE_BLOCK = 0xFB, "ReferenceBlock", Int, Some(3);
+    BLOCK_ADDITIONS = 0x75A1, "BlockAdditions", Master, Some(3);
+    BLOCK_MORE = 0xA6, "BlockMore", Master, Some(4);
+    BLOCK_ADD_ID = 0xEE, "BlockAddID", Uint, Some(5);
+    BLOCK_ADDITIONAL = 0xA5, "BlockAdditional", Binary, Some(5);
+    DISCARD_PADDING = 0x75A2, "DiscardPadding", Int, Some(3);
+
+    // Tracks
+    TRACKS = 0x1654_AE6B, "Tracks", Master, Some(1);
+    TRACK_ENTRY = 0xAE, "TrackEntry", Master, Some(2);
+    TRACK_NUMBER = 0xD7, "TrackNumber", Uint, Some(3);
+    TRACK_UID = 0x73C5, "TrackUID", Uint, Some(3);
+    TRACK_TYPE = 0x83, "TrackType", Uint, Some(3);
+    FLAG_ENABLED = 0xB9, "FlagEnabled", Uint, Some(3);
+    FLAG_DEFAULT = 0x88, "FlagDefault", Uint, Some(3);
+    FLAG_FORCED = 0x55AA, "FlagForced", Uint, Some(3);
+    FLAG_LACING = 0x9C, "FlagLacing", Uint, Some(3);
+    DEFAULT_DURATION = 0x23_E383, "DefaultDuration", Uint, Some(3);
+    MAX_BLOCK_ADDITION_ID = 0x55EE, "MaxBlockAdditionID", Uint, Some(3);
+    NAME = 0x536E, "Name", Utf8, Some(3);
+    LANGUAGE = 0x22_B59C, "Language", String, Some(3);
+    CODEC_ID = 0x86, "CodecID", String, Some(3);
+    CODEC_PRIVATE = 0x63A2, "CodecPrivate", Binary, Some(3);
+    CODEC_NAME = 0x25_8688, "CodecName", Utf8, Some(3);
+    CODEC_DELAY = 0x56AA, "CodecDelay", Uint, Some(3);
+    SEEK_PRE_ROLL = 0x56BB, "SeekPreRoll", Uint, Some(3);
+
+    // Video
+    VIDEO = 0xE0, "Video", Master, Some(3);
+    FLAG_INTERLACED = 0x9A, "FlagInterlaced", Uint, Some(4);
+    STEREO_MODE = 0x53B8, "StereoMode", Uint, Some(4);
+    ALPHA_MODE = 0x53C0, "AlphaMode", Uint, Some(4);
+    PIXEL_WIDTH = 0xB0, "PixelWidth", Uint, Some(4);
+    PIXEL_HEIGHT = 0xBA, "PixelHeight", Uint, Some(4);
+    PIXEL_CROP_BOTTOM = 0x54AA, "PixelCropBottom", Uint, Some(4);
+    PIXEL_CROP_TOP = 0x54BB, "PixelCropTop", Uint, Some(4);
+    PIXEL_CROP_LEFT = 0x54CC, "PixelCropLeft", Uint, Some(4);
+    PIXEL_CROP_RIGHT = 0x54DD, "PixelCropRight", Uint, Some(4);
+    DISPLAY_WIDTH = 0x54B0, "DisplayWidth", Uint, Some(4);
+    DISPLAY_HEIGHT = 0x54BA, "DisplayHeight", Uint, Some(4);
+    DISPLAY_UNIT = 0x54B2, "DisplayUnit", Uint, Some(4);
+    ASPECT_RATIO_TYPE = 0x54B3, "AspectRatioType", Uint, Some(4);
+    FRAME_RATE = 0x23_83E3, "FrameRate", Float, Some(4);
+
+    // Colour
+    COLOUR = 0x55B0, "Colour", Master, Some(4);
+    MATRIX_COEFFICIENTS = 0x55B1, "MatrixCoefficients", Uint, Some(5);
+    BITS_PER_CHANNEL = 0x55B2, "BitsPerChannel", Uint, Some(5);
+    CHROMA_SUBSAMPLING_HORZ = 0x55B3, "ChromaSubsamplingHorz", Uint, Some(5);
+    CHROMA_SUBSAMPLING_VERT = 0x55B4, "ChromaSubsamplingVert", Uint, Some(5);
+    CB_SUBSAMPLING_HORZ = 0x55B5, "CbSubsamplingHorz", Uint, Some(5);
+    CB_SUBSAMPLING_VERT = 0x55B6, "CbSubsamplingVert", Uint, Some(5);
+    CHROMA_SITING_HORZ = 0x55B7, "ChromaSitingHorz", Uint, Some(5);
+    CHROMA_SITING_VERT = 0x55B8, "ChromaSitingVert", Uint, Some(5);
+    RANGE = 0x55B9, "Range", Uint, Some(5);
+    TRANSFER_CHARACTERISTICS = 0x55BA, "TransferCharacteristics", Uint, Some(5);
+    PRIMARIES = 0x55BB, "Primaries", Uint, Some(5);
+    MAX_CLL = 0x55BC, "MaxCLL", Uint, Some(5);
+    MAX_FALL = 0x55BD, "MaxFALL", Uint, Some(5);
+    MASTERING_METADATA = 0x55D0, "MasteringMetadata", Master, Some(5);
+    PRIMARY_R_CHROMATICITY_X = 0x55D1, "PrimaryRChromaticityX", Float, Some(6);
+    PRIMARY_R_CHROMATICITY_Y = 0x55D2, "PrimaryRChromaticityY", Float, Some(6);
+    PRIMARY_G_CHROMATICITY_X = 0x55D3, "PrimaryGChromaticityX", Float, Some(6);
+    PRIMARY_G_CHROMATICITY_Y = 0x55D4, "PrimaryGChromaticityY", Float, Some(6);
+    PRIMARY_B_CHROMATICITY_X = 0x55D5, "PrimaryBChromaticityX", Float, Some(6);
+    PRIMARY_B_CHROMATICITY_Y = 0x55D6, "PrimaryBChromaticityY", Float, Some(6);
+    WHITE_POINT_CHROMATICITY_X = 0x55D7, "WhitePointChromaticityX", Float, Some(6);
+    WHITE_POINT_CHROMATICITY_Y = 0x55D8, "WhitePointChromaticityY", Float, Some(6);
+    LUMINANCE_MAX = 0x55D9, "LuminanceMax", Float, Some(6);
+    LUMINANCE_MIN = 0x55DA, "LuminanceMin", Float, Some(6);
+
+    // Projection
+    PROJECTION = 0x7670, "Projection", Master, Some(4);
+    PROJECTION_TYPE = 0x7671, "ProjectionType", Uint, Some(5);
+    PROJECTION_PRIVATE = 0x7672, "ProjectionPrivate", Binary, Some(5);
+    PROJECTION_POSE_YAW = 0x7673, "ProjectionPoseYaw", Float, Some(5);
+    PROJECTION_POSE_PITCH = 0x7674, "ProjectionPosePitch", Float, Some(5);
+    PROJECTION_POSE_ROLL = 0x7675, "ProjectionPoseRoll", Float, Some(5);
+
+    // Audio
+    AUDIO = 0xE1, "Audio", Master, Some(3);
+    SAMPLING_FREQUENCY = 0xB5, "SamplingFrequency", Float, Some(4);
+    OUTPUT_SAMPLING_FREQUENCY = 0x78B5, "OutputSamplingFrequency", Float, Some(4);
+    CHANNELS = 0x9F, "Channels", Uint, Some(4);
+    BIT_DEPTH = 0x6264, "BitDepth", Uint, Some(4);
+
+    // Encryption
+    CONTENT_ENCODINGS = 0x6D80, "ContentEncodings", Master, Some(3);
+    CONTENT_ENCODING = 0x6240, "ContentEncoding", Master, Some(4);
+    CONTENT_ENCODING_ORDER = 0x5031, "ContentEncodingOrder", Uint, Some(5);
+    CONTENT_ENCODING_SCOPE = 0x5032, "ContentEncodingScope", Uint, Some(5);
+    CONTENT_ENCODING_TYPE = 0x5033, "ContentEncodingType", Uint, Some(5);
+    CONTENT_ENCRYPTION = 0x5035, "ContentEncryption", Master, Some(5);
+    CONTENT_ENC_ALGO = 0x47E1, "ContentEncAlgo", Uint, Some(6);
+    CONTENT_ENC_KEY_ID = 0x47E2, "ContentEncKeyID", Binary, Some(6);
+    CONTENT_ENC_AES_SETTINGS = 0x47E7, "ContentEncAESSettings", Master, Some(6);
+    AES_SETTINGS_CIPHER_MODE = 0x47E8, "AESSettingsCipherMode", Uint, Some(7);
+
+    // Cues
+    CUES = 0x1C53_BB6B, "Cues", Master, Some(1);
+    CUE_POINT = 0xBB, "CuePoint", Master, Some(2);
+    CUE_TIME = 0xB3, "CueTime", Uint, Some(3);
+    CUE_TRACK_POSITIONS = 0xB7, "CueTrackPositions", Master, Some(3);
+    CUE_TRACK = 0xF7, "CueTrack", Uint, Some(4);
+    CUE_CLUSTER_POSITION = 0xF1, "CueClusterPosition", Uint, Some(4);
+    CUE_RELATIVE_POSITION = 0xF0, "CueRelativePosition", Uint, Some(4);
+    CUE_DURATION = 0xB2, "CueDuration", Uint, Some(4);
+    CUE_BLOCK_NUMBER = 0x5378, "CueBlockNumber", Uint, Some(4);
+
+    // Chapters
+    CHAPTERS = 0x1043_A770, "Chapters", Master, Some(1);
+    EDITION_ENTRY = 0x45B9, "EditionEntry", Master, Some(2);
+    CHAPTER_ATOM = 0xB6, "ChapterAtom", Master, Some(3);
+    CHAPTER_UID = 0x73C4, "ChapterUID", Uint, Some(4);
+    CHAPTER_STRING_UID = 0x5654, "ChapterStringUID", Utf8, Some(4);
+    CHAPTER_TIME_START = 0x91, "ChapterTimeStart", Uint, Some(4);
+    CHAPTER_TIME_END = 0x92, "ChapterTimeEnd", Uint, Some(4);
+    CHAPTER_DISPLAY = 0x80, "ChapterDisplay", Master, Some(4);
+    CHAP_STRING = 0x85, "ChapString", Utf8, Some(5);
+    CHAP_LANGUAGE = 0x437C, "ChapLanguage", String, Some(5);
+    CHAP_COUNTRY = 0x437E, "ChapCountry", String, Some(5);
+
+    // Tags
+    TAGS = 0x1254_C367, "Tags", Master, Some(1);
+    TAG = 0x7373, "Tag", Master, Some(2);
+    TARGETS = 0x63C0, "Targets", Master, Some(3);
+    TARGET_TYPE_VALUE = 0x68CA, "TargetTypeValue", Uint, Some(4);
+    TARGET_TYPE = 0x63CA, "TargetType", String, Some(4);
+    TAG_TRACK_UID = 0x63C5, "TagTrackUID", Uint, Some(4);
+    SIMPLE_TAG = 0x67C8, "SimpleTag", Master, Some(3);
+    TAG_NAME = 0x45A3, "TagName", Utf8, Some(4);
+    TAG_LANGUAGE = 0x447A, "TagLanguage", String, Some(4);
+    TAG_DEFAULT = 0x4484, "TagDefault", Uint, Some(4);
+    TAG_STRING = 0x4487, "TagString", Utf8, Some(4);
+    TAG_BINARY = 0x4485, "TagBinary", Binary, Some(4);
+}
+
+impl ElementId {
+    /// Returns the name of this element in the Matroska specification, such as `TrackEntry`, if WebM allows it.
+    #[must_use]
+    pub fn name(self) -> Option<&'static str> {
+        self.info().map(|(name, _, _)| name)
+    }
+
+    /// Returns the type of this element's payload, if WebM allows it.
+    #[must_use]
+    pub fn element_type(self) -> Option<ElementType> {
+        self.info().map(|(_, element_type, _)| element_type)
+    }
+
+    fn level(self) -> Option<u8> {
+        self.info().and_then(|(_, _, level)| level)
+    }
+}
+
+impl fmt::Display for ElementId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{:#X}", self.0),
+        }
+    }
+}
+
+/// The header of an element, as found by [`Reader::next_child`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element {
+    /// What the element is.
+    pub id: ElementId,
+
+    /// Where the element starts, at its ID.
+    pub position: u64,
+
+    /// Where the element's payload starts, right after its size.
+    pub data_position: u64,
+
+    /// The size of the payload, or `None` if it is unknown. Only master elements may have an unknown size.
+    pub size: Option<u64>,
+
+    /// How deeply the element is nested, with 0 for top-level elements such as the `Segment`.
+    pub depth: usize,
+
+    /// Where the enclosing element of known size ends, which an element of unknown size can't go past
+    bound: Option<u64>,
+}
+
+impl Element {
+    /// Returns where this element ends, if its size is known.
+    #[must_use]
+    pub fn end(&self) -> Option<u64> {
+        self.size.map(|size| self.data_position + size)
+    }
+}
+
+/// Bounds on what a [`Reader`] will do, so that malformed or malicious input can't make it use up too much memory
+/// or stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest payload that is read into memory, in bytes. Master elements are never read whole, so this doesn't
+    /// apply to them. Defaults to 64 MiB, which is well above any frame a WebM file holds in practice.
+    pub max_element_size: u64,
+
+    /// How deeply elements may be nested, where the `Segment` is at depth 0. Defaults to 32; the deepest element
+    /// WebM defines is at depth 7.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_element_size: 64 << 20,
+            max_depth: 32,
+        }
+    }
+}
+
+/// A position among the children of an element, or among the top-level elements, for [`Reader::next_child`].
+///
+/// This is separate from the [`Reader`], so that the payloads of children can be read, or their own children
+/// walked, while going through them.
+#[derive(Debug, Clone)]
+pub struct Children {
+    /// Where the next child starts, once `previous` has been skipped
+    position: u64,
+
+    /// Where the children end, if known: the end of the parent, or of its closest ancestor with a known size
+    end: Option<u64>,
+
+    /// The level of the parent in the schema, if its size is unknown. Its children end at the first element with
+    /// the same level or a lower one.
+    unknown_size_level: Option<u8>,
+    depth: usize,
+
+    /// The child returned last, which still needs to be skipped
+    previous: Option<Element>,
+    done: bool,
+}
+
+impl Children {
+    /// Returns where the next child starts, or where the child that failed to be read starts.
+    pub(crate) fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+/// Reads the EBML elements of `R`, see the [module documentation](self).
+///
+/// Positions are counted from the start of `R`, regardless of its position when handed over.
+pub struct Reader<R> {
+    input: R,
+    limits: Limits,
+
+    /// The position of `input`, to avoid seeking when reading on from where the last read stopped
+    position: Option<u64>,
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Creates a [`Reader`] with the default [`Limits`].
+    pub fn new(input: R) -> Self {
+        Self::with_limits(input, Limits::default())
+    }
+
+    /// Creates a [`Reader`] that enforces the given [`Limits`] instead of the defaults.
+    pub fn with_limits(input: R, limits: Limits) -> Self {
+        Self {
+            input,
+            limits,
+            position: None,
+        }
+    }
+
+    /// Returns the [`Limits`] this [`Reader`] enforces.
+    #[must_use]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Consumes this [`Reader`], and returns the input it was created with.
+    #[must_use]
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+
+    /// Returns the elements at the top of the file, usually an EBML header followed by a `Segment`.
+    #[must_use]
+    pub fn top_level(&self) -> Children {
+        Children {
+            position: 0,
+            end: None,
+            unknown_size_level: None,
+            depth: 0,
+            previous: None,
+            done: false,
+        }
+    }
+
+    /// Returns the children of `parent`, which must be a master element.
+    ///
+    /// Fails with [`Error::InvalidData`] if `parent` is known to have another type, or if its children would be
+    /// nested deeper than [`Limits::max_depth`].
+    pub fn children(&self, parent: &Element) -> Result<Children, Error> {
+        if parent.id.element_type().is_some_and(|element_type| element_type != ElementType::Master) {
+            return Err(Error::InvalidData("element is not a master element"));
+        }
+        self.children_of(parent)
+    }
+
+    fn children_of(&self, parent: &Element) -> Result<Children, Error> {
+        if parent.depth >= self.limits.max_depth {
+            return Err(Error::InvalidData("elements are nested too deeply"));
+        }
+
+        // Without a level, an element of unknown size can only end where its enclosing element does
+        Ok(Children {
+            position: parent.data_position,
+            end: parent.end().or(parent.bound),
+            unknown_size_level: parent.size.map_or(parent.id.level(), |_| None),
+            depth: parent.depth + 1,
+            previous: None,
+            done: false,
+        })
+    }
+
+    /// Returns the next element of `children`, or `None` once there are no more.
+    ///
+    /// Elements with an unknown size end at the first element that can't be one of their children, such as the
+    /// next `Cluster` for a `Cluster`. Finding where they end means walking their children, which only happens
+    /// when moving past them. Fails with [`Error::InvalidData`] if the structure is broken, and with [`Error::Io`]
+    /// if reading from the input fails.
+    pub fn next_child(&mut self, children: &mut Children) -> Result<Option<Element>, Error> {
+        if children.done {
+            return Ok(None);
+        }
+        if let Some(previous) = children.previous.take() {
+            children.position = self.end_of(&previous)?;
+        }
+        if children.end.is_some_and(|end| children.position >= end) {
+            children.done = true;
+            return Ok(None);
+        }
+
+        let Some((id, size, header_len)) = self.read_header(children.position)? else {
+            // The end of the input ends everything, though elements of known size shouldn't be cut short by it
+            children.done = true;
+            if children.end.is_some() {
+                return Err(Error::InvalidData("element extends past the end of the input"));
+            }
+            return Ok(None);
+        };
+
+        if let (Some(parent_level), Some(level)) = (children.unknown_size_level, id.level()) {
+            if level <= parent_level {
+                children.done = true;
+                return Ok(None);
+            }
+        }
+
+        let element = Element {
+            id,
+            position: children.position,
+            data_position: children.position + header_len,
+            size,
+            depth: children.depth,
+            bound: children.end,
+        };
+        match (element.end(), children.end) {
+            (Some(element_end), Some(end)) if element_end > end => {
+                return Err(Error::InvalidData("element extends past its parent"));
+            },
+            (None, _) if id.element_type().is_some_and(|element_type| element_type != ElementType::Master) => {
+                return Err(Error::InvalidData("element has an unknown size, but is not a master element"));
+            },
+            _ => {},
+        }
+
+        children.previous = Some(element);
+        Ok(Some(element))
+    }
+
+    /// Returns where `element` ends, walking its children if its size is unknown.
+    fn end_of(&mut self, element: &Element) -> Result<u64, Error> {
+        if let Some(end) = element.end() {
+            return Ok(end);
+        }
+
+        let mut children = self.children_of(element)?;
+        while self.next_child(&mut children)?.is_some() {}
+        Ok(children.position)
+    }
+
+    /// Reads the ID and size of the element at `position`, along with the length of both, or returns `None` at the
+    /// end of the input.
+    fn read_header(&mut self, position: u64) -> Result<Option<(ElementId, Option<u64>, u64)>, Error> {
+        self.seek_to(position)?;
+        let mut first = [0];
+        if self.read(&mut first)? == 0 {
+            return Ok(None);
+        }
+
+        let (id, id_len) = self.read_vint(first[0], 4).ok_or(Error::InvalidData("invalid element ID"))??;
+        let mut first = [0];
+        self.read_exact(&mut first)?;
+        let (size, size_len) = self.read_vint(first[0], 8).ok_or(Error::InvalidData("invalid element size"))??;
+
+        // A size with all bits set means the size is unknown
+        let mask = u64::MAX >> (64 - 7 * size_len);
+        let size = (size & mask != mask).then_some(size & mask);
+        Ok(Some((ElementId(id as u32), size, u64::from(id_len + size_len))))
+    }
+
+    /// Reads the rest of a variable-length integer starting with `first`, returning it with its marker bit intact,
+    /// and its length. Returns `None` if it would be longer than `max_len`.
+    fn read_vint(&mut self, first: u8, max_len: u32) -> Option<Result<(u64, u32), Error>> {
+        let len = first.leading_zeros() + 1;
+        if len > max_len {
+            return None;
+        }
+
+        let mut rest = [0; 7];
+        let rest = &mut rest[..len as usize - 1];
+        Some(self.read_exact(rest).map(|()| {
+            let raw = rest.iter().fold(u64::from(first), |acc, &b| acc << 8 | u64::from(b));
+            (raw, len)
+        }))
+    }
+
+    /// Reads the payload of `element`, regardless of its type.
+    ///
+    /// Fails with [`Error::InvalidData`] if the size is unknown or above [`Limits::max_element_size`], or if the
+    /// input ends before the payload does.
+    pub fn read_binary(&mut self, element: &Element) -> Result<Vec<u8>, Error> {
+        let size = element.size.ok_or(Error::InvalidData("element has an unknown size"))?;
+        if size > self.limits.max_element_size {
+            return Err(Error::InvalidData("element is larger than the size limit"));
+        }
+
+        self.read_at(element.data_position, size)
+    }
+
+    /// Reads at most `len` bytes from the start of the payload of `element`, such as the header of a `SimpleBlock`
+    /// without the frame after it. Unlike [`Reader::read_binary`], this doesn't depend on [`Limits::max_element_size`].
+    pub fn read_prefix(&mut self, element: &Element, len: u64) -> Result<Vec<u8>, Error> {
+        let size = element.size.ok_or(Error::InvalidData("element has an unknown size"))?;
+        self.read_at(element.data_position, size.min(len))
+    }
+
+    fn read_at(&mut self, position: u64, len: u64) -> Result<Vec<u8>, Error> {
+        self.seek_to(position)?;
+        let mut data = Vec::new();
+        let read = (&mut self.input).take(len).read_to_end(&mut data);
+        self.position = None;
+        read.map_err(Error::Io)?;
+        if data.len() as u64 != len {
+            return Err(Error::InvalidData("element extends past the end of the input"));
+        }
+        self.position = Some(position + len);
+        Ok(data)
+    }
+
+    /// Reads the payload of `element` as an unsigned integer of up to 8 bytes.
+    pub fn read_uint(&mut self, element: &Element) -> Result<u64, Error> {
+        if element.size.is_some_and(|size| size > 8) {
+            return Err(Error::InvalidData("integer element is longer than 8 bytes"));
+        }
+        let data = self.read_binary(element)?;
+        Ok(data.iter().fold(0, |acc, &b| acc << 8 | u64::from(b)))
+    }
+
+    /// Reads the payload of `element` as a signed integer of up to 8 bytes, such as a `ReferenceBlock`, or a date.
+    pub fn read_int(&mut self, element: &Element) -> Result<i64, Error> {
+        let value = self.read_uint(element)?;
+        let bits = 8 * element.size.unwrap_or(0) as u32;
+        if bits == 0 {
+            return Ok(0);
+        }
+
+        // Sign-extend from the length of the payload
+        Ok((value << (64 - bits)) as i64 >> (64 - bits))
+    }
+
+    /// Reads the payload of `element` as a 4- or 8-byte float. An empty payload is 0.
+    pub fn read_float(&mut self, element: &Element) -> Result<f64, Error> {
+        match element.size {
+            Some(0) => Ok(0.0),
+            Some(4) => {
+                let data = self.read_binary(element)?;
+                Ok(f64::from(f32::from_be_bytes(data.try_into().expect("the payload should be 4 bytes"))))
+            },
+            Some(8) => {
+                let data = self.read_binary(element)?;
+                Ok(f64::from_be_bytes(data.try_into().expect("the payload should be 8 bytes")))
+            },
+            _ => Err(Error::InvalidData("float element is not 4 or 8 bytes long")),
+        }
+    }
+
+    /// Reads the payload of `element` as a string, without the zero padding EBML allows at its end.
+    pub fn read_string(&mut self, element: &Element) -> Result<String, Error> {
+        let mut data = self.read_binary(element)?;
+        let len = data.iter().rposition(|&b| b != 0).map_or(0, |last| last + 1);
+        data.truncate(len);
+        String::from_utf8(data).map_err(|_| Error::InvalidData("string element is not valid UTF-8"))
+    }
+
+    fn seek_to(&mut self, position: u64) -> Result<(), Error> {
+        if self.position != Some(position) {
+            self.position = None;
+            self.input.seek(SeekFrom::Start(position)).map_err(Error::Io)?;
+            self.position = Some(position);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = loop {
+            match self.input.read(buf) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                result => break result,
+            }
+        };
+        let read = read.map_err(|err| {
+            self.position = None;
+            Error::Io(err)
+        })?;
+        self.position = self.position.map(|position| position + read as u64);
+        Ok(read)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        match self.input.read_exact(buf) {
+            Ok(()) => {
+                self.position = self.position.map(|position| position + buf.len() as u64);
+                Ok(())
+            },
+            Err(err) => {
+                self.position = None;
+                if err.kind() == io::ErrorKind::UnexpectedEof {
+                    Err(Error::InvalidData("element header extends past the end of the input"))
+                } else {
+                    Err(Error::Io(err))
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+    fn element(id: ElementId, payload: &[u8]) -> Vec<u8> {
+        let mut data = id.0.to_be_bytes()[id.0.leading_zeros() as usize / 8..].to_vec();
+        data.push(0x80 | u8::try_from(payload.len()).unwrap());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn unknown_size_element(id: ElementId, payload: &[u8]) -> Vec<u8> {
+        let mut data = id.0.to_be_bytes()[id.0.leading_zeros() as usize / 8..].to_vec();
+        data.extend_from_slice(&UNKNOWN_SIZE);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    /// Returns the IDs of the children of `parent`, or of the top-level elements.
+    fn child_ids<R: Read + Seek>(reader: &mut Reader<R>, parent: Option<&Element>) -> Result<Vec<ElementId>, Error> {
+        let mut children = match parent {
+            Some(parent) => reader.children(parent)?,
+            None => reader.top_level(),
+        };
+        let mut ids = Vec::new();
+        while let Some(child) = reader.next_child(&mut children)? {
+            ids.push(child.id);
+        }
+        Ok(ids)
+    }
+
+    #[test]
+    fn element_ids() {
+        assert_eq!(ElementId::CLUSTER, ElementId(0x1F43_B675));
+        assert_eq!(ElementId::CLUSTER.name(), Some("Cluster"));
+        assert_eq!(ElementId::CLUSTER.element_type(), Some(ElementType::Master));
+        assert_eq!(ElementId::DURATION.element_type(), Some(ElementType::Float));
+        assert_eq!(ElementId::DISCARD_PADDING.element_type(), Some(ElementType::Int));
+        assert_eq!(ElementId::CODEC_PRIVATE.to_string(), "CodecPrivate");
+
+        // Attachments are Matroska, but not WebM
+        assert_eq!(ElementId(0x1941_A469).name(), None);
+        assert_eq!(ElementId(0x1941_A469).to_string(), "0x1941A469");
+    }
+
+    #[test]
+    fn reads_values() {
+        let info = [
+            element(ElementId::TIMECODE_SCALE, &[0x0F, 0x42, 0x40]),
+            element(ElementId::DURATION, &1234.5_f32.to_be_bytes()),
+            element(ElementId::MUXING_APP, b"test\0\0"),
+            element(ElementId::REFERENCE_BLOCK, &[0xFF, 0xD8]),
+        ]
+        .concat();
+        let data = [element(ElementId::EBML, &element(ElementId::DOC_TYPE, b"webm")), element(ElementId::INFO, &info)];
+        let mut reader = Reader::new(Cursor::new(data.concat()));
+        assert_eq!(child_ids(&mut reader, None).unwrap(), [ElementId::EBML, ElementId::INFO]);
+
+        let mut top_level = reader.top_level();
+        let header = reader.next_child(&mut top_level).unwrap().unwrap();
+        let mut children = reader.children(&header).unwrap();
+        let doc_type = reader.next_child(&mut children).unwrap().unwrap();
+        assert_eq!(reader.read_string(&doc_type).unwrap(), "webm");
+        assert_eq!(doc_type.depth, 1);
+
+        let info = reader.next_child(&mut top_level).unwrap().unwrap();
+        assert_eq!(info.position, 5 + 7);
+        let mut children = reader.children(&info).unwrap();
+        let mut next = || reader.next_child(&mut children).unwrap().unwrap();
+        let (timecode_scale, duration, muxing_app, reference_block) = (next(), next(), next(), next());
+        assert_eq!(reader.read_uint(&timecode_scale).unwrap(), 1_000_000);
+        assert_eq!(reader.read_float(&duration).unwrap(), 1234.5);
+        assert_eq!(reader.read_string(&muxing_app).unwrap(), "test");
+        assert_eq!(reader.read_int(&reference_block).unwrap(), -40);
+        assert_eq!(reader.read_prefix(&muxing_app, 2).unwrap(), b"te");
+        assert_eq!(reader.read_prefix(&reference_block, 8).unwrap(), [0xFF, 0xD8]);
+        assert!(reader.next_child(&mut top_level).unwrap().is_none());
+
+        assert!(matches!(reader.children(&duration), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn unknown_sizes() {
+        let first_cluster = [element(ElementId::TIMECODE, &[0]), element(ElementId::SIMPLE_BLOCK, &[0x81, 0, 0, 0x80])];
+        let second_cluster = [element(ElementId::TIMECODE, &[40]), element(ElementId::VOID, &[0; 3])];
+        let segment = [
+            unknown_size_element(ElementId::CLUSTER, &first_cluster.concat()),
+            unknown_size_element(ElementId::CLUSTER, &second_cluster.concat()),
+            element(ElementId::CUES, &element(ElementId::CUE_POINT, &[])),
+        ];
+        let data = [element(ElementId::EBML, &[]), unknown_size_element(ElementId::SEGMENT, &segment.concat())];
+        let mut reader = Reader::new(Cursor::new(data.concat()));
+        assert_eq!(child_ids(&mut reader, None).unwrap(), [ElementId::EBML, ElementId::SEGMENT]);
+
+        let mut top_level = reader.top_level();
+        reader.next_child(&mut top_level).unwrap();
+        let segment = reader.next_child(&mut top_level).unwrap().unwrap();
+        assert_eq!(segment.size, None);
+        assert_eq!(
+            child_ids(&mut reader, Some(&segment)).unwrap(),
+            [ElementId::CLUSTER, ElementId::CLUSTER, ElementId::CUES]
+        );
+
+        // Each cluster ends where the next top-level element starts
+        let mut children = reader.children(&segment).unwrap();
+        let cluster = reader.next_child(&mut children).unwrap().unwrap();
+        assert_eq!(child_ids(&mut reader, Some(&cluster)).unwrap(), [ElementId::TIMECODE, ElementId::SIMPLE_BLOCK]);
+        let cluster = reader.next_child(&mut children).unwrap().unwrap();
+        assert_eq!(child_ids(&mut reader, Some(&cluster)).unwrap(), [ElementId::TIMECODE, ElementId::VOID]);
+        let cues = reader.next_child(&mut children).unwrap().unwrap();
+        assert_eq!(child_ids(&mut reader, Some(&cues)).unwrap(), [ElementId::CUE_POINT]);
+
+        assert!(matches!(reader.read_binary(&segment), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let check = |data: &[u8], limits: Limits| {
+            let mut reader = Reader::with_limits(Cursor::new(data), limits);
+            let mut top_level = reader.top_level();
+            let segment = reader.next_child(&mut top_level)?.unwrap();
+            let mut children = reader.children(&segment)?;
+            while let Some(child) = reader.next_child(&mut children)? {
+                if child.id == ElementId::TRACKS {
+                    child_ids(&mut reader, Some(&child))?;
+                }
+                reader.read_binary(&child)?;
+            }
+            Ok::<_, Error>(())
+        };
+        let invalid = |data: &[u8], limits: Limits| matches!(check(data, limits), Err(Error::InvalidData(_)));
+
+        let info = element(ElementId::INFO, &element(ElementId::TITLE, b"title"));
+        let valid = element(ElementId::SEGMENT, &info);
+        assert!(check(&valid, Limits::default()).is_ok());
+
+        // Too large to read, or nested too deeply
+        let limits = Limits {
+            max_element_size: 4,
+            ..Limits::default()
+        };
+        assert!(invalid(&valid, limits));
+        let nested = element(ElementId::SEGMENT, &element(ElementId::TRACKS, &element(ElementId::TRACK_ENTRY, &[])));
+        let limits = Limits {
+            max_depth: 1,
+            ..Limits::default()
+        };
+        assert!(invalid(&nested, limits));
+
+        // Children that go past their parent, or the end of the input
+        let mut overlong = valid.clone();
+        overlong[9] += 1;
+        assert!(invalid(&overlong, Limits::default()));
+        assert!(invalid(&valid[..valid.len() - 1], Limits::default()));
+        assert!(invalid(&valid[..6], Limits::default()));
+
+        // An ID that is too long, and an unknown size on an element that doesn't have children
+        assert!(invalid(&element(ElementId::SEGMENT, &[0x08, 0, 0, 0, 0x80]), Limits::default()));
+        let unknown_size_title = unknown_size_element(ElementId::TITLE, b"title");
+        assert!(invalid(&element(ElementId::SEGMENT, &unknown_size_title), Limits::default()));
+    }
+}
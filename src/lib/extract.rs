@@ -0,0 +1,306 @@
+//! Extracting a single track of a WebM file into an elementary stream format, such as IVF or Ogg, for tools that
+//! don't read Matroska.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::{BufReader, BufWriter};
+//! use webm::extract::ExtractFormat;
+//!
+//! let input = BufReader::new(File::open("./recording.webm").unwrap());
+//! let output = BufWriter::new(File::create("./audio.opus").unwrap());
+//! webm::extract::extract(input, 2, ExtractFormat::Ogg, output).unwrap();
+//! ```
+
+use std::io::{Read, Seek, Write};
+use std::iter::Peekable;
+
+use crate::codec::av1;
+use crate::codec::opus::{self, OpusHead};
+use crate::codec::vorbis::{PacketDurations, VorbisHeaders};
+use crate::demux::{Demuxer, Frame, TrackInfo, TrackKind};
+use crate::ivf::{IvfHeader, IvfWriter};
+use crate::mux::{Error, TrackNum};
+use crate::ogg::OggPacketWriter;
+
+/// The formats a track can be extracted to with [`extract`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractFormat {
+    /// IVF, for VP8, VP9 and AV1 tracks.
+    Ivf,
+
+    /// Ogg, for Opus (`.opus`) and Vorbis (`.ogg`) tracks.
+    Ogg,
+
+    /// AV1 temporal units in the low overhead bitstream format (AV1 specification section 5), back to back.
+    Av1Obu,
+
+    /// AV1 temporal units in the length-delimited format from Annex B of the AV1 specification.
+    Av1AnnexB,
+}
+
+impl ExtractFormat {
+    /// Returns the usual format for tracks with the given Matroska codec ID: IVF for video, and Ogg for audio.
+    #[must_use]
+    pub fn for_codec_id(codec_id: &str) -> Option<Self> {
+        match codec_id {
+            "V_VP8" | "V_VP9" | "V_AV1" => Some(Self::Ivf),
+            "A_OPUS" | "A_VORBIS" => Some(Self::Ogg),
+            _ => None,
+        }
+    }
+
+    /// Returns whether tracks with the given Matroska codec ID can be extracted to this format.
+    #[must_use]
+    pub fn supports_codec_id(self, codec_id: &str) -> bool {
+        match self {
+            Self::Ivf => matches!(codec_id, "V_VP8" | "V_VP9" | "V_AV1"),
+            Self::Ogg => matches!(codec_id, "A_OPUS" | "A_VORBIS"),
+            Self::Av1Obu | Self::Av1AnnexB => codec_id == "V_AV1",
+        }
+    }
+}
+
+/// Writes the frames of track `track` of the WebM (or Matroska) file in `input` to `output` in the given format,
+/// and returns `output` once done.
+///
+/// Headers are rebuilt from the track's parameters and `CodecPrivate`:
+///
+/// - IVF files get a time base matching the input's timestamp precision. AV1 temporal units get the temporal
+///   delimiters back that WebM leaves out.
+/// - Ogg Opus files get the `OpusHead` from the `CodecPrivate` (or, if there is none, from the track's parameters
+///   and `CodecDelay`), and an empty `OpusTags`. Granule positions are derived from the timestamps, and a
+///   `DiscardPadding` on the last packet is turned into a final granule position that ends the stream early.
+/// - Ogg Vorbis files get the three header packets from the `CodecPrivate`.
+/// - For AV1, the `configOBUs` from the `CodecPrivate` are added to the first temporal unit if it lacks a Sequence
+///   Header OBU.
+///
+/// Fails with [`Error::BadParam`] if there is no such track, or it can't be extracted to `format` (see
+/// [`ExtractFormat::supports_codec_id`]), and with [`Error::InvalidData`] if its `CodecPrivate` is malformed.
+pub fn extract<R: Read + Seek, W: Write>(
+    input: R,
+    track: TrackNum,
+    format: ExtractFormat,
+    output: W,
+) -> Result<W, Error> {
+    let mut demuxer = Demuxer::new(input)?;
+    let info = demuxer.track(track).cloned().ok_or(Error::BadParam)?;
+    if !format.supports_codec_id(&info.codec_id) {
+        return Err(Error::BadParam);
+    }
+    let timecode_scale = demuxer.info().timecode_scale;
+
+    let frames = std::iter::from_fn(|| demuxer.next_frame().transpose())
+        .filter(|frame| frame.as_ref().map_or(true, |frame| frame.track == track))
+        .peekable();
+    match format {
+        ExtractFormat::Ivf => write_ivf(frames, &info, timecode_scale, output),
+        ExtractFormat::Ogg if info.codec_id == "A_OPUS" => write_ogg_opus(frames, &info, output),
+        ExtractFormat::Ogg => write_ogg_vorbis(frames, &info, output),
+        ExtractFormat::Av1Obu | ExtractFormat::Av1AnnexB => {
+            let mut output = output;
+            let mut config_obus = Some(av1::config_obus(&info.codec_private)?);
+            for frame in frames {
+                let temporal_unit = av1_temporal_unit(&frame?.data, config_obus.take())?;
+                let temporal_unit = match format {
+                    ExtractFormat::Av1AnnexB => av1::to_annex_b(&temporal_unit)?,
+                    _ => temporal_unit,
+                };
+                output.write_all(&temporal_unit).map_err(Error::Io)?;
+            }
+            Ok(output)
+        },
+    }
+}
+
+/// Returns an AV1 frame from a WebM block as a temporal unit in the low overhead bitstream format. If
+/// `config_obus` are given, they are added unless the frame has a Sequence Header OBU of its own.
+fn av1_temporal_unit(data: &[u8], config_obus: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let mut has_sequence_header = false;
+    for obu in av1::obus(data) {
+        has_sequence_header |= obu?.obu_type == av1::OBU_SEQUENCE_HEADER;
+    }
+
+    let data = match config_obus {
+        Some(config_obus) if !has_sequence_header => [config_obus, data].concat(),
+        _ => data.to_vec(),
+    };
+    Ok(av1::add_temporal_delimiter(&data)?.into_owned())
+}
+
+fn write_ivf<W: Write>(
+    frames: impl Iterator<Item = Result<Frame, Error>>,
+    info: &TrackInfo,
+    timecode_scale: u64,
+    output: W,
+) -> Result<W, Error> {
+    let TrackKind::Video(video) = info.kind else {
+        return Err(Error::BadParam);
+    };
+    let fourcc = match info.codec_id.as_str() {
+        "V_VP8" => *b"VP80",
+        "V_VP9" => *b"VP90",
+        _ => *b"AV01",
+    };
+    let (Ok(width), Ok(height)) = (u16::try_from(video.width), u16::try_from(video.height)) else {
+        return Err(Error::InvalidData("video dimensions are too large for IVF"));
+    };
+
+    // One time base unit is one unit of the input's timestamps
+    let divisor = gcd(timecode_scale.max(1), 1_000_000_000);
+    let header = IvfHeader {
+        fourcc,
+        width,
+        height,
+        timebase_numerator: u32::try_from(timecode_scale.max(1) / divisor).map_err(|_| Error::BadParam)?,
+        timebase_denominator: (1_000_000_000 / divisor) as u32,
+        frame_count: 0,
+    };
+    let to_timebase = |timestamp_ns: u64| {
+        let timestamp = u128::from(timestamp_ns) * u128::from(header.timebase_denominator)
+            / (u128::from(header.timebase_numerator) * 1_000_000_000);
+        timestamp as u64
+    };
+
+    let mut writer = IvfWriter::new(output, &header)?;
+    let is_av1 = info.codec_id == "V_AV1";
+    let mut config_obus = if is_av1 { Some(av1::config_obus(&info.codec_private)?) } else { None };
+    for frame in frames {
+        let frame = frame?;
+        let timestamp = to_timebase(frame.timestamp_ns);
+        if is_av1 {
+            writer.write_frame(timestamp, &av1_temporal_unit(&frame.data, config_obus.take())?)?;
+        } else {
+            writer.write_frame(timestamp, &frame.data)?;
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+fn write_ogg_opus<W: Write>(
+    frames: Peekable<impl Iterator<Item = Result<Frame, Error>>>,
+    info: &TrackInfo,
+    output: W,
+) -> Result<W, Error> {
+    let head = if info.codec_private.is_empty() {
+        let TrackKind::Audio(audio) = info.kind else {
+            return Err(Error::BadParam);
+        };
+        let channels = u8::try_from(audio.channels).map_err(|_| Error::InvalidData("too many Opus channels"))?;
+        let pre_skip = u16::try_from(ns_to_samples(info.codec_delay_ns, opus::SAMPLE_RATE))
+            .map_err(|_| Error::InvalidData("Opus CodecDelay is too long"))?;
+        OpusHead::new(channels, pre_skip, audio.sample_rate.round() as u32)?
+    } else {
+        OpusHead::parse(&info.codec_private)?
+    };
+
+    let mut tags = Vec::new();
+    tags.extend_from_slice(b"OpusTags");
+    let vendor = concat!("webm-rs ", env!("CARGO_PKG_VERSION"));
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0_u32.to_le_bytes());
+
+    let headers = [head.to_bytes()?, tags];
+    write_ogg(frames, info.number, &headers, opus::SAMPLE_RATE, opus::packet_samples, output)
+}
+
+fn write_ogg_vorbis<W: Write>(
+    frames: Peekable<impl Iterator<Item = Result<Frame, Error>>>,
+    info: &TrackInfo,
+    output: W,
+) -> Result<W, Error> {
+    let headers = VorbisHeaders::from_codec_private(&info.codec_private)?;
+    let mut durations = PacketDurations::new(&headers)?;
+    let packets = headers.packets().map(<[u8]>::to_vec);
+    let sample_rate = headers.info().sample_rate;
+    write_ogg(frames, info.number, &packets, sample_rate, |packet| durations.next_packet_samples(packet), output)
+}
+
+/// Writes an Ogg stream of the given header packets, followed by one packet per frame. Granule positions are the
+/// sample position at the end of each packet, derived from the frame timestamps.
+fn write_ogg<W: Write>(
+    mut frames: Peekable<impl Iterator<Item = Result<Frame, Error>>>,
+    serial: TrackNum,
+    headers: &[Vec<u8>],
+    sample_rate: u32,
+    mut packet_samples: impl FnMut(&[u8]) -> Result<u32, Error>,
+    output: W,
+) -> Result<W, Error> {
+    let mut writer = OggPacketWriter::new(output, serial as u32);
+    for (index, header) in headers.iter().enumerate() {
+        let end_of_stream = index + 1 == headers.len() && frames.peek().is_none();
+        writer.write_packet(header, 0, end_of_stream)?;
+    }
+
+    while let Some(frame) = frames.next() {
+        let frame = frame?;
+        let samples = u64::from(packet_samples(&frame.data)?);
+        let mut granule_position = ns_to_samples(frame.timestamp_ns, sample_rate) + samples;
+        if frame.discard_padding_ns > 0 {
+            granule_position -= ns_to_samples(frame.discard_padding_ns as u64, sample_rate).min(samples);
+        }
+        writer.write_packet(&frame.data, granule_position, frames.peek().is_none())?;
+    }
+
+    Ok(writer.into_inner())
+}
+
+/// Converts a duration to a number of samples, rounding to the nearest one.
+fn ns_to_samples(ns: u64, sample_rate: u32) -> u64 {
+    ((u128::from(ns) * u128::from(sample_rate) + 500_000_000) / 1_000_000_000) as u64
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::ivf::IvfReader;
+    use crate::ogg::OggPacketReader;
+    use crate::remux::tests::live_recording;
+
+    #[test]
+    fn formats() {
+        assert_eq!(ExtractFormat::for_codec_id("V_VP9"), Some(ExtractFormat::Ivf));
+        assert_eq!(ExtractFormat::for_codec_id("A_VORBIS"), Some(ExtractFormat::Ogg));
+        assert_eq!(ExtractFormat::for_codec_id("A_FLAC"), None);
+        assert!(ExtractFormat::Av1AnnexB.supports_codec_id("V_AV1"));
+        assert!(!ExtractFormat::Av1Obu.supports_codec_id("V_VP9"));
+        assert!(!ExtractFormat::Ogg.supports_codec_id("V_VP8"));
+
+        assert_eq!(ns_to_samples(20_000_000, 48_000), 960);
+        assert_eq!(ns_to_samples(6_500_000, 48_000), 312);
+        assert_eq!(gcd(1_000_000, 1_000_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn extracts_tracks() {
+        let ivf = extract(Cursor::new(live_recording()), 1, ExtractFormat::Ivf, Vec::new()).unwrap();
+        let mut reader = IvfReader::new(ivf.as_slice()).unwrap();
+        let header = *reader.header();
+        assert_eq!((header.fourcc, header.width, header.height), (*b"VP80", 352, 288));
+        let frames = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(frames.len(), 15);
+        assert_eq!(header.timestamp_ns(frames[1].timestamp), 40_000_000);
+
+        let ogg = extract(Cursor::new(live_recording()), 2, ExtractFormat::Ogg, Vec::new()).unwrap();
+        let packets = OggPacketReader::new(ogg.as_slice()).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(packets.len(), 32);
+        assert_eq!(OpusHead::parse(&packets[0].data).unwrap().pre_skip, 312);
+        assert!(packets[1].data.starts_with(b"OpusTags"));
+        assert_eq!(packets[2].granule_position, Some(960));
+        assert_eq!((packets[31].granule_position, packets[31].end_of_stream), (Some(28_800), true));
+
+        let result = extract(Cursor::new(live_recording()), 2, ExtractFormat::Ivf, Vec::new());
+        assert!(matches!(result, Err(Error::BadParam)));
+        let result = extract(Cursor::new(live_recording()), 3, ExtractFormat::Ogg, Vec::new());
+        assert!(matches!(result, Err(Error::BadParam)));
+    }
+}
@@ -0,0 +1,376 @@
+//! Reading and writing IVF files, the simple container written by `vpxenc`, `aomenc` and the libvpx/libaom
+//! examples, and remuxing them to WebM.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::Writer;
+//!
+//! let input = BufReader::new(File::open("./encoder-output.ivf").unwrap());
+//! let output = File::create("./encoder-output.webm").unwrap();
+//! webm::ivf::remux_to_webm(input, Writer::new(output)).unwrap();
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crate::codec::{av1, vp9};
+use crate::mux::{Error, SegmentBuilder, VideoCodecId, Writer};
+
+const SIGNATURE: &[u8; 4] = b"DKIF";
+const HEADER_LEN: usize = 32;
+const FRAME_HEADER_LEN: usize = 12;
+
+/// Frames larger than this are assumed to come from a corrupt file, rather than risking huge allocations.
+const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+
+/// The file header of an IVF file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IvfHeader {
+    /// The codec FourCC, such as `VP80`, `VP90` or `AV01`.
+    pub fourcc: [u8; 4],
+
+    /// The frame width, in pixels.
+    pub width: u16,
+
+    /// The frame height, in pixels.
+    pub height: u16,
+
+    /// The numerator of the time base, i.e. the length of one timestamp unit in seconds.
+    pub timebase_numerator: u32,
+
+    /// The denominator of the time base.
+    pub timebase_denominator: u32,
+
+    /// The number of frames, as written by the encoder. This is often inaccurate for streams that were cut short.
+    pub frame_count: u32,
+}
+
+impl IvfHeader {
+    /// Returns the video codec matching [`IvfHeader::fourcc`], if it is one that can be muxed.
+    #[must_use]
+    pub fn codec(&self) -> Option<VideoCodecId> {
+        match &self.fourcc {
+            b"VP80" => Some(VideoCodecId::VP8),
+            b"VP90" => Some(VideoCodecId::VP9),
+            b"AV01" => Some(VideoCodecId::AV1),
+            _ => None,
+        }
+    }
+
+    /// Converts a frame timestamp from time base units to nanoseconds, rounding down.
+    #[must_use]
+    pub fn timestamp_ns(&self, timestamp: u64) -> u64 {
+        let ns = u128::from(timestamp) * u128::from(self.timebase_numerator) * 1_000_000_000
+            / u128::from(self.timebase_denominator);
+        u64::try_from(ns).unwrap_or(u64::MAX)
+    }
+}
+
+/// A single frame from an IVF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IvfFrame {
+    /// The frame timestamp, in time base units (see [`IvfHeader::timestamp_ns`]).
+    pub timestamp: u64,
+
+    /// The encoded frame.
+    pub data: Vec<u8>,
+}
+
+/// Reads the frames of an IVF file one by one.
+#[derive(Debug)]
+pub struct IvfReader<R: Read> {
+    reader: R,
+    header: IvfHeader,
+}
+
+impl<R: Read> IvfReader<R> {
+    /// Reads the file header from `reader`, which must be at the start of an IVF file.
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::InvalidData("IVF header is truncated"),
+            _ => Error::Io(err),
+        })?;
+
+        if &header[..4] != SIGNATURE {
+            return Err(Error::InvalidData("IVF signature not found"));
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+        // The header length is recorded in the file, and may in theory be longer than ours
+        let header_len = usize::from(u16_at(6));
+        if header_len < HEADER_LEN {
+            return Err(Error::InvalidData("IVF header is too short"));
+        }
+        io::copy(&mut (&mut reader).take((header_len - HEADER_LEN) as u64), &mut io::sink()).map_err(Error::Io)?;
+
+        let header = IvfHeader {
+            fourcc: header[8..12].try_into().unwrap(),
+            width: u16_at(12),
+            height: u16_at(14),
+            timebase_denominator: u32_at(16),
+            timebase_numerator: u32_at(20),
+            frame_count: u32_at(24),
+        };
+        if header.timebase_numerator == 0 || header.timebase_denominator == 0 {
+            return Err(Error::InvalidData("IVF time base is invalid"));
+        }
+
+        Ok(Self { reader, header })
+    }
+
+    /// Returns the file header.
+    #[must_use]
+    pub fn header(&self) -> &IvfHeader {
+        &self.header
+    }
+
+    /// Reads the next frame, returning `None` at the end of the file.
+    pub fn next_frame(&mut self) -> Result<Option<IvfFrame>, Error> {
+        let mut frame_header = [0; FRAME_HEADER_LEN];
+        let mut header_read = 0;
+        while header_read < FRAME_HEADER_LEN {
+            match self.reader.read(&mut frame_header[header_read..]) {
+                Ok(0) if header_read == 0 => return Ok(None),
+                Ok(0) => return Err(Error::InvalidData("IVF frame header is truncated")),
+                Ok(len) => header_read += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+
+        let len = u32::from_le_bytes(frame_header[..4].try_into().unwrap());
+        if len > MAX_FRAME_LEN {
+            return Err(Error::InvalidData("IVF frame is too large"));
+        }
+        let timestamp = u64::from_le_bytes(frame_header[4..].try_into().unwrap());
+
+        let mut data = vec![0; len as usize];
+        self.reader.read_exact(&mut data).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => Error::InvalidData("IVF frame is truncated"),
+            _ => Error::Io(err),
+        })?;
+
+        Ok(Some(IvfFrame { timestamp, data }))
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for IvfReader<R> {
+    type Item = Result<IvfFrame, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Writes frames to an IVF file one by one.
+#[derive(Debug)]
+pub struct IvfWriter<W: Write> {
+    writer: W,
+    frames_written: u32,
+}
+
+impl<W: Write> IvfWriter<W> {
+    /// Writes `header` to `writer`. As the header comes first, [`IvfHeader::frame_count`] must be known upfront,
+    /// though readers generally ignore it.
+    pub fn new(mut writer: W, header: &IvfHeader) -> Result<Self, Error> {
+        if header.timebase_numerator == 0 || header.timebase_denominator == 0 {
+            return Err(Error::BadParam);
+        }
+
+        let mut data = [0; HEADER_LEN];
+        data[..4].copy_from_slice(SIGNATURE);
+        data[6..8].copy_from_slice(&(HEADER_LEN as u16).to_le_bytes());
+        data[8..12].copy_from_slice(&header.fourcc);
+        data[12..14].copy_from_slice(&header.width.to_le_bytes());
+        data[14..16].copy_from_slice(&header.height.to_le_bytes());
+        data[16..20].copy_from_slice(&header.timebase_denominator.to_le_bytes());
+        data[20..24].copy_from_slice(&header.timebase_numerator.to_le_bytes());
+        data[24..28].copy_from_slice(&header.frame_count.to_le_bytes());
+        writer.write_all(&data).map_err(Error::Io)?;
+
+        Ok(Self {
+            writer,
+            frames_written: 0,
+        })
+    }
+
+    /// Writes a frame with the given timestamp, in time base units (see [`IvfHeader::timestamp_ns`]).
+    pub fn write_frame(&mut self, timestamp: u64, data: &[u8]) -> Result<(), Error> {
+        let len = u32::try_from(data.len()).ok().filter(|&len| len <= MAX_FRAME_LEN).ok_or(Error::BadParam)?;
+
+        let mut frame_header = [0; FRAME_HEADER_LEN];
+        frame_header[..4].copy_from_slice(&len.to_le_bytes());
+        frame_header[4..].copy_from_slice(&timestamp.to_le_bytes());
+        self.writer.write_all(&frame_header).map_err(Error::Io)?;
+        self.writer.write_all(data).map_err(Error::Io)?;
+
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Returns the number of frames written so far.
+    #[must_use]
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Remuxes an IVF file from `input` into a WebM file written to `writer`, returning the writer once done.
+///
+/// The track is configured from the first frame, which must be a keyframe: for VP9 and AV1, the `CodecPrivate`
+/// data and color information are filled in from its header (see [`SegmentBuilder::add_vp9_track`] and
+/// [`SegmentBuilder::add_av1_track`]). Keyframes are detected from the frame data, rather than trusted blindly.
+pub fn remux_to_webm<R: Read, W: Write>(input: R, writer: Writer<W>) -> Result<Writer<W>, Error> {
+    let mut reader = IvfReader::new(input)?;
+    let header = *reader.header();
+    let codec = header.codec().ok_or(Error::InvalidData("unsupported IVF codec"))?;
+    let Some(first_frame) = reader.next_frame()? else {
+        return Err(Error::InvalidData("IVF file contains no frames"));
+    };
+
+    let builder = SegmentBuilder::new(writer)?;
+    let (builder, track) = match codec {
+        VideoCodecId::VP8 => builder.add_video_track(header.width.into(), header.height.into(), codec, None)?,
+        VideoCodecId::VP9 => builder.add_vp9_track(&vp9::KeyframeHeader::parse(&first_frame.data)?, None, None)?,
+        VideoCodecId::AV1 => builder.add_av1_track(&av1::SequenceHeader::from_obus(&first_frame.data)?, None)?,
+    };
+    let mut segment = builder.build();
+
+    let mut frame = Some(first_frame);
+    while let Some(IvfFrame { timestamp, data }) = frame {
+        segment.add_frame_auto(track, &data, header.timestamp_ns(timestamp))?;
+        frame = reader.next_frame()?;
+    }
+
+    segment.finalize(None).map_err(|_| Error::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ivf_file(fourcc: &[u8; 4], frames: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&0_u16.to_le_bytes());
+        data.extend_from_slice(&32_u16.to_le_bytes());
+        data.extend_from_slice(fourcc);
+        data.extend_from_slice(&352_u16.to_le_bytes());
+        data.extend_from_slice(&288_u16.to_le_bytes());
+        data.extend_from_slice(&30_u32.to_le_bytes());
+        data.extend_from_slice(&1_u32.to_le_bytes());
+        data.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+
+        for (timestamp, frame) in frames {
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(&timestamp.to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_frames() {
+        let file = ivf_file(b"VP80", &[(0, &[1, 2, 3]), (1, &[]), (3, &[4; 100])]);
+        let mut reader = IvfReader::new(file.as_slice()).unwrap();
+        let header = *reader.header();
+        assert_eq!(
+            header,
+            IvfHeader {
+                fourcc: *b"VP80",
+                width: 352,
+                height: 288,
+                timebase_numerator: 1,
+                timebase_denominator: 30,
+                frame_count: 3,
+            }
+        );
+        assert_eq!(header.codec(), Some(VideoCodecId::VP8));
+        assert_eq!(header.timestamp_ns(3), 100_000_000);
+
+        assert_eq!(reader.next_frame().unwrap(), Some(IvfFrame { timestamp: 0, data: vec![1, 2, 3] }));
+        let rest = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rest, [IvfFrame { timestamp: 1, data: vec![] }, IvfFrame { timestamp: 3, data: vec![4; 100] }]);
+    }
+
+    #[test]
+    fn writes_frames() {
+        let header = IvfHeader {
+            fourcc: *b"VP80",
+            width: 352,
+            height: 288,
+            timebase_numerator: 1,
+            timebase_denominator: 30,
+            frame_count: 3,
+        };
+        let mut writer = IvfWriter::new(Vec::new(), &header).unwrap();
+        writer.write_frame(0, &[1, 2, 3]).unwrap();
+        writer.write_frame(1, &[]).unwrap();
+        writer.write_frame(3, &[4; 100]).unwrap();
+        assert_eq!(writer.frames_written(), 3);
+        assert_eq!(writer.into_inner(), ivf_file(b"VP80", &[(0, &[1, 2, 3]), (1, &[]), (3, &[4; 100])]));
+
+        let zero_timebase = IvfHeader {
+            timebase_numerator: 0,
+            ..header
+        };
+        assert!(matches!(IvfWriter::new(Vec::new(), &zero_timebase), Err(Error::BadParam)));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let file = ivf_file(b"VP90", &[(0, &[1, 2, 3])]);
+        assert!(IvfReader::new(&file[..31]).is_err());
+
+        let mut wrong_signature = file.clone();
+        wrong_signature[0] = b'X';
+        assert!(IvfReader::new(wrong_signature.as_slice()).is_err());
+
+        let mut zero_timebase = file.clone();
+        zero_timebase[16..20].fill(0);
+        assert!(IvfReader::new(zero_timebase.as_slice()).is_err());
+
+        let mut reader = IvfReader::new(&file[..file.len() - 1]).unwrap();
+        assert!(matches!(reader.next_frame(), Err(Error::InvalidData(_))));
+        let mut reader = IvfReader::new(&file[..HEADER_LEN + 5]).unwrap();
+        assert!(matches!(reader.next_frame(), Err(Error::InvalidData(_))));
+
+        let unknown_codec = ivf_file(b"H264", &[(0, &[1, 2, 3])]);
+        assert_eq!(IvfReader::new(unknown_codec.as_slice()).unwrap().header().codec(), None);
+    }
+
+    #[test]
+    fn remuxes_vp8() {
+        let keyframe = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01, 0xAB, 0xCD];
+        let inter_frame = [0x31, 0x05, 0x00, 0xEF, 0x12];
+        let file = ivf_file(b"VP80", &[(0, &keyframe), (1, &inter_frame), (2, &inter_frame)]);
+
+        let writer = Writer::new(io::Cursor::new(Vec::new()));
+        let Ok(writer) = remux_to_webm(file.as_slice(), writer) else {
+            panic!("Remuxing unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"V_VP8"));
+        assert!(contains(&keyframe));
+        assert!(contains(&inter_frame));
+
+        // VP9 and AV1 need a keyframe up front to configure the track
+        let file = ivf_file(b"VP90", &[(0, &[0x86, 0x00, 0x40, 0x00])]);
+        let writer = Writer::new(io::Cursor::new(Vec::new()));
+        assert!(matches!(remux_to_webm(file.as_slice(), writer), Err(Error::InvalidData(_))));
+    }
+}
@@ -4,7 +4,8 @@
 //! Consider a crate such as `vpx` if you need encoding as well.
 //!
 //! Actual writing of muxed data is done through a [`mux::Writer`], which lets you supply your own implementation.
-//! This makes it easy to support muxing to files, in-memory buffers, or whatever else you need. Once you have
+//! This makes it easy to support muxing to files, in-memory buffers, or whatever else you need
+//! (see [`mux::Writer::new_in_memory`] for streaming out of memory as muxing progresses). Once you have
 //! a [`mux::Writer`], you create a [`mux::SegmentBuilder`] and add the tracks you need. Finally, you create a
 //! [`mux::Segment`] with that builder, to which you can add media frames.
 //!
@@ -32,41 +33,117 @@
 //! _ = segment.finalize(None).inspect_err(|_| eprintln!("Could not finalize WebM file"));
 //! ```
 
+#[cfg(not(any(feature = "libwebm", feature = "rust-mux")))]
+compile_error!("either the `libwebm` or the `rust-mux` feature must be enabled");
+
+#[cfg(feature = "libwebm")]
 use webm_sys as ffi;
 
+/// Helpers for the codec-specific data that goes along with tracks and frames, such as `CodecPrivate`.
+pub mod codec {
+    mod bits;
+
+    pub mod av1;
+    pub mod opus;
+    pub mod vorbis;
+    pub mod vp8;
+    pub mod vp9;
+}
+
+#[cfg(feature = "libwebm")]
+pub mod concat;
+#[cfg(feature = "libwebm")]
+pub mod demux;
+pub mod ebml;
+#[cfg(feature = "libwebm")]
+pub mod extract;
+pub mod ivf;
+pub mod ogg;
+#[cfg(feature = "libwebm")]
+pub mod remux;
+#[cfg(feature = "libwebm")]
+pub mod repair;
+#[cfg(feature = "libwebm")]
+pub mod split;
+#[cfg(all(feature = "libwebm", feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "libwebm")]
+pub mod trim;
+pub mod validate;
+
 pub mod mux {
+    #[cfg(feature = "rust-mux")]
+    mod ebml;
+    #[cfg(not(feature = "rust-mux"))]
+    mod libwebm;
+    #[cfg(feature = "rust-mux")]
+    mod matroska;
+    mod memory;
     mod segment;
+    mod template;
     mod writer;
 
+    #[cfg(not(feature = "rust-mux"))]
+    use libwebm as backend;
+    #[cfg(feature = "rust-mux")]
+    use matroska as backend;
+
     pub use {
-        crate::ffi::mux::TrackNum,
+        memory::{MemorySink, Patch},
         segment::{Segment, SegmentBuilder},
+        template::{RotatingMuxer, RotationError, RotationPolicy, SegmentTemplate},
         writer::Writer,
     };
 
-    use crate::ffi;
     use std::num::NonZeroU64;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// An opaque number used to identify an added track.
+    pub type TrackNum = u64;
+
+    /// Identifies the segment a [`VideoTrack`] or [`AudioTrack`] handle was created for.
+    ///
+    /// Every [`SegmentBuilder`] gets a unique ID, and all segments spawned from a [`SegmentTemplate`] share the
+    /// template's ID. Passing a track handle to a segment with a different ID fails with [`Error::ForeignTrack`],
+    /// instead of silently writing to whichever track happens to have the same number.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct SegmentId(NonZeroU64);
 
-    /// This is a copyable handle equivalent to a track number
+    impl SegmentId {
+        fn next() -> Self {
+            static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            Self(NonZeroU64::new(id).expect("segment IDs should not run out"))
+        }
+    }
+
+    /// This is a copyable handle equivalent to a track number, tied to the segment it was created for
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-    pub struct VideoTrack(NonZeroU64);
+    pub struct VideoTrack {
+        segment: SegmentId,
+        number: NonZeroU64,
+    }
 
     impl From<VideoTrack> for TrackNum {
         fn from(track: VideoTrack) -> Self {
-            track.0.get()
+            track.number.get()
         }
     }
 
-    /// This is a copyable handle equivalent to a track number
+    /// This is a copyable handle equivalent to a track number, tied to the segment it was created for
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-    pub struct AudioTrack(NonZeroU64);
+    pub struct AudioTrack {
+        segment: SegmentId,
+        number: NonZeroU64,
+    }
 
     impl From<AudioTrack> for TrackNum {
         fn from(track: AudioTrack) -> Self {
-            track.0.get()
+            track.number.get()
         }
     }
 
+    /// Something that identifies a track: a [`VideoTrack`], an [`AudioTrack`], or a raw [`TrackNum`].
     pub trait Track {
         #[must_use]
         fn is_audio(&self) -> bool {
@@ -80,6 +157,13 @@ pub mod mux {
 
         #[must_use]
         fn track_number(&self) -> TrackNum;
+
+        /// Returns the segment this track handle belongs to, if known. Raw track numbers belong to no segment in
+        /// particular, and are only checked for existence.
+        #[must_use]
+        fn segment_id(&self) -> Option<SegmentId> {
+            None
+        }
     }
 
     impl Track for VideoTrack {
@@ -90,7 +174,11 @@ pub mod mux {
 
         #[must_use]
         fn track_number(&self) -> TrackNum {
-            self.0.get()
+            self.number.get()
+        }
+
+        fn segment_id(&self) -> Option<SegmentId> {
+            Some(self.segment)
         }
     }
 
@@ -102,34 +190,125 @@ pub mod mux {
 
         #[must_use]
         fn track_number(&self) -> TrackNum {
-            self.0.get()
+            self.number.get()
+        }
+
+        fn segment_id(&self) -> Option<SegmentId> {
+            Some(self.segment)
+        }
+    }
+
+    impl Track for TrackNum {
+        fn track_number(&self) -> TrackNum {
+            *self
         }
     }
 
     #[derive(Eq, PartialEq, Clone, Copy, Debug)]
-    #[repr(u32)]
     pub enum AudioCodecId {
-        Opus = ffi::mux::OPUS_CODEC_ID,
-        Vorbis = ffi::mux::VORBIS_CODEC_ID,
+        Opus,
+        Vorbis,
+
+        /// FLAC (`A_FLAC`). The `CodecPrivate` must contain the `fLaC` marker followed by the metadata blocks,
+        /// starting with `STREAMINFO`, which [`SegmentBuilder::set_codec_private`] checks. Requires
+        /// [`DocType::Matroska`].
+        Flac,
+
+        /// Uncompressed, little-endian integer PCM (`A_PCM/INT/LIT`). Requires [`DocType::Matroska`] and a bit depth
+        /// of 8, 16, 24 or 32.
+        PcmIntLittleEndian,
+
+        /// Uncompressed IEEE floating point PCM (`A_PCM/FLOAT/IEEE`). Requires [`DocType::Matroska`] and a bit depth
+        /// of 32 or 64.
+        PcmFloat,
     }
 
     impl AudioCodecId {
-        fn get_id(self) -> u32 {
-            self as u32
+        /// Returns `true` if this codec may be used in a WebM file.
+        #[must_use]
+        pub fn is_webm(self) -> bool {
+            matches!(self, Self::Opus | Self::Vorbis)
+        }
+
+        /// Returns the Matroska codec ID string of this codec, such as `A_OPUS`.
+        #[must_use]
+        pub fn codec_id(self) -> &'static str {
+            match self {
+                Self::Opus => "A_OPUS",
+                Self::Vorbis => "A_VORBIS",
+                Self::Flac => "A_FLAC",
+                Self::PcmIntLittleEndian => "A_PCM/INT/LIT",
+                Self::PcmFloat => "A_PCM/FLOAT/IEEE",
+            }
+        }
+
+        /// Looks up the codec with the given Matroska codec ID string, if it is one of the known ones.
+        #[must_use]
+        pub fn from_codec_id(codec_id: &str) -> Option<Self> {
+            [Self::Opus, Self::Vorbis, Self::Flac, Self::PcmIntLittleEndian, Self::PcmFloat]
+                .into_iter()
+                .find(|codec| codec.codec_id() == codec_id)
+        }
+
+        /// Checks whether `bit_depth` is acceptable for this codec.
+        fn is_valid_bit_depth(self, bit_depth: Option<u32>) -> bool {
+            match (self, bit_depth) {
+                (_, Some(0)) => false,
+                (Self::PcmIntLittleEndian, bit_depth) => matches!(bit_depth, Some(8 | 16 | 24 | 32)),
+                (Self::PcmFloat, bit_depth) => matches!(bit_depth, Some(32 | 64)),
+                (Self::Flac, Some(bit_depth)) => (4..=32).contains(&bit_depth),
+                _ => true,
+            }
         }
     }
 
     #[derive(Eq, PartialEq, Clone, Copy, Debug)]
-    #[repr(u32)]
     pub enum VideoCodecId {
-        VP8 = ffi::mux::VP8_CODEC_ID,
-        VP9 = ffi::mux::VP9_CODEC_ID,
-        AV1 = ffi::mux::AV1_CODEC_ID,
+        VP8,
+        VP9,
+        AV1,
     }
 
     impl VideoCodecId {
-        fn get_id(self) -> u32 {
-            self as u32
+        /// Returns the Matroska codec ID string of this codec, such as `V_VP9`.
+        #[must_use]
+        pub fn codec_id(self) -> &'static str {
+            match self {
+                Self::VP8 => "V_VP8",
+                Self::VP9 => "V_VP9",
+                Self::AV1 => "V_AV1",
+            }
+        }
+
+        /// Looks up the codec with the given Matroska codec ID string, if it is one of the known ones.
+        #[must_use]
+        pub fn from_codec_id(codec_id: &str) -> Option<Self> {
+            [Self::VP8, Self::VP9, Self::AV1]
+                .into_iter()
+                .find(|codec| codec.codec_id() == codec_id)
+        }
+    }
+
+    /// The kind of file a segment is written as, which determines the `DocType` in its EBML header.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum DocType {
+        /// A WebM file, which only allows VP8, VP9 and AV1 video, and Opus and Vorbis audio.
+        #[default]
+        WebM,
+
+        /// A general Matroska (`.mkv`/`.mka`) file, which allows arbitrary codec IDs. Its `DocType` is `matroska`,
+        /// whatever codecs its tracks use.
+        Matroska,
+    }
+
+    impl DocType {
+        /// Returns the `DocType` string as written in the EBML header.
+        #[must_use]
+        pub fn as_str(self) -> &'static str {
+            match self {
+                Self::WebM => "webm",
+                Self::Matroska => "matroska",
+            }
         }
     }
 
@@ -141,12 +320,57 @@ pub mod mux {
         /// An parameter with an invalid value was passed to a method.
         BadParam,
 
+        /// A [`VideoTrack`] or [`AudioTrack`] handle was used with a segment other than the one it was created
+        /// for. See [`SegmentId`].
+        ForeignTrack,
+
+        /// The tracks of one of several input files don't match those of the first input, so they can't be
+        /// written to the same segment. `input` is the index of that input, and `reason` describes what differs.
+        IncompatibleTracks { input: usize, reason: &'static str },
+
+        /// Data passed in for parsing, such as codec headers or an input file, is malformed or unsupported.
+        /// The message describes what was wrong with it.
+        InvalidData(&'static str),
+
+        /// The keyframe flag passed along with a frame does not match what the frame data says, see
+        /// [`SegmentBuilder::set_keyframe_validation`].
+        KeyframeMismatch,
+
+        /// An I/O error occurred outside of libwebm, such as when opening a new write destination, or when the
+        /// pure-Rust muxing backend of the `rust-mux` feature writes to its destination.
+        Io(std::io::Error),
+
         /// An unknown error occurred. While this is typically the result of
-        /// incorrect parameters to methods, an internal error in libwebm is
+        /// incorrect parameters to methods, an internal error in the muxer is
         /// also possible.
         Unknown,
     }
 
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::BadParam => f.write_str("invalid parameter"),
+                Self::ForeignTrack => f.write_str("track handle belongs to a different segment"),
+                Self::IncompatibleTracks { input, reason } => {
+                    write!(f, "input {input} has incompatible tracks: {reason}")
+                },
+                Self::InvalidData(message) => write!(f, "invalid data: {message}"),
+                Self::KeyframeMismatch => f.write_str("keyframe flag does not match the frame data"),
+                Self::Io(err) => write!(f, "I/O error: {err}"),
+                Self::Unknown => f.write_str("unknown error"),
+            }
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                Self::Io(err) => Some(err),
+                _ => None,
+            }
+        }
+    }
+
     /// A specification for how pixels in written video frames are subsampled in chroma channels.
     ///
     /// Certain video frame formats (e.g. YUV 4:2:0) have a lower resolution in chroma (Cr/Cb) channels than the
@@ -180,4 +404,55 @@ pub mod mux {
         /// No color clipping is performed.
         Full = 2,
     }
+
+    /// The color space and HDR metadata of a video track, stored in its `Colour` element. See
+    /// [`SegmentBuilder::set_color_metadata`].
+    ///
+    /// Color spaces are given as defined by ISO/IEC 23091-4, where 2 means unspecified.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct ColorMetadata {
+        /// The matrix coefficients, if specified.
+        pub matrix_coefficients: Option<u64>,
+
+        /// The transfer characteristics, if specified.
+        pub transfer_characteristics: Option<u64>,
+
+        /// The color primaries, if specified.
+        pub primaries: Option<u64>,
+
+        /// Where chroma samples are located horizontally and vertically, as Matroska's `ChromaSitingHorz` and
+        /// `ChromaSitingVert` values.
+        pub chroma_siting: (Option<u64>, Option<u64>),
+
+        /// The maximum content light level, in cd/m², if specified.
+        pub max_cll: Option<u64>,
+
+        /// The maximum frame-average light level, in cd/m², if specified.
+        pub max_fall: Option<u64>,
+
+        /// The SMPTE 2086 mastering display metadata, if the track has any.
+        pub mastering: Option<MasteringMetadata>,
+    }
+
+    /// The SMPTE 2086 metadata of the display a video track was mastered on. Missing values are `None`.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct MasteringMetadata {
+        /// The CIE 1931 x and y chromaticity of the red primary.
+        pub red: Option<(f32, f32)>,
+
+        /// The CIE 1931 x and y chromaticity of the green primary.
+        pub green: Option<(f32, f32)>,
+
+        /// The CIE 1931 x and y chromaticity of the blue primary.
+        pub blue: Option<(f32, f32)>,
+
+        /// The CIE 1931 x and y chromaticity of the white point.
+        pub white_point: Option<(f32, f32)>,
+
+        /// The maximum luminance, in cd/m².
+        pub luminance_max: Option<f32>,
+
+        /// The minimum luminance, in cd/m².
+        pub luminance_min: Option<f32>,
+    }
 }
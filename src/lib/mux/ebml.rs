@@ -0,0 +1,121 @@
+//! Encoding of EBML elements, for the pure-Rust muxing backend.
+//!
+//! Elements are assembled in memory and then written out whole, apart from the few whose size or content is only
+//! known later, which the backend writes with a fixed-length size so that it can fill them in in place.
+
+use crate::ebml::ElementId;
+
+/// The size of an element whose end is not known, such as a live segment or cluster, as an 8-byte vint.
+pub(super) const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+/// Length of the size field of elements that are filled in later, such as the segment and clusters.
+pub(super) const FIXED_SIZE_LEN: usize = 8;
+
+/// Appends the element ID `id`, which includes its length marker.
+pub(super) fn id(buf: &mut Vec<u8>, id: ElementId) {
+    let len = (id.0.max(1).ilog2() / 8 + 1) as usize;
+    buf.extend_from_slice(&id.0.to_be_bytes()[4 - len..]);
+}
+
+/// Returns the shortest length a vint holding `value` can have. All ones is reserved for unknown sizes, so it
+/// doesn't count.
+pub(super) fn size_len(value: u64) -> usize {
+    (1..FIXED_SIZE_LEN).find(|&len| value < (1 << (7 * len)) - 1).unwrap_or(FIXED_SIZE_LEN)
+}
+
+/// Appends `value` as a vint of exactly `len` bytes, which must be enough to hold it.
+pub(super) fn size_with_len(buf: &mut Vec<u8>, value: u64, len: usize) {
+    debug_assert!(len >= size_len(value) && len <= FIXED_SIZE_LEN);
+    let marked = value | 1 << (7 * len);
+    buf.extend_from_slice(&marked.to_be_bytes()[8 - len..]);
+}
+
+/// Appends the header of an element, with the shortest size field that fits.
+pub(super) fn header(buf: &mut Vec<u8>, element_id: ElementId, size: u64) {
+    id(buf, element_id);
+    size_with_len(buf, size, size_len(size));
+}
+
+pub(super) fn binary(buf: &mut Vec<u8>, element_id: ElementId, data: &[u8]) {
+    header(buf, element_id, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+pub(super) fn uint(buf: &mut Vec<u8>, element_id: ElementId, value: u64) {
+    let len = (value.max(1).ilog2() / 8 + 1) as usize;
+    binary(buf, element_id, &value.to_be_bytes()[8 - len..]);
+}
+
+pub(super) fn int(buf: &mut Vec<u8>, element_id: ElementId, value: i64) {
+    // The shortest two's complement form whose sign extension gives back `value`
+    let len = (1..8).find(|&len| (value << (64 - 8 * len)) >> (64 - 8 * len) == value).unwrap_or(8);
+    binary(buf, element_id, &value.to_be_bytes()[8 - len..]);
+}
+
+/// Appends a float element. libwebm writes all floats in their 4-byte form, and so does this.
+pub(super) fn float(buf: &mut Vec<u8>, element_id: ElementId, value: f32) {
+    binary(buf, element_id, &value.to_be_bytes());
+}
+
+pub(super) fn string(buf: &mut Vec<u8>, element_id: ElementId, value: &str) {
+    binary(buf, element_id, value.as_bytes());
+}
+
+/// Appends a master element, whose children are appended by `children`.
+pub(super) fn master(buf: &mut Vec<u8>, element_id: ElementId, children: impl FnOnce(&mut Vec<u8>)) {
+    let mut payload = Vec::new();
+    children(&mut payload);
+    binary(buf, element_id, &payload);
+}
+
+/// Appends a `Void` element that is `total_len` bytes long including its header, which must be at least 2.
+pub(super) fn void(buf: &mut Vec<u8>, total_len: usize) {
+    debug_assert!(total_len >= 2);
+    let size_len = (1..=FIXED_SIZE_LEN)
+        .find(|&len| size_len((total_len - 1 - len) as u64) <= len)
+        .unwrap_or(FIXED_SIZE_LEN);
+    let size = total_len - 1 - size_len;
+
+    id(buf, ElementId::VOID);
+    size_with_len(buf, size as u64, size_len);
+    buf.resize(buf.len() + size, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(f: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+        let mut buf = Vec::new();
+        f(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(encode(|buf| uint(buf, ElementId::TRACK_NUMBER, 0)), [0xD7, 0x81, 0x00]);
+        let timecode_scale = [0x2A, 0xD7, 0xB1, 0x83, 0x0F, 0x42, 0x40];
+        assert_eq!(encode(|buf| uint(buf, ElementId::TIMECODE_SCALE, 1_000_000)), timecode_scale);
+        assert_eq!(encode(|buf| int(buf, ElementId::REFERENCE_BLOCK, -40)), [0xFB, 0x81, 0xD8]);
+        assert_eq!(encode(|buf| int(buf, ElementId::REFERENCE_BLOCK, 128)), [0xFB, 0x82, 0x00, 0x80]);
+        assert_eq!(encode(|buf| int(buf, ElementId::DISCARD_PADDING, -129)), [0x75, 0xA2, 0x82, 0xFF, 0x7F]);
+        assert_eq!(encode(|buf| int(buf, ElementId::DISCARD_PADDING, i64::MIN)).len(), 3 + 8);
+    }
+
+    #[test]
+    fn sizes() {
+        // 127 is all ones in one byte, which means "unknown", so it needs two
+        assert_eq!(encode(|buf| header(buf, ElementId::VOID, 126)), [0xEC, 0xFE]);
+        assert_eq!(encode(|buf| header(buf, ElementId::VOID, 127)), [0xEC, 0x40, 0x7F]);
+        assert_eq!(encode(|buf| size_with_len(buf, 5, FIXED_SIZE_LEN)), [1, 0, 0, 0, 0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn voids() {
+        for total_len in [2, 3, 128, 129, 130, 16_385, 16_386] {
+            let void = encode(|buf| void(buf, total_len));
+            assert_eq!(void.len(), total_len, "void of {total_len} bytes");
+            assert_eq!(void[0], 0xEC);
+        }
+    }
+}
@@ -0,0 +1,590 @@
+//! The muxing backend that drives libwebm's `mkvmuxer` through `webm-sys`.
+
+use std::ffi::{c_void, CString};
+use std::io::{Cursor, Seek, Write};
+use std::marker::PhantomPinned;
+use std::pin::Pin;
+use std::ptr::NonNull;
+
+use crate::ebml::{ElementId, Reader};
+use crate::ffi;
+use crate::ffi::mux::{ResultCode, WriterElementStartNotifyFn, WriterGetPosFn, WriterSetPosFn};
+
+use super::{ColorMetadata, DocType, Error, MemorySink, TrackNum, Writer};
+
+/// RAII semantics for an FFI writer. This is simpler than implementing `Drop` on [`MkvWriter`], which
+/// prevents destructuring.
+//
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl Send for OwnedWriterPtr {}
+
+struct OwnedWriterPtr {
+    writer: ffi::mux::WriterNonNullPtr,
+}
+
+impl OwnedWriterPtr {
+    /// ## Safety
+    /// `writer` must be a valid, non-dangling pointer to an FFI writer created with [`ffi::mux::new_writer`].
+    /// After construction, `writer` must not be used by the caller, except via [`Self::as_ptr`].
+    /// The latter also must not be passed to [`ffi::mux::delete_writer`].
+    unsafe fn new(writer: ffi::mux::WriterNonNullPtr) -> Self {
+        Self { writer }
+    }
+
+    fn as_ptr(&self) -> ffi::mux::WriterMutPtr {
+        self.writer.as_ptr()
+    }
+}
+
+impl Drop for OwnedWriterPtr {
+    fn drop(&mut self) {
+        // SAFETY: We are assumed to be the only one allowed to delete this writer (per the requirements of [`Self::new`]).
+        unsafe {
+            ffi::mux::delete_writer(self.writer.as_ptr());
+        }
+    }
+}
+
+/// A libwebm `IMkvWriter` that forwards to the write destination `T`, see [`Writer`](super::Writer).
+pub(super) struct MkvWriter<T>
+where
+    T: Write,
+{
+    writer_data: Pin<Box<MuxWriterData<T>>>,
+    mkv_writer: OwnedWriterPtr,
+}
+
+struct MuxWriterData<T> {
+    dest: T,
+
+    /// Used for tracking position when using a non-Seek write destination
+    bytes_written: u64,
+
+    /// The `DocType` to write instead of libwebm's, along with the EBML header written so far, which is held back
+    /// until it is complete, see [`MkvWriter::set_doc_type`]
+    held_header: Option<(DocType, Vec<u8>)>,
+
+    /// How many bytes replacing the `DocType` added, which libwebm doesn't know about
+    position_shift: u64,
+    _marker: PhantomPinned,
+}
+
+impl<T> MuxWriterData<T> {
+    /// Converts a position in the write destination to the one libwebm expects.
+    fn libwebm_position(&self, position: u64) -> u64 {
+        let held_len = self.held_header.as_ref().map_or(0, |(_, header)| header.len() as u64);
+        (position + held_len).saturating_sub(self.position_shift)
+    }
+}
+
+impl<T: Write> MuxWriterData<T> {
+    fn write(&mut self, buf: &[u8]) -> bool {
+        if let Some((doc_type, header)) = &mut self.held_header {
+            header.extend_from_slice(buf);
+            let Some(rewritten) = replace_doc_type(header, *doc_type) else {
+                return true;
+            };
+
+            let Some(shift) = rewritten.len().checked_sub(header.len()) else {
+                return false;
+            };
+            self.position_shift = shift as u64;
+            self.held_header = None;
+            return self.write_through(&rewritten);
+        }
+
+        self.write_through(buf)
+    }
+
+    fn write_through(&mut self, buf: &[u8]) -> bool {
+        let result = self.dest.write(buf);
+        if let Ok(num_bytes) = result {
+            // Guard against a future universe where sizeof(usize) > sizeof(u64)
+            let num_bytes_u64: u64 = num_bytes.try_into().unwrap();
+
+            self.bytes_written += num_bytes_u64;
+
+            // Partial writes are considered failure
+            num_bytes == buf.len()
+        } else {
+            false
+        }
+    }
+}
+
+/// Returns `data` with the `DocType` in the EBML header at its start replaced by `doc_type`, or `None` if the header
+/// isn't complete yet. Anything that comes after the header is kept.
+///
+/// A header that can't be rewritten, which libwebm never writes, is returned as is.
+fn replace_doc_type(data: &[u8], doc_type: DocType) -> Option<Vec<u8>> {
+    let mut reader = Reader::new(Cursor::new(data));
+    let mut top_level = reader.top_level();
+    let header = reader.next_child(&mut top_level).ok()??;
+    let header_end = usize::try_from(header.end()?).ok().filter(|&end| end <= data.len())?;
+
+    let mut doc_type_element = None;
+    if header.id == ElementId::EBML {
+        let mut children = reader.children(&header).ok()?;
+        while let Ok(Some(child)) = reader.next_child(&mut children) {
+            if child.id == ElementId::DOC_TYPE {
+                doc_type_element = Some(child);
+            }
+        }
+    }
+
+    // libwebm writes every size here in a single byte
+    let size_byte = |size: usize| u8::try_from(size).ok().filter(|&size| size < 0x7F).map(|size| 0x80 | size);
+    let rewritten = doc_type_element.and_then(|element| {
+        let (start, end) = (element.position as usize, usize::try_from(element.end()?).ok()?);
+        let mut payload = data[header.data_position as usize..start].to_vec();
+        payload.extend_from_slice(&[0x42, 0x82, size_byte(doc_type.as_str().len())?]);
+        payload.extend_from_slice(doc_type.as_str().as_bytes());
+        payload.extend_from_slice(&data[end..header_end]);
+
+        let mut rewritten = data[..4].to_vec();
+        rewritten.push(size_byte(payload.len())?);
+        rewritten.extend_from_slice(&payload);
+        rewritten.extend_from_slice(&data[header_end..]);
+        Some(rewritten)
+    });
+    Some(rewritten.unwrap_or_else(|| data.to_vec()))
+}
+
+impl<T> MkvWriter<T>
+where
+    T: Write,
+{
+    pub(super) fn new_non_seek(dest: T) -> Self {
+        extern "C" fn get_pos_fn<T>(data: *mut c_void) -> u64 {
+            // The user-supplied writer does not track its own position.
+            // Use our own based on how much has been written
+            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
+            data.libwebm_position(data.bytes_written)
+        }
+
+        Self::make_writer(dest, get_pos_fn::<T>, None, None)
+    }
+
+    pub(super) fn into_inner(self) -> T {
+        let Self { writer_data, .. } = self;
+        unsafe { Pin::into_inner_unchecked(writer_data).dest }
+    }
+
+    pub(super) fn get_ref(&self) -> &T {
+        &self.writer_data.dest
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut T {
+        // SAFETY: Only the `MuxWriterData` as a whole must stay in place, as libwebm holds a pointer to it.
+        // Its destination may be moved out or replaced freely.
+        unsafe { &mut self.writer_data.as_mut().get_unchecked_mut().dest }
+    }
+
+    pub(super) fn bytes_written(&self) -> u64 {
+        self.writer_data.bytes_written
+    }
+
+    /// Makes the EBML header written next carry `doc_type`. libwebm has no setter for it, and only writes `matroska`
+    /// for tracks with codecs outside of WebM, so the header is held back and rewritten once complete. Positions
+    /// reported to libwebm from then on leave out the bytes this adds.
+    pub(super) fn set_doc_type(&mut self, doc_type: DocType) {
+        // SAFETY: Only the `MuxWriterData` as a whole must stay in place, as libwebm holds a pointer to it
+        let writer_data = unsafe { self.writer_data.as_mut().get_unchecked_mut() };
+        writer_data.held_header = Some((doc_type, Vec::new()));
+    }
+
+    fn make_writer(
+        dest: T,
+        get_pos_fn: WriterGetPosFn,
+        set_pos_fn: Option<WriterSetPosFn>,
+        element_start_notify_fn: Option<WriterElementStartNotifyFn>,
+    ) -> Self {
+        extern "C" fn write_fn<T>(data: *mut c_void, buf: *const c_void, len: usize) -> bool
+        where
+            T: Write,
+        {
+            if buf.is_null() {
+                return false;
+            }
+            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
+            let buf = unsafe { std::slice::from_raw_parts(buf.cast::<u8>(), len) };
+            data.write(buf)
+        }
+
+        let mut writer_data = Box::pin(MuxWriterData {
+            dest,
+            bytes_written: 0,
+            held_header: None,
+            position_shift: 0,
+            _marker: PhantomPinned,
+        });
+        let mkv_writer = unsafe {
+            ffi::mux::new_writer(
+                Some(write_fn::<T>),
+                Some(get_pos_fn),
+                set_pos_fn,
+                element_start_notify_fn,
+                std::ptr::from_mut(writer_data.as_mut().get_unchecked_mut()).cast(),
+            )
+        };
+        assert!(!mkv_writer.is_null());
+
+        MkvWriter {
+            writer_data,
+            mkv_writer: unsafe { OwnedWriterPtr::new(NonNull::new(mkv_writer).unwrap()) },
+        }
+    }
+}
+
+impl<T> MkvWriter<T>
+where
+    T: Write + Seek,
+{
+    pub(super) fn new(dest: T) -> Self {
+        Self::make_seekable_writer(dest, None)
+    }
+
+    fn make_seekable_writer(dest: T, element_start_notify_fn: Option<WriterElementStartNotifyFn>) -> Self {
+        use std::io::SeekFrom;
+
+        extern "C" fn get_pos_fn<T>(data: *mut c_void) -> u64
+        where
+            T: Write + Seek,
+        {
+            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
+            let position = data.dest.stream_position().unwrap();
+            data.libwebm_position(position)
+        }
+        extern "C" fn set_pos_fn<T>(data: *mut c_void, pos: u64) -> bool
+        where
+            T: Write + Seek,
+        {
+            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
+            data.dest.seek(SeekFrom::Start(pos + data.position_shift)).is_ok()
+        }
+
+        Self::make_writer(dest, get_pos_fn::<T>, Some(set_pos_fn::<T>), element_start_notify_fn)
+    }
+}
+
+impl MkvWriter<MemorySink> {
+    pub(super) fn new_in_memory() -> Self {
+        extern "C" fn element_start_notify_fn(data: *mut c_void, element_id: u64, position: i64) {
+            let data = unsafe { data.cast::<MuxWriterData<MemorySink>>().as_mut().unwrap() };
+            if let Ok(position) = u64::try_from(position) {
+                data.dest.element_started(element_id, position + data.position_shift);
+            }
+        }
+
+        Self::make_seekable_writer(MemorySink::new(), Some(element_start_notify_fn))
+    }
+}
+
+/// RAII semantics for an FFI segment. This is simpler than implementing `Drop` on [`MkvSegment`], which
+/// prevents destructuring.
+struct OwnedSegmentPtr {
+    segment: ffi::mux::SegmentNonNullPtr,
+}
+
+impl OwnedSegmentPtr {
+    /// ## Safety
+    /// `segment` must be a valid, non-dangling pointer to an FFI segment created with [`ffi::mux::new_segment`].
+    /// After construction, `segment` must not be used by the caller, except via [`Self::as_ptr`].
+    /// The latter also must not be passed to [`ffi::mux::delete_segment`].
+    unsafe fn new(segment: ffi::mux::SegmentNonNullPtr) -> Self {
+        Self { segment }
+    }
+
+    fn as_ptr(&self) -> ffi::mux::SegmentMutPtr {
+        self.segment.as_ptr()
+    }
+}
+
+impl Drop for OwnedSegmentPtr {
+    fn drop(&mut self) {
+        // SAFETY: We are assumed to be the only one allowed to delete this segment (per the requirements of [`Self::new`]).
+        unsafe {
+            ffi::mux::delete_segment(self.segment.as_ptr());
+        }
+    }
+}
+
+/// A libwebm `mkvmuxer::Segment`, along with the writer it writes to.
+pub(super) struct MkvSegment<W: Write> {
+    // Declared before `writer`, so that it is dropped while the writer is still around
+    segment: OwnedSegmentPtr,
+    writer: Writer<W>,
+}
+
+// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
+// Thus, safety is only conditional on the write destination `W`, hence the `Send` bound on it.
+//
+// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
+unsafe impl<W: Write + Send> Send for MkvSegment<W> {}
+
+impl<W: Write> MkvSegment<W> {
+    pub(super) fn new(mut writer: Writer<W>) -> Result<Self, Error> {
+        let segment = unsafe { ffi::mux::new_segment() };
+        let segment = NonNull::new(segment)
+            .map(|ptr| unsafe { OwnedSegmentPtr::new(ptr) })
+            .ok_or(Error::Unknown)?;
+        let mkv_writer = writer.mkv_writer_mut().mkv_writer.as_ptr();
+        let result = unsafe { ffi::mux::initialize_segment(segment.as_ptr(), mkv_writer) };
+
+        check(result).map(|()| Self { segment, writer })
+    }
+
+    pub(super) fn writer(&self) -> &Writer<W> {
+        &self.writer
+    }
+
+    pub(super) fn writer_mut(&mut self) -> &mut Writer<W> {
+        &mut self.writer
+    }
+
+    pub(super) fn set_doc_type(&mut self, doc_type: DocType) {
+        // libwebm writes `webm` unless a track has a codec outside of WebM
+        if doc_type == DocType::Matroska {
+            self.writer.mkv_writer_mut().set_doc_type(doc_type);
+        }
+    }
+
+    pub(super) fn set_writing_app(&mut self, app_name: &str) -> Result<(), Error> {
+        let name = CString::new(app_name).map_err(|_| Error::BadParam)?;
+        unsafe {
+            ffi::mux::mux_set_writing_app(self.segment.as_ptr(), name.as_ptr());
+        }
+        Ok(())
+    }
+
+    pub(super) fn add_simple_tag(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let name = CString::new(name).map_err(|_| Error::BadParam)?;
+        let value = CString::new(value).map_err(|_| Error::BadParam)?;
+        check(unsafe { ffi::mux::segment_add_simple_tag(self.segment.as_ptr(), name.as_ptr(), value.as_ptr()) })
+    }
+
+    pub(super) fn set_max_cluster_duration(&mut self, duration_ns: u64) -> Result<(), Error> {
+        check(unsafe { ffi::mux::mux_set_max_cluster_duration(self.segment.as_ptr(), duration_ns) })
+    }
+
+    pub(super) fn set_max_cluster_size(&mut self, size: u64) -> Result<(), Error> {
+        check(unsafe { ffi::mux::mux_set_max_cluster_size(self.segment.as_ptr(), size) })
+    }
+
+    pub(super) fn set_output_cues(&mut self, enabled: bool) -> Result<(), Error> {
+        check(unsafe { ffi::mux::mux_set_output_cues(self.segment.as_ptr(), enabled) })
+    }
+
+    pub(super) fn add_video_track(
+        &mut self,
+        width: i32,
+        height: i32,
+        number: i32,
+        codec_id: &str,
+    ) -> Result<TrackNum, Error> {
+        let codec_id = CString::new(codec_id).map_err(|_| Error::BadParam)?;
+        let mut track_num_out: TrackNum = 0;
+        let result = unsafe {
+            ffi::mux::segment_add_video_track_with_codec_id(
+                self.segment.as_ptr(),
+                width,
+                height,
+                number,
+                codec_id.as_ptr(),
+                &mut track_num_out,
+            )
+        };
+        check(result).map(|()| track_num_out)
+    }
+
+    pub(super) fn add_audio_track(
+        &mut self,
+        sample_rate: i32,
+        channels: i32,
+        number: i32,
+        codec_id: &str,
+    ) -> Result<TrackNum, Error> {
+        let codec_id = CString::new(codec_id).map_err(|_| Error::BadParam)?;
+        let mut track_num_out: TrackNum = 0;
+        let result = unsafe {
+            ffi::mux::segment_add_audio_track_with_codec_id(
+                self.segment.as_ptr(),
+                sample_rate,
+                channels,
+                number,
+                codec_id.as_ptr(),
+                &mut track_num_out,
+            )
+        };
+        check(result).map(|()| track_num_out)
+    }
+
+    pub(super) fn set_audio_bit_depth(&mut self, track_num: TrackNum, bit_depth: u64) -> Result<(), Error> {
+        check(unsafe { ffi::mux::mux_set_audio_bit_depth(self.segment.as_ptr(), track_num, bit_depth) })
+    }
+
+    pub(super) fn set_codec_private(&mut self, track_num: TrackNum, data: &[u8]) -> Result<(), Error> {
+        let len: i32 = data.len().try_into().map_err(|_| Error::BadParam)?;
+        check(unsafe { ffi::mux::segment_set_codec_private(self.segment.as_ptr(), track_num, data.as_ptr(), len) })
+    }
+
+    pub(super) fn set_codec_delay(
+        &mut self,
+        track_num: TrackNum,
+        codec_delay_ns: u64,
+        seek_pre_roll_ns: u64,
+    ) -> Result<(), Error> {
+        let segment = self.segment.as_ptr();
+        check(unsafe { ffi::mux::mux_set_codec_delay(segment, track_num, codec_delay_ns, seek_pre_roll_ns) })
+    }
+
+    pub(super) fn set_color(
+        &mut self,
+        track_num: TrackNum,
+        bit_depth: u8,
+        chroma_horizontal: u8,
+        chroma_vertical: u8,
+        color_range: u8,
+    ) -> Result<(), Error> {
+        let result = unsafe {
+            ffi::mux::mux_set_color(
+                self.segment.as_ptr(),
+                track_num,
+                bit_depth,
+                chroma_horizontal,
+                chroma_vertical,
+                color_range,
+            )
+        };
+        check(result)
+    }
+
+    pub(super) fn set_color_metadata(&mut self, track_num: TrackNum, metadata: &ColorMetadata) -> Result<(), Error> {
+        let (mastering, chromaticity) = match metadata.mastering {
+            Some(mastering) => {
+                let mut chromaticity = [-1.0; 8];
+                for (index, value) in [mastering.red, mastering.green, mastering.blue, mastering.white_point]
+                    .into_iter()
+                    .enumerate()
+                {
+                    if let Some((x, y)) = value {
+                        chromaticity[2 * index] = x;
+                        chromaticity[2 * index + 1] = y;
+                    }
+                }
+                (Some(mastering), chromaticity)
+            },
+            None => (None, [-1.0; 8]),
+        };
+
+        let metadata = ffi::mux::ColorMetadata {
+            matrix_coefficients: metadata.matrix_coefficients.unwrap_or(u64::MAX),
+            transfer_characteristics: metadata.transfer_characteristics.unwrap_or(u64::MAX),
+            primaries: metadata.primaries.unwrap_or(u64::MAX),
+            chroma_siting_horiz: metadata.chroma_siting.0.unwrap_or(u64::MAX),
+            chroma_siting_vert: metadata.chroma_siting.1.unwrap_or(u64::MAX),
+            max_cll: metadata.max_cll.unwrap_or(u64::MAX),
+            max_fall: metadata.max_fall.unwrap_or(u64::MAX),
+            has_mastering_metadata: mastering.is_some(),
+            mastering_chromaticity: chromaticity,
+            mastering_luminance_max: mastering.and_then(|mastering| mastering.luminance_max).unwrap_or(-1.0),
+            mastering_luminance_min: mastering.and_then(|mastering| mastering.luminance_min).unwrap_or(-1.0),
+        };
+        check(unsafe { ffi::mux::mux_set_color_metadata(self.segment.as_ptr(), track_num, &metadata) })
+    }
+
+    pub(super) fn set_display_size(&mut self, track_num: TrackNum, width: u32, height: u32) -> Result<(), Error> {
+        let segment = self.segment.as_ptr();
+        check(unsafe { ffi::mux::mux_set_display_size(segment, track_num, width.into(), height.into()) })
+    }
+
+    pub(super) fn set_frame_rate(&mut self, track_num: TrackNum, frame_rate: f64) -> Result<(), Error> {
+        check(unsafe { ffi::mux::mux_set_frame_rate(self.segment.as_ptr(), track_num, frame_rate) })
+    }
+
+    pub(super) fn set_track_name(&mut self, track_num: TrackNum, name: &str) -> Result<(), Error> {
+        let name = CString::new(name).map_err(|_| Error::BadParam)?;
+        check(unsafe { ffi::mux::mux_set_track_name(self.segment.as_ptr(), track_num, name.as_ptr()) })
+    }
+
+    pub(super) fn set_track_language(&mut self, track_num: TrackNum, language: &str) -> Result<(), Error> {
+        let language = CString::new(language).map_err(|_| Error::BadParam)?;
+        check(unsafe { ffi::mux::mux_set_track_language(self.segment.as_ptr(), track_num, language.as_ptr()) })
+    }
+
+    pub(super) fn add_frame(
+        &mut self,
+        track_num: TrackNum,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        // Blocks with a `DiscardPadding` can't be written as `SimpleBlock`s, so only go that way when needed
+        let result = unsafe {
+            if discard_padding_ns == 0 {
+                ffi::mux::segment_add_frame(
+                    self.segment.as_ptr(),
+                    track_num,
+                    data.as_ptr(),
+                    data.len(),
+                    timestamp_ns,
+                    keyframe,
+                )
+            } else {
+                ffi::mux::segment_add_frame_with_discard_padding(
+                    self.segment.as_ptr(),
+                    track_num,
+                    data.as_ptr(),
+                    data.len(),
+                    timestamp_ns,
+                    keyframe,
+                    discard_padding_ns,
+                )
+            }
+        };
+        check(result)
+    }
+
+    pub(super) fn finalize(self, duration: Option<u64>) -> Result<Writer<W>, (Error, Writer<W>)> {
+        let Self { segment, writer } = self;
+        let result = unsafe { ffi::mux::finalize_segment(segment.as_ptr(), duration.unwrap_or(0)) };
+
+        match check(result) {
+            Ok(()) => Ok(writer),
+            Err(err) => Err((err, writer)),
+        }
+    }
+}
+
+fn check(result: ResultCode) -> Result<(), Error> {
+    match result {
+        ResultCode::Ok => Ok(()),
+        ResultCode::BadParam => Err(Error::BadParam),
+        ResultCode::UnknownLibwebmError => Err(Error::Unknown),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The EBML header libwebm writes, followed by the start of a `Segment`.
+    const HEADER: [u8; 44] = [
+        0x1A, 0x45, 0xDF, 0xA3, 0x9F, 0x42, 0x86, 0x81, 0x01, 0x42, 0xF7, 0x81, 0x01, 0x42, 0xF2, 0x81, 0x04, 0x42,
+        0xF3, 0x81, 0x08, 0x42, 0x82, 0x84, b'w', b'e', b'b', b'm', 0x42, 0x87, 0x81, 0x04, 0x42, 0x85, 0x81, 0x02,
+        0x18, 0x53, 0x80, 0x67, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn replaces_doc_type() {
+        assert_eq!(replace_doc_type(&HEADER[..20], DocType::Matroska), None);
+
+        let expected = [&HEADER[..4], &[0xA3], &HEADER[5..21], b"\x42\x82\x88matroska", &HEADER[28..]].concat();
+        assert_eq!(replace_doc_type(&HEADER, DocType::Matroska), Some(expected));
+
+        // libwebm already wrote what is wanted
+        assert_eq!(replace_doc_type(&HEADER, DocType::WebM), Some(HEADER.to_vec()));
+    }
+}
@@ -0,0 +1,1184 @@
+//! The pure-Rust muxing backend, selected with the `rust-mux` feature.
+//!
+//! This writes the same layout as libwebm's `mkvmuxer`: the EBML header, then a segment with a `SeekHead`, `Info`,
+//! `Tracks` and `Tags`, followed by clusters and finally `Cues`. On seekable destinations, the segment size, cluster
+//! sizes, `Duration` and `SeekHead` are filled in once known; otherwise, unknown sizes are left in place, as in a live
+//! stream.
+
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::ebml::ElementId;
+
+use super::{ebml, ColorMetadata, DocType, Error, MemorySink, MasteringMetadata, TrackNum, Writer};
+
+/// The only timecode scale this backend writes, as libwebm does by default: timestamps are in milliseconds.
+const TIMECODE_SCALE: u64 = 1_000_000;
+
+/// Track numbers are limited to what fits into a single byte in block headers, as in libwebm.
+const MAX_TRACK_NUMBER: u64 = 126;
+
+/// The longest a cluster can span, as block timestamps are 16-bit offsets from the cluster's.
+const MAX_CLUSTER_SPAN_NS: u64 = i16::MAX as u64 * TIMECODE_SCALE;
+
+/// The `SeekHead` has room for this many entries: `Info`, `Tracks`, `Tags` and `Cues`, plus one spare.
+const SEEK_ENTRIES: usize = 5;
+
+/// The largest a `Seek` entry can be: its header, plus a 4-byte `SeekID` and an 8-byte `SeekPosition` with theirs.
+const SEEK_ENTRY_MAX_LEN: usize = 3 + (3 + 4) + (3 + 8);
+
+/// Space reserved for the `SeekHead` at the start of the segment, including its own header.
+const SEEK_HEAD_RESERVED_LEN: usize = 4 + 1 + SEEK_ENTRIES * SEEK_ENTRY_MAX_LEN;
+
+const MUXING_APP: &str = concat!("rust-webm-", env!("CARGO_PKG_VERSION"));
+
+/// Writes to the destination `T` and keeps track of the position, for [`Writer`].
+pub(super) struct MkvWriter<T>
+where
+    T: Write,
+{
+    dest: T,
+    bytes_written: u64,
+    position: u64,
+
+    /// Moves to an absolute position in seekable destinations, `None` for others
+    seek_fn: Option<fn(&mut T, u64) -> std::io::Result<u64>>,
+
+    /// Called right before the ID of an element is written, with the ID and position
+    element_start_notify_fn: Option<fn(&mut T, u64, u64)>,
+}
+
+impl<T> MkvWriter<T>
+where
+    T: Write,
+{
+    pub(super) fn new_non_seek(dest: T) -> Self {
+        Self {
+            dest,
+            bytes_written: 0,
+            position: 0,
+            seek_fn: None,
+            element_start_notify_fn: None,
+        }
+    }
+
+    pub(super) fn into_inner(self) -> T {
+        self.dest
+    }
+
+    pub(super) fn get_ref(&self) -> &T {
+        &self.dest
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut T {
+        &mut self.dest
+    }
+
+    pub(super) fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn is_seekable(&self) -> bool {
+        self.seek_fn.is_some()
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.dest.write_all(data).map_err(Error::Io)?;
+        self.bytes_written += data.len() as u64;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+
+    /// Writes `data` at `position`, and then goes back to where writing left off.
+    fn write_at(&mut self, position: u64, data: &[u8]) -> Result<(), Error> {
+        let seek = self.seek_fn.ok_or(Error::Unknown)?;
+        let end = self.position;
+        seek(&mut self.dest, position).map_err(Error::Io)?;
+        self.position = position;
+        self.write(data)?;
+        seek(&mut self.dest, end).map_err(Error::Io)?;
+        self.position = end;
+        Ok(())
+    }
+
+    fn element_start(&mut self, element_id: ElementId) {
+        if let Some(notify) = self.element_start_notify_fn {
+            notify(&mut self.dest, element_id.0.into(), self.position);
+        }
+    }
+}
+
+impl<T> MkvWriter<T>
+where
+    T: Write + Seek,
+{
+    pub(super) fn new(mut dest: T) -> Self {
+        let position = dest.stream_position().unwrap_or(0);
+        Self {
+            dest,
+            bytes_written: 0,
+            position,
+            seek_fn: Some(|dest, position| dest.seek(SeekFrom::Start(position))),
+            element_start_notify_fn: None,
+        }
+    }
+}
+
+impl MkvWriter<MemorySink> {
+    pub(super) fn new_in_memory() -> Self {
+        Self {
+            element_start_notify_fn: Some(MemorySink::element_started),
+            ..Self::new(MemorySink::new())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TrackKind {
+    Video {
+        width: u64,
+        height: u64,
+        display_size: Option<(u64, u64)>,
+        frame_rate: Option<f64>,
+        color: Option<Color>,
+        color_metadata: Option<Box<ColorMetadata>>,
+    },
+    Audio {
+        sample_rate: f32,
+        channels: u64,
+        bit_depth: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    bits_per_channel: u8,
+    chroma_subsampling_horz: u8,
+    chroma_subsampling_vert: u8,
+    range: u8,
+}
+
+#[derive(Debug, Clone)]
+struct TrackEntry {
+    number: TrackNum,
+    uid: u64,
+    kind: TrackKind,
+    codec_id: String,
+    codec_private: Option<Vec<u8>>,
+    name: Option<String>,
+    language: Option<String>,
+    codec_delay_ns: u64,
+    seek_pre_roll_ns: u64,
+
+    /// Timestamp of the last block written on this track, in timecode ticks, for `ReferenceBlock`s
+    last_timecode: Option<u64>,
+}
+
+impl TrackEntry {
+    fn is_video(&self) -> bool {
+        matches!(self.kind, TrackKind::Video { .. })
+    }
+
+    fn write(&self, buf: &mut Vec<u8>) {
+        ebml::master(buf, ElementId::TRACK_ENTRY, |buf| {
+            ebml::uint(buf, ElementId::TRACK_NUMBER, self.number);
+            ebml::uint(buf, ElementId::TRACK_UID, self.uid);
+            ebml::uint(buf, ElementId::TRACK_TYPE, if self.is_video() { 1 } else { 2 });
+            ebml::string(buf, ElementId::CODEC_ID, &self.codec_id);
+            if let Some(codec_private) = &self.codec_private {
+                ebml::binary(buf, ElementId::CODEC_PRIVATE, codec_private);
+            }
+            if let Some(language) = &self.language {
+                ebml::string(buf, ElementId::LANGUAGE, language);
+            }
+            if let Some(name) = &self.name {
+                ebml::string(buf, ElementId::NAME, name);
+            }
+            if self.codec_delay_ns > 0 {
+                ebml::uint(buf, ElementId::CODEC_DELAY, self.codec_delay_ns);
+            }
+            if self.seek_pre_roll_ns > 0 {
+                ebml::uint(buf, ElementId::SEEK_PRE_ROLL, self.seek_pre_roll_ns);
+            }
+
+            match self.kind {
+                TrackKind::Video {
+                    width,
+                    height,
+                    display_size,
+                    frame_rate,
+                    color,
+                    ref color_metadata,
+                } => ebml::master(buf, ElementId::VIDEO, |buf| {
+                    ebml::uint(buf, ElementId::PIXEL_WIDTH, width);
+                    ebml::uint(buf, ElementId::PIXEL_HEIGHT, height);
+                    if let Some((display_width, display_height)) = display_size {
+                        ebml::uint(buf, ElementId::DISPLAY_WIDTH, display_width);
+                        ebml::uint(buf, ElementId::DISPLAY_HEIGHT, display_height);
+                    }
+                    if let Some(frame_rate) = frame_rate {
+                        ebml::float(buf, ElementId::FRAME_RATE, frame_rate as f32);
+                    }
+                    if color.is_some() || color_metadata.is_some() {
+                        ebml::master(buf, ElementId::COLOUR, |buf| write_color(buf, color, color_metadata.as_deref()));
+                    }
+                }),
+                TrackKind::Audio {
+                    sample_rate,
+                    channels,
+                    bit_depth,
+                } => ebml::master(buf, ElementId::AUDIO, |buf| {
+                    ebml::float(buf, ElementId::SAMPLING_FREQUENCY, sample_rate);
+                    ebml::uint(buf, ElementId::CHANNELS, channels);
+                    if let Some(bit_depth) = bit_depth {
+                        ebml::uint(buf, ElementId::BIT_DEPTH, bit_depth);
+                    }
+                }),
+            }
+        });
+    }
+}
+
+/// Writes the children of a `Colour` element, in the order libwebm does.
+fn write_color(buf: &mut Vec<u8>, color: Option<Color>, metadata: Option<&ColorMetadata>) {
+    let optional_uint = |buf: &mut Vec<u8>, element_id, value: Option<u64>| {
+        if let Some(value) = value {
+            ebml::uint(buf, element_id, value);
+        }
+    };
+
+    optional_uint(buf, ElementId::MATRIX_COEFFICIENTS, metadata.and_then(|metadata| metadata.matrix_coefficients));
+    if let Some(color) = color {
+        ebml::uint(buf, ElementId::BITS_PER_CHANNEL, color.bits_per_channel.into());
+        ebml::uint(buf, ElementId::CHROMA_SUBSAMPLING_HORZ, color.chroma_subsampling_horz.into());
+        ebml::uint(buf, ElementId::CHROMA_SUBSAMPLING_VERT, color.chroma_subsampling_vert.into());
+    }
+    optional_uint(buf, ElementId::CHROMA_SITING_HORZ, metadata.and_then(|metadata| metadata.chroma_siting.0));
+    optional_uint(buf, ElementId::CHROMA_SITING_VERT, metadata.and_then(|metadata| metadata.chroma_siting.1));
+    if let Some(color) = color {
+        ebml::uint(buf, ElementId::RANGE, color.range.into());
+    }
+
+    let Some(metadata) = metadata else {
+        return;
+    };
+    optional_uint(buf, ElementId::TRANSFER_CHARACTERISTICS, metadata.transfer_characteristics);
+    optional_uint(buf, ElementId::PRIMARIES, metadata.primaries);
+    optional_uint(buf, ElementId::MAX_CLL, metadata.max_cll);
+    optional_uint(buf, ElementId::MAX_FALL, metadata.max_fall);
+    if let Some(mastering) = &metadata.mastering {
+        ebml::master(buf, ElementId::MASTERING_METADATA, |buf| write_mastering_metadata(buf, mastering));
+    }
+}
+
+fn write_mastering_metadata(buf: &mut Vec<u8>, mastering: &MasteringMetadata) {
+    if let Some(luminance_max) = mastering.luminance_max {
+        ebml::float(buf, ElementId::LUMINANCE_MAX, luminance_max);
+    }
+    if let Some(luminance_min) = mastering.luminance_min {
+        ebml::float(buf, ElementId::LUMINANCE_MIN, luminance_min);
+    }
+
+    let chromaticities = [
+        (mastering.red, ElementId::PRIMARY_R_CHROMATICITY_X, ElementId::PRIMARY_R_CHROMATICITY_Y),
+        (mastering.green, ElementId::PRIMARY_G_CHROMATICITY_X, ElementId::PRIMARY_G_CHROMATICITY_Y),
+        (mastering.blue, ElementId::PRIMARY_B_CHROMATICITY_X, ElementId::PRIMARY_B_CHROMATICITY_Y),
+        (mastering.white_point, ElementId::WHITE_POINT_CHROMATICITY_X, ElementId::WHITE_POINT_CHROMATICITY_Y),
+    ];
+    for (chromaticity, x_id, y_id) in chromaticities {
+        if let Some((x, y)) = chromaticity {
+            ebml::float(buf, x_id, x);
+            ebml::float(buf, y_id, y);
+        }
+    }
+}
+
+/// Where the parts of the segment header that are filled in on finalization are, once it has been written.
+#[derive(Debug, Clone, Copy)]
+struct SegmentLayout {
+    /// Position of the segment's size field
+    size_position: u64,
+
+    /// Position of the segment's payload, which all positions within the segment are relative to
+    data_position: u64,
+
+    /// Position of the `Duration` element, whose value is a 4-byte float
+    duration_position: u64,
+
+    /// Positions of the top-level elements to list in the `SeekHead`, relative to `data_position`
+    info_offset: u64,
+    tracks_offset: u64,
+    tags_offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Cluster {
+    /// Position of the cluster's ID
+    position: u64,
+
+    /// Position of the cluster's payload, right after its size field
+    data_position: u64,
+    timecode: u64,
+    block_count: u64,
+
+    /// Set until a keyframe of the cues track has been written to this cluster, and indexed
+    needs_cue_point: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CuePoint {
+    timecode: u64,
+    track: TrackNum,
+    cluster_offset: u64,
+    block_number: u64,
+}
+
+/// A frame held back to be written later, see [`MkvSegment::add_frame`].
+#[derive(Debug, Clone)]
+struct QueuedFrame {
+    track_num: TrackNum,
+    data: Vec<u8>,
+    timestamp_ns: u64,
+    keyframe: bool,
+    discard_padding_ns: i64,
+}
+
+/// A Matroska segment being written to a [`Writer`], mirroring what libwebm's `mkvmuxer::Segment` does.
+pub(super) struct MkvSegment<W: Write> {
+    writer: Writer<W>,
+    doc_type: DocType,
+    writing_app: Option<String>,
+    tags: Vec<(String, String)>,
+    tracks: Vec<TrackEntry>,
+    max_cluster_duration_ns: u64,
+    max_cluster_size: u64,
+    output_cues: bool,
+
+    /// Set once the headers have been written, which happens on the first frame
+    layout: Option<SegmentLayout>,
+    cluster: Option<Cluster>,
+    cue_points: Vec<CuePoint>,
+
+    /// Audio frames waiting for the next video frame, so that audio from the start of a video keyframe goes into
+    /// the cluster that keyframe starts. libwebm does the same. The queue is written out once it would fill a
+    /// cluster, so that it stays bounded if no video comes.
+    queued_frames: VecDeque<QueuedFrame>,
+    queued_bytes: u64,
+    last_timestamp_ns: u64,
+}
+
+impl<W: Write> MkvSegment<W> {
+    pub(super) fn new(writer: Writer<W>) -> Result<Self, Error> {
+        Ok(Self {
+            writer,
+            doc_type: DocType::WebM,
+            writing_app: None,
+            tags: Vec::new(),
+            tracks: Vec::new(),
+            max_cluster_duration_ns: 0,
+            max_cluster_size: 0,
+            output_cues: true,
+            layout: None,
+            cluster: None,
+            cue_points: Vec::new(),
+            queued_frames: VecDeque::new(),
+            queued_bytes: 0,
+            last_timestamp_ns: 0,
+        })
+    }
+
+    pub(super) fn writer(&self) -> &Writer<W> {
+        &self.writer
+    }
+
+    pub(super) fn writer_mut(&mut self) -> &mut Writer<W> {
+        &mut self.writer
+    }
+
+    pub(super) fn set_doc_type(&mut self, doc_type: DocType) {
+        self.doc_type = doc_type;
+    }
+
+    pub(super) fn set_writing_app(&mut self, app_name: &str) -> Result<(), Error> {
+        self.writing_app = Some(check_string(app_name)?.to_owned());
+        Ok(())
+    }
+
+    pub(super) fn add_simple_tag(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        self.tags.push((check_string(name)?.to_owned(), check_string(value)?.to_owned()));
+        Ok(())
+    }
+
+    pub(super) fn set_max_cluster_duration(&mut self, duration_ns: u64) -> Result<(), Error> {
+        self.max_cluster_duration_ns = duration_ns;
+        Ok(())
+    }
+
+    pub(super) fn set_max_cluster_size(&mut self, size: u64) -> Result<(), Error> {
+        self.max_cluster_size = size;
+        Ok(())
+    }
+
+    pub(super) fn set_output_cues(&mut self, enabled: bool) -> Result<(), Error> {
+        self.output_cues = enabled;
+        Ok(())
+    }
+
+    pub(super) fn add_video_track(
+        &mut self,
+        width: i32,
+        height: i32,
+        number: i32,
+        codec_id: &str,
+    ) -> Result<TrackNum, Error> {
+        let kind = TrackKind::Video {
+            width: width.try_into().map_err(|_| Error::BadParam)?,
+            height: height.try_into().map_err(|_| Error::BadParam)?,
+            display_size: None,
+            frame_rate: None,
+            color: None,
+            color_metadata: None,
+        };
+        self.add_track(number, codec_id, kind)
+    }
+
+    pub(super) fn add_audio_track(
+        &mut self,
+        sample_rate: i32,
+        channels: i32,
+        number: i32,
+        codec_id: &str,
+    ) -> Result<TrackNum, Error> {
+        let kind = TrackKind::Audio {
+            sample_rate: sample_rate as f32,
+            channels: channels.try_into().map_err(|_| Error::BadParam)?,
+            bit_depth: None,
+        };
+        self.add_track(number, codec_id, kind)
+    }
+
+    fn add_track(&mut self, number: i32, codec_id: &str, kind: TrackKind) -> Result<TrackNum, Error> {
+        let number = TrackNum::try_from(number).map_err(|_| Error::Unknown)?;
+        if number > MAX_TRACK_NUMBER || self.layout.is_some() {
+            return Err(Error::Unknown);
+        }
+
+        // Zero means "choose one", which is the lowest free number from the track count on
+        let number = match number {
+            0 => (self.tracks.len() as u64 + 1..=MAX_TRACK_NUMBER)
+                .find(|&number| self.track(number).is_none())
+                .ok_or(Error::Unknown)?,
+            number if self.track(number).is_some() => return Err(Error::Unknown),
+            number => number,
+        };
+
+        self.tracks.push(TrackEntry {
+            number,
+            uid: make_uid(number),
+            kind,
+            codec_id: check_string(codec_id)?.to_owned(),
+            codec_private: None,
+            name: None,
+            language: None,
+            codec_delay_ns: 0,
+            seek_pre_roll_ns: 0,
+            last_timecode: None,
+        });
+        Ok(number)
+    }
+
+    pub(super) fn set_audio_bit_depth(&mut self, track_num: TrackNum, bit_depth: u64) -> Result<(), Error> {
+        match &mut self.track_mut(track_num)?.kind {
+            TrackKind::Audio { bit_depth: depth, .. } => *depth = Some(bit_depth),
+            TrackKind::Video { .. } => return Err(Error::BadParam),
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_codec_private(&mut self, track_num: TrackNum, data: &[u8]) -> Result<(), Error> {
+        let track = self.track_mut(track_num)?;
+        if data.is_empty() {
+            return Err(Error::Unknown);
+        }
+
+        track.codec_private = Some(data.to_vec());
+        Ok(())
+    }
+
+    pub(super) fn set_codec_delay(
+        &mut self,
+        track_num: TrackNum,
+        codec_delay_ns: u64,
+        seek_pre_roll_ns: u64,
+    ) -> Result<(), Error> {
+        let track = self.track_mut(track_num)?;
+        track.codec_delay_ns = codec_delay_ns;
+        track.seek_pre_roll_ns = seek_pre_roll_ns;
+        Ok(())
+    }
+
+    pub(super) fn set_color(
+        &mut self,
+        track_num: TrackNum,
+        bit_depth: u8,
+        chroma_horizontal: u8,
+        chroma_vertical: u8,
+        color_range: u8,
+    ) -> Result<(), Error> {
+        match &mut self.track_mut(track_num)?.kind {
+            TrackKind::Video { color, .. } => {
+                *color = Some(Color {
+                    bits_per_channel: bit_depth,
+                    chroma_subsampling_horz: chroma_horizontal,
+                    chroma_subsampling_vert: chroma_vertical,
+                    range: color_range,
+                });
+            },
+            TrackKind::Audio { .. } => return Err(Error::BadParam),
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_color_metadata(&mut self, track_num: TrackNum, metadata: &ColorMetadata) -> Result<(), Error> {
+        match &mut self.track_mut(track_num)?.kind {
+            TrackKind::Video { color_metadata, .. } => *color_metadata = Some(Box::new(*metadata)),
+            TrackKind::Audio { .. } => return Err(Error::BadParam),
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_display_size(&mut self, track_num: TrackNum, width: u32, height: u32) -> Result<(), Error> {
+        match &mut self.track_mut(track_num)?.kind {
+            TrackKind::Video { display_size, .. } => *display_size = Some((width.into(), height.into())),
+            TrackKind::Audio { .. } => return Err(Error::BadParam),
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_frame_rate(&mut self, track_num: TrackNum, frame_rate: f64) -> Result<(), Error> {
+        match &mut self.track_mut(track_num)?.kind {
+            TrackKind::Video { frame_rate: rate, .. } => *rate = Some(frame_rate),
+            TrackKind::Audio { .. } => return Err(Error::BadParam),
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_track_name(&mut self, track_num: TrackNum, name: &str) -> Result<(), Error> {
+        let name = check_string(name)?.to_owned();
+        self.track_mut(track_num)?.name = Some(name);
+        Ok(())
+    }
+
+    pub(super) fn set_track_language(&mut self, track_num: TrackNum, language: &str) -> Result<(), Error> {
+        let language = check_string(language)?.to_owned();
+        self.track_mut(track_num)?.language = Some(language);
+        Ok(())
+    }
+
+    pub(super) fn add_frame(
+        &mut self,
+        track_num: TrackNum,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        let track = self.track(track_num).ok_or(Error::Unknown)?;
+        let is_video = track.is_video();
+        if data.is_empty() || self.cluster.is_some_and(|cluster| timestamp_ns / TIMECODE_SCALE < cluster.timecode) {
+            return Err(Error::Unknown);
+        }
+        if self.layout.is_none() {
+            self.write_headers()?;
+        }
+
+        let frame = QueuedFrame {
+            track_num,
+            data: data.to_vec(),
+            timestamp_ns,
+            keyframe,
+            discard_padding_ns,
+        };
+        if !is_video && self.tracks.iter().any(TrackEntry::is_video) {
+            self.queued_bytes += frame.data.len() as u64;
+            self.queued_frames.push_back(frame);
+            if self.queue_is_full() {
+                self.write_queued_frames()?;
+            }
+            return Ok(());
+        }
+
+        // Audio from before this frame belongs to the current cluster, the rest goes after it
+        while self.queued_frames.front().is_some_and(|queued| queued.timestamp_ns < timestamp_ns) {
+            let queued = self.queued_frames.pop_front().expect("the queue should not be empty");
+            self.queued_bytes -= queued.data.len() as u64;
+            self.write_frame(&queued)?;
+        }
+        self.write_frame(&frame)?;
+        self.write_queued_frames()
+    }
+
+    pub(super) fn finalize(mut self, duration: Option<u64>) -> Result<Writer<W>, (Error, Writer<W>)> {
+        match self.write_trailer(duration) {
+            Ok(()) => Ok(self.writer),
+            Err(err) => Err((err, self.writer)),
+        }
+    }
+
+    fn track(&self, track_num: TrackNum) -> Option<&TrackEntry> {
+        self.tracks.iter().find(|track| track.number == track_num)
+    }
+
+    fn track_mut(&mut self, track_num: TrackNum) -> Result<&mut TrackEntry, Error> {
+        self.tracks.iter_mut().find(|track| track.number == track_num).ok_or(Error::BadParam)
+    }
+
+    fn out(&mut self) -> &mut MkvWriter<W> {
+        self.writer.mkv_writer_mut()
+    }
+
+    fn write_headers(&mut self) -> Result<(), Error> {
+        let mut buf = Vec::new();
+        ebml::master(&mut buf, ElementId::EBML, |buf| {
+            ebml::uint(buf, ElementId::EBML_VERSION, 1);
+            ebml::uint(buf, ElementId::EBML_READ_VERSION, 1);
+            ebml::uint(buf, ElementId::EBML_MAX_ID_LENGTH, 4);
+            ebml::uint(buf, ElementId::EBML_MAX_SIZE_LENGTH, 8);
+            ebml::string(buf, ElementId::DOC_TYPE, self.doc_type.as_str());
+            ebml::uint(buf, ElementId::DOC_TYPE_VERSION, 4);
+            ebml::uint(buf, ElementId::DOC_TYPE_READ_VERSION, 2);
+        });
+        ebml::id(&mut buf, ElementId::SEGMENT);
+        let start = self.out().position;
+        let size_position = start + buf.len() as u64;
+        buf.extend_from_slice(&ebml::UNKNOWN_SIZE);
+        let data_position = start + buf.len() as u64;
+        let offset = |buf: &Vec<u8>| start + buf.len() as u64 - data_position;
+
+        // The `SeekHead` and `Duration` can only be filled in later, which needs seeking
+        let seekable = self.out().is_seekable();
+        if seekable {
+            ebml::void(&mut buf, SEEK_HEAD_RESERVED_LEN);
+        }
+
+        let info_offset = offset(&buf);
+        let mut info = Vec::new();
+        ebml::uint(&mut info, ElementId::TIMECODE_SCALE, TIMECODE_SCALE);
+        let duration_offset = info.len() as u64;
+        if seekable {
+            ebml::float(&mut info, ElementId::DURATION, 0.0);
+        }
+        ebml::string(&mut info, ElementId::MUXING_APP, MUXING_APP);
+        ebml::string(&mut info, ElementId::WRITING_APP, self.writing_app.as_deref().unwrap_or(MUXING_APP));
+        ebml::header(&mut buf, ElementId::INFO, info.len() as u64);
+        let duration_position = start + buf.len() as u64 + duration_offset;
+        buf.extend_from_slice(&info);
+
+        let tracks_offset = offset(&buf);
+        ebml::master(&mut buf, ElementId::TRACKS, |buf| {
+            for track in &self.tracks {
+                track.write(buf);
+            }
+        });
+
+        let tags_offset = (!self.tags.is_empty()).then(|| offset(&buf));
+        if tags_offset.is_some() {
+            ebml::master(&mut buf, ElementId::TAGS, |buf| {
+                for (name, value) in &self.tags {
+                    ebml::master(buf, ElementId::TAG, |buf| {
+                        ebml::master(buf, ElementId::SIMPLE_TAG, |buf| {
+                            ebml::string(buf, ElementId::TAG_NAME, name);
+                            ebml::string(buf, ElementId::TAG_STRING, value);
+                        });
+                    });
+                }
+            });
+        }
+
+        self.out().write(&buf)?;
+        self.layout = Some(SegmentLayout {
+            size_position,
+            data_position,
+            duration_position,
+            info_offset,
+            tracks_offset,
+            tags_offset,
+        });
+        Ok(())
+    }
+
+    /// Writes `frame` as a block, starting a new cluster first if needed.
+    fn write_frame(&mut self, frame: &QueuedFrame) -> Result<(), Error> {
+        let timecode = frame.timestamp_ns / TIMECODE_SCALE;
+        let track = self.track(frame.track_num).ok_or(Error::Unknown)?;
+        let is_video = track.is_video();
+        let reference_timecode = track.last_timecode;
+
+        let position = self.out().position;
+        let new_cluster = match self.cluster {
+            None => true,
+            Some(cluster) if timecode < cluster.timecode => return Err(Error::Unknown),
+            Some(cluster) => {
+                timecode - cluster.timecode > i16::MAX as u64
+                    || (frame.keyframe && is_video)
+                    || (self.max_cluster_duration_ns > 0
+                        && frame.timestamp_ns - cluster.timecode * TIMECODE_SCALE >= self.max_cluster_duration_ns)
+                    || (self.max_cluster_size > 0 && position - cluster.data_position >= self.max_cluster_size)
+            },
+        };
+        if new_cluster {
+            self.start_cluster(timecode)?;
+        }
+        let mut cluster = self.cluster.expect("a cluster should have been started");
+
+        let relative_timecode = i16::try_from(timecode - cluster.timecode).map_err(|_| Error::Unknown)?;
+        let mut block = Vec::with_capacity(frame.data.len() + 4);
+        ebml::size_with_len(&mut block, frame.track_num, 1);
+        block.extend_from_slice(&relative_timecode.to_be_bytes());
+
+        // Blocks with a `DiscardPadding` can't be written as `SimpleBlock`s, so only go that way when needed
+        let mut buf = Vec::new();
+        if frame.discard_padding_ns == 0 {
+            block.push(if frame.keyframe { 0x80 } else { 0 });
+            block.extend_from_slice(&frame.data);
+            ebml::binary(&mut buf, ElementId::SIMPLE_BLOCK, &block);
+        } else {
+            block.push(0);
+            block.extend_from_slice(&frame.data);
+            ebml::master(&mut buf, ElementId::BLOCK_GROUP, |buf| {
+                ebml::binary(buf, ElementId::BLOCK, &block);
+                if let Some(reference) = reference_timecode.filter(|_| !frame.keyframe) {
+                    ebml::int(buf, ElementId::REFERENCE_BLOCK, reference as i64 - timecode as i64);
+                }
+                ebml::int(buf, ElementId::DISCARD_PADDING, frame.discard_padding_ns);
+            });
+        }
+        self.out().write(&buf)?;
+
+        cluster.block_count += 1;
+        if self.output_cues && cluster.needs_cue_point && frame.keyframe && self.cues_track() == Some(frame.track_num) {
+            let layout = self.layout.expect("the headers should have been written");
+            self.cue_points.push(CuePoint {
+                timecode,
+                track: frame.track_num,
+                cluster_offset: cluster.position - layout.data_position,
+                block_number: cluster.block_count,
+            });
+            cluster.needs_cue_point = false;
+        }
+        self.cluster = Some(cluster);
+
+        if let Ok(track) = self.track_mut(frame.track_num) {
+            track.last_timecode = Some(timecode);
+        }
+        self.last_timestamp_ns = self.last_timestamp_ns.max(frame.timestamp_ns);
+        Ok(())
+    }
+
+    fn write_queued_frames(&mut self) -> Result<(), Error> {
+        while let Some(queued) = self.queued_frames.pop_front() {
+            self.queued_bytes -= queued.data.len() as u64;
+            self.write_frame(&queued)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the queued frames span as much as a cluster may, in time or in size.
+    fn queue_is_full(&self) -> bool {
+        let (Some(first), Some(last)) = (self.queued_frames.front(), self.queued_frames.back()) else {
+            return false;
+        };
+        let max_span_ns = match self.max_cluster_duration_ns {
+            0 => MAX_CLUSTER_SPAN_NS,
+            duration_ns => duration_ns.min(MAX_CLUSTER_SPAN_NS),
+        };
+        last.timestamp_ns.saturating_sub(first.timestamp_ns) >= max_span_ns
+            || (self.max_cluster_size > 0 && self.queued_bytes >= self.max_cluster_size)
+    }
+
+    /// The track cue points are made for: the first video track, or the first track if there is no video.
+    fn cues_track(&self) -> Option<TrackNum> {
+        let track = self.tracks.iter().find(|track| track.is_video()).or(self.tracks.first());
+        track.map(|track| track.number)
+    }
+
+    fn start_cluster(&mut self, timecode: u64) -> Result<(), Error> {
+        self.finish_cluster()?;
+
+        self.out().element_start(ElementId::CLUSTER);
+        let position = self.out().position;
+        let mut buf = Vec::new();
+        ebml::id(&mut buf, ElementId::CLUSTER);
+        buf.extend_from_slice(&ebml::UNKNOWN_SIZE);
+        let data_position = position + buf.len() as u64;
+        ebml::uint(&mut buf, ElementId::TIMECODE, timecode);
+        self.out().write(&buf)?;
+
+        self.cluster = Some(Cluster {
+            position,
+            data_position,
+            timecode,
+            block_count: 0,
+            needs_cue_point: true,
+        });
+        Ok(())
+    }
+
+    /// Fills in the size of the current cluster, if the destination allows it.
+    fn finish_cluster(&mut self) -> Result<(), Error> {
+        let Some(cluster) = self.cluster.take() else {
+            return Ok(());
+        };
+        if !self.out().is_seekable() {
+            return Ok(());
+        }
+
+        let size = self.out().position - cluster.data_position;
+        let mut buf = Vec::new();
+        ebml::size_with_len(&mut buf, size, ebml::FIXED_SIZE_LEN);
+        self.out().write_at(cluster.data_position - ebml::FIXED_SIZE_LEN as u64, &buf)
+    }
+
+    fn write_trailer(&mut self, duration: Option<u64>) -> Result<(), Error> {
+        if self.layout.is_none() {
+            self.write_headers()?;
+        }
+        let layout = self.layout.expect("the headers should have been written");
+        self.write_queued_frames()?;
+        self.finish_cluster()?;
+
+        let mut cues_offset = None;
+        if self.output_cues && !self.cue_points.is_empty() {
+            self.out().element_start(ElementId::CUES);
+            cues_offset = Some(self.out().position - layout.data_position);
+
+            let mut buf = Vec::new();
+            ebml::master(&mut buf, ElementId::CUES, |buf| {
+                for cue_point in &self.cue_points {
+                    ebml::master(buf, ElementId::CUE_POINT, |buf| {
+                        ebml::uint(buf, ElementId::CUE_TIME, cue_point.timecode);
+                        ebml::master(buf, ElementId::CUE_TRACK_POSITIONS, |buf| {
+                            ebml::uint(buf, ElementId::CUE_TRACK, cue_point.track);
+                            ebml::uint(buf, ElementId::CUE_CLUSTER_POSITION, cue_point.cluster_offset);
+                            if cue_point.block_number > 1 {
+                                ebml::uint(buf, ElementId::CUE_BLOCK_NUMBER, cue_point.block_number);
+                            }
+                        });
+                    });
+                }
+            });
+            self.out().write(&buf)?;
+        }
+
+        if !self.out().is_seekable() {
+            return Ok(());
+        }
+
+        // An explicit duration is in timecode ticks, as with libwebm
+        let duration = duration.map_or(self.last_timestamp_ns as f32 / TIMECODE_SCALE as f32, |ticks| ticks as f32);
+        let mut buf = Vec::new();
+        ebml::float(&mut buf, ElementId::DURATION, duration);
+        self.out().write_at(layout.duration_position, &buf)?;
+
+        let entries = [
+            (ElementId::INFO, Some(layout.info_offset)),
+            (ElementId::TRACKS, Some(layout.tracks_offset)),
+            (ElementId::TAGS, layout.tags_offset),
+            (ElementId::CUES, cues_offset),
+        ];
+        let mut seek_head = Vec::new();
+        for (element_id, offset) in entries {
+            let Some(offset) = offset else {
+                continue;
+            };
+            ebml::master(&mut seek_head, ElementId::SEEK, |buf| {
+                let mut id = Vec::new();
+                ebml::id(&mut id, element_id);
+                ebml::binary(buf, ElementId::SEEK_ID, &id);
+                ebml::uint(buf, ElementId::SEEK_POSITION, offset);
+            });
+        }
+
+        // What is left of the reserved space becomes a `Void`, which needs at least two bytes. If there would only be
+        // one, the `SeekHead` takes it with a longer size field instead.
+        let mut buf = Vec::new();
+        ebml::id(&mut buf, ElementId::SEEK_HEAD);
+        let size_len = ebml::size_len(seek_head.len() as u64);
+        let size_len = size_len + usize::from(buf.len() + size_len + seek_head.len() + 1 == SEEK_HEAD_RESERVED_LEN);
+        ebml::size_with_len(&mut buf, seek_head.len() as u64, size_len);
+        buf.extend_from_slice(&seek_head);
+        let remaining = SEEK_HEAD_RESERVED_LEN - buf.len();
+        if remaining > 0 {
+            ebml::void(&mut buf, remaining);
+        }
+        self.out().write_at(layout.data_position, &buf)?;
+
+        let mut buf = Vec::new();
+        let size = self.out().position - layout.data_position;
+        ebml::size_with_len(&mut buf, size, ebml::FIXED_SIZE_LEN);
+        self.out().write_at(layout.size_position, &buf)
+    }
+}
+
+/// Makes sure `value` can be written as an EBML string. libwebm takes C strings, so they can't contain NUL either.
+fn check_string(value: &str) -> Result<&str, Error> {
+    if value.contains('\0') {
+        return Err(Error::BadParam);
+    }
+    Ok(value)
+}
+
+/// Makes a random track UID of 56 bits, as libwebm does.
+fn make_uid(track_num: TrackNum) -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(track_num);
+    (hasher.finish() >> 8).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    #[cfg(feature = "libwebm")]
+    use crate::ebml::{Children, ElementId, ElementType, Reader};
+    use crate::mux::{AudioCodecId, ColorMetadata, MasteringMetadata, SegmentBuilder, VideoCodecId, Writer};
+    use crate::validate::{validate, Mode};
+
+    /// Muxes a second of video at 25 fps with a keyframe every 10 frames, and Opus audio in 20 ms packets.
+    fn mux<W: std::io::Write>(writer: Writer<W>) -> Writer<W> {
+        let builder = SegmentBuilder::new(writer).unwrap().add_tag("TITLE", "test").unwrap();
+        let (builder, video_track) = builder.add_video_track(320, 240, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+
+        for i in 0..50_u64 {
+            segment.add_frame(audio_track, &[0xFC, 0xFF, 0xFE], i * 20_000_000, true).unwrap();
+            if i % 2 == 0 {
+                let keyframe = i % 20 == 0;
+                segment.add_frame(video_track, &[i as u8; 64], i * 20_000_000, keyframe).unwrap();
+            }
+        }
+        let last_packet = [0xFC, 0xFF, 0xFE];
+        segment.add_frame_with_discard_padding(audio_track, &last_packet, 1_000_000_000, true, 6_500_000).unwrap();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        writer
+    }
+
+    /// Muxes the same frames as [`mux`] with libwebm, through `webm-sys` directly, as the libwebm backend is not
+    /// built alongside this one.
+    #[cfg(feature = "libwebm")]
+    fn mux_with_libwebm() -> Vec<u8> {
+        use std::ffi::{c_void, CString};
+        use std::io::Write;
+
+        use crate::ffi::mux::{self as ffi, ResultCode};
+
+        extern "C" fn write_fn(data: *mut c_void, buf: *const c_void, len: usize) -> bool {
+            let dest = unsafe { data.cast::<Cursor<Vec<u8>>>().as_mut().unwrap() };
+            let buf = unsafe { std::slice::from_raw_parts(buf.cast::<u8>(), len) };
+            dest.write_all(buf).is_ok()
+        }
+        extern "C" fn get_pos_fn(data: *mut c_void) -> u64 {
+            unsafe { data.cast::<Cursor<Vec<u8>>>().as_ref().unwrap() }.position()
+        }
+        extern "C" fn set_pos_fn(data: *mut c_void, pos: u64) -> bool {
+            unsafe { data.cast::<Cursor<Vec<u8>>>().as_mut().unwrap() }.set_position(pos);
+            true
+        }
+
+        let mut dest = Cursor::new(Vec::new());
+        let user_data = std::ptr::from_mut(&mut dest).cast();
+        let writer = unsafe { ffi::new_writer(Some(write_fn), Some(get_pos_fn), Some(set_pos_fn), None, user_data) };
+        let segment = unsafe { ffi::new_segment() };
+        assert!(!writer.is_null() && !segment.is_null());
+
+        let (name, value) = (CString::new("TITLE").unwrap(), CString::new("test").unwrap());
+        let (vp8, opus) = (CString::new("V_VP8").unwrap(), CString::new("A_OPUS").unwrap());
+        let (mut video_track, mut audio_track) = (0, 0);
+        unsafe {
+            assert_eq!(ffi::initialize_segment(segment, writer), ResultCode::Ok);
+            assert_eq!(ffi::segment_add_simple_tag(segment, name.as_ptr(), value.as_ptr()), ResultCode::Ok);
+            let add_video = ffi::segment_add_video_track_with_codec_id;
+            assert_eq!(add_video(segment, 320, 240, 0, vp8.as_ptr(), &mut video_track), ResultCode::Ok);
+            let add_audio = ffi::segment_add_audio_track_with_codec_id;
+            assert_eq!(add_audio(segment, 48_000, 2, 0, opus.as_ptr(), &mut audio_track), ResultCode::Ok);
+        }
+
+        let add_frame = |track, data: &[u8], timestamp_ns, keyframe, discard_padding_ns| {
+            let result = unsafe {
+                ffi::segment_add_frame_with_discard_padding(
+                    segment,
+                    track,
+                    data.as_ptr(),
+                    data.len(),
+                    timestamp_ns,
+                    keyframe,
+                    discard_padding_ns,
+                )
+            };
+            assert_eq!(result, ResultCode::Ok);
+        };
+        for i in 0..50_u64 {
+            add_frame(audio_track, &[0xFC, 0xFF, 0xFE], i * 20_000_000, true, 0);
+            if i % 2 == 0 {
+                add_frame(video_track, &[i as u8; 64], i * 20_000_000, i % 20 == 0, 0);
+            }
+        }
+        add_frame(audio_track, &[0xFC, 0xFF, 0xFE], 1_000_000_000, true, 6_500_000);
+
+        unsafe {
+            assert_eq!(ffi::finalize_segment(segment, 0), ResultCode::Ok);
+            ffi::delete_segment(segment);
+            ffi::delete_writer(writer);
+        }
+        dest.into_inner()
+    }
+
+    /// Lists the elements in `data` in order, with their depth and the raw value of those that are not masters.
+    ///
+    /// `Void`s are left out, and so are the values that differ between muxers: track UIDs, app names, and the
+    /// positions that depend on the length of those.
+    #[cfg(feature = "libwebm")]
+    fn element_tree(data: &[u8]) -> Vec<(usize, ElementId, Option<Vec<u8>>)> {
+        fn walk(reader: &mut Reader<Cursor<&[u8]>>, children: &mut Children, tree: &mut Vec<TreeEntry>) {
+            while let Some(element) = reader.next_child(children).unwrap() {
+                let value = match element.id {
+                    ElementId::VOID => continue,
+                    ElementId::TRACK_UID
+                    | ElementId::MUXING_APP
+                    | ElementId::WRITING_APP
+                    | ElementId::SEEK_POSITION
+                    | ElementId::CUE_CLUSTER_POSITION => None,
+                    id if id.element_type() == Some(ElementType::Master) => {
+                        tree.push((element.depth, element.id, None));
+                        let mut grandchildren = reader.children(&element).unwrap();
+                        walk(reader, &mut grandchildren, tree);
+                        continue;
+                    },
+                    _ => Some(reader.read_binary(&element).unwrap()),
+                };
+                tree.push((element.depth, element.id, value));
+            }
+        }
+        type TreeEntry = (usize, ElementId, Option<Vec<u8>>);
+
+        let mut reader = Reader::new(Cursor::new(data));
+        let mut top_level = reader.top_level();
+        let mut tree = Vec::new();
+        walk(&mut reader, &mut top_level, &mut tree);
+        tree
+    }
+
+    /// Finds the value of the first `Duration` element, which is always written as a 4-byte float.
+    fn duration(data: &[u8]) -> Option<f32> {
+        let position = data.windows(3).position(|window| window == [0x44, 0x89, 0x84])?;
+        Some(f32::from_be_bytes(data[position + 3..position + 7].try_into().unwrap()))
+    }
+
+    #[test]
+    fn seekable_output_is_valid() {
+        let output = mux(Writer::new(Cursor::new(Vec::new()))).into_inner().into_inner();
+        assert_eq!(validate(Cursor::new(&output), Mode::File).unwrap(), []);
+        assert_eq!(duration(&output), Some(1000.0));
+
+        // The `SeekHead` takes the place of the reserved space, ahead of `Info`
+        let seek_head = output.windows(4).position(|window| window == [0x11, 0x4D, 0x9B, 0x74]).unwrap();
+        assert!(output[seek_head + super::SEEK_HEAD_RESERVED_LEN..].starts_with(&[0x15, 0x49, 0xA9, 0x66]));
+    }
+
+    #[test]
+    #[cfg(feature = "libwebm")]
+    fn matches_libwebm() {
+        let output = mux(Writer::new(Cursor::new(Vec::new()))).into_inner().into_inner();
+        assert_eq!(element_tree(&output), element_tree(&mux_with_libwebm()));
+    }
+
+    #[test]
+    fn live_output_is_valid() {
+        let output = mux(Writer::new_non_seek(Vec::new())).into_inner();
+        assert_eq!(validate(Cursor::new(&output), Mode::Live).unwrap(), []);
+        assert_eq!(duration(&output), None);
+        assert!(!output.windows(4).any(|window| window == [0x11, 0x4D, 0x9B, 0x74]));
+    }
+
+    #[test]
+    fn empty_segment() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, _) = builder.add_video_track(320, 240, VideoCodecId::VP9, None).unwrap();
+        let Ok(writer) = builder.build().finalize(Some(1234)) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let output = writer.into_inner().into_inner();
+        assert_eq!(validate(Cursor::new(&output), Mode::File).unwrap(), []);
+        assert_eq!(duration(&output), Some(1234.0));
+    }
+
+    #[test]
+    fn audio_queue_is_bounded() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, _) = builder.add_video_track(320, 240, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.set_max_cluster_duration(1_000_000_000).unwrap().build();
+
+        // Without any video, audio is written out once it spans a cluster
+        segment.add_frame(audio_track, &[0xFC, 0xFF, 0xFE], 0, true).unwrap();
+        let header_len = segment.writer().bytes_written();
+        for i in 1..100_u64 {
+            segment.add_frame(audio_track, &[0xFC, 0xFF, 0xFE], i * 20_000_000, true).unwrap();
+        }
+        assert!(segment.writer().bytes_written() >= header_len + 51 * 3);
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+        assert_eq!(validate(Cursor::new(&output), Mode::File).unwrap(), []);
+    }
+
+    #[test]
+    fn writes_hdr_metadata() {
+        let metadata = ColorMetadata {
+            matrix_coefficients: Some(9),
+            transfer_characteristics: Some(16),
+            primaries: Some(9),
+            chroma_siting: (None, None),
+            max_cll: Some(1000),
+            max_fall: None,
+            mastering: Some(MasteringMetadata {
+                red: None,
+                green: None,
+                blue: None,
+                white_point: Some((0.3127, 0.329)),
+                luminance_max: Some(1000.0),
+                luminance_min: None,
+            }),
+        };
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, video_track) = builder.add_video_track(320, 240, VideoCodecId::VP9, None).unwrap();
+        let builder = builder.set_color_metadata(video_track, &metadata).unwrap();
+        let builder = builder.set_display_size(video_track, 640, 240).unwrap();
+        let builder = builder.set_frame_rate(video_track, 25.0).unwrap();
+        let Ok(writer) = builder.build().finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+
+        let output = writer.into_inner().into_inner();
+        assert_eq!(validate(Cursor::new(&output), Mode::File).unwrap(), []);
+        let contains = |element: &[u8]| output.windows(element.len()).any(|window| window == element);
+        assert!(contains(&[0x54, 0xB0, 0x82, 0x02, 0x80]));
+        let (matrix, transfer, primaries) = ([0x55, 0xB1, 0x81, 9], [0x55, 0xBA, 0x81, 16], [0x55, 0xBB, 0x81, 9]);
+        assert!(contains(&[&matrix[..], &transfer, &primaries, &[0x55, 0xBC, 0x82, 0x03, 0xE8]].concat()));
+        assert!(contains(&[&[0x55, 0xD9, 0x84][..], &1000.0_f32.to_be_bytes()].concat()));
+        assert!(contains(&[&[0x55, 0xD8, 0x84][..], &0.329_f32.to_be_bytes()].concat()));
+
+        // Only what was set is written, not the fields of `SegmentBuilder::set_color`
+        assert!(!contains(&[0x55, 0xB2]));
+        assert!(!contains(&[0x55, 0xD1]));
+    }
+}
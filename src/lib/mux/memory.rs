@@ -0,0 +1,255 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use bytes::{Bytes, BytesMut};
+
+/// Matroska element IDs after which everything written so far is final.
+const CLUSTER_ID: u64 = 0x1F43_B675;
+const CUES_ID: u64 = 0x1C53_BB6B;
+
+/// An in-memory, growable write destination, for use with [`Writer::new_in_memory()`](super::Writer::new_in_memory).
+///
+/// Like an `io::Cursor<Vec<u8>>`, this supports seeking, so the muxer can go back and fill in the segment size,
+/// `Duration`, `SeekHead` and `Cues` once they are known. In addition, it keeps track of where the cluster that is
+/// currently being written begins. The muxer never touches cluster data again once a new cluster has started, so
+/// everything before that point can be taken out with [`MemorySink::take_finalized()`] as [`Bytes`] while muxing
+/// continues, without copying.
+///
+/// ## Patches to drained data
+/// The segment header (including `SeekHead` and `Duration`) comes before the first cluster, but is only completed
+/// when the segment is finalized. If it has already been taken out by then, the late writes cannot be applied to
+/// it, and are instead recorded as [`Patch`]es, available via [`MemorySink::take_patches()`]. If your final
+/// destination supports random access (e.g. a file or a ranged upload), apply them there. If it doesn't (e.g. an
+/// HTTP response body), they can be dropped: the data that was sent is still a playable WebM stream, but it won't
+/// have an accurate duration or seeking information.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    buf: BytesMut,
+
+    /// Stream offset of the first byte in `buf`. Everything before it has been taken out.
+    start: u64,
+
+    /// Stream offset at which the next write will happen
+    pos: u64,
+
+    /// Stream offset of the most recent element after which no more changes will be made
+    finalized_end: u64,
+
+    patches: Vec<Patch>,
+}
+
+/// A write to a region of the stream that was already taken out of a [`MemorySink`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// The stream offset at which [`Patch::data`] must be written.
+    pub offset: u64,
+
+    /// The bytes that replace the ones previously taken out at [`Patch::offset`].
+    pub data: Bytes,
+}
+
+impl MemorySink {
+    /// Creates a new, empty [`MemorySink`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes out all bytes that are known to be final, i.e. everything up to the start of the cluster currently being
+    /// written. Returns `None` if there is nothing new to take.
+    ///
+    /// Concatenating all returned chunks (followed by the result of [`MemorySink::take_remaining()`] once the
+    /// segment has been finalized) yields the complete stream, minus any [`Patch`]es.
+    pub fn take_finalized(&mut self) -> Option<Bytes> {
+        let available = self.finalized_end.saturating_sub(self.start);
+        self.take(available)
+    }
+
+    /// Takes out all bytes written so far, regardless of whether they are final.
+    ///
+    /// This is intended to be called after the segment has been finalized. Any later writes to the returned region
+    /// will become [`Patch`]es.
+    pub fn take_remaining(&mut self) -> Option<Bytes> {
+        self.take(self.buf.len() as u64)
+    }
+
+    /// Takes out all writes to regions of the stream that had already been taken out when they happened, in the order
+    /// they were made. Later patches may overlap earlier ones.
+    pub fn take_patches(&mut self) -> Vec<Patch> {
+        std::mem::take(&mut self.patches)
+    }
+
+    /// Returns the total length of the stream written so far, including bytes that have already been taken out.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.start + self.buf.len() as u64
+    }
+
+    /// Returns `true` if nothing has been written yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Called by the muxer right before it writes the ID of an element at `position`.
+    pub(crate) fn element_started(&mut self, element_id: u64, position: u64) {
+        if matches!(element_id, CLUSTER_ID | CUES_ID) {
+            self.finalized_end = self.finalized_end.max(position);
+        }
+    }
+
+    fn take(&mut self, len: u64) -> Option<Bytes> {
+        let len = usize::try_from(len).unwrap_or(usize::MAX).min(self.buf.len());
+        if len == 0 {
+            return None;
+        }
+
+        self.start += len as u64;
+        Some(self.buf.split_to(len).freeze())
+    }
+}
+
+impl Write for MemorySink {
+    fn write(&mut self, mut data: &[u8]) -> io::Result<usize> {
+        let written = data.len();
+
+        // Whatever lands before `start` has been taken out already, and must become a patch
+        if self.pos < self.start {
+            let patch_len = usize::try_from(self.start - self.pos).map_or(data.len(), |len| len.min(data.len()));
+            let (patch, rest) = data.split_at(patch_len);
+            self.patches.push(Patch {
+                offset: self.pos,
+                data: Bytes::copy_from_slice(patch),
+            });
+            self.pos += patch_len as u64;
+            data = rest;
+        }
+
+        if !data.is_empty() {
+            let offset = usize::try_from(self.pos - self.start)
+                .map_err(|_| io::Error::new(io::ErrorKind::OutOfMemory, "write position out of range"))?;
+
+            // Seeking past the end and writing leaves a zero-filled gap, just like `Cursor`
+            if offset > self.buf.len() {
+                self.buf.resize(offset, 0);
+            }
+
+            let overwrite_len = (self.buf.len() - offset).min(data.len());
+            let (overwrite, append) = data.split_at(overwrite_len);
+            self.buf[offset..offset + overwrite_len].copy_from_slice(overwrite);
+            self.buf.extend_from_slice(append);
+            self.pos += data.len() as u64;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for MemorySink {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        let new_pos = new_pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+        })?;
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn behaves_like_cursor() {
+        let mut sink = MemorySink::new();
+        let mut cursor = io::Cursor::new(Vec::new());
+
+        for dest in [&mut sink as &mut dyn WriteSeek, &mut cursor] {
+            dest.write_all(b"hello world").unwrap();
+            dest.seek(SeekFrom::Start(6)).unwrap();
+            dest.write_all(b"there, friend").unwrap();
+            dest.seek(SeekFrom::End(3)).unwrap();
+            dest.write_all(b"!").unwrap();
+            dest.seek(SeekFrom::Current(-8)).unwrap();
+            dest.write_all(b"F").unwrap();
+        }
+
+        assert_eq!(sink.len(), cursor.get_ref().len() as u64);
+        assert_eq!(sink.take_remaining().unwrap(), cursor.into_inner());
+        assert!(sink.take_patches().is_empty());
+    }
+
+    #[test]
+    fn takes_only_finalized() {
+        let mut sink = MemorySink::new();
+        assert!(sink.take_finalized().is_none());
+
+        sink.write_all(b"header").unwrap();
+        sink.element_started(CLUSTER_ID, 6);
+        sink.write_all(b"cluster1").unwrap();
+        assert!(sink.take_finalized().unwrap() == "header");
+        assert!(sink.take_finalized().is_none());
+
+        // Other elements don't finalize anything
+        sink.element_started(0xA3, 8);
+        assert!(sink.take_finalized().is_none());
+
+        sink.element_started(CLUSTER_ID, 14);
+        sink.write_all(b"cluster2").unwrap();
+        assert!(sink.take_finalized().unwrap() == "cluster1");
+        assert_eq!(sink.len(), 22);
+
+        sink.element_started(CUES_ID, 22);
+        sink.write_all(b"cues").unwrap();
+        assert!(sink.take_finalized().unwrap() == "cluster2");
+        assert!(sink.take_remaining().unwrap() == "cues");
+        assert!(sink.take_remaining().is_none());
+    }
+
+    #[test]
+    fn writes_to_taken_regions_become_patches() {
+        let mut sink = MemorySink::new();
+        sink.write_all(b"abcdef").unwrap();
+        sink.element_started(CLUSTER_ID, 4);
+        assert!(sink.take_finalized().unwrap() == "abcd");
+
+        // Straddles the boundary between taken and retained data
+        sink.seek(SeekFrom::Start(2)).unwrap();
+        sink.write_all(b"XYZ").unwrap();
+        sink.seek(SeekFrom::Start(0)).unwrap();
+        sink.write_all(b"Q").unwrap();
+
+        assert_eq!(
+            sink.take_patches(),
+            [
+                Patch { offset: 2, data: Bytes::from_static(b"XY") },
+                Patch { offset: 0, data: Bytes::from_static(b"Q") },
+            ]
+        );
+        assert!(sink.take_remaining().unwrap() == "Zf");
+    }
+
+    #[test]
+    fn rejects_negative_seek() {
+        let mut sink = MemorySink::new();
+        assert!(sink.seek(SeekFrom::Current(-1)).is_err());
+        assert!(sink.seek(SeekFrom::End(-1)).is_err());
+        assert_eq!(sink.stream_position().unwrap(), 0);
+    }
+
+    trait WriteSeek: Write + Seek {}
+    impl<T: Write + Seek> WriteSeek for T {}
+}
@@ -1,76 +1,145 @@
+use std::borrow::Cow;
 use std::io::Write;
 use std::num::NonZeroU64;
-use std::ptr::NonNull;
 
-use crate::ffi;
-use crate::ffi::mux::{ResultCode, TrackNum};
+use crate::codec::av1::{self, SequenceHeader};
+use crate::codec::opus::{self, OpusHead};
+use crate::codec::vorbis::VorbisHeaders;
+use crate::codec::{vp8, vp9};
 
+use super::backend::MkvSegment;
 use super::{
-    writer::Writer, AudioCodecId, AudioTrack, ColorRange, ColorSubsampling, Error, VideoCodecId,
-    VideoTrack,
+    writer::Writer, AudioCodecId, AudioTrack, ColorMetadata, ColorRange, ColorSubsampling, DocType, Error,
+    SegmentId, Track, TrackNum, VideoCodecId, VideoTrack,
 };
 
-/// RAII semantics for an FFI segment. This is simpler than implementing `Drop` on [`Segment`], which
-/// prevents destructuring.
-struct OwnedSegmentPtr {
-    segment: ffi::mux::SegmentNonNullPtr,
+/// A builder for [`Segment`].
+///
+/// Once you have a [`Writer`], you can use this to specify the tracks and track parameters you want, then build a
+/// [`Segment`], allowing you to write frames.
+pub struct SegmentBuilder<W: Write> {
+    segment: MkvSegment<W>,
+    id: SegmentId,
+    doc_type: DocType,
+    has_non_webm_codec: bool,
+    tracks: Vec<TrackInfo>,
+    validate_keyframes: bool,
 }
 
-impl OwnedSegmentPtr {
-    /// ## Safety
-    /// `segment` must be a valid, non-dangling pointer to an FFI segment created with [`ffi::mux::new_segment`].
-    /// After construction, `segment` must not be used by the caller, except via [`Self::as_ptr`].
-    /// The latter also must not be passed to [`ffi::mux::delete_segment`].
-    unsafe fn new(segment: ffi::mux::SegmentNonNullPtr) -> Self {
-        Self { segment }
-    }
+/// What is known about a track that was added to a segment, for validating later calls that refer to it.
+#[derive(Debug, Clone)]
+struct TrackInfo {
+    number: NonZeroU64,
+    codec_id: String,
 
-    fn as_ptr(&self) -> ffi::mux::SegmentMutPtr {
-        self.segment.as_ptr()
-    }
+    /// The channel count of audio tracks, `None` for video tracks
+    channels: Option<u32>,
+
+    opus_clock: OpusClock,
 }
 
-impl Drop for OwnedSegmentPtr {
-    fn drop(&mut self) {
-        // SAFETY: We are assumed to be the only one allowed to delete this segment (per the requirements of [`Self::new`]).
-        unsafe {
-            ffi::mux::delete_segment(self.segment.as_ptr());
-        }
+/// Where the next automatically timestamped Opus packet goes, see [`Segment::add_opus_packet`].
+#[derive(Debug, Clone, Copy, Default)]
+struct OpusClock {
+    start_ns: u64,
+
+    /// Number of samples (at 48 kHz) since `start_ns`
+    samples: u64,
+}
+
+impl OpusClock {
+    fn timestamp_ns(self) -> u64 {
+        self.start_ns + opus::samples_to_ns(self.samples)
     }
 }
 
-/// A builder for [`Segment`].
-///
-/// Once you have a [`Writer`], you can use this to specify the tracks and track parameters you want, then build a
-/// [`Segment`], allowing you to write frames.
-pub struct SegmentBuilder<W: Write> {
-    segment: OwnedSegmentPtr,
-    writer: Writer<W>,
+impl TrackInfo {
+    /// Determines whether `frame` is a keyframe from its data, if this track's codec is one that allows this.
+    fn detect_keyframe(&self, frame: &[u8]) -> Option<Result<bool, Error>> {
+        let is_keyframe = match VideoCodecId::from_codec_id(&self.codec_id)? {
+            VideoCodecId::VP8 => vp8::is_keyframe(frame),
+            VideoCodecId::VP9 => vp9::is_keyframe(frame),
+            VideoCodecId::AV1 => av1::is_keyframe(frame),
+        };
+        Some(is_keyframe)
+    }
 }
 
 impl<W: Write> SegmentBuilder<W> {
     /// Creates a new [`SegmentBuilder`] with default configuration, that writes to the specified [`Writer`].
     pub fn new(writer: Writer<W>) -> Result<Self, Error> {
-        let segment = unsafe { ffi::mux::new_segment() };
-        let segment = NonNull::new(segment)
-            .map(|ptr| unsafe { OwnedSegmentPtr::new(ptr) })
-            .ok_or(Error::Unknown)?;
-        let result = unsafe { ffi::mux::initialize_segment(segment.as_ptr(), writer.mkv_writer()) };
+        Self::with_id(writer, SegmentId::next())
+    }
 
-        match result {
-            ResultCode::Ok => Ok(SegmentBuilder { segment, writer }),
-            ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
-        }
+    /// Creates a new [`SegmentBuilder`] whose track handles are tagged with the given `id`.
+    pub(crate) fn with_id(writer: Writer<W>, id: SegmentId) -> Result<Self, Error> {
+        Ok(SegmentBuilder {
+            segment: MkvSegment::new(writer)?,
+            id,
+            doc_type: DocType::WebM,
+            has_non_webm_codec: false,
+            tracks: Vec::new(),
+            validate_keyframes: false,
+        })
     }
 
     /// Sets the name of the writing application. This will show up under the `WritingApp` Matroska element.
-    pub fn set_writing_app(self, app_name: &str) -> Result<Self, Error> {
-        let name = std::ffi::CString::new(app_name).map_err(|_| Error::BadParam)?;
-        unsafe {
-            ffi::mux::mux_set_writing_app(self.segment.as_ptr(), name.as_ptr());
+    pub fn set_writing_app(mut self, app_name: &str) -> Result<Self, Error> {
+        self.segment.set_writing_app(app_name)?;
+        Ok(self)
+    }
+
+    /// Adds a tag with a single `SimpleTag` to this segment, applying to the segment as a whole.
+    ///
+    /// See the [Matroska tagging guidelines](https://www.matroska.org/technical/tagging.html) for common tag names,
+    /// such as `TITLE` or `ENCODER`.
+    pub fn add_tag(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        self.segment.add_simple_tag(name, value)?;
+        Ok(self)
+    }
+
+    /// Sets whether this segment is written as a WebM or a general Matroska file. The default is
+    /// [`DocType::WebM`], which restricts tracks to the codecs allowed in WebM.
+    ///
+    /// Switching back to [`DocType::WebM`] fails if a track with a non-WebM codec has already been added.
+    pub fn set_doc_type(mut self, doc_type: DocType) -> Result<Self, Error> {
+        if doc_type == DocType::WebM && self.has_non_webm_codec {
+            return Err(Error::BadParam);
         }
 
+        self.doc_type = doc_type;
+        Ok(self)
+    }
+
+    /// Sets whether the `keyframe` flag passed to [`Segment::add_frame`] is checked against the frame data, for
+    /// VP8, VP9 and AV1 tracks. If enabled, mismatches fail with [`Error::KeyframeMismatch`], rather than silently
+    /// producing a file that can't be seeked properly. This is disabled by default.
+    ///
+    /// See also [`Segment::add_frame_auto`], which doesn't need the flag at all.
+    #[must_use]
+    pub fn set_keyframe_validation(mut self, enabled: bool) -> Self {
+        self.validate_keyframes = enabled;
+        self
+    }
+
+    /// Sets the longest duration a cluster may span, in nanoseconds, after which a new cluster is started at the next
+    /// frame. Zero means no limit. Shorter clusters make seeking more precise, at the cost of some overhead.
+    pub fn set_max_cluster_duration(mut self, duration_ns: u64) -> Result<Self, Error> {
+        self.segment.set_max_cluster_duration(duration_ns)?;
+        Ok(self)
+    }
+
+    /// Sets the largest size a cluster may grow to, in bytes, after which a new cluster is started at the next frame.
+    /// Zero means no limit, which is the default.
+    pub fn set_max_cluster_size(mut self, size: u64) -> Result<Self, Error> {
+        self.segment.set_max_cluster_size(size)?;
+        Ok(self)
+    }
+
+    /// Sets whether a `Cues` element (the seek index) is written when the segment is finalized. This is enabled by
+    /// default.
+    pub fn set_cues(mut self, enabled: bool) -> Result<Self, Error> {
+        self.segment.set_output_cues(enabled)?;
         Ok(self)
     }
 
@@ -81,55 +150,109 @@ impl<W: Write> SegmentBuilder<W> {
     /// number already exists, however, this method will fail. Leave as `None` to allow an available number to be
     /// chosen for you.
     pub fn add_video_track(
-        self,
+        mut self,
         width: u32,
         height: u32,
         codec: VideoCodecId,
         desired_track_num: Option<TrackNum>,
     ) -> Result<(Self, VideoTrack), Error> {
-        let mut track_num_out: TrackNum = 0;
+        let add = |segment: &mut MkvSegment<W>, width, height, number| {
+            segment.add_video_track(width, height, number, codec.codec_id())
+        };
+        let number = self.add_track(width, height, desired_track_num, add)?;
+        self.record_track(number, codec.codec_id(), None);
 
-        // Zero is not a valid track number, and to libwebm means "choose one for me".
-        // If this is the user's intent, they should instead pass `None`.
-        if desired_track_num == Some(0) {
-            return Err(Error::BadParam);
-        }
+        let track = VideoTrack {
+            segment: self.id,
+            number,
+        };
+        Ok((self, track))
+    }
 
-        // libwebm requires i32 for these
-        let width: i32 = try_as_i32(width)?;
-        let height: i32 = try_as_i32(height)?;
-        if width == 0 || height == 0 {
-            return Err(Error::BadParam);
-        }
-        let requested_track_num: i32 = try_as_i32(desired_track_num.unwrap_or(0))?;
+    /// Adds a new AV1 video track to this segment, returning its track number. The track is configured from the
+    /// stream's Sequence Header OBU: its size is the maximum frame size, the `CodecPrivate` data is set to the
+    /// matching `av1C` record, and color information is set as with [`SegmentBuilder::set_color`].
+    ///
+    /// See [`SegmentBuilder::add_video_track`] for the meaning of `desired_track_num`.
+    pub fn add_av1_track(
+        self,
+        sequence_header: &SequenceHeader,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        let (builder, track) = self.add_video_track(
+            sequence_header.max_frame_width,
+            sequence_header.max_frame_height,
+            VideoCodecId::AV1,
+            desired_track_num,
+        )?;
 
-        let result = unsafe {
-            ffi::mux::segment_add_video_track(
-                self.segment.as_ptr(),
-                width,
-                height,
-                requested_track_num,
-                codec.get_id(),
-                &mut track_num_out,
-            )
-        };
+        let builder = builder
+            .set_codec_private(track, &sequence_header.to_codec_private())?
+            .set_color(
+                track,
+                sequence_header.bit_depth,
+                sequence_header.color_subsampling(),
+                sequence_header.color_range(),
+            )?;
+        Ok((builder, track))
+    }
 
-        match result {
-            ResultCode::Ok => {
-                let track_num_out = NonZeroU64::new(track_num_out).ok_or(Error::Unknown)?;
+    /// Adds a new VP9 video track to this segment, returning its track number. The track is configured from the
+    /// header of the stream's first keyframe: its size is the frame size, the `CodecPrivate` data is set to the
+    /// matching codec features (including `level`, if given), and color information is set as with
+    /// [`SegmentBuilder::set_color`].
+    ///
+    /// See [`SegmentBuilder::add_video_track`] for the meaning of `desired_track_num`.
+    pub fn add_vp9_track(
+        self,
+        keyframe_header: &vp9::KeyframeHeader,
+        level: Option<u8>,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        let (builder, track) = self.add_video_track(
+            keyframe_header.width,
+            keyframe_header.height,
+            VideoCodecId::VP9,
+            desired_track_num,
+        )?;
 
-                // If a specific track number was requested, make sure we got it
-                if let Some(desired) = desired_track_num {
-                    if desired != track_num_out.get() {
-                        return Err(Error::Unknown);
-                    }
-                }
+        let builder = builder
+            .set_codec_private(track, &keyframe_header.to_codec_private(level))?
+            .set_color(
+                track,
+                keyframe_header.bit_depth,
+                keyframe_header.color_subsampling(),
+                keyframe_header.color_range(),
+            )?;
+        Ok((builder, track))
+    }
 
-                Ok((self, VideoTrack(track_num_out)))
-            },
-            ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
-        }
+    /// Adds a new video track with an arbitrary Matroska codec ID (such as `V_MPEG4/ISO/AVC`) to this segment,
+    /// returning its track number. Any `CodecPrivate` data the codec requires must be set with
+    /// [`SegmentBuilder::set_codec_private`].
+    ///
+    /// Codecs not allowed in WebM require [`DocType::Matroska`], see [`SegmentBuilder::set_doc_type`]. Otherwise,
+    /// this works just like [`SegmentBuilder::add_video_track`].
+    pub fn add_video_track_with_codec_id(
+        mut self,
+        width: u32,
+        height: u32,
+        codec_id: &str,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        check_codec_id(self.doc_type, codec_id)?;
+        let add = |segment: &mut MkvSegment<W>, width, height, number| {
+            segment.add_video_track(width, height, number, codec_id)
+        };
+        let number = self.add_track(width, height, desired_track_num, add)?;
+        self.record_track(number, codec_id, None);
+
+        self.has_non_webm_codec |= !is_webm_codec_id(codec_id.as_bytes());
+        let track = VideoTrack {
+            segment: self.id,
+            number,
+        };
+        Ok((self, track))
     }
 
     /// Adds a new audio track to this segment, returning its track number.
@@ -138,15 +261,101 @@ impl<W: Write> SegmentBuilder<W> {
     /// method succeeds, the returned track number is guaranteed to match the requested one. If a track with that
     /// number already exists, however, this method will fail. Leave as `None` to allow an available number to be
     /// chosen for you.
+    ///
+    /// `bit_depth` sets the `BitDepth` element, which is required for PCM codecs, and optional for others.
+    /// Codecs that are not allowed in WebM require [`DocType::Matroska`], see [`SegmentBuilder::set_doc_type`].
     pub fn add_audio_track(
-        self,
+        mut self,
         sample_rate: u32,
         channels: u32,
+        bit_depth: Option<u32>,
         codec: AudioCodecId,
         desired_track_num: Option<TrackNum>,
     ) -> Result<(Self, AudioTrack), Error> {
-        let mut track_num_out: TrackNum = 0;
+        if !codec.is_valid_bit_depth(bit_depth) || (self.doc_type == DocType::WebM && !codec.is_webm()) {
+            return Err(Error::BadParam);
+        }
+
+        let add = |segment: &mut MkvSegment<W>, rate, channels, number| {
+            segment.add_audio_track(rate, channels, number, codec.codec_id())
+        };
+        let number = self.add_track(sample_rate, channels, desired_track_num, add)?;
+        self.set_bit_depth(number, bit_depth)?;
+        self.record_track(number, codec.codec_id(), Some(channels));
+        self.has_non_webm_codec |= !codec.is_webm();
+
+        let track = AudioTrack {
+            segment: self.id,
+            number,
+        };
+        Ok((self, track))
+    }
 
+    /// Adds a new Vorbis audio track to this segment, returning its track number. The sample rate and channel count
+    /// are taken from the identification header, and the `CodecPrivate` data is assembled from all three headers.
+    ///
+    /// See [`SegmentBuilder::add_audio_track`] for the meaning of `desired_track_num`.
+    pub fn add_vorbis_track(
+        self,
+        headers: &VorbisHeaders,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, AudioTrack), Error> {
+        let info = headers.info();
+        let (builder, track) = self.add_audio_track(
+            info.sample_rate,
+            info.channels.into(),
+            None,
+            AudioCodecId::Vorbis,
+            desired_track_num,
+        )?;
+
+        let builder = builder.set_codec_private(track, &headers.to_codec_private())?;
+        Ok((builder, track))
+    }
+
+    /// Adds a new audio track with an arbitrary Matroska codec ID (such as `A_AAC`) to this segment, returning its
+    /// track number. Any `CodecPrivate` data the codec requires must be set with
+    /// [`SegmentBuilder::set_codec_private`].
+    ///
+    /// Codecs not allowed in WebM require [`DocType::Matroska`], see [`SegmentBuilder::set_doc_type`]. Otherwise,
+    /// this works just like [`SegmentBuilder::add_audio_track`].
+    pub fn add_audio_track_with_codec_id(
+        mut self,
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec_id: &str,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, AudioTrack), Error> {
+        check_codec_id(self.doc_type, codec_id)?;
+        if bit_depth == Some(0) {
+            return Err(Error::BadParam);
+        }
+
+        let add = |segment: &mut MkvSegment<W>, rate, channels, number| {
+            segment.add_audio_track(rate, channels, number, codec_id)
+        };
+        let number = self.add_track(sample_rate, channels, desired_track_num, add)?;
+        self.set_bit_depth(number, bit_depth)?;
+        self.record_track(number, codec_id, Some(channels));
+
+        self.has_non_webm_codec |= !is_webm_codec_id(codec_id.as_bytes());
+        let track = AudioTrack {
+            segment: self.id,
+            number,
+        };
+        Ok((self, track))
+    }
+
+    /// Validates the parameters shared by all kinds of tracks, and then adds one using `add`. The two numeric
+    /// parameters are passed on to it as `i32`, which libwebm requires.
+    fn add_track(
+        &mut self,
+        param1: u32,
+        param2: u32,
+        desired_track_num: Option<TrackNum>,
+        add: impl FnOnce(&mut MkvSegment<W>, i32, i32, i32) -> Result<TrackNum, Error>,
+    ) -> Result<NonZeroU64, Error> {
         // Zero is not a valid track number, and to libwebm means "choose one for me".
         // If this is the user's intent, they should instead pass `None`.
         if desired_track_num == Some(0) {
@@ -154,103 +363,190 @@ impl<W: Write> SegmentBuilder<W> {
         }
 
         // libwebm requires i32 for these
-        let sample_rate: i32 = try_as_i32(sample_rate)?;
-        let channels: i32 = try_as_i32(channels)?;
-        if sample_rate == 0 || channels == 0 {
+        let param1: i32 = try_as_i32(param1)?;
+        let param2: i32 = try_as_i32(param2)?;
+        if param1 == 0 || param2 == 0 {
             return Err(Error::BadParam);
         }
         let requested_track_num: i32 = try_as_i32(desired_track_num.unwrap_or(0))?;
 
-        let result = unsafe {
-            ffi::mux::segment_add_audio_track(
-                self.segment.as_ptr(),
-                sample_rate,
-                channels,
-                requested_track_num,
-                codec.get_id(),
-                &mut track_num_out,
-            )
-        };
+        let track_num_out = add(&mut self.segment, param1, param2, requested_track_num)?;
+        let track_num_out = NonZeroU64::new(track_num_out).ok_or(Error::Unknown)?;
 
-        match result {
-            ResultCode::Ok => {
-                let track_num_out = NonZeroU64::new(track_num_out).ok_or(Error::Unknown)?;
+        // If a specific track number was requested, make sure we got it
+        if let Some(desired) = desired_track_num {
+            if desired != track_num_out.get() {
+                return Err(Error::Unknown);
+            }
+        }
 
-                // If a specific track number was requested, make sure we got it
-                if let Some(desired) = desired_track_num {
-                    if desired != track_num_out.get() {
-                        return Err(Error::Unknown);
-                    }
-                }
+        Ok(track_num_out)
+    }
 
-                Ok((self, AudioTrack(track_num_out)))
-            },
-            ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+    fn record_track(&mut self, number: NonZeroU64, codec_id: &str, channels: Option<u32>) {
+        self.tracks.push(TrackInfo {
+            number,
+            codec_id: codec_id.to_owned(),
+            channels,
+            opus_clock: OpusClock::default(),
+        });
+    }
+
+    fn set_bit_depth(&mut self, track_num: NonZeroU64, bit_depth: Option<u32>) -> Result<(), Error> {
+        match bit_depth {
+            Some(bit_depth) => self.segment.set_audio_bit_depth(track_num.get(), bit_depth.into()),
+            None => Ok(()),
         }
     }
 
     /// Sets the `CodecPrivate` data for the specified track. If you have a [`VideoTrack`] or [`AudioTrack`], you
     /// can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
-    pub fn set_codec_private(self, track: impl Into<TrackNum>, data: &[u8]) -> Result<Self, Error> {
-        unsafe {
-            let len: i32 = data.len().try_into().map_err(|_| Error::BadParam)?;
-            let result = ffi::mux::segment_set_codec_private(
-                self.segment.as_ptr(),
-                track.into(),
-                data.as_ptr(),
-                len,
-            );
-
-            match result {
-                ResultCode::Ok => Ok(self),
-                ResultCode::BadParam => Err(Error::BadParam),
-                _ => Err(Error::Unknown),
-            }
+    ///
+    /// For [`AudioCodecId::Flac`] tracks, this fails with [`Error::BadParam`] unless `data` starts with the `fLaC`
+    /// marker and a `STREAMINFO` metadata block.
+    pub fn set_codec_private(mut self, track: impl Track, data: &[u8]) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        let info = find_track(&self.tracks, track.track_number());
+        if info.is_some_and(|info| info.codec_id == AudioCodecId::Flac.codec_id()) && !is_flac_codec_private(data) {
+            return Err(Error::BadParam);
         }
+
+        self.segment.set_codec_private(track.track_number(), data)?;
+        Ok(self)
+    }
+
+    /// Sets the `CodecPrivate` data of an Opus track to the given [`OpusHead`]. This also sets the track's
+    /// `CodecDelay` to the pre-skip, so that players drop it without shifting the audio, and its `SeekPreRoll` to
+    /// the recommended 80 ms.
+    ///
+    /// Fails if the track does not use [`AudioCodecId::Opus`], if the header is not
+    /// [valid](OpusHead::validate), or if its channel count differs from the one the track was added with.
+    pub fn set_opus_head(mut self, track: AudioTrack, head: &OpusHead) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        let info = find_track(&self.tracks, track.track_number()).ok_or(Error::BadParam)?;
+        if info.codec_id != AudioCodecId::Opus.codec_id() || info.channels != Some(head.channels.into()) {
+            return Err(Error::BadParam);
+        }
+
+        let data = head.to_bytes()?;
+        self.segment.set_codec_delay(track.track_number(), head.pre_skip_ns(), opus::SEEK_PRE_ROLL_NS)?;
+        self.set_codec_private(track, &data)
+    }
+
+    /// Sets the `CodecDelay` and `SeekPreRoll` of the specified track, in nanoseconds: how much of the decoder output
+    /// should be dropped from the start, and how long before a seek target decoding should start.
+    ///
+    /// For Opus tracks, [`SegmentBuilder::set_opus_head`] sets both from the header, which is usually what you want.
+    pub fn set_codec_delay(
+        mut self,
+        track: impl Track,
+        codec_delay_ns: u64,
+        seek_pre_roll_ns: u64,
+    ) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.segment.set_codec_delay(track.track_number(), codec_delay_ns, seek_pre_roll_ns)?;
+        Ok(self)
     }
 
     /// Sets color information for the specified video track.
     pub fn set_color(
-        self,
+        mut self,
         track: VideoTrack,
         bit_depth: u8,
         subsampling: ColorSubsampling,
         color_range: ColorRange,
     ) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
         let color_range = match color_range {
             ColorRange::Unspecified => 0,
             ColorRange::Broadcast => 1,
             ColorRange::Full => 2,
         };
 
-        let result = unsafe {
-            ffi::mux::mux_set_color(
-                self.segment.as_ptr(),
-                track.into(),
-                bit_depth,
-                subsampling.chroma_horizontal,
-                subsampling.chroma_vertical,
-                color_range,
-            )
-        };
+        self.segment.set_color(
+            track.into(),
+            bit_depth,
+            subsampling.chroma_horizontal,
+            subsampling.chroma_vertical,
+            color_range,
+        )?;
+        Ok(self)
+    }
+
+    /// Sets the color space and HDR metadata of the specified video track, such as its transfer characteristics and
+    /// mastering display. This is written to the same `Colour` element as [`SegmentBuilder::set_color`], and
+    /// either may be set first.
+    pub fn set_color_metadata(mut self, track: VideoTrack, metadata: &ColorMetadata) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.segment.set_color_metadata(track.into(), metadata)?;
+        Ok(self)
+    }
+
+    /// Sets the size at which frames of the specified video track should be displayed, for when it differs from the
+    /// frame size, such as with non-square pixels.
+    pub fn set_display_size(mut self, track: VideoTrack, width: u32, height: u32) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        if width == 0 || height == 0 {
+            return Err(Error::BadParam);
+        }
+
+        self.segment.set_display_size(track.into(), width, height)?;
+        Ok(self)
+    }
 
-        match result {
-            ResultCode::Ok => Ok(self),
-            ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+    /// Sets the frame rate of the specified video track, written as its `FrameRate` element. This is only
+    /// informational, as frames are timed by their timestamps.
+    pub fn set_frame_rate(mut self, track: VideoTrack, frame_rate: f64) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        if !frame_rate.is_finite() || frame_rate <= 0.0 {
+            return Err(Error::BadParam);
         }
+
+        self.segment.set_frame_rate(track.into(), frame_rate)?;
+        Ok(self)
+    }
+
+    /// Sets the human-readable name of the specified track, written as its `Name` element.
+    pub fn set_track_name(mut self, track: impl Track, name: &str) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.segment.set_track_name(track.track_number(), name)?;
+        Ok(self)
+    }
+
+    /// Sets the language of the specified track, written as its `Language` element. This is a language code in the
+    /// Matroska form (ISO 639-2, such as `eng`).
+    pub fn set_track_language(mut self, track: impl Track, language: &str) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.segment.set_track_language(track.track_number(), language)?;
+        Ok(self)
     }
 
     /// Finalizes track information and makes the segment ready to accept video/audio frames.
     #[must_use]
     pub fn build(self) -> Segment<W> {
-        let Self { segment, writer } = self;
+        let Self {
+            mut segment,
+            id,
+            doc_type,
+            tracks,
+            validate_keyframes,
+            ..
+        } = self;
+
+        segment.set_doc_type(doc_type);
         Segment {
-            ffi: segment,
-            writer,
+            segment,
+            id,
+            tracks,
+            validate_keyframes,
         }
     }
+
+    /// Returns the ID that track handles created by this builder are tied to.
+    #[must_use]
+    pub fn id(&self) -> SegmentId {
+        self.id
+    }
 }
 
 impl<W: Write> std::fmt::Debug for SegmentBuilder<W> {
@@ -271,16 +567,12 @@ impl<W: Write> std::fmt::Debug for SegmentBuilder<W> {
 /// This performs a few final writes, and the resulting WebM may not be playable without it.
 /// Notably, for memory safety reasons, just dropping a [`Segment`] will not finalize it!
 pub struct Segment<W: Write> {
-    ffi: OwnedSegmentPtr,
-    writer: Writer<W>,
+    segment: MkvSegment<W>,
+    id: SegmentId,
+    tracks: Vec<TrackInfo>,
+    validate_keyframes: bool,
 }
 
-// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
-// Thus, safety is only conditional on the write destination `W`, hence the `Send` bound on it.
-//
-// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
-unsafe impl<W: Write + Send> Send for Segment<W> {}
-
 impl<W: Write> Segment<W> {
     /// Adds a frame to the track with the specified track number. If you have a [`VideoTrack`] or
     /// [`AudioTrack`], you can either pass it directly, or call `track_number()` to get the underlying [`TrackNum`].
@@ -288,29 +580,140 @@ impl<W: Write> Segment<W> {
     /// The timestamp must be in nanosecond units, and must be monotonically increasing with respect to all other
     /// timestamps written so far, including those of other tracks! Repeating the last written timestamp is allowed,
     /// however players generally don't handle this well if both such frames are on the same track.
+    ///
+    /// For tracks added as [`VideoCodecId::AV1`], temporal delimiter OBUs are removed from `data` before writing it,
+    /// as WebM does not allow them.
+    ///
+    /// If keyframe validation is enabled (see [`SegmentBuilder::set_keyframe_validation`]), `keyframe` must match
+    /// the frame data, or this fails with [`Error::KeyframeMismatch`].
     pub fn add_frame(
         &mut self,
-        track: impl Into<TrackNum>,
+        track: impl Track,
         data: &[u8],
         timestamp_ns: u64,
         keyframe: bool,
     ) -> Result<(), Error> {
-        let result = unsafe {
-            ffi::mux::segment_add_frame(
-                self.ffi.as_ptr(),
-                track.into(),
-                data.as_ptr(),
-                data.len(),
-                timestamp_ns,
-                keyframe,
-            )
-        };
+        self.write_frame(track, data, timestamp_ns, keyframe, 0)
+    }
+
+    /// Adds a frame just like [`Segment::add_frame`], along with a `DiscardPadding` of `discard_padding_ns`
+    /// nanoseconds: the duration of audio at the end of the frame (or at the start, if negative) that players should
+    /// drop after decoding.
+    ///
+    /// This is how the end of an Opus stream is trimmed to the exact sample, as the last packet is usually padded
+    /// to a full frame. A `discard_padding_ns` of zero is the same as calling [`Segment::add_frame`].
+    pub fn add_frame_with_discard_padding(
+        &mut self,
+        track: impl Track,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        self.write_frame(track, data, timestamp_ns, keyframe, discard_padding_ns)
+    }
 
-        match result {
-            ResultCode::Ok => Ok(()),
-            ResultCode::BadParam => Err(Error::BadParam),
-            _ => Err(Error::Unknown),
+    fn write_frame(
+        &mut self,
+        track: impl Track,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        check_segment_id(self.id, &track)?;
+        let info = find_track(&self.tracks, track.track_number());
+        if self.validate_keyframes {
+            if let Some(detected) = info.and_then(|info| info.detect_keyframe(data)) {
+                if detected? != keyframe {
+                    return Err(Error::KeyframeMismatch);
+                }
+            }
         }
+
+        let data = match info {
+            Some(info) if info.codec_id == VideoCodecId::AV1.codec_id() => av1::strip_temporal_delimiters(data)?,
+            _ => Cow::Borrowed(data),
+        };
+
+        self.segment.add_frame(track.track_number(), &data, timestamp_ns, keyframe, discard_padding_ns)
+    }
+
+    /// Adds a frame just like [`Segment::add_frame`], but determines whether it is a keyframe from the frame data
+    /// itself, and returns the result.
+    ///
+    /// This works for VP8, VP9 and AV1 tracks, and audio tracks, on which every frame is treated as a keyframe.
+    /// For any other track, this fails with [`Error::BadParam`].
+    pub fn add_frame_auto(&mut self, track: impl Track, data: &[u8], timestamp_ns: u64) -> Result<bool, Error> {
+        check_segment_id(self.id, &track)?;
+        let info = find_track(&self.tracks, track.track_number()).ok_or(Error::BadParam)?;
+        let keyframe = match info.detect_keyframe(data) {
+            Some(detected) => detected?,
+            None if info.channels.is_some() => true,
+            None => return Err(Error::BadParam),
+        };
+
+        self.add_frame(track, data, timestamp_ns, keyframe)?;
+        Ok(keyframe)
+    }
+
+    /// Sets the timestamp (in nanoseconds) from which [`Segment::add_opus_packet`] counts on `track`, and starts
+    /// counting over. Without this, the first packet is placed at zero.
+    pub fn set_opus_start(&mut self, track: AudioTrack, start_timestamp_ns: u64) -> Result<(), Error> {
+        let info = self.opus_track_mut(track)?;
+        info.opus_clock = OpusClock {
+            start_ns: start_timestamp_ns,
+            samples: 0,
+        };
+        Ok(())
+    }
+
+    /// Adds an Opus packet to `track`, timestamped right after the previous one added this way. Returns the
+    /// timestamp (in nanoseconds) it was written with.
+    ///
+    /// Durations are taken from each packet's TOC byte (see [`opus::packet_samples`]), and timestamps are computed
+    /// from the total number of samples so far, so they don't drift. Timestamps still need to be increasing across
+    /// all tracks, as with [`Segment::add_frame`].
+    ///
+    /// The first packet is placed at the start timestamp (see [`Segment::set_opus_start`]). The decoder's pre-skip
+    /// is accounted for by the `CodecDelay` [`SegmentBuilder::set_opus_head`] writes, so the first audible sample
+    /// plays at that timestamp as well.
+    pub fn add_opus_packet(&mut self, track: AudioTrack, packet: &[u8]) -> Result<u64, Error> {
+        let samples = opus::packet_samples(packet)?;
+        let clock = self.opus_track_mut(track)?.opus_clock;
+        let timestamp_ns = clock.timestamp_ns();
+
+        self.add_frame(track, packet, timestamp_ns, true)?;
+        self.opus_track_mut(track)?.opus_clock.samples += u64::from(samples);
+        Ok(timestamp_ns)
+    }
+
+    fn opus_track_mut(&mut self, track: AudioTrack) -> Result<&mut TrackInfo, Error> {
+        check_segment_id(self.id, &track)?;
+        self.tracks
+            .iter_mut()
+            .find(|info| info.number == track.number && info.codec_id == AudioCodecId::Opus.codec_id())
+            .ok_or(Error::BadParam)
+    }
+
+    /// Returns the ID that track handles for this segment are tied to.
+    #[must_use]
+    pub fn id(&self) -> SegmentId {
+        self.id
+    }
+
+    /// Gets a reference to the [`Writer`] this segment writes to.
+    #[must_use]
+    pub fn writer(&self) -> &Writer<W> {
+        self.segment.writer()
+    }
+
+    /// Gets a mutable reference to the write destination of the underlying [`Writer`].
+    ///
+    /// See [`Writer::get_mut()`] for caveats. This is how you reach a [`MemorySink`](super::MemorySink) to take out
+    /// finalized data while still adding frames.
+    pub fn dest_mut(&mut self) -> &mut W {
+        self.segment.writer_mut().get_mut()
     }
 
     /// Finalizes the segment and consumes it, returning the underlying writer. Note that the finalizing process will
@@ -321,15 +724,14 @@ impl<W: Write> Segment<W> {
     /// You may specify an explicit `duration` to be written to the segment's `Duration` element. However, this requires
     /// seeking and thus will be ignored if the writer was not created with [`Seek`](std::io::Seek) support.
     ///
-    /// Finalization is known to fail if no frames have been written.
+    /// With libwebm, finalization is known to fail if no frames have been written.
     pub fn finalize(self, duration: Option<u64>) -> Result<Writer<W>, Writer<W>> {
-        let Self { ffi, writer } = self;
-        let result = unsafe { ffi::mux::finalize_segment(ffi.as_ptr(), duration.unwrap_or(0)) };
+        self.segment.finalize(duration).map_err(|(_, writer)| writer)
+    }
 
-        match result {
-            ResultCode::Ok => Ok(writer),
-            _ => Err(writer),
-        }
+    /// Finalizes the segment like [`Segment::finalize`], but also reports why finalizing failed.
+    pub(crate) fn finalize_with_error(self, duration: Option<u64>) -> Result<Writer<W>, (Error, Writer<W>)> {
+        self.segment.finalize(duration)
     }
 }
 
@@ -341,17 +743,123 @@ impl<W: Write> std::fmt::Debug for Segment<W> {
     }
 }
 
-fn try_as_i32(x: impl TryInto<i32>) -> Result<i32, Error> {
+/// The codec IDs allowed in WebM files. libwebm writes the `matroska` DocType if any track uses a different one.
+const WEBM_CODEC_IDS: &[&[u8]] = &[b"V_VP8", b"V_VP9", b"V_AV1", b"A_OPUS", b"A_VORBIS"];
+
+fn find_track(tracks: &[TrackInfo], number: TrackNum) -> Option<&TrackInfo> {
+    tracks.iter().find(|info| info.number.get() == number)
+}
+
+/// Returns whether `data` starts the way the `CodecPrivate` of a FLAC track must: with the `fLaC` marker, followed
+/// by a `STREAMINFO` metadata block, which always comes first and is 34 bytes long.
+fn is_flac_codec_private(data: &[u8]) -> bool {
+    const STREAMINFO_LEN: u8 = 34;
+
+    let Some((header, rest)) = data.strip_prefix(b"fLaC").and_then(<[u8]>::split_first_chunk::<4>) else {
+        return false;
+    };
+    // The top bit of the type flags the last metadata block, and `STREAMINFO` is type 0
+    header[0] & 0x7F == 0 && header[1..] == [0, 0, STREAMINFO_LEN] && rest.len() >= usize::from(STREAMINFO_LEN)
+}
+
+pub(super) fn is_webm_codec_id(codec_id: &[u8]) -> bool {
+    WEBM_CODEC_IDS.contains(&codec_id)
+}
+
+/// Makes sure `codec_id` is a well-formed codec ID that `doc_type` allows, for tracks added by codec ID.
+pub(super) fn check_codec_id(doc_type: DocType, codec_id: &str) -> Result<(), Error> {
+    // Matroska codec IDs are printable ASCII
+    if codec_id.is_empty() || !codec_id.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(Error::BadParam);
+    }
+    if doc_type == DocType::WebM && !is_webm_codec_id(codec_id.as_bytes()) {
+        return Err(Error::BadParam);
+    }
+
+    Ok(())
+}
+
+/// Makes sure a track handle was created for the segment with ID `id`. Raw track numbers always pass.
+pub(super) fn check_segment_id(id: SegmentId, track: &impl Track) -> Result<(), Error> {
+    match track.segment_id() {
+        Some(track_segment) if track_segment != id => Err(Error::ForeignTrack),
+        _ => Ok(()),
+    }
+}
+
+pub(super) fn try_as_i32(x: impl TryInto<i32>) -> Result<i32, Error> {
     x.try_into().map_err(|_| Error::BadParam)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::ebml::{Element, ElementId, Reader};
     use crate::mux::Writer;
+    use crate::validate::{validate, Mode, ViolationKind};
 
     use super::*;
     use std::io::Cursor;
 
+    /// The output of a finished segment, read back to check what the muxer wrote.
+    struct Output(Reader<Cursor<Vec<u8>>>);
+
+    impl Output {
+        fn new(output: Vec<u8>) -> Self {
+            Self(Reader::new(Cursor::new(output)))
+        }
+
+        /// Returns the children of `parent`, or the top-level elements.
+        fn children(&mut self, parent: Option<&Element>) -> Vec<Element> {
+            let mut children = match parent {
+                Some(parent) => self.0.children(parent).unwrap(),
+                None => self.0.top_level(),
+            };
+            let mut elements = Vec::new();
+            while let Some(child) = self.0.next_child(&mut children).unwrap() {
+                elements.push(child);
+            }
+            elements
+        }
+
+        /// Finds the first element along `path`, starting at the children of `parent`, or at the top level.
+        fn find(&mut self, parent: Option<&Element>, path: &[ElementId]) -> Option<Element> {
+            let mut parent = parent.copied();
+            for &id in path {
+                parent = Some(self.children(parent.as_ref()).into_iter().find(|child| child.id == id)?);
+            }
+            parent
+        }
+
+        fn get(&mut self, parent: Option<&Element>, path: &[ElementId]) -> Element {
+            self.find(parent, path).unwrap_or_else(|| panic!("{path:?} should exist"))
+        }
+
+        fn binary(&mut self, parent: &Element, id: ElementId) -> Vec<u8> {
+            let element = self.get(Some(parent), &[id]);
+            self.0.read_binary(&element).unwrap()
+        }
+
+        fn string(&mut self, parent: &Element, id: ElementId) -> String {
+            let element = self.get(Some(parent), &[id]);
+            self.0.read_string(&element).unwrap()
+        }
+
+        fn uint(&mut self, parent: &Element, id: ElementId) -> u64 {
+            let element = self.get(Some(parent), &[id]);
+            self.0.read_uint(&element).unwrap()
+        }
+
+        fn float(&mut self, parent: &Element, id: ElementId) -> f64 {
+            let element = self.get(Some(parent), &[id]);
+            self.0.read_float(&element).unwrap()
+        }
+
+        /// Returns the first `TrackEntry` of the segment.
+        fn track_entry(&mut self) -> Element {
+            self.get(None, &[ElementId::SEGMENT, ElementId::TRACKS, ElementId::TRACK_ENTRY])
+        }
+    }
+
     fn make_segment_builder() -> SegmentBuilder<Cursor<Vec<u8>>> {
         let output = Vec::new();
         let writer = Writer::new(Cursor::new(output));
@@ -387,7 +895,404 @@ mod tests {
             panic!("First video track unexpectedly failed")
         };
 
-        let audio_track = builder.add_audio_track(420, 420, AudioCodecId::Opus, Some(123));
+        let audio_track = builder.add_audio_track(420, 420, None, AudioCodecId::Opus, Some(123));
         assert!(audio_track.is_err());
     }
+
+    #[test]
+    fn foreign_track_handles() {
+        fn make_builder_with_tracks() -> (SegmentBuilder<Cursor<Vec<u8>>>, VideoTrack, AudioTrack) {
+            let builder = make_segment_builder();
+            let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP8, None).unwrap();
+            let (builder, audio_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+            (builder, video_track, audio_track)
+        }
+
+        let (builder_a, video_a, audio_a) = make_builder_with_tracks();
+        let (builder_b, video_b, audio_b) = make_builder_with_tracks();
+
+        // Both builders chose the same numbers, but the handles are not interchangeable
+        assert_eq!(video_a.track_number(), video_b.track_number());
+        assert_ne!(builder_a.id(), builder_b.id());
+        let color = builder_b.set_color(video_a, 8, ColorSubsampling::default(), ColorRange::Full);
+        assert!(matches!(color, Err(Error::ForeignTrack)));
+        let (builder_c, ..) = make_builder_with_tracks();
+        assert!(matches!(builder_c.set_codec_private(audio_a, &[0; 19]), Err(Error::ForeignTrack)));
+
+        let mut segment_a = builder_a.set_codec_private(audio_a, &[0; 19]).unwrap().build();
+        assert!(matches!(segment_a.add_frame(video_b, &[0; 16], 0, true), Err(Error::ForeignTrack)));
+        assert!(matches!(segment_a.add_frame(audio_b, &[0; 16], 0, true), Err(Error::ForeignTrack)));
+        segment_a.add_frame(video_a, &[0; 16], 0, true).unwrap();
+
+        // Raw track numbers are only checked by the muxing backend
+        segment_a.add_frame(audio_b.track_number(), &[0; 16], 1, true).unwrap();
+    }
+
+    #[test]
+    fn opus_head_channel_count() {
+        fn make_builder_with_tracks() -> (SegmentBuilder<Cursor<Vec<u8>>>, AudioTrack, AudioTrack) {
+            let builder = make_segment_builder();
+            let (builder, opus_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+            let (builder, vorbis_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Vorbis, None).unwrap();
+            (builder, opus_track, vorbis_track)
+        }
+
+        let stereo_head = OpusHead::new(2, 312, 48_000).unwrap();
+        let surround_head = OpusHead::new(6, 312, 48_000).unwrap();
+        let (builder, opus_track, _) = make_builder_with_tracks();
+        assert!(builder.set_opus_head(opus_track, &surround_head).is_err());
+        let (builder, _, vorbis_track) = make_builder_with_tracks();
+        assert!(builder.set_opus_head(vorbis_track, &stereo_head).is_err());
+
+        let (builder, opus_track, _) = make_builder_with_tracks();
+        let mut segment = builder.set_opus_head(opus_track, &stereo_head).unwrap().build();
+        segment.add_frame(opus_track, &[0; 16], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+
+        let mut output = Output::new(output);
+        let entry = output.track_entry();
+        assert_eq!(output.binary(&entry, ElementId::CODEC_PRIVATE), stereo_head.to_bytes().unwrap());
+        assert_eq!(output.uint(&entry, ElementId::CODEC_DELAY), 6_500_000);
+        assert_eq!(output.uint(&entry, ElementId::SEEK_PRE_ROLL), 80_000_000);
+    }
+
+    #[test]
+    fn opus_packet_timestamps() {
+        let builder = make_segment_builder();
+        let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP8, None).unwrap();
+        let (builder, opus_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let (builder, vorbis_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Vorbis, None).unwrap();
+        let mut segment = builder.build();
+
+        // CELT 20 ms, then CELT 2.5 ms three times, then SILK 60 ms with two frames
+        assert_eq!(segment.add_opus_packet(opus_track, &[0xFC, 0xAB]).unwrap(), 0);
+        assert_eq!(segment.add_opus_packet(opus_track, &[0x80, 0xAB]).unwrap(), 20_000_000);
+        assert_eq!(segment.add_opus_packet(opus_track, &[0x80, 0xAB]).unwrap(), 22_500_000);
+        assert_eq!(segment.add_opus_packet(opus_track, &[0x80, 0xAB]).unwrap(), 25_000_000);
+        assert_eq!(segment.add_opus_packet(opus_track, &[0x19, 0xAB]).unwrap(), 27_500_000);
+
+        segment.set_opus_start(opus_track, 1_000_000_000).unwrap();
+        assert_eq!(segment.add_opus_packet(opus_track, &[0xFC, 0xAB]).unwrap(), 1_000_000_000);
+        assert_eq!(segment.add_opus_packet(opus_track, &[0xFC, 0xAB]).unwrap(), 1_020_000_000);
+
+        // Invalid packets don't advance the clock
+        assert!(segment.add_opus_packet(opus_track, &[]).is_err());
+        assert_eq!(segment.add_opus_packet(opus_track, &[0xFC, 0xAB]).unwrap(), 1_040_000_000);
+
+        assert!(segment.add_opus_packet(vorbis_track, &[0xFC, 0xAB]).is_err());
+        let video_as_audio = AudioTrack {
+            segment: video_track.segment,
+            number: video_track.number,
+        };
+        assert!(segment.set_opus_start(video_as_audio, 0).is_err());
+    }
+
+    #[test]
+    fn vorbis_track_from_headers() {
+        let identification = [
+            b"\x01vorbis".as_slice(),
+            &[0, 0, 0, 0, 2, 0x44, 0xAC, 0, 0, 0, 0, 0, 0, 0, 0xF4, 1, 0, 0, 0, 0, 0, 0xB8, 1],
+        ]
+        .concat();
+        let headers = VorbisHeaders::new(&identification, b"\x03vorbis\x01", b"\x05vorbis\x01").unwrap();
+
+        let (builder, audio_track) = make_segment_builder().add_vorbis_track(&headers, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(audio_track, &[0; 16], 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+
+        let mut output = Output::new(output);
+        let entry = output.track_entry();
+        assert_eq!(output.string(&entry, ElementId::CODEC_ID), "A_VORBIS");
+        assert_eq!(output.binary(&entry, ElementId::CODEC_PRIVATE), headers.to_codec_private());
+        let audio = output.get(Some(&entry), &[ElementId::AUDIO]);
+        assert_eq!(output.float(&audio, ElementId::SAMPLING_FREQUENCY), 44_100.0);
+        assert_eq!(output.uint(&audio, ElementId::CHANNELS), 2);
+    }
+
+    #[test]
+    fn av1_temporal_delimiters_stripped() {
+        let sequence_header_obu = [
+            0x0A, 0x0E, 0x00, 0x00, 0x00, 0x42, 0x22, 0xBF, 0x1F, 0x3F, 0xFE, 0x64, 0x04, 0x04, 0x04, 0x10,
+        ];
+        let sequence_header = SequenceHeader::from_obus(&sequence_header_obu).unwrap();
+        let temporal_delimiter = [0x12, 0x00];
+        let frame = [0x32, 0x08, 0xDE, 0xAD, 0xBE, 0xEF, 0xDE, 0xAD, 0xBE, 0xEF];
+
+        let (builder, video_track) = make_segment_builder().add_av1_track(&sequence_header, None).unwrap();
+        let mut segment = builder.build();
+        let first_frame = [temporal_delimiter.as_slice(), &sequence_header_obu, &frame].concat();
+        segment.add_frame(video_track, &first_frame, 0, true).unwrap();
+        let second_frame = [temporal_delimiter.as_slice(), &frame].concat();
+        segment.add_frame(video_track, &second_frame, 1_000_000, false).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(&[sequence_header_obu.as_slice(), &frame].concat()));
+        assert!(!contains(&first_frame));
+        assert!(!contains(&second_frame));
+
+        let mut output = Output::new(output);
+        let entry = output.track_entry();
+        assert_eq!(output.string(&entry, ElementId::CODEC_ID), "V_AV1");
+        assert_eq!(output.binary(&entry, ElementId::CODEC_PRIVATE), sequence_header.to_codec_private());
+    }
+
+    #[test]
+    fn keyframe_detection() {
+        let keyframe = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01];
+        let inter_frame = [0x31, 0x05, 0x00, 0xAB];
+
+        let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+        let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let (builder, custom_track) = builder.add_video_track_with_codec_id(420, 420, "V_THEORA", None).unwrap();
+        let mut segment = builder.build();
+
+        assert!(segment.add_frame_auto(video_track, &keyframe, 0).unwrap());
+        assert!(segment.add_frame_auto(audio_track, &[0xFC; 8], 1).unwrap());
+        assert!(!segment.add_frame_auto(video_track, &inter_frame, 2).unwrap());
+        assert!(segment.add_frame_auto(video_track, &[0x50, 0x42], 3).is_err());
+        assert!(matches!(segment.add_frame_auto(custom_track, &[0; 8], 3), Err(Error::BadParam)));
+
+        // Without validation, the flag is trusted
+        segment.add_frame(video_track, &inter_frame, 3, true).unwrap();
+
+        let builder = make_segment_builder().set_keyframe_validation(true);
+        let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let mut segment = builder.build();
+        assert!(matches!(segment.add_frame(video_track, &keyframe, 0, false), Err(Error::KeyframeMismatch)));
+        segment.add_frame(video_track, &keyframe, 0, true).unwrap();
+        assert!(matches!(segment.add_frame(video_track, &inter_frame, 1, true), Err(Error::KeyframeMismatch)));
+        segment.add_frame(video_track, &inter_frame, 1, false).unwrap();
+        segment.add_frame(audio_track, &[0xFC; 8], 2, false).unwrap();
+    }
+
+    #[test]
+    fn matroska_codec_ids() {
+        // WebM mode only allows WebM codecs, whichever way they are specified
+        let builder = make_segment_builder();
+        assert!(builder.add_video_track_with_codec_id(420, 420, "V_MPEG4/ISO/AVC", None).is_err());
+        let builder = make_segment_builder();
+        let (builder, _) = builder.add_video_track_with_codec_id(420, 420, "V_VP9", None).unwrap();
+        assert!(builder.add_audio_track_with_codec_id(48_000, 2, None, "A_AAC", None).is_err());
+
+        let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+        assert!(builder.add_video_track_with_codec_id(420, 420, "", None).is_err());
+        let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+        let (builder, video_track) = builder.add_video_track_with_codec_id(420, 420, "V_MPEG4/ISO/AVC", None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track_with_codec_id(44_100, 2, None, "A_AAC", None).unwrap();
+        let builder = builder
+            .set_codec_private(video_track, &[1, 0x64, 0, 0x1F, 0xFF, 0xE0, 0])
+            .unwrap()
+            .set_codec_private(audio_track, &[0x12, 0x10])
+            .unwrap();
+
+        // Can't go back now that there are non-WebM tracks
+        let Ok(builder) = builder.set_doc_type(DocType::Matroska) else {
+            panic!("Setting the same doc type again should succeed")
+        };
+        assert!(builder.set_doc_type(DocType::WebM).is_err());
+    }
+
+    #[test]
+    fn matroska_doc_type_written() {
+        let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+        let (builder, video_track) = builder.add_video_track_with_codec_id(420, 420, "V_MPEG4/ISO/AVC", None).unwrap();
+        let (builder, audio_track) = builder.add_audio_track_with_codec_id(44_100, 2, None, "A_AAC", None).unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(video_track, &[0; 32], 0, true).unwrap();
+        segment.add_frame(audio_track, &[0; 16], 1, true).unwrap();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"matroska"));
+        assert!(contains(b"V_MPEG4/ISO/AVC"));
+        assert!(contains(b"A_AAC"));
+    }
+
+    #[test]
+    fn matroska_doc_type_written_for_webm_codecs() {
+        let doc_type = |audio_codec, bit_depth| {
+            let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+            let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP9, None).unwrap();
+            let (builder, audio_track) = builder.add_audio_track(48_000, 2, bit_depth, audio_codec, None).unwrap();
+            let mut segment = builder.build();
+            segment.add_frame(video_track, &[0; 32], 0, true).unwrap();
+            segment.add_frame(audio_track, &[0; 16], 1, true).unwrap();
+
+            let Ok(writer) = segment.finalize(None) else {
+                panic!("Finalization unexpectedly failed")
+            };
+            let output = writer.into_inner().into_inner();
+            // Apart from what makes it Matroska, the file must still be sound, as libwebm has its header rewritten
+            let violations = validate(Cursor::new(&output), Mode::File).unwrap();
+            assert!(violations
+                .iter()
+                .all(|violation| matches!(violation.kind, ViolationKind::DocType(_) | ViolationKind::CodecId { .. })));
+            let mut output = Output::new(output);
+            let header = output.get(None, &[ElementId::EBML]);
+            output.string(&header, ElementId::DOC_TYPE)
+        };
+
+        // The `DocType` that was set is written, even if all tracks would also fit into WebM
+        assert_eq!(doc_type(AudioCodecId::Flac, Some(16)), "matroska");
+        assert_eq!(doc_type(AudioCodecId::Opus, None), "matroska");
+    }
+
+    #[test]
+    fn lossless_audio_track_headers() {
+        const AUDIO_CODECS: [AudioCodecId; 3] =
+            [AudioCodecId::Flac, AudioCodecId::PcmIntLittleEndian, AudioCodecId::PcmFloat];
+
+        // Not allowed in WebM
+        for codec in AUDIO_CODECS {
+            assert!(make_segment_builder().add_audio_track(48_000, 2, Some(32), codec, None).is_err());
+        }
+
+        let matroska_builder = || make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+        for (codec, bit_depth) in [
+            (AudioCodecId::PcmIntLittleEndian, None),
+            (AudioCodecId::PcmIntLittleEndian, Some(12)),
+            (AudioCodecId::PcmFloat, Some(16)),
+            (AudioCodecId::Flac, Some(0)),
+        ] {
+            assert!(matroska_builder().add_audio_track(48_000, 2, bit_depth, codec, None).is_err());
+        }
+
+        let flac_private = [b"fLaC".as_slice(), &[0x80, 0, 0, 34], &[0xAB; 34]].concat();
+        let expected = [
+            (AudioCodecId::Flac, "A_FLAC", 96_000, 2, Some(24)),
+            (AudioCodecId::PcmIntLittleEndian, "A_PCM/INT/LIT", 48_000, 1, Some(16)),
+            (AudioCodecId::PcmFloat, "A_PCM/FLOAT/IEEE", 44_100, 6, Some(64)),
+            (AudioCodecId::Opus, "A_OPUS", 48_000, 2, None),
+        ];
+
+        let mut builder = matroska_builder();
+        let mut tracks = Vec::new();
+        for (codec, _, sample_rate, channels, bit_depth) in expected {
+            let (new_builder, track) = builder.add_audio_track(sample_rate, channels, bit_depth, codec, None).unwrap();
+            builder = new_builder;
+            tracks.push(track);
+        }
+        let builder = builder.set_codec_private(tracks[0], &flac_private).unwrap();
+        let mut segment = builder.build();
+        for (i, &track) in tracks.iter().enumerate() {
+            segment.add_frame(track, &[0; 64], i as u64, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+
+        let mut output = Output::new(output);
+        let ebml_header = output.get(None, &[ElementId::EBML]);
+        assert_eq!(output.string(&ebml_header, ElementId::DOC_TYPE), "matroska");
+
+        let tracks_element = output.get(None, &[ElementId::SEGMENT, ElementId::TRACKS]);
+        let entries = output.children(Some(&tracks_element));
+        assert_eq!(entries.len(), expected.len());
+        for (entry, (track, (codec, codec_id, sample_rate, channels, bit_depth))) in
+            entries.into_iter().zip(tracks.iter().zip(expected))
+        {
+            assert_eq!(entry.id, ElementId::TRACK_ENTRY);
+            assert_eq!(output.uint(&entry, ElementId::TRACK_NUMBER), track.track_number());
+            assert_eq!(output.string(&entry, ElementId::CODEC_ID), codec_id);
+
+            let audio = output.get(Some(&entry), &[ElementId::AUDIO]);
+            assert_eq!(output.float(&audio, ElementId::SAMPLING_FREQUENCY), f64::from(sample_rate));
+            assert_eq!(output.uint(&audio, ElementId::CHANNELS), u64::from(channels));
+            let written_bit_depth = output.find(Some(&audio), &[ElementId::BIT_DEPTH]);
+            let written_bit_depth = written_bit_depth.map(|element| output.0.read_uint(&element).unwrap());
+            assert_eq!(written_bit_depth, bit_depth.map(u64::from));
+
+            if codec == AudioCodecId::Flac {
+                assert_eq!(output.binary(&entry, ElementId::CODEC_PRIVATE), flac_private);
+            }
+        }
+    }
+
+    #[test]
+    fn flac_codec_private_needs_streaminfo() {
+        let make_builder_with_tracks = || {
+            let builder = make_segment_builder().set_doc_type(DocType::Matroska).unwrap();
+            let (builder, flac) = builder.add_audio_track(48_000, 2, Some(16), AudioCodecId::Flac, None).unwrap();
+            let (builder, custom) = builder.add_audio_track_with_codec_id(48_000, 2, None, "A_FLAC", None).unwrap();
+            (builder, flac, custom)
+        };
+
+        let streaminfo = [0xAB; 34];
+        let invalid = [
+            // Cut short
+            [b"fLaC".as_slice(), &[0x80, 0, 0, 34]].concat(),
+            // Starting with a `PADDING` block, or with a `STREAMINFO` of the wrong length
+            [b"fLaC".as_slice(), &[0x01, 0, 0, 34], &streaminfo].concat(),
+            [b"fLaC".as_slice(), &[0x80, 0, 0, 33], &streaminfo].concat(),
+            // Missing the marker
+            [[0x80, 0, 0, 34].as_slice(), &streaminfo].concat(),
+        ];
+        for data in &invalid {
+            let (builder, flac, _) = make_builder_with_tracks();
+            assert!(matches!(builder.set_codec_private(flac, data), Err(Error::BadParam)));
+            let (builder, _, custom) = make_builder_with_tracks();
+            assert!(matches!(builder.set_codec_private(custom, data), Err(Error::BadParam)));
+        }
+
+        // Further metadata blocks may follow
+        let valid = [b"fLaC".as_slice(), &[0x00, 0, 0, 34], &streaminfo, &[0x81, 0, 0, 2, 0, 0]].concat();
+        let (builder, flac, custom) = make_builder_with_tracks();
+        let builder = builder.set_codec_private(flac, &valid).unwrap().set_codec_private(custom, &valid).unwrap();
+
+        // Only FLAC tracks are checked
+        let (builder, pcm) =
+            builder.add_audio_track(48_000, 2, Some(16), AudioCodecId::PcmIntLittleEndian, None).unwrap();
+        assert!(builder.set_codec_private(pcm, &invalid[0]).is_ok());
+    }
+
+    #[test]
+    fn in_memory_streaming() {
+        let builder = SegmentBuilder::new(Writer::new_in_memory()).unwrap();
+        let (builder, video_track) = builder.add_video_track(420, 420, VideoCodecId::VP8, None).unwrap();
+        let mut segment = builder.build();
+
+        // Every video keyframe starts a new cluster, so earlier ones become available right away
+        let mut streamed = Vec::new();
+        for i in 0..5_u8 {
+            segment.add_frame(video_track, &[i; 100], u64::from(i) * 1_000_000_000, true).unwrap();
+            if let Some(chunk) = segment.dest_mut().take_finalized() {
+                streamed.extend_from_slice(&chunk);
+            }
+        }
+        assert!(streamed.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]));
+        let streamed_before_finalize = streamed.len();
+
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalization unexpectedly failed")
+        };
+        let mut sink = writer.into_inner();
+        streamed.extend_from_slice(&sink.take_remaining().unwrap());
+        assert_eq!(streamed.len() as u64, sink.len());
+
+        // Finalization fills in the header, which was already taken out
+        let patches = sink.take_patches();
+        assert!(!patches.is_empty());
+        for patch in patches {
+            let offset = usize::try_from(patch.offset).unwrap();
+            assert!(offset + patch.data.len() <= streamed_before_finalize);
+            streamed[offset..offset + patch.data.len()].copy_from_slice(&patch.data);
+        }
+    }
 }
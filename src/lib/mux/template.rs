@@ -0,0 +1,983 @@
+use std::io::{self, Write};
+use std::num::NonZeroU64;
+
+use crate::codec::av1::SequenceHeader;
+use crate::codec::opus::OpusHead;
+use crate::codec::vorbis::VorbisHeaders;
+use crate::codec::vp9;
+
+use super::segment::check_segment_id;
+use super::{
+    AudioCodecId, AudioTrack, ColorMetadata, ColorRange, ColorSubsampling, DocType, Error, Segment, SegmentBuilder,
+    SegmentId, Track, TrackNum, VideoCodecId, VideoTrack, Writer,
+};
+
+/// A reusable description of a segment's tracks and metadata, from which any number of [`Segment`]s can be spawned.
+///
+/// The methods mirror those of [`SegmentBuilder`], and record each call, to be replayed on the builder of every
+/// segment spawned from the template. Every call is also checked right away, by replaying the template so far, so
+/// that mistakes are reported by the method that made them rather than when spawning a segment. Track numbers are
+/// fixed when a track is added, so the [`VideoTrack`] and [`AudioTrack`] handles the template returns are valid for
+/// every segment spawned from it. This is useful for splitting a continuous recording into several files, see also
+/// [`RotatingMuxer`].
+///
+/// Segments spawned from a template share its [`SegmentId`]. This includes tracks added to them individually on top
+/// of the template, which you should thus avoid mixing up between segments.
+#[derive(Debug, Clone)]
+pub struct SegmentTemplate {
+    id: SegmentId,
+    operations: Vec<Operation>,
+}
+
+impl Default for SegmentTemplate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A recorded call to one of the [`SegmentBuilder`] methods. Tracks are added with the number they got when the
+/// call was recorded, so that they have the same one in every segment.
+#[derive(Debug, Clone)]
+enum Operation {
+    SetDocType(DocType),
+    SetWritingApp(String),
+    AddTag(String, String),
+    SetKeyframeValidation(bool),
+    SetMaxClusterDuration(u64),
+    SetMaxClusterSize(u64),
+    SetCues(bool),
+    AddVideoTrack {
+        width: u32,
+        height: u32,
+        codec: VideoCodecId,
+        number: Option<TrackNum>,
+    },
+    AddAv1Track {
+        sequence_header: SequenceHeader,
+        number: Option<TrackNum>,
+    },
+    AddVp9Track {
+        keyframe_header: vp9::KeyframeHeader,
+        level: Option<u8>,
+        number: Option<TrackNum>,
+    },
+    AddVideoTrackWithCodecId {
+        width: u32,
+        height: u32,
+        codec_id: String,
+        number: Option<TrackNum>,
+    },
+    AddAudioTrack {
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec: AudioCodecId,
+        number: Option<TrackNum>,
+    },
+    AddVorbisTrack {
+        headers: VorbisHeaders,
+        number: Option<TrackNum>,
+    },
+    AddAudioTrackWithCodecId {
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec_id: String,
+        number: Option<TrackNum>,
+    },
+    SetCodecPrivate(TrackNum, Vec<u8>),
+    SetOpusHead(AudioTrack, OpusHead),
+    SetCodecDelay(TrackNum, u64, u64),
+    SetColor(VideoTrack, u8, ColorSubsampling, ColorRange),
+    SetColorMetadata(VideoTrack, ColorMetadata),
+    SetDisplaySize(VideoTrack, u32, u32),
+    SetFrameRate(VideoTrack, f64),
+    SetTrackName(TrackNum, String),
+    SetTrackLanguage(TrackNum, String),
+}
+
+impl Operation {
+    /// Applies this operation to `builder`, returning the number of the track it added, if any.
+    fn apply<W: Write>(&self, builder: SegmentBuilder<W>) -> Result<(SegmentBuilder<W>, Option<NonZeroU64>), Error> {
+        let builder = match self {
+            Self::SetDocType(doc_type) => builder.set_doc_type(*doc_type)?,
+            Self::SetWritingApp(app_name) => builder.set_writing_app(app_name)?,
+            Self::AddTag(name, value) => builder.add_tag(name, value)?,
+            Self::SetKeyframeValidation(enabled) => builder.set_keyframe_validation(*enabled),
+            Self::SetMaxClusterDuration(duration_ns) => builder.set_max_cluster_duration(*duration_ns)?,
+            Self::SetMaxClusterSize(size) => builder.set_max_cluster_size(*size)?,
+            Self::SetCues(enabled) => builder.set_cues(*enabled)?,
+            Self::AddVideoTrack {
+                width,
+                height,
+                codec,
+                number,
+            } => return added_video(builder.add_video_track(*width, *height, *codec, *number)),
+            Self::AddAv1Track { sequence_header, number } => {
+                return added_video(builder.add_av1_track(sequence_header, *number));
+            },
+            Self::AddVp9Track {
+                keyframe_header,
+                level,
+                number,
+            } => return added_video(builder.add_vp9_track(keyframe_header, *level, *number)),
+            Self::AddVideoTrackWithCodecId {
+                width,
+                height,
+                codec_id,
+                number,
+            } => return added_video(builder.add_video_track_with_codec_id(*width, *height, codec_id, *number)),
+            Self::AddAudioTrack {
+                sample_rate,
+                channels,
+                bit_depth,
+                codec,
+                number,
+            } => return added_audio(builder.add_audio_track(*sample_rate, *channels, *bit_depth, *codec, *number)),
+            Self::AddVorbisTrack { headers, number } => return added_audio(builder.add_vorbis_track(headers, *number)),
+            Self::AddAudioTrackWithCodecId {
+                sample_rate,
+                channels,
+                bit_depth,
+                codec_id,
+                number,
+            } => {
+                let added =
+                    builder.add_audio_track_with_codec_id(*sample_rate, *channels, *bit_depth, codec_id, *number);
+                return added_audio(added);
+            },
+            Self::SetCodecPrivate(track, data) => builder.set_codec_private(*track, data)?,
+            Self::SetOpusHead(track, head) => builder.set_opus_head(*track, head)?,
+            Self::SetCodecDelay(track, codec_delay_ns, seek_pre_roll_ns) => {
+                builder.set_codec_delay(*track, *codec_delay_ns, *seek_pre_roll_ns)?
+            },
+            Self::SetColor(track, bit_depth, subsampling, color_range) => {
+                builder.set_color(*track, *bit_depth, *subsampling, *color_range)?
+            },
+            Self::SetColorMetadata(track, metadata) => builder.set_color_metadata(*track, metadata)?,
+            Self::SetDisplaySize(track, width, height) => builder.set_display_size(*track, *width, *height)?,
+            Self::SetFrameRate(track, frame_rate) => builder.set_frame_rate(*track, *frame_rate)?,
+            Self::SetTrackName(track, name) => builder.set_track_name(*track, name)?,
+            Self::SetTrackLanguage(track, language) => builder.set_track_language(*track, language)?,
+        };
+        Ok((builder, None))
+    }
+
+    /// Fixes the number of the track this operation adds, if it adds one.
+    fn set_track_num(&mut self, track_num: NonZeroU64) {
+        match self {
+            Self::AddVideoTrack { number, .. }
+            | Self::AddAv1Track { number, .. }
+            | Self::AddVp9Track { number, .. }
+            | Self::AddVideoTrackWithCodecId { number, .. }
+            | Self::AddAudioTrack { number, .. }
+            | Self::AddVorbisTrack { number, .. }
+            | Self::AddAudioTrackWithCodecId { number, .. } => *number = Some(track_num.get()),
+            _ => {},
+        }
+    }
+
+    /// Returns the number of the track this operation adds, if it adds a video track.
+    fn video_track_num(&self) -> Option<TrackNum> {
+        match self {
+            Self::AddVideoTrack { number, .. }
+            | Self::AddAv1Track { number, .. }
+            | Self::AddVp9Track { number, .. }
+            | Self::AddVideoTrackWithCodecId { number, .. } => *number,
+            _ => None,
+        }
+    }
+}
+
+fn added_video<W: Write>(
+    added: Result<(SegmentBuilder<W>, VideoTrack), Error>,
+) -> Result<(SegmentBuilder<W>, Option<NonZeroU64>), Error> {
+    added.map(|(builder, track)| (builder, Some(track.number)))
+}
+
+fn added_audio<W: Write>(
+    added: Result<(SegmentBuilder<W>, AudioTrack), Error>,
+) -> Result<(SegmentBuilder<W>, Option<NonZeroU64>), Error> {
+    added.map(|(builder, track)| (builder, Some(track.number)))
+}
+
+impl SegmentTemplate {
+    /// Creates an empty template with no tracks.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id: SegmentId::next(),
+            operations: Vec::new(),
+        }
+    }
+
+    /// Returns the ID that track handles from this template, and segments spawned from it, are tied to.
+    #[must_use]
+    pub fn id(&self) -> SegmentId {
+        self.id
+    }
+
+    /// See [`SegmentBuilder::set_doc_type`].
+    pub fn set_doc_type(self, doc_type: DocType) -> Result<Self, Error> {
+        self.record(Operation::SetDocType(doc_type))
+    }
+
+    /// See [`SegmentBuilder::set_writing_app`].
+    pub fn set_writing_app(self, app_name: &str) -> Result<Self, Error> {
+        self.record(Operation::SetWritingApp(app_name.to_owned()))
+    }
+
+    /// See [`SegmentBuilder::add_tag`].
+    pub fn add_tag(self, name: &str, value: &str) -> Result<Self, Error> {
+        self.record(Operation::AddTag(name.to_owned(), value.to_owned()))
+    }
+
+    /// See [`SegmentBuilder::set_keyframe_validation`].
+    #[must_use]
+    pub fn set_keyframe_validation(mut self, enabled: bool) -> Self {
+        self.operations.push(Operation::SetKeyframeValidation(enabled));
+        self
+    }
+
+    /// See [`SegmentBuilder::set_max_cluster_duration`].
+    #[must_use]
+    pub fn set_max_cluster_duration(mut self, duration_ns: u64) -> Self {
+        self.operations.push(Operation::SetMaxClusterDuration(duration_ns));
+        self
+    }
+
+    /// See [`SegmentBuilder::set_max_cluster_size`].
+    #[must_use]
+    pub fn set_max_cluster_size(mut self, size: u64) -> Self {
+        self.operations.push(Operation::SetMaxClusterSize(size));
+        self
+    }
+
+    /// See [`SegmentBuilder::set_cues`].
+    #[must_use]
+    pub fn set_cues(mut self, enabled: bool) -> Self {
+        self.operations.push(Operation::SetCues(enabled));
+        self
+    }
+
+    /// See [`SegmentBuilder::add_video_track`].
+    pub fn add_video_track(
+        self,
+        width: u32,
+        height: u32,
+        codec: VideoCodecId,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        self.record_video_track(Operation::AddVideoTrack {
+            width,
+            height,
+            codec,
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_av1_track`].
+    pub fn add_av1_track(
+        self,
+        sequence_header: &SequenceHeader,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        self.record_video_track(Operation::AddAv1Track {
+            sequence_header: sequence_header.clone(),
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_vp9_track`].
+    pub fn add_vp9_track(
+        self,
+        keyframe_header: &vp9::KeyframeHeader,
+        level: Option<u8>,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        self.record_video_track(Operation::AddVp9Track {
+            keyframe_header: *keyframe_header,
+            level,
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_video_track_with_codec_id`].
+    pub fn add_video_track_with_codec_id(
+        self,
+        width: u32,
+        height: u32,
+        codec_id: &str,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, VideoTrack), Error> {
+        self.record_video_track(Operation::AddVideoTrackWithCodecId {
+            width,
+            height,
+            codec_id: codec_id.to_owned(),
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_audio_track`].
+    pub fn add_audio_track(
+        self,
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec: AudioCodecId,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, AudioTrack), Error> {
+        self.record_audio_track(Operation::AddAudioTrack {
+            sample_rate,
+            channels,
+            bit_depth,
+            codec,
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_vorbis_track`].
+    pub fn add_vorbis_track(
+        self,
+        headers: &VorbisHeaders,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, AudioTrack), Error> {
+        self.record_audio_track(Operation::AddVorbisTrack {
+            headers: headers.clone(),
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::add_audio_track_with_codec_id`].
+    pub fn add_audio_track_with_codec_id(
+        self,
+        sample_rate: u32,
+        channels: u32,
+        bit_depth: Option<u32>,
+        codec_id: &str,
+        desired_track_num: Option<TrackNum>,
+    ) -> Result<(Self, AudioTrack), Error> {
+        self.record_audio_track(Operation::AddAudioTrackWithCodecId {
+            sample_rate,
+            channels,
+            bit_depth,
+            codec_id: codec_id.to_owned(),
+            number: desired_track_num,
+        })
+    }
+
+    /// See [`SegmentBuilder::set_codec_private`].
+    pub fn set_codec_private(self, track: impl Track, data: &[u8]) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.record(Operation::SetCodecPrivate(track.track_number(), data.to_vec()))
+    }
+
+    /// See [`SegmentBuilder::set_opus_head`].
+    pub fn set_opus_head(self, track: AudioTrack, head: &OpusHead) -> Result<Self, Error> {
+        self.record(Operation::SetOpusHead(track, head.clone()))
+    }
+
+    /// See [`SegmentBuilder::set_codec_delay`].
+    pub fn set_codec_delay(self, track: impl Track, codec_delay_ns: u64, seek_pre_roll_ns: u64) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.record(Operation::SetCodecDelay(track.track_number(), codec_delay_ns, seek_pre_roll_ns))
+    }
+
+    /// See [`SegmentBuilder::set_color`].
+    pub fn set_color(
+        self,
+        track: VideoTrack,
+        bit_depth: u8,
+        subsampling: ColorSubsampling,
+        color_range: ColorRange,
+    ) -> Result<Self, Error> {
+        self.record(Operation::SetColor(track, bit_depth, subsampling, color_range))
+    }
+
+    /// See [`SegmentBuilder::set_color_metadata`].
+    pub fn set_color_metadata(self, track: VideoTrack, metadata: &ColorMetadata) -> Result<Self, Error> {
+        self.record(Operation::SetColorMetadata(track, *metadata))
+    }
+
+    /// See [`SegmentBuilder::set_display_size`].
+    pub fn set_display_size(self, track: VideoTrack, width: u32, height: u32) -> Result<Self, Error> {
+        self.record(Operation::SetDisplaySize(track, width, height))
+    }
+
+    /// See [`SegmentBuilder::set_frame_rate`].
+    pub fn set_frame_rate(self, track: VideoTrack, frame_rate: f64) -> Result<Self, Error> {
+        self.record(Operation::SetFrameRate(track, frame_rate))
+    }
+
+    /// See [`SegmentBuilder::set_track_name`].
+    pub fn set_track_name(self, track: impl Track, name: &str) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.record(Operation::SetTrackName(track.track_number(), name.to_owned()))
+    }
+
+    /// See [`SegmentBuilder::set_track_language`].
+    pub fn set_track_language(self, track: impl Track, language: &str) -> Result<Self, Error> {
+        check_segment_id(self.id, &track)?;
+        self.record(Operation::SetTrackLanguage(track.track_number(), language.to_owned()))
+    }
+
+    /// Returns `true` if this template contains at least one video track.
+    #[must_use]
+    pub fn has_video(&self) -> bool {
+        self.operations.iter().any(|operation| operation.video_track_num().is_some())
+    }
+
+    /// Creates a [`SegmentBuilder`] writing to `writer`, with everything in this template already applied.
+    /// Further tracks or metadata may be added before building it.
+    pub fn builder<W: Write>(&self, writer: Writer<W>) -> Result<SegmentBuilder<W>, Error> {
+        let mut builder = SegmentBuilder::with_id(writer, self.id)?;
+        for operation in &self.operations {
+            (builder, _) = operation.apply(builder)?;
+        }
+        Ok(builder)
+    }
+
+    /// Spawns a fresh [`Segment`] writing to `writer`, configured according to this template.
+    pub fn build<W: Write>(&self, writer: Writer<W>) -> Result<Segment<W>, Error> {
+        Ok(self.builder(writer)?.build())
+    }
+
+    fn is_video_track(&self, track: TrackNum) -> bool {
+        self.operations.iter().any(|operation| operation.video_track_num() == Some(track))
+    }
+
+    /// Records `operation`, once replaying the template with it has shown that it is valid.
+    fn record(self, operation: Operation) -> Result<Self, Error> {
+        Ok(self.record_track(operation)?.0)
+    }
+
+    /// Records `operation` like [`SegmentTemplate::record`], returning the number of the track it added, if any.
+    fn record_track(mut self, operation: Operation) -> Result<(Self, Option<NonZeroU64>), Error> {
+        self.operations.push(operation);
+
+        // The builder is only there to check the operations, and is dropped before anything is written
+        let mut builder = SegmentBuilder::with_id(Writer::new_non_seek(io::sink()), self.id)?;
+        let mut added = None;
+        for operation in &self.operations {
+            (builder, added) = operation.apply(builder)?;
+        }
+
+        if let (Some(operation), Some(number)) = (self.operations.last_mut(), added) {
+            operation.set_track_num(number);
+        }
+        Ok((self, added))
+    }
+
+    fn record_video_track(self, operation: Operation) -> Result<(Self, VideoTrack), Error> {
+        let (template, number) = self.record_track(operation)?;
+        let track = VideoTrack {
+            segment: template.id,
+            number: number.ok_or(Error::Unknown)?,
+        };
+        Ok((template, track))
+    }
+
+    fn record_audio_track(self, operation: Operation) -> Result<(Self, AudioTrack), Error> {
+        let (template, number) = self.record_track(operation)?;
+        let track = AudioTrack {
+            segment: template.id,
+            number: number.ok_or(Error::Unknown)?,
+        };
+        Ok((template, track))
+    }
+}
+
+/// Conditions under which a [`RotatingMuxer`] moves on to a new segment.
+///
+/// A new segment is only ever started on a video keyframe (or on any frame, if there are no video tracks), once
+/// at least one of the limits has been reached. Segments may thus run somewhat past the limits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Start a new segment once the current one spans at least this many nanoseconds.
+    pub max_duration_ns: Option<u64>,
+
+    /// Start a new segment once at least this many bytes have been written to the current one.
+    pub max_bytes: Option<u64>,
+}
+
+/// Writes a continuous stream of frames into a sequence of segments, each with its own [`Writer`], moving on to the
+/// next one according to a [`RotationPolicy`].
+///
+/// All segments are spawned from the same [`SegmentTemplate`], so track handles from it can be used throughout.
+/// Each segment's timestamps are shifted so that it starts at zero, making every output a standalone file. Frames
+/// that arrive after the one starting a segment but are timestamped earlier, as is common for interleaved audio, are
+/// moved up to the start of the segment.
+pub struct RotatingMuxer<W: Write, F> {
+    template: SegmentTemplate,
+    policy: RotationPolicy,
+    new_writer: F,
+    current: Option<CurrentSegment<W>>,
+    segments_started: u64,
+}
+
+struct CurrentSegment<W: Write> {
+    segment: Segment<W>,
+    first_timestamp_ns: u64,
+}
+
+/// An error from a [`RotatingMuxer`].
+///
+/// No writer is ever dropped on failure: if a segment could not be finalized, its [`Writer`] is handed back in
+/// `writer`, and if a segment was finalized before starting the next one failed, it is handed back in `finished`.
+pub struct RotationError<W: Write> {
+    /// What went wrong.
+    pub error: Error,
+
+    /// The writer of the segment that could not be finalized, if that is what failed.
+    pub writer: Option<Writer<W>>,
+
+    /// The writer of a segment that was successfully finalized to make room for the frame being added, when what
+    /// failed came after that.
+    pub finished: Option<Writer<W>>,
+}
+
+impl<W: Write> From<Error> for RotationError<W> {
+    fn from(error: Error) -> Self {
+        Self {
+            error,
+            writer: None,
+            finished: None,
+        }
+    }
+}
+
+impl<W: Write> std::fmt::Debug for RotationError<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RotationError")
+            .field("error", &self.error)
+            .field("has_writer", &self.writer.is_some())
+            .field("has_finished", &self.finished.is_some())
+            .finish()
+    }
+}
+
+impl<W: Write> std::fmt::Display for RotationError<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.error, f)
+    }
+}
+
+impl<W: Write> std::error::Error for RotationError<W> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl<W, F> RotatingMuxer<W, F>
+where
+    W: Write,
+    F: FnMut(u64) -> std::io::Result<Writer<W>>,
+{
+    /// Creates a new [`RotatingMuxer`]. `new_writer` is called with a zero-based, increasing index whenever a new
+    /// segment is needed, e.g. to open a new file. The first one is created on the first call to
+    /// [`RotatingMuxer::add_frame`].
+    pub fn new(template: SegmentTemplate, policy: RotationPolicy, new_writer: F) -> Self {
+        Self {
+            template,
+            policy,
+            new_writer,
+            current: None,
+            segments_started: 0,
+        }
+    }
+
+    /// Adds a frame, starting a new segment first if the current one has reached the limits set by the
+    /// [`RotationPolicy`] and this frame is a suitable starting point.
+    ///
+    /// The same timestamp rules as for [`Segment::add_frame`] apply across segment boundaries. If a segment was
+    /// finished to make room for this frame, its [`Writer`] is returned.
+    pub fn add_frame(
+        &mut self,
+        track: impl Track,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+    ) -> Result<Option<Writer<W>>, RotationError<W>> {
+        self.add_frame_with_discard_padding(track, data, timestamp_ns, keyframe, 0)
+    }
+
+    /// Adds a frame just like [`RotatingMuxer::add_frame`], along with a `DiscardPadding`, see
+    /// [`Segment::add_frame_with_discard_padding`].
+    pub fn add_frame_with_discard_padding(
+        &mut self,
+        track: impl Track,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<Option<Writer<W>>, RotationError<W>> {
+        check_segment_id(self.template.id, &track)?;
+        let finished = if self.current.is_some() && self.should_rotate(track.track_number(), timestamp_ns, keyframe) {
+            self.finish_current()?
+        } else {
+            None
+        };
+
+        match self.add_to_current(track, data, timestamp_ns, keyframe, discard_padding_ns) {
+            Ok(()) => Ok(finished),
+            Err(error) => Err(RotationError {
+                error,
+                writer: None,
+                finished,
+            }),
+        }
+    }
+
+    /// Finalizes the current segment, if any, and returns its [`Writer`].
+    pub fn finish(mut self) -> Result<Option<Writer<W>>, RotationError<W>> {
+        self.finish_current()
+    }
+
+    /// Returns the number of segments started so far.
+    #[must_use]
+    pub fn segments_started(&self) -> u64 {
+        self.segments_started
+    }
+
+    fn should_rotate(&self, track: TrackNum, timestamp_ns: u64, keyframe: bool) -> bool {
+        let Some(current) = &self.current else {
+            return false;
+        };
+
+        let is_start_point = if self.template.has_video() {
+            keyframe && self.template.is_video_track(track)
+        } else {
+            true
+        };
+        if !is_start_point {
+            return false;
+        }
+
+        let duration_reached = self
+            .policy
+            .max_duration_ns
+            .is_some_and(|max| timestamp_ns.saturating_sub(current.first_timestamp_ns) >= max);
+        let size_reached = self
+            .policy
+            .max_bytes
+            .is_some_and(|max| current.segment.writer().bytes_written() >= max);
+        duration_reached || size_reached
+    }
+
+    fn finish_current(&mut self) -> Result<Option<Writer<W>>, RotationError<W>> {
+        match self.current.take() {
+            Some(current) => current.segment.finalize_with_error(None).map(Some).map_err(|(error, writer)| {
+                RotationError {
+                    error,
+                    writer: Some(writer),
+                    finished: None,
+                }
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Adds a frame to the current segment, starting one if there is none.
+    fn add_to_current(
+        &mut self,
+        track: impl Track,
+        data: &[u8],
+        timestamp_ns: u64,
+        keyframe: bool,
+        discard_padding_ns: i64,
+    ) -> Result<(), Error> {
+        let current = match &mut self.current {
+            Some(current) => current,
+            None => {
+                let writer = (self.new_writer)(self.segments_started).map_err(Error::Io)?;
+                self.segments_started += 1;
+                self.current.insert(CurrentSegment {
+                    segment: self.template.build(writer)?,
+                    first_timestamp_ns: timestamp_ns,
+                })
+            },
+        };
+
+        let timestamp_ns = timestamp_ns.saturating_sub(current.first_timestamp_ns);
+        current.segment.add_frame_with_discard_padding(track, data, timestamp_ns, keyframe, discard_padding_ns)
+    }
+}
+
+impl<W: Write, F> std::fmt::Debug for RotatingMuxer<W, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RotatingMuxer")
+            .field("template", &self.template)
+            .field("policy", &self.policy)
+            .field("segments_started", &self.segments_started)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ebml::{ElementId, Reader};
+    use std::io::Cursor;
+
+    #[test]
+    fn assigns_track_numbers() {
+        let template = SegmentTemplate::new();
+        let (template, video) = template.add_video_track(640, 480, VideoCodecId::VP9, None).unwrap();
+        let (template, audio) = template.add_audio_track(48_000, 2, None, AudioCodecId::Opus, Some(5)).unwrap();
+        let (template, video2) = template.add_video_track(640, 480, VideoCodecId::VP9, None).unwrap();
+        assert_eq!(TrackNum::from(video), 1);
+        assert_eq!(TrackNum::from(audio), 5);
+        assert_eq!(TrackNum::from(video2), 3);
+
+        assert!(template.clone().add_audio_track(48_000, 2, None, AudioCodecId::Opus, Some(5)).is_err());
+        assert!(template.clone().add_audio_track(48_000, 2, None, AudioCodecId::Opus, Some(0)).is_err());
+        assert!(template.clone().add_audio_track(48_000, 2, None, AudioCodecId::Opus, Some(123_456)).is_err());
+        assert!(template.clone().set_codec_private(42_u64, &[1, 2, 3]).is_err());
+
+        let subsampling = ColorSubsampling::default();
+        let audio_as_video = VideoTrack {
+            segment: audio.segment,
+            number: audio.number,
+        };
+        assert!(template.clone().set_color(audio_as_video, 8, subsampling, ColorRange::Full).is_err());
+        assert!(template.clone().set_color(video, 8, subsampling, ColorRange::Full).is_ok());
+
+        let (other, other_video) = SegmentTemplate::new().add_video_track(640, 480, VideoCodecId::VP9, None).unwrap();
+        assert_eq!(other_video.track_number(), video.track_number());
+        assert!(matches!(other.set_codec_private(video, &[1]), Err(Error::ForeignTrack)));
+        assert!(matches!(template.set_codec_private(other_video, &[1]), Err(Error::ForeignTrack)));
+    }
+
+    #[test]
+    fn custom_codecs_need_matroska() {
+        let template = SegmentTemplate::new();
+        assert!(template.clone().add_audio_track_with_codec_id(48_000, 2, None, "A_FLAC", None).is_err());
+        assert!(template.clone().add_audio_track(48_000, 2, Some(16), AudioCodecId::Flac, None).is_err());
+        let (template, _) = template.add_audio_track_with_codec_id(48_000, 2, None, "A_OPUS", None).unwrap();
+        let template = template.set_doc_type(DocType::WebM).unwrap();
+
+        let template = template.set_doc_type(DocType::Matroska).unwrap();
+        let (template, _) = template.add_audio_track_with_codec_id(48_000, 2, None, "A_FLAC", None).unwrap();
+        assert!(template.clone().set_doc_type(DocType::WebM).is_err());
+        assert!(template.clone().add_audio_track(48_000, 2, None, AudioCodecId::PcmFloat, None).is_err());
+        assert!(template.clone().add_audio_track(48_000, 2, Some(24), AudioCodecId::PcmFloat, None).is_err());
+        let (template, _) = template.add_audio_track(48_000, 2, Some(64), AudioCodecId::PcmFloat, None).unwrap();
+        assert!(template.set_doc_type(DocType::Matroska).is_ok());
+    }
+
+    #[test]
+    fn opus_head_matches_track() {
+        let template = SegmentTemplate::new().set_doc_type(DocType::Matroska).unwrap();
+        let (template, video) = template.add_video_track(640, 480, VideoCodecId::VP9, None).unwrap();
+        let (template, stereo) = template.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let (template, custom) = template.add_audio_track_with_codec_id(48_000, 6, None, "A_OPUS", None).unwrap();
+        let (template, flac) = template.add_audio_track(48_000, 2, Some(16), AudioCodecId::Flac, None).unwrap();
+
+        let stereo_head = OpusHead::new(2, 312, 48_000).unwrap();
+        let surround_head = OpusHead::new(6, 312, 48_000).unwrap();
+        assert!(template.clone().set_opus_head(stereo, &surround_head).is_err());
+        assert!(template.clone().set_opus_head(flac, &stereo_head).is_err());
+        let video_as_audio = AudioTrack {
+            segment: video.segment,
+            number: video.number,
+        };
+        assert!(template.clone().set_opus_head(video_as_audio, &stereo_head).is_err());
+
+        let template = template.set_codec_private(stereo, &[0; 19]).unwrap();
+        let template = template.set_opus_head(stereo, &stereo_head).unwrap();
+        let template = template.set_opus_head(custom, &surround_head).unwrap();
+        assert!(template.build(Writer::new(Cursor::new(Vec::new()))).is_ok());
+    }
+
+    /// Returns the children of each `TrackEntry` in a finished segment, except for the random `TrackUID`.
+    fn track_entries(bytes: Vec<u8>) -> Vec<Vec<(ElementId, Vec<u8>)>> {
+        let mut reader = Reader::new(Cursor::new(bytes));
+        let mut elements = reader.top_level();
+        let mut entries = Vec::new();
+        while let Some(element) = reader.next_child(&mut elements).unwrap() {
+            if ![ElementId::SEGMENT, ElementId::TRACKS, ElementId::TRACK_ENTRY].contains(&element.id) {
+                continue;
+            }
+            if element.id != ElementId::TRACK_ENTRY {
+                elements = reader.children(&element).unwrap();
+                continue;
+            }
+
+            let mut children = reader.children(&element).unwrap();
+            let mut entry = Vec::new();
+            while let Some(child) = reader.next_child(&mut children).unwrap() {
+                if child.id != ElementId::TRACK_UID {
+                    entry.push((child.id, reader.read_binary(&child).unwrap()));
+                }
+            }
+            entries.push(entry);
+        }
+        entries
+    }
+
+    #[test]
+    fn replays_builder_calls() {
+        let identification = [
+            b"\x01vorbis".as_slice(),
+            &[0, 0, 0, 0, 1, 0x22, 0x56, 0, 0, 0, 0, 0, 0, 0, 0xFA, 0, 0, 0, 0, 0, 0, 0xB8, 1],
+        ]
+        .concat();
+        let headers = VorbisHeaders::new(&identification, b"\x03vorbis\x01", b"\x05vorbis\x01").unwrap();
+        let sequence_header_obu = [
+            0x0A, 0x0E, 0x00, 0x00, 0x00, 0x42, 0x22, 0xBF, 0x1F, 0x3F, 0xFE, 0x64, 0x04, 0x04, 0x04, 0x10,
+        ];
+        let sequence_header = SequenceHeader::from_obus(&sequence_header_obu).unwrap();
+        let keyframe = [0xA2, 0x49, 0x83, 0x42, 0x50, 0x09, 0xFE, 0x05, 0x9E, 0x00];
+        let keyframe_header = vp9::KeyframeHeader::parse(&keyframe).unwrap();
+        let opus_head = OpusHead::new(2, 312, 48_000).unwrap();
+
+        let (template, av1_track) = SegmentTemplate::new().add_av1_track(&sequence_header, None).unwrap();
+        let (template, vp9_track) = template.add_vp9_track(&keyframe_header, Some(31), Some(7)).unwrap();
+        let (template, vorbis) = template.add_vorbis_track(&headers, None).unwrap();
+        let (template, opus) = template.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let template = template
+            .set_opus_head(opus, &opus_head)
+            .and_then(|template| template.set_frame_rate(vp9_track, 30.0))
+            .and_then(|template| template.set_track_name(vorbis, "Commentary"))
+            .unwrap();
+
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, _) = builder.add_av1_track(&sequence_header, Some(av1_track.track_number())).unwrap();
+        let (builder, direct_vp9) = builder.add_vp9_track(&keyframe_header, Some(31), Some(7)).unwrap();
+        let (builder, _) = builder.add_vorbis_track(&headers, Some(vorbis.track_number())).unwrap();
+        let (builder, direct_opus) = builder
+            .add_audio_track(48_000, 2, None, AudioCodecId::Opus, Some(opus.track_number()))
+            .unwrap();
+        let builder = builder
+            .set_opus_head(direct_opus, &opus_head)
+            .and_then(|builder| builder.set_frame_rate(direct_vp9, 30.0))
+            .and_then(|builder| builder.set_track_name(vorbis.track_number(), "Commentary"))
+            .unwrap();
+
+        let finish = |segment: Segment<Cursor<Vec<u8>>>| match segment.finalize(None) {
+            Ok(writer) => writer.into_inner().into_inner(),
+            Err(_) => panic!("the segment should be finalized"),
+        };
+        let from_template = finish(template.build(Writer::new(Cursor::new(Vec::new()))).unwrap());
+        let direct = finish(builder.build());
+        let entries = track_entries(from_template);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries, track_entries(direct));
+    }
+
+    #[test]
+    fn rotates_on_keyframes() {
+        let template = SegmentTemplate::new().add_tag("TITLE", "Rotation").unwrap();
+        let (template, video) = template.add_video_track(640, 480, VideoCodecId::VP8, None).unwrap();
+        let (template, audio) = template.add_audio_track(48_000, 1, None, AudioCodecId::Opus, None).unwrap();
+        let template = template.set_codec_private(audio, &[0; 19]).unwrap();
+
+        let policy = RotationPolicy {
+            max_duration_ns: Some(2_000_000_000),
+            max_bytes: None,
+        };
+        let mut muxer = RotatingMuxer::new(template, policy, |_| Ok(Writer::new(Cursor::new(Vec::new()))));
+
+        let mut finished = Vec::new();
+        for second in 0..7_u64 {
+            let timestamp_ns = second * 1_000_000_000;
+            // Only every third second has a keyframe, so rotation has to wait for it
+            finished.extend(muxer.add_frame(video, &[1; 64], timestamp_ns, second % 3 == 0).unwrap());
+            finished.extend(muxer.add_frame(audio, &[2; 16], timestamp_ns + 1, true).unwrap());
+        }
+        finished.extend(muxer.finish().unwrap());
+
+        assert_eq!(finished.len(), 3);
+        for writer in finished {
+            let bytes = writer.into_inner().into_inner();
+            assert!(bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]));
+        }
+    }
+
+    #[test]
+    fn rotates_with_audio_before_keyframes() {
+        let (template, video) = SegmentTemplate::new().add_video_track(640, 480, VideoCodecId::VP8, None).unwrap();
+        let (template, audio) = template.add_audio_track(48_000, 1, None, AudioCodecId::Opus, None).unwrap();
+        let template = template.set_codec_private(audio, &[0; 19]).unwrap();
+
+        let policy = RotationPolicy {
+            max_duration_ns: Some(1_000_000_000),
+            max_bytes: None,
+        };
+        let mut muxer = RotatingMuxer::new(template, policy, |_| Ok(Writer::new(Cursor::new(Vec::new()))));
+
+        // Interleaved audio is often stored after a keyframe while being timestamped a little earlier
+        let mut finished = Vec::new();
+        for second in 0..3_u64 {
+            let timestamp_ns = second * 1_000_000_000;
+            finished.extend(muxer.add_frame(video, &[1; 64], timestamp_ns, true).unwrap());
+            let audio_timestamp_ns = timestamp_ns.saturating_sub(10_000_000);
+            finished.extend(muxer.add_frame(audio, &[2; 16], audio_timestamp_ns, true).unwrap());
+            finished.extend(muxer.add_frame(audio, &[2; 16], timestamp_ns + 10_000_000, true).unwrap());
+        }
+        finished.extend(muxer.finish().unwrap());
+        assert_eq!(finished.len(), 3);
+    }
+
+    #[test]
+    fn rotates_returning_failed_writer() {
+        struct FailingDest {
+            fail: std::rc::Rc<std::cell::Cell<bool>>,
+        }
+
+        impl Write for FailingDest {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if self.fail.get() {
+                    return Err(std::io::Error::other("destination is gone"));
+                }
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let template = SegmentTemplate::new();
+        let (template, audio) = template.add_audio_track(48_000, 1, None, AudioCodecId::Opus, None).unwrap();
+        let template = template.set_codec_private(audio, &[0; 19]).unwrap();
+
+        let fail = std::rc::Rc::default();
+        let new_writer = |_| Ok(Writer::new_non_seek(FailingDest { fail: std::rc::Rc::clone(&fail) }));
+        let mut muxer = RotatingMuxer::new(template, RotationPolicy::default(), new_writer);
+        assert!(muxer.add_frame(audio, &[2; 16], 0, true).unwrap().is_none());
+
+        fail.set(true);
+        let Err(err) = muxer.finish() else {
+            panic!("finalizing should fail once the destination is gone");
+        };
+        assert!(err.writer.is_some());
+        assert!(!matches!(err.error, Error::Unknown));
+    }
+
+    #[test]
+    fn rotates_returning_finished_writer() {
+        let (template, video) = SegmentTemplate::new().add_video_track(640, 480, VideoCodecId::VP8, None).unwrap();
+        let policy = RotationPolicy {
+            max_duration_ns: Some(1_000_000_000),
+            max_bytes: None,
+        };
+        let new_writer = |index| match index {
+            0 => Ok(Writer::new(Cursor::new(Vec::new()))),
+            _ => Err(std::io::Error::other("out of space")),
+        };
+        let mut muxer = RotatingMuxer::new(template, policy, new_writer);
+        assert!(muxer.add_frame(video, &[1; 64], 0, true).unwrap().is_none());
+
+        // The first segment is already finalized when the second can't be started, and must not be lost
+        let Err(err) = muxer.add_frame(video, &[1; 64], 1_000_000_000, true) else {
+            panic!("starting the second segment should fail");
+        };
+        assert!(matches!(err.error, Error::Io(_)));
+        assert!(err.writer.is_none());
+        let Some(finished) = err.finished else {
+            panic!("the finalized segment should be handed back");
+        };
+        assert!(finished.into_inner().into_inner().starts_with(&[0x1A, 0x45, 0xDF, 0xA3]));
+    }
+}
@@ -1,45 +1,7 @@
-use std::ffi::c_void;
 use std::io::{Seek, Write};
-use std::marker::PhantomPinned;
-use std::pin::Pin;
-use std::ptr::NonNull;
 
-use crate::ffi;
-use crate::ffi::mux::{WriterGetPosFn, WriterSetPosFn};
-
-/// RAII semantics for an FFI writer. This is simpler than implementing `Drop` on [`Writer`], which
-/// prevents destructuring.
-//
-// SAFETY: `libwebm` does not contain thread-locals or anything that would violate `Send`-safety.
-// `libwebm` is not thread-safe, however, which is why we do not implement `Sync`.
-unsafe impl Send for OwnedWriterPtr {}
-
-struct OwnedWriterPtr {
-    writer: ffi::mux::WriterNonNullPtr,
-}
-
-impl OwnedWriterPtr {
-    /// ## Safety
-    /// `writer` must be a valid, non-dangling pointer to an FFI writer created with [`ffi::mux::new_writer`].
-    /// After construction, `writer` must not be used by the caller, except via [`Self::as_ptr`].
-    /// The latter also must not be passed to [`ffi::mux::delete_writer`].
-    unsafe fn new(writer: ffi::mux::WriterNonNullPtr) -> Self {
-        Self { writer }
-    }
-
-    fn as_ptr(&self) -> ffi::mux::WriterMutPtr {
-        self.writer.as_ptr()
-    }
-}
-
-impl Drop for OwnedWriterPtr {
-    fn drop(&mut self) {
-        // SAFETY: We are assumed to be the only one allowed to delete this writer (per the requirements of [`Self::new`]).
-        unsafe {
-            ffi::mux::delete_writer(self.writer.as_ptr());
-        }
-    }
-}
+use super::backend::MkvWriter;
+use super::MemorySink;
 
 /// Structure for writing a muxed WebM stream to the user-supplied write destination `T`.
 ///
@@ -47,21 +9,22 @@ impl Drop for OwnedWriterPtr {
 /// It is recommended, but not required, that `T` also implement [`Seek`]. This allows the resulting WebM
 /// file to have things like seeking headers and a stream duration known upfront.
 ///
+/// For in-memory muxing, [`Writer::new_in_memory()`] provides a buffer that can be drained while muxing continues.
+///
 /// Once this [`Writer`] is created, you can use it to create one or more [`Segment`](crate::mux::Segment)s.
+/// To write the same kind of segment to several destinations in turn, see
+/// [`SegmentTemplate`](crate::mux::SegmentTemplate) and [`RotatingMuxer`](crate::mux::RotatingMuxer).
+///
+/// ## Backends
+/// By default, muxing is done by libwebm. With the `rust-mux` feature, a pure-Rust EBML writer is used instead,
+/// which doesn't need a C++ toolchain, and works on targets such as `wasm32`. Both produce equivalent files, though
+/// not byte-for-byte identical ones: the `MuxingApp` and track UIDs differ, and so may the exact placement of
+/// cluster boundaries.
 pub struct Writer<T>
 where
     T: Write,
 {
-    writer_data: Pin<Box<MuxWriterData<T>>>,
-    mkv_writer: OwnedWriterPtr,
-}
-
-struct MuxWriterData<T> {
-    dest: T,
-
-    /// Used for tracking position when using a non-Seek write destination
-    bytes_written: u64,
-    _marker: PhantomPinned,
+    inner: MkvWriter<T>,
 }
 
 impl<T> Writer<T>
@@ -71,14 +34,9 @@ where
     /// Creates a [`Writer`] for a destination that does not support [`Seek`].
     /// If it does support [`Seek`], you should use [`Writer::new()`] instead.
     pub fn new_non_seek(dest: T) -> Writer<T> {
-        extern "C" fn get_pos_fn<T>(data: *mut c_void) -> u64 {
-            // The user-supplied writer does not track its own position.
-            // Use our own based on how much has been written
-            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
-            data.bytes_written
+        Self {
+            inner: MkvWriter::new_non_seek(dest),
         }
-
-        Self::make_writer(dest, get_pos_fn::<T>, None)
     }
 
     /// Consumes this [`Writer`], and returns the user-supplied write destination
@@ -87,63 +45,32 @@ where
     /// It does not flush any unwritten data.
     #[must_use]
     pub fn into_inner(self) -> T {
-        let Self { writer_data, .. } = self;
-        unsafe { Pin::into_inner_unchecked(writer_data).dest }
+        self.inner.into_inner()
     }
 
-    pub(crate) fn mkv_writer(&self) -> ffi::mux::WriterMutPtr {
-        self.mkv_writer.as_ptr()
+    /// Gets a reference to the user-supplied write destination.
+    #[must_use]
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
     }
 
-    fn make_writer(
-        dest: T,
-        get_pos_fn: WriterGetPosFn,
-        set_pos_fn: Option<WriterSetPosFn>,
-    ) -> Self {
-        extern "C" fn write_fn<T>(data: *mut c_void, buf: *const c_void, len: usize) -> bool
-        where
-            T: Write,
-        {
-            if buf.is_null() {
-                return false;
-            }
-            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
-            let buf = unsafe { std::slice::from_raw_parts(buf.cast::<u8>(), len) };
-
-            let result = data.dest.write(buf);
-            if let Ok(num_bytes) = result {
-                // Guard against a future universe where sizeof(usize) > sizeof(u64)
-                let num_bytes_u64: u64 = num_bytes.try_into().unwrap();
-
-                data.bytes_written += num_bytes_u64;
-
-                // Partial writes are considered failure
-                num_bytes == len
-            } else {
-                false
-            }
-        }
+    /// Gets a mutable reference to the user-supplied write destination.
+    ///
+    /// Writing to it directly will corrupt the muxed stream, but this is useful for taking out data that has
+    /// already been written, e.g. with [`MemorySink::take_finalized()`].
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
 
-        let mut writer_data = Box::pin(MuxWriterData {
-            dest,
-            bytes_written: 0,
-            _marker: PhantomPinned,
-        });
-        let mkv_writer = unsafe {
-            ffi::mux::new_writer(
-                Some(write_fn::<T>),
-                Some(get_pos_fn),
-                set_pos_fn,
-                None,
-                std::ptr::from_mut(writer_data.as_mut().get_unchecked_mut()).cast(),
-            )
-        };
-        assert!(!mkv_writer.is_null());
+    /// Returns the total number of bytes passed to the write destination so far. For seekable destinations, this
+    /// includes data that was later overwritten.
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.inner.bytes_written()
+    }
 
-        Writer {
-            writer_data,
-            mkv_writer: unsafe { OwnedWriterPtr::new(NonNull::new(mkv_writer).unwrap()) },
-        }
+    pub(super) fn mkv_writer_mut(&mut self) -> &mut MkvWriter<T> {
+        &mut self.inner
     }
 }
 
@@ -156,24 +83,24 @@ where
     ///
     /// You can use `io::Cursor::new(Vec::new())` for in-memory writing, or `BufReader::new(File)`.
     pub fn new(dest: T) -> Writer<T> {
-        use std::io::SeekFrom;
-
-        extern "C" fn get_pos_fn<T>(data: *mut c_void) -> u64
-        where
-            T: Write + Seek,
-        {
-            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
-            data.dest.stream_position().unwrap()
-        }
-        extern "C" fn set_pos_fn<T>(data: *mut c_void, pos: u64) -> bool
-        where
-            T: Write + Seek,
-        {
-            let data = unsafe { data.cast::<MuxWriterData<T>>().as_mut().unwrap() };
-            data.dest.seek(SeekFrom::Start(pos)).is_ok()
+        Self {
+            inner: MkvWriter::new(dest),
         }
+    }
+}
 
-        Self::make_writer(dest, get_pos_fn::<T>, Some(set_pos_fn::<T>))
+impl Writer<MemorySink> {
+    /// Creates a [`Writer`] for an in-memory buffer. Unlike a plain `io::Cursor<Vec<u8>>`, the resulting
+    /// [`MemorySink`] lets you take out the parts of the stream that are already final while muxing continues.
+    /// See [`MemorySink`] for details.
+    ///
+    /// While a [`Segment`](crate::mux::Segment) is being written, the sink can be reached through
+    /// [`Segment::dest_mut()`](crate::mux::Segment::dest_mut).
+    #[must_use]
+    pub fn new_in_memory() -> Writer<MemorySink> {
+        Self {
+            inner: MkvWriter::new_in_memory(),
+        }
     }
 }
 
@@ -0,0 +1,593 @@
+//! Reading and writing packets of Ogg files, and remuxing Ogg Opus (`.opus`) and Ogg Vorbis (`.ogg`) audio to WebM.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::Writer;
+//!
+//! let input = BufReader::new(File::open("./recording.opus").unwrap());
+//! let output = File::create("./recording.webm").unwrap();
+//! webm::ogg::remux_to_webm(input, Writer::new(output)).unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+use crate::codec::opus::{self, OpusHead};
+use crate::codec::vorbis::{PacketDurations, VorbisHeaders};
+use crate::mux::{AudioCodecId, AudioTrack, Error, Segment, SegmentBuilder, Writer};
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+const CHECKSUM_OFFSET: usize = 22;
+
+const CONTINUED_PACKET: u8 = 0x01;
+const BEGINNING_OF_STREAM: u8 = 0x02;
+const END_OF_STREAM: u8 = 0x04;
+
+/// The granule position of pages on which no packet ends.
+const NO_GRANULE_POSITION: u64 = u64::MAX;
+
+/// Packets larger than this are assumed to come from a corrupt file, rather than risking huge allocations.
+const MAX_PACKET_LEN: usize = 16 * 1024 * 1024;
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+/// A packet from an Ogg stream, see [`OggPacketReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OggPacket {
+    /// The packet data.
+    pub data: Vec<u8>,
+
+    /// The granule position of the page this packet ends on, if it is the last packet to end on that page. Its
+    /// meaning depends on the codec: for Opus and Vorbis, it is the number of samples decoded by the end of this
+    /// packet.
+    pub granule_position: Option<u64>,
+
+    /// Whether this is the last packet of the stream.
+    pub end_of_stream: bool,
+}
+
+/// Reads the packets of an Ogg file one by one.
+///
+/// Only the first logical stream is read: pages belonging to other streams multiplexed into the same file are
+/// skipped, and reading stops at the end of the first stream.
+#[derive(Debug)]
+pub struct OggPacketReader<R: Read> {
+    reader: R,
+    serial: Option<u32>,
+
+    /// The start of a packet that continues on the next page, or `None` if what remains of it should be skipped
+    pending: Option<Vec<u8>>,
+    packets: VecDeque<OggPacket>,
+    finished: bool,
+}
+
+/// A page of an Ogg stream, the unit packets are split into.
+struct Page {
+    header_type: u8,
+    granule_position: u64,
+    serial: u32,
+    lacing_values: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl<R: Read> OggPacketReader<R> {
+    /// Creates a reader for `reader`, which must be at the start of an Ogg file.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            serial: None,
+            pending: Some(Vec::new()),
+            packets: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the serial number of the logical stream being read, once the first page has been read.
+    #[must_use]
+    pub fn serial(&self) -> Option<u32> {
+        self.serial
+    }
+
+    /// Reads the next packet, returning `None` at the end of the stream.
+    ///
+    /// Files that end without an end-of-stream page (such as recordings that were cut short) simply end after the
+    /// last complete packet.
+    pub fn next_packet(&mut self) -> Result<Option<OggPacket>, Error> {
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Ok(Some(packet));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+
+            let Some(page) = self.read_page()? else {
+                self.finished = true;
+                continue;
+            };
+            let serial = match self.serial {
+                Some(serial) => serial,
+                None if page.header_type & BEGINNING_OF_STREAM != 0 => *self.serial.insert(page.serial),
+                None => return Err(Error::InvalidData("Ogg stream does not start with a beginning-of-stream page")),
+            };
+            if page.serial == serial {
+                self.add_page(page)?;
+            }
+        }
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Splits a page of this reader's stream into packets, and queues those that are complete.
+    fn add_page(&mut self, page: Page) -> Result<(), Error> {
+        let mut packet = match (page.header_type & CONTINUED_PACKET != 0, self.pending.take()) {
+            // If the start of the packet was lost, what remains of it is skipped
+            (true, pending) => pending,
+            // An unfinished packet is abandoned, if there is one
+            (false, _) => Some(Vec::new()),
+        };
+
+        let mut body = page.body.as_slice();
+        for &lacing_value in &page.lacing_values {
+            let (segment, rest) = body.split_at(lacing_value.into());
+            body = rest;
+            if let Some(packet) = &mut packet {
+                if packet.len() + segment.len() > MAX_PACKET_LEN {
+                    return Err(Error::InvalidData("Ogg packet is too large"));
+                }
+                packet.extend_from_slice(segment);
+            }
+
+            // Packets are split into segments of 255 bytes, and end with a shorter one
+            if lacing_value < 255 {
+                if let Some(data) = packet.replace(Vec::new()) {
+                    self.packets.push_back(OggPacket {
+                        data,
+                        granule_position: None,
+                        end_of_stream: false,
+                    });
+                }
+            }
+        }
+        // Only a final segment of 255 bytes leaves a packet unfinished
+        self.pending = packet.filter(|_| page.lacing_values.last().is_none_or(|&value| value == 255));
+
+        let end_of_stream = page.header_type & END_OF_STREAM != 0;
+        if let Some(last) = self.packets.back_mut() {
+            if page.granule_position != NO_GRANULE_POSITION {
+                last.granule_position = Some(page.granule_position);
+            }
+            last.end_of_stream = end_of_stream;
+        }
+        self.finished = end_of_stream;
+        Ok(())
+    }
+
+    /// Reads the next page of any stream, returning `None` at the end of the file.
+    fn read_page(&mut self) -> Result<Option<Page>, Error> {
+        let mut header = [0; PAGE_HEADER_LEN];
+        let mut header_read = 0;
+        while header_read < PAGE_HEADER_LEN {
+            match self.reader.read(&mut header[header_read..]) {
+                Ok(0) if header_read == 0 => return Ok(None),
+                Ok(0) => return Err(Error::InvalidData("Ogg page header is truncated")),
+                Ok(len) => header_read += len,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+
+        if &header[..4] != CAPTURE_PATTERN {
+            return Err(Error::InvalidData("Ogg capture pattern not found"));
+        }
+        if header[4] != 0 {
+            return Err(Error::InvalidData("unsupported Ogg version"));
+        }
+
+        let mut lacing_values = vec![0; usize::from(header[26])];
+        read_exact(&mut self.reader, &mut lacing_values)?;
+        let mut body = vec![0; lacing_values.iter().map(|&value| usize::from(value)).sum()];
+        read_exact(&mut self.reader, &mut body)?;
+
+        // The checksum covers the whole page, with the checksum itself zeroed out
+        let checksum = u32::from_le_bytes(header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].try_into().unwrap());
+        header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].fill(0);
+        if crc(0, &[&header[..], &lacing_values, &body].concat()) != checksum {
+            return Err(Error::InvalidData("Ogg page checksum mismatch"));
+        }
+
+        Ok(Some(Page {
+            header_type: header[5],
+            granule_position: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: u32::from_le_bytes(header[14..18].try_into().unwrap()),
+            lacing_values,
+            body,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for OggPacketReader<R> {
+    type Item = Result<OggPacket, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+fn read_exact(reader: &mut impl Read, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        io::ErrorKind::UnexpectedEof => Error::InvalidData("Ogg page is truncated"),
+        _ => Error::Io(err),
+    })
+}
+
+/// Writes the packets of a single logical stream to an Ogg file one by one.
+///
+/// Every packet starts a new page, which is simple and keeps granule positions exact, at the cost of some overhead.
+#[derive(Debug)]
+pub struct OggPacketWriter<W: Write> {
+    writer: W,
+    serial: u32,
+    sequence_number: u32,
+    finished: bool,
+}
+
+impl<W: Write> OggPacketWriter<W> {
+    /// Creates a writer for a logical stream with the given serial number.
+    pub fn new(writer: W, serial: u32) -> Self {
+        Self {
+            writer,
+            serial,
+            sequence_number: 0,
+            finished: false,
+        }
+    }
+
+    /// Writes a packet. `granule_position` is recorded on the page the packet ends on, see
+    /// [`OggPacket::granule_position`]. The first packet written gets the beginning-of-stream flag, and once a
+    /// packet is written with `end_of_stream` set, writing any more fails with [`Error::BadParam`].
+    pub fn write_packet(&mut self, data: &[u8], granule_position: u64, end_of_stream: bool) -> Result<(), Error> {
+        if self.finished || granule_position == NO_GRANULE_POSITION {
+            return Err(Error::BadParam);
+        }
+
+        // A packet is laced as 255-byte segments, ending with a shorter one, which may be empty. Pages hold up to 255
+        // segments each.
+        let mut lacing_values = vec![255; data.len() / 255];
+        lacing_values.push((data.len() % 255) as u8);
+        let page_count = lacing_values.len().div_ceil(255);
+
+        let mut body = data;
+        for (index, lacing_values) in lacing_values.chunks(255).enumerate() {
+            let last_page = index + 1 == page_count;
+            let mut header_type = 0;
+            if index > 0 {
+                header_type |= CONTINUED_PACKET;
+            }
+            if self.sequence_number == 0 {
+                header_type |= BEGINNING_OF_STREAM;
+            }
+            if last_page && end_of_stream {
+                header_type |= END_OF_STREAM;
+            }
+
+            let body_len = lacing_values.iter().map(|&value| usize::from(value)).sum();
+            let (page_body, rest) = body.split_at(body_len);
+            body = rest;
+
+            let mut page = Vec::with_capacity(PAGE_HEADER_LEN + lacing_values.len() + body_len);
+            page.extend_from_slice(CAPTURE_PATTERN);
+            page.push(0);
+            page.push(header_type);
+            page.extend_from_slice(&(if last_page { granule_position } else { NO_GRANULE_POSITION }).to_le_bytes());
+            page.extend_from_slice(&self.serial.to_le_bytes());
+            page.extend_from_slice(&self.sequence_number.to_le_bytes());
+            page.extend_from_slice(&[0; 4]);
+            page.push(lacing_values.len() as u8);
+            page.extend_from_slice(lacing_values);
+            page.extend_from_slice(page_body);
+
+            let checksum = crc(0, &page);
+            page[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+            self.writer.write_all(&page).map_err(Error::Io)?;
+            self.sequence_number += 1;
+        }
+
+        self.finished = end_of_stream;
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Remuxes an Ogg Opus or Ogg Vorbis file from `input` into a WebM file with a single audio track, written to
+/// `writer`, returning the writer once done.
+///
+/// The track's `CodecPrivate` is built from the header packets, and for Opus, `CodecDelay` is set from the
+/// pre-skip (see [`SegmentBuilder::set_opus_head`]). Timestamps are derived from the granule positions, so streams
+/// that don't start at zero keep their offset. If the final granule position shows that the stream ends partway
+/// through the last packet, the rest of it is trimmed with a `DiscardPadding`.
+pub fn remux_to_webm<R: Read, W: Write>(input: R, writer: Writer<W>) -> Result<Writer<W>, Error> {
+    let mut reader = OggPacketReader::new(input);
+    let mut next_header = |missing: &'static str| reader.next_packet()?.ok_or(Error::InvalidData(missing));
+    let first = next_header("Ogg file contains no packets")?;
+
+    let builder = SegmentBuilder::new(writer)?;
+    let segment = if first.data.starts_with(b"OpusHead") {
+        let head = OpusHead::parse(&first.data)?;
+        let tags = next_header("Opus comment header not found")?;
+        if !tags.data.starts_with(b"OpusTags") {
+            return Err(Error::InvalidData("Opus comment header not found"));
+        }
+
+        let channels = head.channels.into();
+        let (builder, track) = builder.add_audio_track(opus::SAMPLE_RATE, channels, None, AudioCodecId::Opus, None)?;
+        let mut segment = builder.set_opus_head(track, &head)?.build();
+        write_packets(&mut reader, &mut segment, track, opus::SAMPLE_RATE, opus::packet_samples)?;
+        segment
+    } else {
+        let comment = next_header("Vorbis comment header not found")?;
+        let setup = next_header("Vorbis setup header not found")?;
+        let headers = VorbisHeaders::new(&first.data, &comment.data, &setup.data)?;
+
+        let mut durations = PacketDurations::new(&headers)?;
+        let (builder, track) = builder.add_vorbis_track(&headers, None)?;
+        let mut segment = builder.build();
+        let sample_rate = headers.info().sample_rate;
+        write_packets(&mut reader, &mut segment, track, sample_rate, |packet| durations.next_packet_samples(packet))?;
+        segment
+    };
+
+    segment.finalize(None).map_err(|_| Error::Unknown)
+}
+
+/// Writes the audio packets remaining in `reader` to `track`, timestamped from their granule positions.
+fn write_packets<R: Read, W: Write>(
+    reader: &mut OggPacketReader<R>,
+    segment: &mut Segment<W>,
+    track: AudioTrack,
+    sample_rate: u32,
+    mut packet_samples: impl FnMut(&[u8]) -> Result<u32, Error>,
+) -> Result<(), Error> {
+    let samples_to_ns = |samples: u64| (u128::from(samples) * 1_000_000_000 / u128::from(sample_rate)) as u64;
+
+    // Granule positions only mark the end of pages, so the start position is worked out from the first page
+    let mut first_page = Vec::new();
+    while let Some(packet) = reader.next_packet()? {
+        let samples = packet_samples(&packet.data)?;
+        let page_end = packet.granule_position.is_some();
+        first_page.push((packet, samples));
+        if page_end {
+            break;
+        }
+    }
+    let first_page_samples = first_page.iter().map(|&(_, samples)| u64::from(samples)).sum();
+    let mut position = match first_page.last() {
+        Some((OggPacket { granule_position: Some(end), .. }, _)) => end.saturating_sub(first_page_samples),
+        _ => 0,
+    };
+
+    let rest = std::iter::from_fn(|| reader.next_packet().transpose()).map(|packet| {
+        let packet = packet?;
+        let samples = packet_samples(&packet.data)?;
+        Ok((packet, samples))
+    });
+    for packet in first_page.into_iter().map(Ok).chain(rest) {
+        let (packet, samples) = packet?;
+        let timestamp_ns = samples_to_ns(position);
+        position += u64::from(samples);
+        if packet.data.is_empty() {
+            continue;
+        }
+
+        // The final granule position may end before the last packet does, cutting off encoder padding
+        let discard_samples = match packet.granule_position {
+            Some(end) if packet.end_of_stream => position.saturating_sub(end).min(samples.into()),
+            _ => 0,
+        };
+        let discard_padding_ns = samples_to_ns(discard_samples) as i64;
+        segment.add_frame_with_discard_padding(track, &packet.data, timestamp_ns, true, discard_padding_ns)?;
+    }
+
+    Ok(())
+}
+
+/// The CRC-32 variant used for Ogg page checksums: polynomial `0x04C11DB7`, without reflection or final XOR.
+fn crc(initial: u32, data: &[u8]) -> u32 {
+    data.iter().fold(initial, |crc, &byte| crc << 8 ^ CRC_TABLE[usize::from((crc >> 24) as u8 ^ byte)])
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < table.len() {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04C1_1DB7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an Ogg page containing `packets`, the first of which may continue from the previous page, and the last
+    /// of which may continue on the next one if it is longer than `last_len`.
+    fn page(
+        header_type: u8,
+        granule_position: u64,
+        serial: u32,
+        packets: &[&[u8]],
+        last_len: Option<usize>,
+    ) -> Vec<u8> {
+        let mut lacing_values = Vec::new();
+        let mut body = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            let is_last = i == packets.len() - 1;
+            let packet = match last_len {
+                Some(len) if is_last => &packet[..len],
+                _ => packet,
+            };
+            lacing_values.extend(std::iter::repeat_n(255, packet.len() / 255));
+            if !(is_last && last_len.is_some()) {
+                lacing_values.push((packet.len() % 255) as u8);
+            }
+            body.extend_from_slice(packet);
+        }
+
+        let mut data = CAPTURE_PATTERN.to_vec();
+        data.push(0);
+        data.push(header_type);
+        data.extend_from_slice(&granule_position.to_le_bytes());
+        data.extend_from_slice(&serial.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.push(lacing_values.len() as u8);
+        data.extend_from_slice(&lacing_values);
+        data.extend_from_slice(&body);
+
+        let checksum = crc(0, &data);
+        data[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+        data
+    }
+
+    fn packet(data: &[u8], granule_position: Option<u64>, end_of_stream: bool) -> OggPacket {
+        OggPacket {
+            data: data.to_vec(),
+            granule_position,
+            end_of_stream,
+        }
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc(0, b"123456789"), 0x89A1_897F);
+    }
+
+    #[test]
+    fn reads_packets() {
+        let long = [7; 600];
+        let mut file = page(BEGINNING_OF_STREAM, 0, 1, &[b"header"], None);
+        file.extend(page(BEGINNING_OF_STREAM, 0, 2, &[b"other stream"], None));
+        file.extend(page(0, 1000, 1, &[b"a", b"", &long], Some(255)));
+        file.extend(page(0, 0, 2, &[b"other packet"], None));
+        file.extend(page(CONTINUED_PACKET, NO_GRANULE_POSITION, 1, &[&long[255..]], Some(255)));
+        file.extend(page(CONTINUED_PACKET | END_OF_STREAM, 2000, 1, &[&long[510..], &[0; 255]], None));
+        file.extend(page(END_OF_STREAM, 0, 2, &[b"other packet"], None));
+
+        let mut reader = OggPacketReader::new(file.as_slice());
+        assert_eq!(reader.next_packet().unwrap(), Some(packet(b"header", Some(0), false)));
+        assert_eq!(reader.serial(), Some(1));
+        let rest = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            rest,
+            [
+                packet(b"a", None, false),
+                packet(b"", Some(1000), false),
+                packet(&long, None, false),
+                packet(&[0; 255], Some(2000), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_lost_packet_starts() {
+        let mut file = page(BEGINNING_OF_STREAM, 0, 1, &[b"header"], None);
+        file.extend(page(CONTINUED_PACKET, 5, 1, &[b"orphan", b"next"], None));
+        file.extend(page(0, NO_GRANULE_POSITION, 1, &[&[1; 300]], Some(255)));
+        file.extend(page(0, 6, 1, &[b"last"], None));
+
+        let packets = OggPacketReader::new(file.as_slice()).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(
+            packets,
+            [packet(b"header", Some(0), false), packet(b"next", Some(5), false), packet(b"last", Some(6), false)]
+        );
+    }
+
+    #[test]
+    fn writes_packets() {
+        let long = [7; 600];
+        let huge = vec![8; 255 * 255 + 10];
+        let mut writer = OggPacketWriter::new(Vec::new(), 5);
+        writer.write_packet(b"header", 0, false).unwrap();
+        writer.write_packet(&long, 1000, false).unwrap();
+        writer.write_packet(&[9; 255], 2000, false).unwrap();
+        writer.write_packet(&huge, 3000, true).unwrap();
+        assert!(matches!(writer.write_packet(b"late", 4000, true), Err(Error::BadParam)));
+
+        let file = writer.into_inner();
+        let mut reader = OggPacketReader::new(file.as_slice());
+        let packets = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(reader.serial(), Some(5));
+        let expected: [(&[u8], _, _); 4] = [
+            (b"header", Some(0), false),
+            (&long, Some(1000), false),
+            (&[9; 255], Some(2000), false),
+            (&huge, Some(3000), true),
+        ];
+        assert_eq!(packets.len(), expected.len());
+        for (packet, (data, granule_position, end_of_stream)) in packets.iter().zip(expected) {
+            assert_eq!(packet.data, data);
+            assert_eq!((packet.granule_position, packet.end_of_stream), (granule_position, end_of_stream));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let file = page(BEGINNING_OF_STREAM, 0, 1, &[b"header"], None);
+        let read_all = |data: &[u8]| OggPacketReader::new(data).collect::<Result<Vec<_>, _>>();
+        assert!(read_all(&file).is_ok());
+        assert!(read_all(&[]).unwrap().is_empty());
+
+        assert!(matches!(read_all(&file[..20]), Err(Error::InvalidData(_))));
+        assert!(matches!(read_all(&file[..file.len() - 1]), Err(Error::InvalidData(_))));
+
+        let mut corrupt = file.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(read_all(&corrupt), Err(Error::InvalidData(_))));
+
+        let mut wrong_pattern = file.clone();
+        wrong_pattern[0] = b'X';
+        assert!(matches!(read_all(&wrong_pattern), Err(Error::InvalidData(_))));
+
+        let no_beginning = page(0, 0, 1, &[b"header"], None);
+        assert!(matches!(read_all(&no_beginning), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn remuxes_opus() {
+        let head = OpusHead::new(2, 312, 48_000).unwrap().to_bytes().unwrap();
+        // CELT 20 ms packets of 960 samples, with the last one cut short by 480 samples
+        let audio = [0xFC, 0x01, 0x02];
+        let mut file = page(BEGINNING_OF_STREAM, 0, 1, &[&head], None);
+        file.extend(page(0, 0, 1, &[b"OpusTags\0\0\0\0\0\0\0\0"], None));
+        file.extend(page(0, 1920, 1, &[&audio, &audio], None));
+        file.extend(page(END_OF_STREAM, 2400, 1, &[&audio], None));
+
+        let writer = Writer::new(io::Cursor::new(Vec::new()));
+        let Ok(writer) = remux_to_webm(file.as_slice(), writer) else {
+            panic!("Remuxing unexpectedly failed")
+        };
+        let output = writer.into_inner().into_inner();
+        let contains = |needle: &[u8]| output.windows(needle.len()).any(|window| window == needle);
+        assert!(contains(b"A_OPUS"));
+        assert!(contains(&head));
+
+        // DiscardPadding of 10 ms
+        assert!(contains(&[0x75, 0xA2, 0x84, 0x00, 0x98, 0x96, 0x80]));
+    }
+}
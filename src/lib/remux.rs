@@ -0,0 +1,440 @@
+//! Rewriting an existing WebM file with different settings, without decoding: dropping tracks, changing how
+//! clusters and cues are written, or changing tags and track metadata.
+//!
+//! With a seekable output, this also fills in what is missing from files that were written live, such as those
+//! from a browser's `MediaRecorder`: the `Duration`, `Cues` and element sizes are all written on finalization.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::Writer;
+//! use webm::remux::RemuxOptions;
+//!
+//! // Strip the audio
+//! let input = BufReader::new(File::open("./recording.webm").unwrap());
+//! let output = Writer::new(File::create("./video-only.webm").unwrap());
+//! webm::remux::remux(input, output, &RemuxOptions::new().drop_audio()).unwrap();
+//! ```
+
+use std::io::{Read, Seek, Write};
+
+use crate::demux::{Demuxer, TrackInfo, TrackKind};
+use crate::mux::{AudioCodecId, DocType, Error, SegmentTemplate, TrackNum, VideoCodecId, Writer};
+
+/// What to change when remuxing with [`remux`]. By default, everything is copied as is.
+#[derive(Debug, Clone)]
+pub struct RemuxOptions {
+    selected_tracks: Option<Vec<TrackNum>>,
+    dropped_tracks: Vec<TrackNum>,
+    drop_video: bool,
+    drop_audio: bool,
+    max_cluster_duration: Option<u64>,
+    max_cluster_size: Option<u64>,
+    cues: bool,
+    copy_tags: bool,
+    tags: Vec<(String, String)>,
+    track_names: Vec<(TrackNum, String)>,
+    track_languages: Vec<(TrackNum, String)>,
+    writing_app: Option<String>,
+}
+
+impl Default for RemuxOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RemuxOptions {
+    /// Creates options that copy all tracks and tags as they are.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            selected_tracks: None,
+            dropped_tracks: Vec::new(),
+            drop_video: false,
+            drop_audio: false,
+            max_cluster_duration: None,
+            max_cluster_size: None,
+            cues: true,
+            copy_tags: true,
+            tags: Vec::new(),
+            track_names: Vec::new(),
+            track_languages: Vec::new(),
+            writing_app: None,
+        }
+    }
+
+    /// Only copies the tracks with the given numbers. All of them must exist in the input.
+    #[must_use]
+    pub fn select_tracks(mut self, tracks: &[TrackNum]) -> Self {
+        self.selected_tracks = Some(tracks.to_vec());
+        self
+    }
+
+    /// Leaves out the track with the given number, which must exist in the input.
+    #[must_use]
+    pub fn drop_track(mut self, track: TrackNum) -> Self {
+        self.dropped_tracks.push(track);
+        self
+    }
+
+    /// Leaves out all video tracks.
+    #[must_use]
+    pub fn drop_video(mut self) -> Self {
+        self.drop_video = true;
+        self
+    }
+
+    /// Leaves out all audio tracks.
+    #[must_use]
+    pub fn drop_audio(mut self) -> Self {
+        self.drop_audio = true;
+        self
+    }
+
+    /// See [`SegmentBuilder::set_max_cluster_duration`](crate::mux::SegmentBuilder::set_max_cluster_duration).
+    #[must_use]
+    pub fn set_max_cluster_duration(mut self, duration_ns: u64) -> Self {
+        self.max_cluster_duration = Some(duration_ns);
+        self
+    }
+
+    /// See [`SegmentBuilder::set_max_cluster_size`](crate::mux::SegmentBuilder::set_max_cluster_size).
+    #[must_use]
+    pub fn set_max_cluster_size(mut self, size: u64) -> Self {
+        self.max_cluster_size = Some(size);
+        self
+    }
+
+    /// See [`SegmentBuilder::set_cues`](crate::mux::SegmentBuilder::set_cues).
+    #[must_use]
+    pub fn set_cues(mut self, enabled: bool) -> Self {
+        self.cues = enabled;
+        self
+    }
+
+    /// Sets whether the tags of the input are copied. This is enabled by default.
+    #[must_use]
+    pub fn copy_tags(mut self, enabled: bool) -> Self {
+        self.copy_tags = enabled;
+        self
+    }
+
+    /// Adds a tag to the output, after any copied from the input. See
+    /// [`SegmentBuilder::add_tag`](crate::mux::SegmentBuilder::add_tag).
+    #[must_use]
+    pub fn add_tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Changes the name of a track. See [`SegmentBuilder::set_track_name`](crate::mux::SegmentBuilder::set_track_name).
+    #[must_use]
+    pub fn set_track_name(mut self, track: TrackNum, name: &str) -> Self {
+        self.track_names.push((track, name.to_owned()));
+        self
+    }
+
+    /// Changes the language of a track. See
+    /// [`SegmentBuilder::set_track_language`](crate::mux::SegmentBuilder::set_track_language).
+    #[must_use]
+    pub fn set_track_language(mut self, track: TrackNum, language: &str) -> Self {
+        self.track_languages.push((track, language.to_owned()));
+        self
+    }
+
+    /// See [`SegmentBuilder::set_writing_app`](crate::mux::SegmentBuilder::set_writing_app).
+    #[must_use]
+    pub fn set_writing_app(mut self, app_name: &str) -> Self {
+        self.writing_app = Some(app_name.to_owned());
+        self
+    }
+
+    /// Returns whether `track` should be copied.
+    fn is_copied(&self, track: &TrackInfo) -> bool {
+        let dropped_kind = match track.kind {
+            TrackKind::Video(_) => self.drop_video,
+            TrackKind::Audio(_) => self.drop_audio,
+            TrackKind::Other => true,
+        };
+        let selected = self.selected_tracks.as_ref().is_none_or(|selected| selected.contains(&track.number));
+        selected && !dropped_kind && !self.dropped_tracks.contains(&track.number)
+    }
+}
+
+/// Copies the tracks of the WebM (or Matroska) file in `input` into a new file written to `writer`, according to
+/// `options`, and returns the writer once done.
+///
+/// Frames are copied as they are, along with their keyframe flags and `DiscardPadding`. Tracks keep their numbers,
+/// codec, `CodecPrivate`, `CodecDelay`, `SeekPreRoll`, name, language and color information. Tracks that are
+/// neither video nor audio (such as subtitles) can't be written, and are always left out.
+///
+/// For the output to get a `Duration`, `Cues` and a `SeekHead`, `writer` must be seekable (see [`Writer::new`]).
+///
+/// Fails with [`Error::BadParam`] if `options` refer to tracks that don't exist, or if no tracks are left to copy.
+pub fn remux<R: Read + Seek, W: Write>(
+    input: R,
+    writer: Writer<W>,
+    options: &RemuxOptions,
+) -> Result<Writer<W>, Error> {
+    let mut demuxer = Demuxer::new(input)?;
+    let tracks: Vec<_> = demuxer.tracks().iter().filter(|track| options.is_copied(track)).cloned().collect();
+
+    let referenced_tracks = options.selected_tracks.iter().flatten().chain(&options.dropped_tracks);
+    let renamed_tracks = options.track_names.iter().chain(&options.track_languages).map(|(track, _)| track);
+    if referenced_tracks.chain(renamed_tracks).any(|&number| demuxer.track(number).is_none()) || tracks.is_empty() {
+        return Err(Error::BadParam);
+    }
+
+    let mut template = SegmentTemplate::new().set_doc_type(doc_type(&demuxer.info().doc_type))?;
+    for track in &tracks {
+        template = copy_track(template, track)?;
+    }
+
+    let mut builder = template.builder(writer)?.set_cues(options.cues)?;
+    if let Some(duration_ns) = options.max_cluster_duration {
+        builder = builder.set_max_cluster_duration(duration_ns)?;
+    }
+    if let Some(size) = options.max_cluster_size {
+        builder = builder.set_max_cluster_size(size)?;
+    }
+    if let Some(app_name) = &options.writing_app {
+        builder = builder.set_writing_app(app_name)?;
+    }
+
+    let copied_tags = demuxer.tags().iter().filter(|_| options.copy_tags);
+    for (name, value) in copied_tags.chain(&options.tags) {
+        builder = builder.add_tag(name, value)?;
+    }
+
+    for (track, name) in &options.track_names {
+        builder = builder.set_track_name(*track, name)?;
+    }
+    for (track, language) in &options.track_languages {
+        builder = builder.set_track_language(*track, language)?;
+    }
+
+    let mut segment = builder.build();
+    while let Some(frame) = demuxer.next_frame()? {
+        if !tracks.iter().any(|track| track.number == frame.track) {
+            continue;
+        }
+
+        segment.add_frame_with_discard_padding(
+            frame.track,
+            &frame.data,
+            frame.timestamp_ns,
+            frame.keyframe,
+            frame.discard_padding_ns,
+        )?;
+    }
+
+    segment.finalize(None).map_err(|_| Error::Unknown)
+}
+
+/// Returns the [`DocType`] to write a copy of a file with the given `DocType` string as.
+pub(crate) fn doc_type(doc_type: &str) -> DocType {
+    match doc_type {
+        "webm" => DocType::WebM,
+        _ => DocType::Matroska,
+    }
+}
+
+/// Adds a copy of `track` to `template`, with the same number and parameters.
+pub(crate) fn copy_track(template: SegmentTemplate, track: &TrackInfo) -> Result<SegmentTemplate, Error> {
+    let number = Some(track.number);
+    let template = match track.kind {
+        TrackKind::Video(video) => {
+            let (template, video_track) = match VideoCodecId::from_codec_id(&track.codec_id) {
+                Some(codec) => template.add_video_track(video.width, video.height, codec, number)?,
+                None => template.add_video_track_with_codec_id(video.width, video.height, &track.codec_id, number)?,
+            };
+            let template = match video.color {
+                Some(color) => template.set_color(video_track, color.bit_depth, color.subsampling, color.range)?,
+                None => template,
+            };
+            let template = match &video.color_metadata {
+                Some(color_metadata) => template.set_color_metadata(video_track, color_metadata)?,
+                None => template,
+            };
+            let template = match video.display_size {
+                Some((width, height)) => template.set_display_size(video_track, width, height)?,
+                None => template,
+            };
+            match video.frame_rate {
+                Some(frame_rate) => template.set_frame_rate(video_track, frame_rate)?,
+                None => template,
+            }
+        },
+        TrackKind::Audio(audio) => {
+            // Matroska allows fractional sample rates, which libwebm can't write anyway
+            let sample_rate = audio.sample_rate.round() as u32;
+            let (template, _) = match AudioCodecId::from_codec_id(&track.codec_id) {
+                Some(codec) => template.add_audio_track(sample_rate, audio.channels, audio.bit_depth, codec, number)?,
+                None => template.add_audio_track_with_codec_id(
+                    sample_rate,
+                    audio.channels,
+                    audio.bit_depth,
+                    &track.codec_id,
+                    number,
+                )?,
+            };
+            template
+        },
+        TrackKind::Other => return Err(Error::BadParam),
+    };
+
+    let template = match track.codec_private.as_slice() {
+        [] => template,
+        codec_private => template.set_codec_private(track.number, codec_private)?,
+    };
+    let template = match (track.codec_delay_ns, track.seek_pre_roll_ns) {
+        (0, 0) => template,
+        (codec_delay_ns, seek_pre_roll_ns) => template.set_codec_delay(track.number, codec_delay_ns, seek_pre_roll_ns)?,
+    };
+    let template = match &track.name {
+        Some(name) => template.set_track_name(track.number, name)?,
+        None => template,
+    };
+    match &track.language {
+        Some(language) => template.set_track_language(track.number, language),
+        None => Ok(template),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::mux::{ColorMetadata, ColorRange, ColorSubsampling, MasteringMetadata, SegmentBuilder};
+
+    const KEYFRAME: [u8; 12] = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01, 0xAB, 0xCD];
+    const INTER_FRAME: [u8; 5] = [0x31, 0x05, 0x00, 0xEF, 0x12];
+
+    /// Writes a short file with a VP8 track (number 1) and an Opus track (number 2), like `MediaRecorder` would:
+    /// without seeking, so without `Duration` and `Cues`.
+    pub(crate) fn live_recording() -> Vec<u8> {
+        let builder = SegmentBuilder::new(Writer::new_non_seek(Vec::new())).unwrap();
+        let (builder, video) = builder.add_video_track(352, 288, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_codec_delay(audio, 6_500_000, 80_000_000).unwrap();
+
+        let mut segment = builder.build();
+        for i in 0..30 {
+            let timestamp_ns = i * 20_000_000;
+            if i % 2 == 0 {
+                let frame = if i % 10 == 0 { KEYFRAME.as_slice() } else { INTER_FRAME.as_slice() };
+                segment.add_frame(video, frame, timestamp_ns, i % 10 == 0).unwrap();
+            }
+            segment.add_frame(audio, &[0xFC, i as u8], timestamp_ns, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing unexpectedly failed")
+        };
+        writer.into_inner()
+    }
+
+    fn remux_recording(options: &RemuxOptions) -> Result<Demuxer<Cursor<Vec<u8>>>, Error> {
+        let output = remux(Cursor::new(live_recording()), Writer::new(Cursor::new(Vec::new())), options)?;
+        Demuxer::new(Cursor::new(output.into_inner().into_inner()))
+    }
+
+    #[test]
+    fn copies_everything_by_default() {
+        let mut demuxer = remux_recording(&RemuxOptions::new().add_tag("TITLE", "Remuxed")).unwrap();
+
+        // Finalizing with seeking fills in the duration
+        assert_eq!(demuxer.info().duration_ns, Some(580_000_000));
+        assert_eq!(demuxer.tags(), [("TITLE".to_owned(), "Remuxed".to_owned())]);
+        let audio = demuxer.track(2).unwrap();
+        assert_eq!((audio.codec_delay_ns, audio.seek_pre_roll_ns), (6_500_000, 80_000_000));
+
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(frames.len(), 45);
+        assert_eq!(frames.iter().filter(|frame| frame.keyframe && frame.track == 1).count(), 3);
+    }
+
+    #[test]
+    fn drops_and_renames_tracks() {
+        let options = RemuxOptions::new().drop_audio().set_track_name(1, "Camera");
+        let mut demuxer = remux_recording(&options).unwrap();
+        assert_eq!(demuxer.tracks().len(), 1);
+        assert_eq!(demuxer.tracks()[0].name.as_deref(), Some("Camera"));
+        assert!(demuxer.all(|frame| frame.unwrap().track == 1));
+
+        let demuxer = remux_recording(&RemuxOptions::new().select_tracks(&[2])).unwrap();
+        assert_eq!(demuxer.tracks().len(), 1);
+        assert_eq!(demuxer.tracks()[0].codec_id, "A_OPUS");
+
+        assert!(matches!(remux_recording(&RemuxOptions::new().drop_track(3)), Err(Error::BadParam)));
+        assert!(matches!(remux_recording(&RemuxOptions::new().drop_video().drop_audio()), Err(Error::BadParam)));
+    }
+
+    #[test]
+    fn keeps_matroska_doc_type() {
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let builder = builder.set_doc_type(DocType::Matroska).unwrap();
+        let (builder, video) = builder.add_video_track(352, 288, VideoCodecId::VP8, None).unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(video, &KEYFRAME, 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing unexpectedly failed")
+        };
+
+        let input = writer.into_inner().into_inner();
+        let output = remux(Cursor::new(input), Writer::new(Cursor::new(Vec::new())), &RemuxOptions::new()).unwrap();
+        let mut demuxer = Demuxer::new(Cursor::new(output.into_inner().into_inner())).unwrap();
+        assert_eq!(demuxer.info().doc_type, "matroska");
+        assert_eq!(demuxer.next_frame().unwrap().unwrap().data, KEYFRAME);
+    }
+
+    #[test]
+    fn copies_hdr_metadata() {
+        let color_metadata = ColorMetadata {
+            matrix_coefficients: Some(9),
+            transfer_characteristics: Some(16),
+            primaries: Some(9),
+            chroma_siting: (Some(1), Some(2)),
+            max_cll: Some(1000),
+            max_fall: Some(400),
+            mastering: Some(MasteringMetadata {
+                red: Some((0.708, 0.292)),
+                green: Some((0.17, 0.797)),
+                blue: Some((0.131, 0.046)),
+                white_point: Some((0.3127, 0.329)),
+                luminance_max: Some(1000.0),
+                luminance_min: Some(0.005),
+            }),
+        };
+
+        let builder = SegmentBuilder::new(Writer::new(Cursor::new(Vec::new()))).unwrap();
+        let (builder, video) = builder.add_video_track(352, 288, VideoCodecId::VP8, None).unwrap();
+        let subsampling = ColorSubsampling { chroma_horizontal: 1, chroma_vertical: 1 };
+        let builder = builder
+            .set_color(video, 10, subsampling, ColorRange::Broadcast)
+            .unwrap()
+            .set_color_metadata(video, &color_metadata)
+            .unwrap()
+            .set_display_size(video, 704, 288)
+            .unwrap()
+            .set_frame_rate(video, 25.0)
+            .unwrap();
+        let mut segment = builder.build();
+        segment.add_frame(video, &KEYFRAME, 0, true).unwrap();
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing unexpectedly failed")
+        };
+
+        let input = writer.into_inner().into_inner();
+        let output = remux(Cursor::new(input), Writer::new(Cursor::new(Vec::new())), &RemuxOptions::new()).unwrap();
+        let demuxer = Demuxer::new(Cursor::new(output.into_inner().into_inner())).unwrap();
+        let TrackKind::Video(info) = demuxer.tracks()[0].kind else {
+            panic!("Expected a video track, got {:?}", demuxer.tracks()[0].kind)
+        };
+        assert_eq!(info.display_size, Some((704, 288)));
+        assert_eq!(info.frame_rate, Some(25.0));
+        assert_eq!(info.color.map(|color| (color.bit_depth, color.subsampling)), Some((10, subsampling)));
+        assert_eq!(info.color_metadata, Some(color_metadata));
+    }
+}
@@ -0,0 +1,164 @@
+//! Making WebM files written live seekable, such as the recordings of a browser's `MediaRecorder`.
+//!
+//! Files written without seeking have no `Duration`, no `Cues` and no `SeekHead`, and their segment and clusters
+//! have unknown sizes. Players can show them, but not seek in them. [`repair`] rewrites such a file with all of
+//! these filled in.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//!
+//! let input = BufReader::new(File::open("./recording.webm").unwrap());
+//! let output = File::create("./recording-fixed.webm").unwrap();
+//! webm::repair::repair(input, output).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use crate::codec::opus;
+use crate::demux::{Demuxer, TrackKind};
+use crate::mux::{Error, SegmentTemplate, Writer};
+use crate::remux::{copy_track, doc_type};
+
+/// The length of one `Duration` unit in files written by this crate, in nanoseconds.
+pub(crate) const TIMECODE_SCALE: u64 = 1_000_000;
+
+/// Copies the WebM (or Matroska) file in `input` to `output`, writing the `Duration`, `Cues`, `SeekHead` and element
+/// sizes that files written live are missing, and returns `output` once done.
+///
+/// All video and audio tracks, frames and tags are copied as they are, along with the `DocType` and the name of the
+/// writing application. The duration is taken as the end of the last frame: for Opus, the length of the last packet
+/// is known, and for other codecs, the last frame of a track is assumed to be as long as the one before it.
+///
+/// Fails with [`Error::InvalidData`] if `input` could not be parsed, or has no video or audio tracks.
+pub fn repair<R: Read + Seek, W: Write + Seek>(input: R, output: W) -> Result<W, Error> {
+    let mut demuxer = Demuxer::new(input)?;
+    let tracks: Vec<_> = demuxer
+        .tracks()
+        .iter()
+        .filter(|track| !matches!(track.kind, TrackKind::Other))
+        .cloned()
+        .collect();
+    if tracks.is_empty() {
+        return Err(Error::InvalidData("file has no video or audio tracks"));
+    }
+
+    let mut template = SegmentTemplate::new().set_doc_type(doc_type(&demuxer.info().doc_type))?;
+    if let Some(app_name) = &demuxer.info().writing_app {
+        template = template.set_writing_app(app_name)?;
+    }
+    for (name, value) in demuxer.tags() {
+        template = template.add_tag(name, value)?;
+    }
+    for track in &tracks {
+        template = copy_track(template, track)?;
+    }
+
+    let mut segment = template.build(Writer::new(output))?;
+    let mut track_ends: HashMap<_, TrackEnd> = HashMap::new();
+    while let Some(frame) = demuxer.next_frame()? {
+        let Some(track) = tracks.iter().find(|track| track.number == frame.track) else {
+            continue;
+        };
+
+        segment.add_frame_with_discard_padding(
+            frame.track,
+            &frame.data,
+            frame.timestamp_ns,
+            frame.keyframe,
+            frame.discard_padding_ns,
+        )?;
+
+        let known_duration_ns = match track.codec_id.as_str() {
+            "A_OPUS" => opus::packet_duration_ns(&frame.data).ok(),
+            _ => None,
+        };
+        let end = track_ends.entry(frame.track).or_default();
+        end.update(frame.timestamp_ns, known_duration_ns);
+    }
+
+    let duration_ns = track_ends.values().map(TrackEnd::end_ns).max().unwrap_or(0);
+    match segment.finalize(Some(duration_ns.div_ceil(TIMECODE_SCALE))) {
+        Ok(writer) => Ok(writer.into_inner()),
+        Err(_) => Err(Error::Unknown),
+    }
+}
+
+/// Tracks when the last frame of a track ends.
+#[derive(Debug, Default)]
+pub(crate) struct TrackEnd {
+    previous_timestamp_ns: Option<u64>,
+    last_timestamp_ns: u64,
+    last_duration_ns: u64,
+}
+
+impl TrackEnd {
+    pub(crate) fn update(&mut self, timestamp_ns: u64, known_duration_ns: Option<u64>) {
+        let previous_timestamp_ns = self.previous_timestamp_ns.replace(timestamp_ns);
+        let guessed_duration_ns = previous_timestamp_ns.map_or(0, |previous| timestamp_ns.saturating_sub(previous));
+        self.last_timestamp_ns = timestamp_ns;
+        self.last_duration_ns = known_duration_ns.unwrap_or(guessed_duration_ns);
+    }
+
+    pub(crate) fn end_ns(&self) -> u64 {
+        self.last_timestamp_ns + self.last_duration_ns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::mux::{AudioCodecId, SegmentBuilder, VideoCodecId};
+
+    const KEYFRAME: [u8; 12] = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01, 0xAB, 0xCD];
+    const INTER_FRAME: [u8; 5] = [0x31, 0x05, 0x00, 0xEF, 0x12];
+
+    #[test]
+    fn track_end() {
+        let mut end = TrackEnd::default();
+        end.update(0, None);
+        assert_eq!(end.end_ns(), 0);
+        end.update(33_000_000, None);
+        end.update(66_000_000, None);
+        assert_eq!(end.end_ns(), 99_000_000);
+        end.update(80_000_000, Some(20_000_000));
+        assert_eq!(end.end_ns(), 100_000_000);
+    }
+
+    #[test]
+    fn repairs_live_recording() {
+        let builder = SegmentBuilder::new(Writer::new_non_seek(Vec::new())).unwrap();
+        let (builder, video) = builder.add_video_track(352, 288, VideoCodecId::VP8, None).unwrap();
+        let (builder, audio) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, None).unwrap();
+        let builder = builder.set_writing_app("Recorder").unwrap();
+
+        let mut segment = builder.build();
+        for i in 0..30 {
+            let timestamp_ns = i * 20_000_000;
+            if i % 2 == 0 {
+                let frame = if i % 10 == 0 { KEYFRAME.as_slice() } else { INTER_FRAME.as_slice() };
+                segment.add_frame(video, frame, timestamp_ns, i % 10 == 0).unwrap();
+            }
+            // 20 ms Opus packets
+            segment.add_frame(audio, &[0xFC, i as u8], timestamp_ns, true).unwrap();
+        }
+        let Ok(writer) = segment.finalize(None) else {
+            panic!("Finalizing unexpectedly failed")
+        };
+        let recording = writer.into_inner();
+        assert_eq!(Demuxer::new(Cursor::new(recording.as_slice())).unwrap().info().duration_ns, None);
+
+        let repaired = repair(Cursor::new(recording), Cursor::new(Vec::new())).unwrap().into_inner();
+        let mut demuxer = Demuxer::new(Cursor::new(repaired)).unwrap();
+        assert_eq!(demuxer.info().duration_ns, Some(600_000_000));
+        assert_eq!(demuxer.info().writing_app.as_deref(), Some("Recorder"));
+        assert_eq!(demuxer.tracks().len(), 2);
+        assert_eq!(demuxer.by_ref().count(), 45);
+
+        let result = repair(Cursor::new(b"garbage".to_vec()), Cursor::new(Vec::new()));
+        assert!(matches!(result, Err(Error::InvalidData(_))));
+    }
+}
@@ -0,0 +1,187 @@
+//! Splitting a WebM file into several shorter ones at keyframes, without re-encoding.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::{RotationPolicy, Writer};
+//!
+//! // Split into files of about ten seconds each
+//! let input = BufReader::new(File::open("./recording.webm").unwrap());
+//! let policy = RotationPolicy {
+//!     max_duration_ns: Some(10_000_000_000),
+//!     max_bytes: None,
+//! };
+//! let new_writer = |index| Ok(Writer::new(File::create(format!("./part{index}.webm"))?));
+//! webm::split::split(input, policy, new_writer, |_| Ok(())).unwrap();
+//! ```
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::demux::{Demuxer, TrackKind};
+use crate::mux::{Error, RotatingMuxer, RotationPolicy, SegmentTemplate, Writer};
+use crate::remux::{copy_track, doc_type};
+
+/// Writes the frames of the WebM (or Matroska) file in `input` into a sequence of files, moving on to the next one
+/// according to `policy`, and returns the number of files written.
+///
+/// This works like a [`RotatingMuxer`]: every file starts on a video keyframe (or on any frame, for files without
+/// video) and has timestamps starting at zero. `new_writer` is called with a zero-based index whenever a new file is
+/// needed, and every file is passed to `finished` once finalized. For the files to get a `Duration`, `Cues` and a
+/// `SeekHead`, the writers must be seekable (see [`Writer::new`]).
+///
+/// Tracks, tags and the `DocType` are copied into every file as with [`remux`](crate::remux::remux). Tracks that are
+/// neither video nor audio are left out.
+///
+/// Fails with [`Error::Io`] if `new_writer` or `finished` fail. Files that were completed before a failure are still
+/// passed to `finished`.
+pub fn split<R, W, F, G>(input: R, policy: RotationPolicy, new_writer: F, mut finished: G) -> Result<u64, Error>
+where
+    R: Read + Seek,
+    W: Write,
+    F: FnMut(u64) -> io::Result<Writer<W>>,
+    G: FnMut(Writer<W>) -> io::Result<()>,
+{
+    let mut demuxer = Demuxer::new(input)?;
+    let tracks: Vec<_> = demuxer
+        .tracks()
+        .iter()
+        .filter(|track| !matches!(track.kind, TrackKind::Other))
+        .cloned()
+        .collect();
+    if tracks.is_empty() {
+        return Err(Error::InvalidData("file has no video or audio tracks"));
+    }
+
+    let info = demuxer.info();
+    let mut template = SegmentTemplate::new().set_doc_type(doc_type(&info.doc_type))?;
+    if let Some(app_name) = &info.writing_app {
+        template = template.set_writing_app(app_name)?;
+    }
+    for (name, value) in demuxer.tags() {
+        template = template.add_tag(name, value)?;
+    }
+    for track in &tracks {
+        template = copy_track(template, track)?;
+    }
+
+    let mut muxer = RotatingMuxer::new(template, policy, new_writer);
+    while let Some(frame) = demuxer.next_frame()? {
+        if !tracks.iter().any(|track| track.number == frame.track) {
+            continue;
+        }
+
+        let result = muxer.add_frame_with_discard_padding(
+            frame.track,
+            &frame.data,
+            frame.timestamp_ns,
+            frame.keyframe,
+            frame.discard_padding_ns,
+        );
+        let finished_writer = match result {
+            Ok(finished_writer) => finished_writer,
+            Err(err) => {
+                // A file that was completed before the failure is still handed over
+                if let Some(writer) = err.finished {
+                    finished(writer).map_err(Error::Io)?;
+                }
+                return Err(err.error);
+            },
+        };
+        if let Some(writer) = finished_writer {
+            finished(writer).map_err(Error::Io)?;
+        }
+    }
+
+    let files = muxer.segments_started();
+    if let Some(writer) = muxer.finish().map_err(|err| err.error)? {
+        finished(writer).map_err(Error::Io)?;
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::remux::tests::live_recording;
+
+    #[test]
+    fn splits_at_keyframes() {
+        let policy = RotationPolicy {
+            max_duration_ns: Some(150_000_000),
+            max_bytes: None,
+        };
+        let mut outputs = Vec::new();
+        let new_writer = |_| Ok(Writer::new(Cursor::new(Vec::new())));
+        let files = split(Cursor::new(live_recording()), policy, new_writer, |writer| {
+            outputs.push(writer.into_inner().into_inner());
+            Ok(())
+        })
+        .unwrap();
+
+        // Keyframes are 200 ms apart
+        assert_eq!(files, 3);
+        assert_eq!(outputs.len(), 3);
+        for output in outputs {
+            let mut demuxer = Demuxer::new(Cursor::new(output)).unwrap();
+            assert_eq!(demuxer.tracks().len(), 2);
+            assert_eq!(demuxer.track(2).unwrap().codec_delay_ns, 6_500_000);
+
+            let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(frames.len(), 15);
+            assert_eq!((frames[0].track, frames[0].timestamp_ns, frames[0].keyframe), (1, 0, true));
+        }
+    }
+
+    #[test]
+    fn splits_by_size() {
+        let split_recording = |max_bytes| {
+            let policy = RotationPolicy {
+                max_duration_ns: None,
+                max_bytes: Some(max_bytes),
+            };
+            let mut outputs = Vec::new();
+            let new_writer = |_| Ok(Writer::new(Cursor::new(Vec::new())));
+            let files = split(Cursor::new(live_recording()), policy, new_writer, |writer| {
+                outputs.push(writer.into_inner().into_inner());
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!(files, outputs.len() as u64);
+            outputs
+        };
+
+        assert_eq!(split_recording(u64::MAX).len(), 1);
+
+        // The headers alone exceed the limit, so every keyframe starts a new file
+        let outputs = split_recording(1);
+        assert_eq!(outputs.len(), 3);
+        for output in outputs {
+            let mut demuxer = Demuxer::new(Cursor::new(output)).unwrap();
+            let frame = demuxer.next_frame().unwrap().unwrap();
+            assert_eq!((frame.track, frame.timestamp_ns, frame.keyframe), (1, 0, true));
+        }
+    }
+
+    #[test]
+    fn hands_over_finished_files_on_failure() {
+        let policy = RotationPolicy {
+            max_duration_ns: Some(150_000_000),
+            max_bytes: None,
+        };
+        let mut outputs = Vec::new();
+        let new_writer = |index| match index {
+            0 => Ok(Writer::new(Cursor::new(Vec::new()))),
+            _ => Err(io::Error::other("out of space")),
+        };
+        let result = split(Cursor::new(live_recording()), policy, new_writer, |writer| {
+            outputs.push(writer.into_inner().into_inner());
+            Ok(())
+        });
+
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(Demuxer::new(Cursor::new(&outputs[0])).unwrap().count(), 15);
+    }
+}
@@ -0,0 +1,445 @@
+//! Synthetic streams for testing, and a harness that muxes them and checks what libwebm's parser reads back.
+//!
+//! Only available with the `test-util` feature. The frames are not decodable, but their headers are valid enough for
+//! keyframe detection and track setup.
+//!
+//! ```no_run
+//! use webm::test_util::{RoundTrip, SyntheticCodec, SyntheticTrack};
+//!
+//! RoundTrip::new()
+//!     .add_track(SyntheticTrack::new(SyntheticCodec::Vp9, 40_000_000, 50).set_keyframe_interval(10))
+//!     .add_track(SyntheticTrack::new(SyntheticCodec::Opus, 20_000_000, 100))
+//!     .set_max_cluster_duration(500_000_000)
+//!     .assert_round_trip();
+//! ```
+
+use std::io::Cursor;
+
+use crate::codec::av1::SequenceHeader;
+use crate::codec::opus::OpusHead;
+use crate::codec::vp9::KeyframeHeader;
+use crate::demux::{Demuxer, Frame, TrackKind};
+use crate::mux::{AudioCodecId, Error, SegmentBuilder, Track, TrackNum, VideoCodecId, Writer};
+use crate::validate::{validate, Mode};
+
+/// The start of a 352x288 VP8 keyframe.
+const VP8_KEYFRAME: [u8; 10] = [0x50, 0x42, 0x00, 0x9D, 0x01, 0x2A, 0x60, 0x01, 0x20, 0x01];
+const VP8_INTER_FRAME: [u8; 3] = [0x31, 0x05, 0x00];
+
+/// The start of a 352x288 VP9 profile 0 keyframe.
+const VP9_KEYFRAME: [u8; 10] = [0x82, 0x49, 0x83, 0x42, 0x20, 0x15, 0xF0, 0x11, 0xF0, 0x00];
+const VP9_INTER_FRAME: [u8; 4] = [0x86, 0x00, 0x40, 0x00];
+
+/// The Sequence Header OBU of a 352x288, 8-bit 4:2:0 AV1 stream, which every keyframe is preceded by.
+const AV1_SEQUENCE_HEADER: [u8; 16] = [
+    0x0A, 0x0E, 0x00, 0x00, 0x00, 0x42, 0x22, 0xBF, 0x1F, 0x3F, 0xFE, 0x64, 0x04, 0x04, 0x04, 0x10,
+];
+
+/// The frame width and height of all synthetic video streams.
+pub const VIDEO_SIZE: (u32, u32) = (352, 288);
+
+/// The pre-skip of synthetic Opus streams, in samples at 48 kHz (6.5 ms).
+pub const OPUS_PRE_SKIP: u16 = 312;
+
+/// A codec [`SyntheticTrack`]s can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntheticCodec {
+    Vp8,
+    Vp9,
+    Av1,
+
+    /// Stereo Opus at 48 kHz, in 20 ms packets.
+    Opus,
+}
+
+impl SyntheticCodec {
+    /// Returns whether this is a video codec.
+    #[must_use]
+    pub fn is_video(self) -> bool {
+        self != Self::Opus
+    }
+
+    /// Returns synthetic frame number `index` of a stream. Every frame is different, so that mixed up frames can be
+    /// told apart. Opus packets are always keyframes.
+    #[must_use]
+    pub fn frame(self, index: u32, keyframe: bool) -> Vec<u8> {
+        // The trailing zero keeps VP9 frames from looking like superframes
+        let tag = [index.to_be_bytes().as_slice(), &[0]].concat();
+        match (self, keyframe) {
+            (Self::Vp8, true) => [VP8_KEYFRAME.as_slice(), &tag].concat(),
+            (Self::Vp8, false) => [VP8_INTER_FRAME.as_slice(), &tag].concat(),
+            (Self::Vp9, true) => [VP9_KEYFRAME.as_slice(), &tag].concat(),
+            (Self::Vp9, false) => [VP9_INTER_FRAME.as_slice(), &tag].concat(),
+            (Self::Av1, keyframe) => {
+                // A frame OBU with show_existing_frame = 0, show_frame = 1 and the frame type in between
+                let frame_header = if keyframe { 0x10 } else { 0x30 };
+                let frame = [[0x32, tag.len() as u8 + 1, frame_header].as_slice(), &tag].concat();
+                if keyframe {
+                    [AV1_SEQUENCE_HEADER.as_slice(), &frame].concat()
+                } else {
+                    frame
+                }
+            },
+            // CELT-only fullband, 20 ms, stereo, one frame
+            (Self::Opus, _) => [[0xFC].as_slice(), &tag].concat(),
+        }
+    }
+}
+
+/// A track of synthetic frames at a constant frame rate, starting at zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticTrack {
+    pub codec: SyntheticCodec,
+
+    /// The time between frames, in nanoseconds.
+    pub frame_interval_ns: u64,
+
+    /// The number of frames.
+    pub frame_count: u32,
+
+    /// Every how many frames there is a keyframe, for video. The first frame is always one.
+    pub keyframe_interval: u32,
+
+    /// The `Name` of the track, if any.
+    pub name: Option<String>,
+
+    /// The `Language` of the track, if any.
+    pub language: Option<String>,
+}
+
+impl SyntheticTrack {
+    /// Creates a track of `frame_count` frames, `frame_interval_ns` apart. Video tracks get a keyframe every 30
+    /// frames.
+    #[must_use]
+    pub fn new(codec: SyntheticCodec, frame_interval_ns: u64, frame_count: u32) -> Self {
+        Self {
+            codec,
+            frame_interval_ns,
+            frame_count,
+            keyframe_interval: 30,
+            name: None,
+            language: None,
+        }
+    }
+
+    /// Sets every how many frames there is a keyframe. Zero means only the first frame is one.
+    #[must_use]
+    pub fn set_keyframe_interval(mut self, interval: u32) -> Self {
+        self.keyframe_interval = interval;
+        self
+    }
+
+    /// Sets the `Name` of the track.
+    #[must_use]
+    pub fn set_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the `Language` of the track.
+    #[must_use]
+    pub fn set_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_owned());
+        self
+    }
+
+    fn is_keyframe(&self, index: u32) -> bool {
+        !self.codec.is_video() || index.is_multiple_of(self.keyframe_interval)
+    }
+}
+
+/// A file to mux from [`SyntheticTrack`]s, and check against what libwebm's parser reads back from it.
+///
+/// Tracks are numbered from 1 in the order they are added, and their frames are written interleaved by timestamp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTrip {
+    pub tracks: Vec<SyntheticTrack>,
+
+    /// Whether the file is written with [`Writer::new`] rather than [`Writer::new_non_seek`].
+    pub seekable: bool,
+
+    /// Whether `Cues` are written.
+    pub cues: bool,
+
+    /// The longest duration of a cluster in nanoseconds, or zero for libwebm's default.
+    pub max_cluster_duration_ns: u64,
+
+    /// The largest size of a cluster in bytes, or zero for no limit.
+    pub max_cluster_size: u64,
+
+    /// The writing application, if set.
+    pub writing_app: Option<String>,
+
+    /// The `SimpleTag`s of the file.
+    pub tags: Vec<(String, String)>,
+}
+
+impl Default for RoundTrip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RoundTrip {
+    /// Creates a round trip with no tracks, written seekably with `Cues`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            seekable: true,
+            cues: true,
+            max_cluster_duration_ns: 0,
+            max_cluster_size: 0,
+            writing_app: None,
+            tags: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn add_track(mut self, track: SyntheticTrack) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    /// Sets whether the file is written as to a seekable destination. Files written otherwise have no `Cues`.
+    #[must_use]
+    pub fn set_seekable(mut self, seekable: bool) -> Self {
+        self.seekable = seekable;
+        self
+    }
+
+    #[must_use]
+    pub fn set_cues(mut self, cues: bool) -> Self {
+        self.cues = cues;
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_cluster_duration(mut self, duration_ns: u64) -> Self {
+        self.max_cluster_duration_ns = duration_ns;
+        self
+    }
+
+    #[must_use]
+    pub fn set_max_cluster_size(mut self, size: u64) -> Self {
+        self.max_cluster_size = size;
+        self
+    }
+
+    #[must_use]
+    pub fn set_writing_app(mut self, app_name: &str) -> Self {
+        self.writing_app = Some(app_name.to_owned());
+        self
+    }
+
+    #[must_use]
+    pub fn add_tag(mut self, name: &str, value: &str) -> Self {
+        self.tags.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Returns the frames of all tracks, in the order they are written.
+    #[must_use]
+    pub fn frames(&self) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        for (number, track) in (1..).zip(&self.tracks) {
+            for index in 0..track.frame_count {
+                let keyframe = track.is_keyframe(index);
+                frames.push(Frame {
+                    track: number,
+                    timestamp_ns: u64::from(index) * track.frame_interval_ns,
+                    keyframe,
+                    discard_padding_ns: 0,
+                    data: track.codec.frame(index, keyframe),
+                });
+            }
+        }
+
+        // Sorting is stable, so frames at the same time are in track order
+        frames.sort_by_key(|frame| frame.timestamp_ns);
+        frames
+    }
+
+    /// Returns when the last frame ends, in nanoseconds.
+    #[must_use]
+    pub fn duration_ns(&self) -> u64 {
+        let track_end = |track: &SyntheticTrack| u64::from(track.frame_count) * track.frame_interval_ns;
+        self.tracks.iter().map(track_end).max().unwrap_or(0)
+    }
+
+    /// Muxes the file, returning its bytes. Keyframe flags are validated against the frame data.
+    pub fn mux(&self) -> Result<Vec<u8>, Error> {
+        let mut builder = SegmentBuilder::new(self.writer())?
+            .set_keyframe_validation(true)
+            .set_cues(self.cues)?
+            .set_max_cluster_size(self.max_cluster_size)?;
+        if self.max_cluster_duration_ns != 0 {
+            builder = builder.set_max_cluster_duration(self.max_cluster_duration_ns)?;
+        }
+        if let Some(app_name) = &self.writing_app {
+            builder = builder.set_writing_app(app_name)?;
+        }
+        for (name, value) in &self.tags {
+            builder = builder.add_tag(name, value)?;
+        }
+
+        for (number, track) in (1..).zip(&self.tracks) {
+            let (width, height) = VIDEO_SIZE;
+            let number = Some(number);
+            builder = match track.codec {
+                SyntheticCodec::Vp8 => {
+                    let (builder, handle) = builder.add_video_track(width, height, VideoCodecId::VP8, number)?;
+                    track_metadata(builder, handle, track)?
+                },
+                SyntheticCodec::Vp9 => {
+                    let header = KeyframeHeader::parse(&VP9_KEYFRAME)?;
+                    let (builder, handle) = builder.add_vp9_track(&header, None, number)?;
+                    track_metadata(builder, handle, track)?
+                },
+                SyntheticCodec::Av1 => {
+                    let header = SequenceHeader::from_obus(&AV1_SEQUENCE_HEADER)?;
+                    let (builder, handle) = builder.add_av1_track(&header, number)?;
+                    track_metadata(builder, handle, track)?
+                },
+                SyntheticCodec::Opus => {
+                    let (builder, handle) = builder.add_audio_track(48_000, 2, None, AudioCodecId::Opus, number)?;
+                    let builder = builder.set_opus_head(handle, &OpusHead::new(2, OPUS_PRE_SKIP, 48_000)?)?;
+                    track_metadata(builder, handle, track)?
+                },
+            };
+        }
+
+        let mut segment = builder.build();
+        for frame in self.frames() {
+            segment.add_frame(frame.track, &frame.data, frame.timestamp_ns, frame.keyframe)?;
+        }
+
+        // The `Duration` is in milliseconds, the default timecode scale
+        let duration = self.duration_ns().div_ceil(1_000_000);
+        let writer = segment.finalize(Some(duration)).map_err(|_| Error::Unknown)?;
+        Ok(writer.into_inner().into_inner())
+    }
+
+    fn writer(&self) -> Writer<Cursor<Vec<u8>>> {
+        if self.seekable {
+            Writer::new(Cursor::new(Vec::new()))
+        } else {
+            Writer::new_non_seek(Cursor::new(Vec::new()))
+        }
+    }
+
+    /// Parses `file` with libwebm and checks that it contains exactly the tracks, frames, tags and metadata this
+    /// round trip describes, and that it passes [`validate`]. Returns what differs first, if anything.
+    pub fn check(&self, file: &[u8]) -> Result<(), String> {
+        let mode = if self.seekable { Mode::File } else { Mode::Live };
+        let violations = validate(Cursor::new(file), mode).map_err(|err| format!("validation failed: {err}"))?;
+        if let Some(violation) = violations.first() {
+            return Err(format!("file is not valid WebM, {violation}"));
+        }
+
+        let mut demuxer = Demuxer::new(Cursor::new(file)).map_err(|err| format!("file could not be parsed: {err}"))?;
+        let info = demuxer.info();
+        expect_eq("DocType", info.doc_type.as_str(), "webm")?;
+        expect_eq("TimecodeScale", info.timecode_scale, 1_000_000)?;
+        let expected_duration = self.seekable.then(|| self.duration_ns().div_ceil(1_000_000) * 1_000_000);
+        expect_eq("Duration", info.duration_ns, expected_duration)?;
+        if let Some(app_name) = &self.writing_app {
+            expect_eq("WritingApp", info.writing_app.as_ref(), Some(app_name))?;
+        }
+        expect_eq("tags", demuxer.tags(), self.tags.as_slice())?;
+
+        expect_eq("number of tracks", demuxer.tracks().len(), self.tracks.len())?;
+        for (number, expected) in (1..).zip(&self.tracks) {
+            let track = demuxer.track(number).ok_or_else(|| format!("track {number} is missing"))?;
+            let context = |what: &str| format!("{what} of track {number}");
+            expect_eq(&context("name"), track.name.as_ref(), expected.name.as_ref())?;
+            if let Some(language) = &expected.language {
+                expect_eq(&context("language"), track.language.as_ref(), Some(language))?;
+            }
+
+            match (&track.kind, expected.codec) {
+                (TrackKind::Video(video), codec) if codec.is_video() => {
+                    let codec_id = match codec {
+                        SyntheticCodec::Vp8 => VideoCodecId::VP8,
+                        SyntheticCodec::Vp9 => VideoCodecId::VP9,
+                        _ => VideoCodecId::AV1,
+                    };
+                    expect_eq(&context("codec"), track.codec_id.as_str(), codec_id.codec_id())?;
+                    expect_eq(&context("size"), (video.width, video.height), VIDEO_SIZE)?;
+                    if codec == SyntheticCodec::Av1 {
+                        let header = SequenceHeader::from_obus(&AV1_SEQUENCE_HEADER).map_err(|err| err.to_string())?;
+                        expect_eq(&context("CodecPrivate"), track.codec_private.clone(), header.to_codec_private())?;
+                    }
+                },
+                (TrackKind::Audio(audio), SyntheticCodec::Opus) => {
+                    expect_eq(&context("codec"), track.codec_id.as_str(), "A_OPUS")?;
+                    expect_eq(&context("audio parameters"), (audio.sample_rate, audio.channels), (48_000.0, 2))?;
+                    let head = OpusHead::new(2, OPUS_PRE_SKIP, 48_000).and_then(|head| head.to_bytes());
+                    expect_eq(&context("CodecPrivate"), Ok(track.codec_private.clone()), head.map_err(|_| ()))?;
+                    expect_eq(&context("CodecDelay"), track.codec_delay_ns, 6_500_000)?;
+                    expect_eq(&context("SeekPreRoll"), track.seek_pre_roll_ns, 80_000_000)?;
+                },
+                (kind, codec) => return Err(format!("track {number} is {kind:?}, expected {codec:?}")),
+            }
+        }
+
+        // libwebm may hold frames back to start clusters on keyframes, so only the order within tracks is kept
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().map_err(|err| format!("reading failed: {err}"))?;
+        let expected_frames = self.frames();
+        for number in 1..=self.tracks.len() as TrackNum {
+            let read = frames.iter().filter(|frame| frame.track == number);
+            let expected = expected_frames.iter().filter(|frame| frame.track == number);
+            expect_eq(&format!("number of frames on track {number}"), read.clone().count(), expected.clone().count())?;
+            for (index, (read, expected)) in read.zip(expected).enumerate() {
+                expect_eq(&format!("frame {index} of track {number}"), read, expected)?;
+            }
+        }
+
+        let cue_points = demuxer.cue_points().map_err(|err| format!("reading cues failed: {err}"))?;
+        if !self.seekable || !self.cues {
+            return expect_eq("number of cue points", cue_points.len(), 0);
+        }
+        if cue_points.is_empty() {
+            return Err("file has no cue points".to_owned());
+        }
+        for cue_point in cue_points {
+            let is_keyframe = |frame: &&Frame| {
+                frame.track == cue_point.track && frame.timestamp_ns == cue_point.timestamp_ns && frame.keyframe
+            };
+            if !expected_frames.iter().any(|frame| is_keyframe(&frame)) {
+                return Err(format!("{cue_point:?} does not point to a keyframe"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Muxes the file and checks it, panicking with what went wrong if anything did.
+    pub fn assert_round_trip(&self) {
+        let file = self.mux().unwrap_or_else(|err| panic!("muxing {self:?} failed: {err}"));
+        if let Err(message) = self.check(&file) {
+            panic!("round trip of {self:?} failed: {message}");
+        }
+    }
+}
+
+fn track_metadata<T: Track + Copy>(
+    mut builder: SegmentBuilder<Cursor<Vec<u8>>>,
+    handle: T,
+    track: &SyntheticTrack,
+) -> Result<SegmentBuilder<Cursor<Vec<u8>>>, Error> {
+    if let Some(name) = &track.name {
+        builder = builder.set_track_name(handle, name)?;
+    }
+    if let Some(language) = &track.language {
+        builder = builder.set_track_language(handle, language)?;
+    }
+    Ok(builder)
+}
+
+fn expect_eq<T: PartialEq + std::fmt::Debug>(what: &str, actual: T, expected: T) -> Result<(), String> {
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("{what} is {actual:?}, expected {expected:?}"))
+    }
+}
@@ -0,0 +1,270 @@
+//! Cutting a time range out of a WebM file without re-encoding.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::mux::Writer;
+//!
+//! // Keep seconds 10 to 25
+//! let input = BufReader::new(File::open("./recording.webm").unwrap());
+//! let output = Writer::new(File::create("./clip.webm").unwrap());
+//! webm::trim::trim(input, output, 10_000_000_000, 25_000_000_000).unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use crate::codec::opus;
+use crate::demux::{Demuxer, Frame, TrackInfo, TrackKind};
+use crate::mux::{Error, SegmentTemplate, TrackNum, Writer};
+use crate::remux::{copy_track, doc_type};
+use crate::repair::{TrackEnd, TIMECODE_SCALE};
+
+/// A track being copied by [`trim`].
+struct TrimmedTrack {
+    info: TrackInfo,
+    is_video: bool,
+    is_opus: bool,
+
+    /// The `CodecDelay` of the output track, in nanoseconds.
+    codec_delay_ns: u64,
+
+    /// Whether a frame has been written yet. Video tracks must start with a keyframe.
+    started: bool,
+
+    /// Whether a frame past the end of the range has been read.
+    finished: bool,
+    end: TrackEnd,
+}
+
+impl TrimmedTrack {
+    fn new(info: TrackInfo) -> Self {
+        Self {
+            is_video: matches!(info.kind, TrackKind::Video(_)),
+            is_opus: info.codec_id == "A_OPUS",
+            codec_delay_ns: info.codec_delay_ns,
+            started: false,
+            finished: false,
+            end: TrackEnd::default(),
+            info,
+        }
+    }
+
+    /// Returns the duration of `frame`, if its codec makes it known.
+    fn frame_duration_ns(&self, frame: &Frame) -> Option<u64> {
+        self.is_opus.then(|| opus::packet_duration_ns(&frame.data).ok()).flatten()
+    }
+
+    /// Returns whether `frame` is needed to play the track from `cut_ns` on. For Opus, this includes the packets
+    /// that overlap `cut_ns`, along with the `SeekPreRoll` before them.
+    fn needed_from(&self, frame: &Frame, cut_ns: u64) -> bool {
+        // Timestamps include the `CodecDelay`, which is discarded from the start of the decoded audio
+        let cut_ns = cut_ns + self.info.codec_delay_ns;
+        match self.frame_duration_ns(frame) {
+            Some(duration_ns) => frame.timestamp_ns + duration_ns + self.info.seek_pre_roll_ns > cut_ns,
+            None => frame.timestamp_ns >= cut_ns,
+        }
+    }
+
+    /// Returns whether `frame` starts playing before `end_ns`.
+    fn starts_before(&self, frame: &Frame, end_ns: u64) -> bool {
+        frame.timestamp_ns < end_ns.saturating_add(self.info.codec_delay_ns)
+    }
+}
+
+/// Copies the frames of the WebM (or Matroska) file in `input` between `start_ns` (inclusive) and `end_ns`
+/// (exclusive) into a new file written to `writer`, and returns the writer once done. Timestamps start at zero in
+/// the output.
+///
+/// As nothing is re-encoded, the output starts at the last video keyframe at or before `start_ns`, so it may include
+/// some more video at the start. Opus audio is cut exactly: the `CodecDelay` of the output track is raised to discard
+/// the audio before that keyframe, and `DiscardPadding` is set on the last packet to discard the audio after
+/// `end_ns`. For other codecs, frames are kept if they start within the range.
+///
+/// Tracks, tags and the `DocType` are copied as with [`remux`](crate::remux::remux). Tracks that are neither video
+/// nor audio are left out.
+///
+/// Fails with [`Error::BadParam`] if `start_ns` is not before `end_ns`, or if the range contains no frames.
+pub fn trim<R: Read + Seek, W: Write>(
+    input: R,
+    writer: Writer<W>,
+    start_ns: u64,
+    end_ns: u64,
+) -> Result<Writer<W>, Error> {
+    if start_ns >= end_ns {
+        return Err(Error::BadParam);
+    }
+
+    let mut demuxer = Demuxer::new(input)?;
+    let mut tracks: HashMap<TrackNum, TrimmedTrack> = demuxer
+        .tracks()
+        .iter()
+        .filter(|track| !matches!(track.kind, TrackKind::Other))
+        .map(|track| (track.number, TrimmedTrack::new(track.clone())))
+        .collect();
+    if tracks.is_empty() {
+        return Err(Error::InvalidData("file has no video or audio tracks"));
+    }
+
+    // Without an index of keyframes, the frames since the last video keyframe are kept until `start_ns` is reached
+    let has_video = tracks.values().any(|track| track.is_video);
+    let mut keyframes_ns = HashMap::new();
+    let mut cut_ns = (!has_video).then_some(start_ns);
+    let mut buffered = Vec::new();
+    let mut reached_start = false;
+    while let Some(frame) = demuxer.next_frame()? {
+        let Some(track) = tracks.get(&frame.track) else {
+            continue;
+        };
+
+        if track.is_video && frame.keyframe && frame.timestamp_ns <= start_ns {
+            keyframes_ns.insert(frame.track, frame.timestamp_ns);
+            let keyframe_cut_ns = keyframes_ns.values().copied().min().unwrap_or(start_ns);
+            cut_ns = Some(keyframe_cut_ns);
+            buffered.retain(|frame: &Frame| tracks[&frame.track].needed_from(frame, keyframe_cut_ns));
+        }
+
+        reached_start = frame.timestamp_ns >= start_ns;
+        if cut_ns.is_none_or(|cut_ns| track.needed_from(&frame, cut_ns)) {
+            buffered.push(frame);
+        }
+        if reached_start {
+            break;
+        }
+    }
+    if !reached_start {
+        return Err(Error::BadParam);
+    }
+    let cut_ns = cut_ns.unwrap_or(start_ns);
+    buffered.retain(|frame| tracks[&frame.track].needed_from(frame, cut_ns));
+
+    // Opus packets that start before the cut are played from it on by raising the `CodecDelay`
+    for track in tracks.values_mut().filter(|track| track.is_opus) {
+        if let Some(first) = buffered.iter().find(|frame| frame.track == track.info.number) {
+            track.codec_delay_ns = (cut_ns + track.info.codec_delay_ns).saturating_sub(first.timestamp_ns);
+        }
+    }
+
+    let info = demuxer.info();
+    let mut template = SegmentTemplate::new().set_doc_type(doc_type(&info.doc_type))?;
+    if let Some(app_name) = &info.writing_app {
+        template = template.set_writing_app(app_name)?;
+    }
+    for (name, value) in demuxer.tags() {
+        template = template.add_tag(name, value)?;
+    }
+    for track in demuxer.tracks().iter().filter(|track| tracks.contains_key(&track.number)) {
+        template = copy_track(template, track)?;
+        let trimmed = &tracks[&track.number];
+        if trimmed.codec_delay_ns != track.codec_delay_ns {
+            template = template.set_codec_delay(track.number, trimmed.codec_delay_ns, track.seek_pre_roll_ns)?;
+        }
+    }
+
+    let mut segment = template.build(writer)?;
+    let mut written_frames = 0;
+    let rest = std::iter::from_fn(|| demuxer.next_frame().transpose());
+    for frame in buffered.into_iter().map(Ok).chain(rest) {
+        let frame = frame?;
+        let Some(track) = tracks.get_mut(&frame.track) else {
+            continue;
+        };
+        if !track.starts_before(&frame, end_ns) {
+            track.finished = true;
+            if tracks.values().all(|track| track.finished) {
+                break;
+            }
+            continue;
+        }
+        if !track.needed_from(&frame, cut_ns) || (track.is_video && !track.started && !frame.keyframe) {
+            continue;
+        }
+
+        // Audio after `end_ns` is discarded through `DiscardPadding`
+        let duration_ns = track.frame_duration_ns(&frame);
+        let mut discard_padding_ns = frame.discard_padding_ns;
+        if let Some(duration_ns) = duration_ns {
+            let track_end_ns = end_ns.saturating_add(track.info.codec_delay_ns);
+            let overlap_ns = (frame.timestamp_ns + duration_ns).saturating_sub(track_end_ns);
+            discard_padding_ns = discard_padding_ns.max(overlap_ns.min(duration_ns) as i64);
+        }
+
+        let timestamp_ns =
+            (frame.timestamp_ns + track.codec_delay_ns).saturating_sub(cut_ns + track.info.codec_delay_ns);
+        segment.add_frame_with_discard_padding(
+            frame.track,
+            &frame.data,
+            timestamp_ns,
+            frame.keyframe,
+            discard_padding_ns,
+        )?;
+        track.started = true;
+        track.end.update(timestamp_ns.saturating_sub(track.codec_delay_ns), duration_ns);
+        written_frames += 1;
+    }
+
+    if written_frames == 0 {
+        return Err(Error::BadParam);
+    }
+
+    // The `Duration` is the time played, which ends at `end_ns` at the latest
+    let duration_ns = tracks.values().map(|track| track.end.end_ns()).max().unwrap_or(0).min(end_ns - cut_ns);
+    segment
+        .finalize(Some(duration_ns.div_ceil(TIMECODE_SCALE)))
+        .map_err(|_| Error::Unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::remux::tests::live_recording;
+
+    fn trim_recording(start_ns: u64, end_ns: u64) -> Result<Demuxer<Cursor<Vec<u8>>>, Error> {
+        let output = trim(Cursor::new(live_recording()), Writer::new(Cursor::new(Vec::new())), start_ns, end_ns)?;
+        Demuxer::new(Cursor::new(output.into_inner().into_inner()))
+    }
+
+    #[test]
+    fn rejects_empty_range() {
+        let result = trim(Cursor::new(Vec::new()), Writer::new_non_seek(Vec::new()), 1000, 1000);
+        assert!(matches!(result, Err(Error::BadParam)));
+    }
+
+    #[test]
+    fn trims_from_keyframe() {
+        let mut demuxer = trim_recording(250_000_000, 400_000_000).unwrap();
+        assert_eq!(demuxer.info().duration_ns, Some(200_000_000));
+
+        // The audio starts 80 ms before the keyframe at 200 ms, for the `SeekPreRoll`
+        let audio = demuxer.track(2).unwrap();
+        assert_eq!((audio.codec_delay_ns, audio.seek_pre_roll_ns), (86_500_000, 80_000_000));
+
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        let video: Vec<_> = frames.iter().filter(|frame| frame.track == 1).collect();
+        assert_eq!(video.len(), 5);
+        assert_eq!((video[0].timestamp_ns, video[0].keyframe), (0, true));
+        assert_eq!(video[4].timestamp_ns, 160_000_000);
+
+        let audio: Vec<_> = frames.iter().filter(|frame| frame.track == 2).collect();
+        assert_eq!(audio.len(), 15);
+        assert_eq!(audio[0].timestamp_ns, 0);
+        assert_eq!(audio[14].timestamp_ns, 280_000_000);
+        assert_eq!(audio[14].discard_padding_ns, 13_500_000);
+
+        assert!(matches!(trim_recording(1_000_000_000, 2_000_000_000), Err(Error::BadParam)));
+    }
+
+    #[test]
+    fn trims_to_unbounded_end() {
+        let mut demuxer = trim_recording(250_000_000, u64::MAX).unwrap();
+        assert_eq!(demuxer.track(2).unwrap().codec_delay_ns, 86_500_000);
+
+        let frames = demuxer.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(frames.iter().filter(|frame| frame.track == 1).count(), 10);
+        let audio: Vec<_> = frames.iter().filter(|frame| frame.track == 2).collect();
+        assert_eq!(audio.len(), 24);
+        assert_eq!(audio[23].discard_padding_ns, 0);
+    }
+}
@@ -0,0 +1,679 @@
+//! Checking that a file sticks to the subset of Matroska that WebM allows.
+//!
+//! [`validate`] walks the EBML structure of a file with an [`ebml::Reader`](crate::ebml::Reader) rather than going
+//! through libwebm, so it can report problems that the parser would accept or quietly work around.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use webm::validate::{validate, Mode};
+//!
+//! let input = BufReader::new(File::open("./my-cool-file.webm").unwrap());
+//! for violation in validate(input, Mode::File).unwrap() {
+//!     println!("{violation}");
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Seek};
+
+use crate::ebml::{Children, Element, ElementId, ElementType, Reader};
+use crate::mux::Error;
+
+// IDs as plain numbers, to match on
+const EBML: u32 = ElementId::EBML.0;
+const DOC_TYPE: u32 = ElementId::DOC_TYPE.0;
+const VOID: u32 = ElementId::VOID.0;
+const SEGMENT: u32 = ElementId::SEGMENT.0;
+const TIMECODE_SCALE: u32 = ElementId::TIMECODE_SCALE.0;
+const TRACK_ENTRY: u32 = ElementId::TRACK_ENTRY.0;
+const TRACK_NUMBER: u32 = ElementId::TRACK_NUMBER.0;
+const TRACK_TYPE: u32 = ElementId::TRACK_TYPE.0;
+const CODEC_ID: u32 = ElementId::CODEC_ID.0;
+const CLUSTER: u32 = ElementId::CLUSTER.0;
+const TIMECODE: u32 = ElementId::TIMECODE.0;
+const SIMPLE_BLOCK: u32 = ElementId::SIMPLE_BLOCK.0;
+const BLOCK_GROUP: u32 = ElementId::BLOCK_GROUP.0;
+const BLOCK: u32 = ElementId::BLOCK.0;
+const REFERENCE_BLOCK: u32 = ElementId::REFERENCE_BLOCK.0;
+const CUE_POINT: u32 = ElementId::CUE_POINT.0;
+const CUE_TIME: u32 = ElementId::CUE_TIME.0;
+const CUE_TRACK_POSITIONS: u32 = ElementId::CUE_TRACK_POSITIONS.0;
+const CUE_TRACK: u32 = ElementId::CUE_TRACK.0;
+const CUE_CLUSTER_POSITION: u32 = ElementId::CUE_CLUSTER_POSITION.0;
+
+const VIDEO_TRACK_TYPE: u64 = 1;
+
+/// The codec IDs WebM allows.
+const WEBM_CODEC_IDS: [&str; 9] = [
+    "V_VP8",
+    "V_VP9",
+    "V_AV1",
+    "A_VORBIS",
+    "A_OPUS",
+    "D_WEBVTT/SUBTITLES",
+    "D_WEBVTT/CAPTIONS",
+    "D_WEBVTT/DESCRIPTIONS",
+    "D_WEBVTT/METADATA",
+];
+
+/// Strings longer than this are not read, as none of the ones checked are anywhere near it.
+const MAX_STRING_LEN: u64 = 1024;
+
+/// The longest block header: a track number of up to 8 bytes, a 16-bit timestamp and the flags.
+const MAX_BLOCK_HEADER_LEN: u64 = 8 + 2 + 1;
+
+/// The range of the 16-bit timestamps of blocks relative to their cluster.
+const RELATIVE_TIMESTAMP_RANGE: i64 = 1 << 16;
+
+/// Returns whether `id` is an element WebM allows, and if so, whether it is a master element.
+fn webm_element(id: u32) -> Option<bool> {
+    ElementId(id).element_type().map(|element_type| element_type == ElementType::Master)
+}
+
+/// How the file being validated was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Written to a file or other seekable destination, so all element sizes must be known.
+    File,
+
+    /// Written live, such as for streaming. The `Segment` and `Cluster` elements may have unknown sizes.
+    Live,
+}
+
+/// A way in which a file checked by [`validate`] breaks the WebM specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Where in the file the offending element starts.
+    pub position: u64,
+
+    /// What is wrong with it.
+    pub kind: ViolationKind,
+}
+
+/// What is wrong in a [`Violation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ViolationKind {
+    /// The EBML structure is broken, so the rest of the file could not be checked. The message describes what was
+    /// wrong.
+    InvalidStructure(&'static str),
+
+    /// An element that WebM does not allow, or that is not known at all.
+    DisallowedElement { id: u32 },
+
+    /// An element with an unknown size, which is only allowed for `Segment` and `Cluster` elements of files written
+    /// live.
+    UnknownSize { id: u32 },
+
+    /// The `DocType` is not `webm`.
+    DocType(String),
+
+    /// A track has a codec WebM does not allow.
+    CodecId { track: u64, codec_id: String },
+
+    /// A block's timestamp relative to its cluster jumps by more than 32767 timestamp units from that of the block
+    /// before it on the same track and in the same cluster. That is what a timestamp too far from the cluster's
+    /// looks like after being cut to 16 bits.
+    ClusterTimestampOverflow { track: u64, relative_timestamp: i16 },
+
+    /// A block has an earlier timestamp than the block before it on the same track.
+    NonMonotonicTimestamp { track: u64, timestamp_ns: u64, previous_timestamp_ns: u64 },
+
+    /// The first block of a video track in a cluster is not a keyframe.
+    MissingKeyframe { track: u64 },
+
+    /// A cue point does not point to a cluster containing a keyframe of its track at its time.
+    CuePosition { track: u64, timestamp_ns: u64, cluster_position: u64 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: ", self.position)?;
+        match &self.kind {
+            ViolationKind::InvalidStructure(message) => write!(f, "invalid structure: {message}"),
+            ViolationKind::DisallowedElement { id } => write!(f, "element {id:#X} is not allowed in WebM"),
+            ViolationKind::UnknownSize { id } => write!(f, "element {id:#X} has an unknown size"),
+            ViolationKind::DocType(doc_type) => write!(f, "DocType is {doc_type:?} instead of \"webm\""),
+            ViolationKind::CodecId { track, codec_id } => {
+                write!(f, "track {track} has codec {codec_id:?}, which is not allowed in WebM")
+            },
+            ViolationKind::ClusterTimestampOverflow {
+                track,
+                relative_timestamp,
+            } => write!(
+                f,
+                "block on track {track} has a timestamp relative to its cluster that overflowed ({relative_timestamp})"
+            ),
+            ViolationKind::NonMonotonicTimestamp {
+                track,
+                timestamp_ns,
+                previous_timestamp_ns,
+            } => write!(
+                f,
+                "block on track {track} at {timestamp_ns} ns comes after one at {previous_timestamp_ns} ns"
+            ),
+            ViolationKind::MissingKeyframe { track } => {
+                write!(f, "cluster does not start with a keyframe on video track {track}")
+            },
+            ViolationKind::CuePosition {
+                track,
+                timestamp_ns,
+                cluster_position,
+            } => write!(
+                f,
+                "cue point for track {track} at {timestamp_ns} ns points to {cluster_position}, which is not a \
+                 cluster with a keyframe at that time"
+            ),
+        }
+    }
+}
+
+/// Walks the EBML structure of the file in `input` and returns everything in it that breaks the WebM
+/// specification, in the order found. An empty list means the file is valid, as far as these checks go:
+///
+/// * Elements that WebM does not allow, and a `DocType` other than `webm`
+/// * Codecs other than VP8, VP9, AV1, Vorbis, Opus and WebVTT
+/// * Element sizes that are unknown, where `mode` doesn't allow them
+/// * Blocks whose timestamp relative to their cluster overflowed, judged by the previous block of the same track
+/// * Blocks with an earlier timestamp than the one before them on the same track
+/// * Clusters that don't start with a keyframe on each video track
+/// * Cue points that don't point to a keyframe of their track at their time
+///
+/// Checking stops at the first [`ViolationKind::InvalidStructure`], as the rest of the file can't be made sense of.
+/// Fails only with [`Error::Io`], if reading from `input` failed.
+pub fn validate<R: Read + Seek>(input: R, mode: Mode) -> Result<Vec<Violation>, Error> {
+    let mut validator = Validator::new(input, mode);
+    match validator.walk_file() {
+        Ok(()) => validator.check_cues(),
+        Err(Stop::Invalid(position, message)) => {
+            validator.report(position, ViolationKind::InvalidStructure(message));
+        },
+        Err(Stop::Io(err)) => return Err(Error::Io(err)),
+    }
+    Ok(validator.violations)
+}
+
+/// Why walking the file stopped early.
+enum Stop {
+    Invalid(u64, &'static str),
+    Io(io::Error),
+}
+
+impl Stop {
+    /// Stops on an error from the [`Reader`], blaming the element at `position`.
+    fn at(position: u64, error: Error) -> Self {
+        match error {
+            Error::Io(err) => Self::Io(err),
+            Error::InvalidData(message) => Self::Invalid(position, message),
+            _ => Self::Invalid(position, "element could not be read"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackState {
+    is_video: bool,
+    last_timestamp: Option<u64>,
+
+    /// The position of the cluster of the last block, and the timestamp relative to it that block was meant to
+    /// have, which is outside the 16-bit range if it overflowed
+    last_relative_timestamp: Option<(u64, i64)>,
+}
+
+/// A `TrackEntry` or `CueTrackPositions` being read.
+#[derive(Default)]
+struct Fields {
+    number: Option<u64>,
+    track_type: Option<u64>,
+    codec_id: Option<String>,
+    cluster_position: Option<u64>,
+}
+
+struct Cue {
+    position: u64,
+    time: u64,
+    track: u64,
+    cluster_position: u64,
+}
+
+struct Validator<R> {
+    reader: Reader<R>,
+    mode: Mode,
+    violations: Vec<Violation>,
+
+    timecode_scale: u64,
+    tracks: HashMap<u64, TrackState>,
+    segment_data_position: u64,
+
+    /// The fields of the innermost `TrackEntry` or `CueTrackPositions` being read
+    fields: Fields,
+
+    /// The cluster being read: its position relative to the segment data, its timestamp and the tracks with a block
+    /// in it so far
+    cluster_position: u64,
+    cluster_timecode: Option<u64>,
+    cluster_tracks: HashSet<u64>,
+
+    /// The `Block` of the `BlockGroup` being read, and whether it has a `ReferenceBlock`
+    group_block: Option<(u64, Vec<u8>)>,
+    group_has_reference: bool,
+
+    /// The position of the cluster, track and timestamp of every keyframe, to check cue points against
+    keyframes: HashSet<(u64, u64, u64)>,
+    cue_time: Option<u64>,
+    cues: Vec<Cue>,
+}
+
+impl<R: Read + Seek> Validator<R> {
+    fn new(input: R, mode: Mode) -> Self {
+        Self {
+            reader: Reader::new(input),
+            mode,
+            violations: Vec::new(),
+            timecode_scale: 1_000_000,
+            tracks: HashMap::new(),
+            segment_data_position: 0,
+            fields: Fields::default(),
+            cluster_position: 0,
+            cluster_timecode: None,
+            cluster_tracks: HashSet::new(),
+            group_block: None,
+            group_has_reference: false,
+            keyframes: HashSet::new(),
+            cue_time: None,
+            cues: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, position: u64, kind: ViolationKind) {
+        self.violations.push(Violation { position, kind });
+    }
+
+    fn next_child(&mut self, children: &mut Children) -> Result<Option<Element>, Stop> {
+        self.reader.next_child(children).map_err(|error| Stop::at(children.position(), error))
+    }
+
+    fn read_uint(&mut self, element: &Element) -> Result<u64, Stop> {
+        self.reader.read_uint(element).map_err(|error| Stop::at(element.position, error))
+    }
+
+    fn read_string(&mut self, element: &Element) -> Result<String, Stop> {
+        if element.size.is_some_and(|size| size > MAX_STRING_LEN) {
+            return Err(Stop::Invalid(element.position, "element is too long"));
+        }
+        let data = self.reader.read_binary(element).map_err(|error| Stop::at(element.position, error))?;
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        Ok(String::from_utf8_lossy(&data[..len]).into_owned())
+    }
+
+    fn walk_file(&mut self) -> Result<(), Stop> {
+        let mut top_level = self.reader.top_level();
+        match self.next_child(&mut top_level)? {
+            Some(element) if element.id == ElementId::EBML => self.walk_element(element)?,
+            _ => return Err(Stop::Invalid(0, "file does not start with an EBML header")),
+        }
+
+        while let Some(element) = self.next_child(&mut top_level)? {
+            if !matches!(element.id.0, SEGMENT | EBML | VOID) {
+                return Err(Stop::Invalid(element.position, "top-level element is not an EBML header or Segment"));
+            }
+            self.walk_element(element)?;
+        }
+        Ok(())
+    }
+
+    fn walk_element(&mut self, element: Element) -> Result<(), Stop> {
+        let id = element.id.0;
+        let Some(is_master) = webm_element(id) else {
+            self.report(element.position, ViolationKind::DisallowedElement { id });
+            if element.size.is_none() {
+                return Err(Stop::Invalid(element.position, "element can't have an unknown size"));
+            }
+            return Ok(());
+        };
+
+        if element.size.is_none() {
+            match (id, self.mode) {
+                (SEGMENT | CLUSTER, Mode::Live) => {},
+                (SEGMENT | CLUSTER, Mode::File) => {
+                    self.report(element.position, ViolationKind::UnknownSize { id });
+                },
+                _ => return Err(Stop::Invalid(element.position, "element can't have an unknown size")),
+            }
+        }
+
+        if !is_master {
+            return self.read_value(&element);
+        }
+
+        match id {
+            SEGMENT => self.segment_data_position = element.data_position,
+            TRACK_ENTRY | CUE_TRACK_POSITIONS => self.fields = Fields::default(),
+            CLUSTER => {
+                self.cluster_position = element.position - self.segment_data_position;
+                self.cluster_timecode = None;
+                self.cluster_tracks.clear();
+            },
+            BLOCK_GROUP => {
+                self.group_block = None;
+                self.group_has_reference = false;
+            },
+            CUE_POINT => self.cue_time = None,
+            _ => {},
+        }
+
+        // The reader bounds the nesting, which keeps crafted files from exhausting the stack
+        let mut children = self.reader.children(&element).map_err(|error| Stop::at(element.position, error))?;
+        while let Some(child) = self.next_child(&mut children)? {
+            self.walk_element(child)?;
+        }
+
+        match id {
+            TRACK_ENTRY => self.end_track_entry(&element),
+            CUE_TRACK_POSITIONS => self.end_cue_track_positions(&element),
+            BLOCK_GROUP => {
+                if let Some((position, data)) = self.group_block.take() {
+                    let keyframe = !self.group_has_reference;
+                    self.check_block(position, &data, keyframe)?;
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn read_value(&mut self, element: &Element) -> Result<(), Stop> {
+        match element.id.0 {
+            DOC_TYPE => {
+                let doc_type = self.read_string(element)?;
+                if doc_type != "webm" {
+                    self.report(element.position, ViolationKind::DocType(doc_type));
+                }
+            },
+            TIMECODE_SCALE => self.timecode_scale = self.read_uint(element)?,
+            TRACK_NUMBER => self.fields.number = Some(self.read_uint(element)?),
+            TRACK_TYPE => self.fields.track_type = Some(self.read_uint(element)?),
+            CODEC_ID => self.fields.codec_id = Some(self.read_string(element)?),
+            TIMECODE => self.cluster_timecode = Some(self.read_uint(element)?),
+            SIMPLE_BLOCK => {
+                // Only the track number, timestamp and flags are needed
+                let data = self.read_block_header(element)?;
+                let keyframe = data.last().is_some_and(|flags| flags & 0x80 != 0);
+                self.check_block(element.position, &data, keyframe)?;
+            },
+            BLOCK => self.group_block = Some((element.position, self.read_block_header(element)?)),
+            REFERENCE_BLOCK => self.group_has_reference = true,
+            CUE_TIME => self.cue_time = Some(self.read_uint(element)?),
+            CUE_TRACK => self.fields.number = Some(self.read_uint(element)?),
+            CUE_CLUSTER_POSITION => self.fields.cluster_position = Some(self.read_uint(element)?),
+            _ => {},
+        }
+        Ok(())
+    }
+
+    /// Reads the start of a block, up to and including its flags.
+    fn read_block_header(&mut self, element: &Element) -> Result<Vec<u8>, Stop> {
+        let mut data = self
+            .reader
+            .read_prefix(element, MAX_BLOCK_HEADER_LEN)
+            .map_err(|error| Stop::at(element.position, error))?;
+        if let Some(&first) = data.first() {
+            let len = first.leading_zeros() as usize + 1;
+            if first == 0 || len + 3 > data.len() {
+                return Err(Stop::Invalid(element.position, "block is too short"));
+            }
+            data.truncate(len + 3);
+        }
+        Ok(data)
+    }
+
+    /// Checks a block, given its data up to and including its flags.
+    fn check_block(&mut self, position: u64, data: &[u8], keyframe: bool) -> Result<(), Stop> {
+        let Some(&first) = data.first() else {
+            return Err(Stop::Invalid(position, "block is empty"));
+        };
+        let track_len = first.leading_zeros() as usize + 1;
+        let track = data[..track_len].iter().fold(0, |acc, &b| acc << 8 | u64::from(b));
+        let track = track & (u64::MAX >> (64 - 7 * track_len));
+        let relative_timestamp = i16::from_be_bytes([data[track_len], data[track_len + 1]]);
+
+        let Some(cluster_timecode) = self.cluster_timecode else {
+            return Err(Stop::Invalid(position, "block comes before the Timecode of its cluster"));
+        };
+        let Some(state) = self.tracks.get_mut(&track) else {
+            return Err(Stop::Invalid(position, "block belongs to a track that doesn't exist"));
+        };
+
+        // Negative relative timestamps are fine, but a muxer that let one overflow meant it to be a multiple of the
+        // 16-bit range away. Take whichever is closest to the previous block of the track in this cluster.
+        let mut meant_relative_timestamp = i64::from(relative_timestamp);
+        let in_cluster = state.last_relative_timestamp.filter(|&(cluster, _)| cluster == self.cluster_position);
+        if let Some((_, previous)) = in_cluster {
+            let wraps = (previous - meant_relative_timestamp + RELATIVE_TIMESTAMP_RANGE / 2)
+                .div_euclid(RELATIVE_TIMESTAMP_RANGE);
+            meant_relative_timestamp += wraps * RELATIVE_TIMESTAMP_RANGE;
+        }
+        state.last_relative_timestamp = Some((self.cluster_position, meant_relative_timestamp));
+        let overflowed = meant_relative_timestamp != i64::from(relative_timestamp);
+
+        let timestamp = cluster_timecode.saturating_add_signed(meant_relative_timestamp);
+        let timestamp_ns = timestamp.saturating_mul(self.timecode_scale);
+        let previous = state.last_timestamp.replace(timestamp);
+        let is_video = state.is_video;
+        if overflowed {
+            self.report(position, ViolationKind::ClusterTimestampOverflow { track, relative_timestamp });
+        }
+        if let Some(previous) = previous.filter(|&previous| timestamp < previous) {
+            let previous_timestamp_ns = previous.saturating_mul(self.timecode_scale);
+            self.report(position, ViolationKind::NonMonotonicTimestamp {
+                track,
+                timestamp_ns,
+                previous_timestamp_ns,
+            });
+        }
+        if self.cluster_tracks.insert(track) && is_video && !keyframe {
+            self.report(position, ViolationKind::MissingKeyframe { track });
+        }
+        if keyframe {
+            self.keyframes.insert((self.cluster_position, track, timestamp));
+        }
+        Ok(())
+    }
+
+    fn end_track_entry(&mut self, element: &Element) {
+        let fields = std::mem::take(&mut self.fields);
+        let Some(number) = fields.number else {
+            return;
+        };
+
+        let codec_id = fields.codec_id.unwrap_or_default();
+        if !WEBM_CODEC_IDS.contains(&codec_id.as_str()) {
+            self.report(element.position, ViolationKind::CodecId { track: number, codec_id });
+        }
+        self.tracks.insert(number, TrackState {
+            is_video: fields.track_type == Some(VIDEO_TRACK_TYPE),
+            ..TrackState::default()
+        });
+    }
+
+    fn end_cue_track_positions(&mut self, element: &Element) {
+        let fields = std::mem::take(&mut self.fields);
+        let (Some(time), Some(track), Some(cluster_position)) = (self.cue_time, fields.number, fields.cluster_position)
+        else {
+            return;
+        };
+        self.cues.push(Cue {
+            position: element.position,
+            time,
+            track,
+            cluster_position,
+        });
+    }
+
+    /// Checks the cue points against the keyframes, once the whole file has been read.
+    fn check_cues(&mut self) {
+        for cue in std::mem::take(&mut self.cues) {
+            if !self.keyframes.contains(&(cue.cluster_position, cue.track, cue.time)) {
+                self.report(cue.position, ViolationKind::CuePosition {
+                    track: cue.track,
+                    timestamp_ns: cue.time.saturating_mul(self.timecode_scale),
+                    cluster_position: cue.cluster_position,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const TRACKS: u32 = ElementId::TRACKS.0;
+    const CUES: u32 = ElementId::CUES.0;
+    const ATTACHMENTS: u32 = 0x1941_A469;
+    const UNKNOWN_SIZE: u64 = u64::MAX;
+
+    fn element(id: u32, payload: &[u8]) -> Vec<u8> {
+        sized_element(id, payload.len() as u64, payload)
+    }
+
+    fn sized_element(id: u32, size: u64, payload: &[u8]) -> Vec<u8> {
+        let id_bytes = id.to_be_bytes();
+        let mut data: Vec<_> = id_bytes.iter().copied().skip_while(|&b| b == 0).collect();
+        if size == UNKNOWN_SIZE {
+            data.push(0xFF);
+        } else {
+            data.extend((size | 0x0100_0000_0000_0000).to_be_bytes());
+        }
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn uint(id: u32, value: u64) -> Vec<u8> {
+        element(id, &value.to_be_bytes())
+    }
+
+    fn track_entry(number: u8, track_type: u8, codec_id: &str) -> Vec<u8> {
+        let fields = [
+            uint(TRACK_NUMBER, number.into()),
+            uint(TRACK_TYPE, track_type.into()),
+            element(CODEC_ID, codec_id.as_bytes()),
+        ];
+        element(TRACK_ENTRY, &fields.concat())
+    }
+
+    fn simple_block(track: u8, relative_timestamp: i16, keyframe: bool) -> Vec<u8> {
+        let [high, low] = relative_timestamp.to_be_bytes();
+        let flags = if keyframe { 0x80 } else { 0 };
+        element(SIMPLE_BLOCK, &[0x80 | track, high, low, flags, 0xAB, 0xCD])
+    }
+
+    fn cluster(timecode: u64, blocks: &[Vec<u8>]) -> Vec<u8> {
+        element(CLUSTER, &[uint(TIMECODE, timecode), blocks.concat()].concat())
+    }
+
+    fn cue_point(time: u64, track: u64, cluster_position: u64) -> Vec<u8> {
+        let positions = [uint(CUE_TRACK, track), uint(CUE_CLUSTER_POSITION, cluster_position)];
+        let positions = element(CUE_TRACK_POSITIONS, &positions.concat());
+        element(CUE_POINT, &[uint(CUE_TIME, time), positions].concat())
+    }
+
+    fn file(doc_type: &str, segment_size: Option<u64>, children: &[Vec<u8>]) -> Vec<u8> {
+        let header = element(EBML, &element(DOC_TYPE, doc_type.as_bytes()));
+        let payload = children.concat();
+        let segment = sized_element(SEGMENT, segment_size.unwrap_or(payload.len() as u64), &payload);
+        [header, segment].concat()
+    }
+
+    fn kinds(data: Vec<u8>, mode: Mode) -> Vec<ViolationKind> {
+        let violations = validate(Cursor::new(data), mode).unwrap();
+        violations.into_iter().map(|violation| violation.kind).collect()
+    }
+
+    #[test]
+    fn accepts_valid_file() {
+        let tracks = element(TRACKS, &[track_entry(1, 1, "V_VP9"), track_entry(2, 2, "A_OPUS")].concat());
+        let first = cluster(0, &[simple_block(1, 0, true), simple_block(2, 0, true), simple_block(1, 40, false)]);
+        // Negative timestamps relative to the cluster are fine, as long as they don't go back on their track
+        let second = cluster(80, &[simple_block(1, 0, true), simple_block(2, -10, true), simple_block(2, 20, true)]);
+
+        // Cluster positions are relative to the start of the segment data
+        let positions = [tracks.len() as u64, (tracks.len() + first.len()) as u64];
+        let cues = element(CUES, &[cue_point(0, 1, positions[0]), cue_point(80, 1, positions[1])].concat());
+        let data = file("webm", None, &[tracks, first, second, cues]);
+        assert_eq!(kinds(data, Mode::File), []);
+
+        let blocks = [uint(TIMECODE, 0), simple_block(1, 0, true)].concat();
+        let live_cluster = sized_element(CLUSTER, UNKNOWN_SIZE, &blocks);
+        let children = [element(TRACKS, &track_entry(1, 1, "V_VP8")), live_cluster.clone(), live_cluster];
+        let unknown_sizes = file("webm", Some(UNKNOWN_SIZE), &children);
+        assert_eq!(kinds(unknown_sizes.clone(), Mode::Live), []);
+        let unknown_size = |id| ViolationKind::UnknownSize { id };
+        assert_eq!(
+            kinds(unknown_sizes, Mode::File),
+            [unknown_size(SEGMENT), unknown_size(CLUSTER), unknown_size(CLUSTER)]
+        );
+    }
+
+    #[test]
+    fn reports_violations() {
+        let tracks = element(TRACKS, &[track_entry(1, 1, "V_MPEG4/ISO/AVC"), track_entry(2, 2, "A_OPUS")].concat());
+        let attachments = element(ATTACHMENTS, &[]);
+        let blocks = [
+            simple_block(1, 0, false),
+            simple_block(2, 10, true),
+            simple_block(2, -20, true),
+            simple_block(2, 32_000, true),
+            // Meant to be 33536, more than a 16-bit timestamp can hold
+            simple_block(2, -32_000, true),
+        ];
+        let first = cluster(100, &blocks);
+        let cluster_position = (tracks.len() + attachments.len()) as u64;
+        let cues = element(CUES, &cue_point(100, 1, cluster_position));
+        let data = file("matroska", None, &[tracks, attachments, first, cues]);
+        assert_eq!(
+            kinds(data, Mode::File),
+            [
+                ViolationKind::DocType("matroska".to_owned()),
+                ViolationKind::CodecId {
+                    track: 1,
+                    codec_id: "V_MPEG4/ISO/AVC".to_owned()
+                },
+                ViolationKind::DisallowedElement { id: ATTACHMENTS },
+                ViolationKind::MissingKeyframe { track: 1 },
+                ViolationKind::NonMonotonicTimestamp {
+                    track: 2,
+                    timestamp_ns: 80_000_000,
+                    previous_timestamp_ns: 110_000_000
+                },
+                ViolationKind::ClusterTimestampOverflow {
+                    track: 2,
+                    relative_timestamp: -32_000
+                },
+                ViolationKind::CuePosition {
+                    track: 1,
+                    timestamp_ns: 100_000_000,
+                    cluster_position,
+                },
+            ]
+        );
+
+        let truncated = file("webm", Some(1000), &[]);
+        assert!(matches!(kinds(truncated, Mode::File)[..], [ViolationKind::InvalidStructure(_)]));
+        assert!(matches!(kinds(b"garbage".to_vec(), Mode::Live)[..], [ViolationKind::InvalidStructure(_)]));
+
+        // Nesting deep enough to exhaust the stack, if it weren't for the limit
+        let header_len = sized_element(BLOCK_GROUP, 0, &[]).len() as u64;
+        let nested: Vec<u8> =
+            (0..100_000).rev().flat_map(|depth| sized_element(BLOCK_GROUP, depth * header_len, &[])).collect();
+        let data = file("webm", None, &[cluster(0, &[nested])]);
+        assert_eq!(
+            kinds(data, Mode::File),
+            [ViolationKind::InvalidStructure("elements are nested too deeply")]
+        );
+    }
+}
@@ -9,6 +9,8 @@
 
 #include <stdint.h>
 #include <assert.h>
+#include <string>
+#include <vector>
 
 extern "C" {
   enum class ResultCode: int32_t {
@@ -103,6 +105,31 @@ extern "C" {
     auto info = segment->GetSegmentInfo();
     info->set_writing_app(name);
   }
+  ResultCode mux_segment_add_simple_tag(MuxSegmentPtr segment, const char *name, const char *value) {
+    if(segment == nullptr || name == nullptr || value == nullptr) { return ResultCode::BadParam; }
+
+    mkvmuxer::Tag* tag = segment->AddTag();
+    if(tag == nullptr || !tag->add_simple_tag(name, value)) { return ResultCode::UnknownLibwebmError; }
+    return ResultCode::Ok;
+  }
+  ResultCode mux_set_max_cluster_duration(MuxSegmentPtr segment, uint64_t max_duration_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    segment->set_max_cluster_duration(max_duration_ns);
+    return ResultCode::Ok;
+  }
+  ResultCode mux_set_max_cluster_size(MuxSegmentPtr segment, uint64_t max_size) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    segment->set_max_cluster_size(max_size);
+    return ResultCode::Ok;
+  }
+  ResultCode mux_set_output_cues(MuxSegmentPtr segment, bool output_cues) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    segment->OutputCues(output_cues);
+    return ResultCode::Ok;
+  }
   ResultCode mux_finalize_segment(MuxSegmentPtr segment, uint64_t timeCodeDuration) {
     if (timeCodeDuration) {
       segment->set_duration(timeCodeDuration);
@@ -118,9 +145,13 @@ extern "C" {
   typedef mkvmuxer::VideoTrack* MuxVideoTrackPtr;
   typedef mkvmuxer::AudioTrack* MuxAudioTrackPtr;
 
+  // Numeric codec IDs, only kept for the deprecated `mux_segment_add_*_track` functions
   // audio
   const uint32_t OPUS_CODEC_ID = 0;
   const uint32_t VORBIS_CODEC_ID = 1;
+  const uint32_t FLAC_CODEC_ID = 2;
+  const uint32_t PCM_INT_LIT_CODEC_ID = 3;
+  const uint32_t PCM_FLOAT_CODEC_ID = 4;
 
   // video
   const uint32_t VP8_CODEC_ID = 0;
@@ -134,11 +165,23 @@ extern "C" {
     return ResultCode::Ok;
   }
 
+  ResultCode mux_segment_add_video_track_with_codec_id(MuxSegmentPtr segment, const int32_t width,
+                                                      const int32_t height, const int32_t number,
+                                                      const char* codec_id, TrackNum* track_num_out) {
+    if(segment == nullptr || codec_id == nullptr || track_num_out == nullptr) { return ResultCode::BadParam; }
+
+    TrackNum track_num = segment->AddVideoTrack(width, height, number);
+    if(track_num == 0) { return ResultCode::UnknownLibwebmError; }
+
+    auto video = static_cast<MuxVideoTrackPtr>(segment->GetTrackByNumber(track_num));
+    video->set_codec_id(codec_id);
+
+    *track_num_out = track_num;
+    return ResultCode::Ok;
+  }
   ResultCode mux_segment_add_video_track(MuxSegmentPtr segment, const int32_t width,
                                                const int32_t height, const int32_t number,
                                                const uint32_t codec_id, TrackNum* track_num_out) {
-    if(segment == nullptr || track_num_out == nullptr) { return ResultCode::BadParam; }
-
     const char* codec_id_str = nullptr;
     switch(codec_id) {
     case VP8_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kVp8CodecId; break;
@@ -147,11 +190,18 @@ extern "C" {
     default: return ResultCode::BadParam;
     }
 
-    TrackNum track_num = segment->AddVideoTrack(width, height, number);
+    return mux_segment_add_video_track_with_codec_id(segment, width, height, number, codec_id_str, track_num_out);
+  }
+  ResultCode mux_segment_add_audio_track_with_codec_id(MuxSegmentPtr segment, const int32_t sample_rate,
+                                                      const int32_t channels, const int32_t number,
+                                                      const char* codec_id, TrackNum* track_num_out) {
+    if(segment == nullptr || codec_id == nullptr || track_num_out == nullptr) { return ResultCode::BadParam; }
+
+    const auto track_num = segment->AddAudioTrack(sample_rate, channels, number);
     if(track_num == 0) { return ResultCode::UnknownLibwebmError; }
 
-    auto video = static_cast<MuxVideoTrackPtr>(segment->GetTrackByNumber(track_num));
-    video->set_codec_id(codec_id_str);
+    auto audio = static_cast<MuxAudioTrackPtr>(segment->GetTrackByNumber(track_num));
+    audio->set_codec_id(codec_id);
 
     *track_num_out = track_num;
     return ResultCode::Ok;
@@ -159,25 +209,74 @@ extern "C" {
   ResultCode mux_segment_add_audio_track(MuxSegmentPtr segment, const int32_t sample_rate,
                                                const int32_t channels, const int32_t number,
                                                const uint32_t codec_id, TrackNum* track_num_out) {
-    if(segment == nullptr || track_num_out == nullptr) { return ResultCode::BadParam; }
-
     const char* codec_id_str = nullptr;
     switch(codec_id) {
     case OPUS_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kOpusCodecId; break;
     case VORBIS_CODEC_ID: codec_id_str = mkvmuxer::Tracks::kVorbisCodecId; break;
+    // libwebm has no constants for these, as they are not allowed in WebM
+    case FLAC_CODEC_ID: codec_id_str = "A_FLAC"; break;
+    case PCM_INT_LIT_CODEC_ID: codec_id_str = "A_PCM/INT/LIT"; break;
+    case PCM_FLOAT_CODEC_ID: codec_id_str = "A_PCM/FLOAT/IEEE"; break;
     default: return ResultCode::BadParam;
     }
 
-    const auto track_num = segment->AddAudioTrack(sample_rate, channels, number);
-    if(track_num == 0) { return ResultCode::UnknownLibwebmError; }
+    return mux_segment_add_audio_track_with_codec_id(segment, sample_rate, channels, number, codec_id_str, track_num_out);
+  }
 
-    auto audio = static_cast<MuxAudioTrackPtr>(segment->GetTrackByNumber(track_num));
-    audio->set_codec_id(codec_id_str);
+  ResultCode mux_set_audio_bit_depth(MuxSegmentPtr segment, TrackNum audio_track_num, uint64_t bit_depth) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
 
-    *track_num_out = track_num;
+    MuxTrackPtr track = segment->GetTrackByNumber(audio_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kAudio) { return ResultCode::BadParam; }
+    auto audio = static_cast<MuxAudioTrackPtr>(track);
+
+    audio->set_bit_depth(bit_depth);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_codec_delay(MuxSegmentPtr segment, TrackNum track_num, uint64_t codec_delay_ns, uint64_t seek_pre_roll_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return ResultCode::BadParam; }
+
+    track->set_codec_delay(codec_delay_ns);
+    track->set_seek_pre_roll(seek_pre_roll_ns);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_track_name(MuxSegmentPtr segment, TrackNum track_num, const char* name) {
+    if(segment == nullptr || name == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return ResultCode::BadParam; }
+
+    track->set_name(name);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_track_language(MuxSegmentPtr segment, TrackNum track_num, const char* language) {
+    if(segment == nullptr || language == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(track_num);
+    if(track == nullptr) { return ResultCode::BadParam; }
+
+    track->set_language(language);
     return ResultCode::Ok;
   }
 
+  // Copies everything `mux_set_color_metadata` sets, so that setting the rest of the color doesn't drop it
+  static bool copy_color_metadata(const mkvmuxer::Colour& from, mkvmuxer::Colour& to) {
+    to.set_matrix_coefficients(from.matrix_coefficients());
+    to.set_transfer_characteristics(from.transfer_characteristics());
+    to.set_primaries(from.primaries());
+    to.set_chroma_siting_horz(from.chroma_siting_horz());
+    to.set_chroma_siting_vert(from.chroma_siting_vert());
+    to.set_max_cll(from.max_cll());
+    to.set_max_fall(from.max_fall());
+    return from.mastering_metadata() == nullptr || to.SetMasteringMetadata(*from.mastering_metadata());
+  }
+
   ResultCode mux_set_color(MuxSegmentPtr segment, TrackNum video_track_num, uint8_t bits, uint8_t sampling_horiz, uint8_t sampling_vert, uint8_t color_range) {
     mkvmuxer::Colour color;
 
@@ -185,6 +284,9 @@ extern "C" {
     if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) { return ResultCode::BadParam; }
     auto video = static_cast<MuxVideoTrackPtr>(track);
 
+    if(video->colour() != nullptr && !copy_color_metadata(*video->colour(), color)) {
+      return ResultCode::UnknownLibwebmError;
+    }
     color.set_bits_per_channel(bits);
     color.set_chroma_subsampling_horz(sampling_horiz);
     color.set_chroma_subsampling_vert(sampling_vert);
@@ -195,6 +297,94 @@ extern "C" {
     return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
   }
 
+  // Mirrors `ColorMetadata` in lib.rs. Missing integers are `mkvmuxer::Colour::kValueNotPresent`, and missing
+  // floats are negative.
+  struct MuxColorMetadata {
+    uint64_t matrix_coefficients;
+    uint64_t transfer_characteristics;
+    uint64_t primaries;
+    uint64_t chroma_siting_horiz;
+    uint64_t chroma_siting_vert;
+    uint64_t max_cll;
+    uint64_t max_fall;
+    bool has_mastering_metadata;
+    float mastering_chromaticity[8];
+    float mastering_luminance_max;
+    float mastering_luminance_min;
+  };
+
+  ResultCode mux_set_color_metadata(MuxSegmentPtr segment, TrackNum video_track_num, const MuxColorMetadata* metadata) {
+    if(segment == nullptr || metadata == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) { return ResultCode::BadParam; }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+
+    // Keep what `mux_set_color` has set
+    mkvmuxer::Colour color;
+    if(const mkvmuxer::Colour* current = video->colour()) {
+      color.set_bits_per_channel(current->bits_per_channel());
+      color.set_chroma_subsampling_horz(current->chroma_subsampling_horz());
+      color.set_chroma_subsampling_vert(current->chroma_subsampling_vert());
+      color.set_range(current->range());
+    }
+
+    color.set_matrix_coefficients(metadata->matrix_coefficients);
+    color.set_transfer_characteristics(metadata->transfer_characteristics);
+    color.set_primaries(metadata->primaries);
+    color.set_chroma_siting_horz(metadata->chroma_siting_horiz);
+    color.set_chroma_siting_vert(metadata->chroma_siting_vert);
+    color.set_max_cll(metadata->max_cll);
+    color.set_max_fall(metadata->max_fall);
+
+    if(metadata->has_mastering_metadata) {
+      mkvmuxer::PrimaryChromaticity chromaticity[4];
+      const mkvmuxer::PrimaryChromaticity* present[4] = {};
+      for(int i = 0; i < 4; i++) {
+        const float x = metadata->mastering_chromaticity[2 * i];
+        const float y = metadata->mastering_chromaticity[2 * i + 1];
+        if(x >= 0 && y >= 0) {
+          chromaticity[i] = mkvmuxer::PrimaryChromaticity(x, y);
+          present[i] = &chromaticity[i];
+        }
+      }
+
+      mkvmuxer::MasteringMetadata mastering;
+      if(!mastering.SetChromaticity(present[0], present[1], present[2], present[3])) {
+        return ResultCode::UnknownLibwebmError;
+      }
+      if(metadata->mastering_luminance_max >= 0) { mastering.set_luminance_max(metadata->mastering_luminance_max); }
+      if(metadata->mastering_luminance_min >= 0) { mastering.set_luminance_min(metadata->mastering_luminance_min); }
+      if(!color.SetMasteringMetadata(mastering)) { return ResultCode::UnknownLibwebmError; }
+    }
+
+    bool success = video->SetColour(color);
+    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+  }
+
+  ResultCode mux_set_display_size(MuxSegmentPtr segment, TrackNum video_track_num, uint64_t width, uint64_t height) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) { return ResultCode::BadParam; }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+
+    video->set_display_width(width);
+    video->set_display_height(height);
+    return ResultCode::Ok;
+  }
+
+  ResultCode mux_set_frame_rate(MuxSegmentPtr segment, TrackNum video_track_num, double frame_rate) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    MuxTrackPtr track = segment->GetTrackByNumber(video_track_num);
+    if(track == nullptr || track->type() != mkvmuxer::Tracks::kVideo) { return ResultCode::BadParam; }
+    auto video = static_cast<MuxVideoTrackPtr>(track);
+
+    video->set_frame_rate(frame_rate);
+    return ResultCode::Ok;
+  }
+
   ResultCode mux_segment_add_frame(MuxSegmentPtr segment, TrackNum track_num,
                              const uint8_t* frame, const size_t length,
                              const uint64_t timestamp_ns, const bool keyframe) {
@@ -203,5 +393,446 @@ extern "C" {
     bool success = segment->AddFrame(frame, length, track_num, timestamp_ns, keyframe);
     return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
   }
+  ResultCode mux_segment_add_frame_with_discard_padding(MuxSegmentPtr segment, TrackNum track_num,
+                                                       const uint8_t* frame, const size_t length,
+                                                       const uint64_t timestamp_ns, const bool keyframe,
+                                                       const int64_t discard_padding_ns) {
+    if(segment == nullptr) { return ResultCode::BadParam; }
+
+    bool success = segment->AddFrameWithDiscardPadding(frame, length, discard_padding_ns, track_num, timestamp_ns, keyframe);
+    return success ? ResultCode::Ok : ResultCode::UnknownLibwebmError;
+  }
+
+
+  struct FfiMkvReader: public mkvparser::IMkvReader {
+  public:
+    typedef bool (*ReadFun)(void*, uint64_t, size_t, unsigned char*);
+
+    ReadFun read_  = nullptr;
+    int64_t length_ = 0;
+
+    mutable void* user_data = nullptr;
+
+    FfiMkvReader() = default;
+    virtual ~FfiMkvReader() = default;
+
+    int Read(long long pos, long len, unsigned char* buf) override final {
+      assert(this->read_ != nullptr);
+      if(pos < 0 || len < 0) { return -1; }
+      if(len == 0) { return 0; }
+
+      return this->read_(this->user_data, static_cast<uint64_t>(pos), static_cast<size_t>(len), buf) ? 0 : -1;
+    }
+    int Length(long long* total, long long* available) override final {
+      // The whole input is known to be available up front
+      if(total != nullptr) { *total = this->length_; }
+      if(available != nullptr) { *available = this->length_; }
+      return 0;
+    }
+  };
+  typedef FfiMkvReader* DemuxReaderPtr;
+
+  DemuxReaderPtr demux_new_reader(FfiMkvReader::ReadFun read, uint64_t length, void* user_data) {
+    if(read == nullptr) { return nullptr; }
+
+    FfiMkvReader* reader = new FfiMkvReader;
+    reader->read_ = read;
+    reader->length_ = static_cast<int64_t>(length);
+    reader->user_data = user_data;
+    return reader;
+  }
+  void demux_delete_reader(DemuxReaderPtr reader) {
+    delete reader;
+  }
 
+  struct DemuxChapterInfo {
+    uint64_t edition;
+    uint64_t uid;
+    const char* string_uid;
+    int64_t start_ns;
+    int64_t end_ns;
+    const char* title;
+    const char* language;
+  };
+
+  struct DemuxCuePointInfo {
+    int64_t timestamp_ns;
+    uint64_t track_number;
+    int64_t cluster_position;
+    int64_t block_number;
+  };
+
+  struct DemuxSegment {
+    mkvparser::Segment* segment = nullptr;
+    mkvparser::EBMLHeader header;
+    std::string doc_type;
+
+    // Filled in on first use, as these are only reachable through linked lists
+    std::vector<DemuxChapterInfo> chapters;
+    bool chapters_loaded = false;
+    std::vector<DemuxCuePointInfo> cue_points;
+    bool cue_points_loaded = false;
+
+    // Where `demux_next_frame` is at
+    const mkvparser::Cluster* cluster = nullptr;
+    const mkvparser::BlockEntry* entry = nullptr;
+    int frame_index = 0;
+    bool finished = false;
+
+    ~DemuxSegment() { delete segment; }
+  };
+  typedef DemuxSegment* DemuxSegmentPtr;
+
+  struct DemuxSegmentInfo {
+    const char* doc_type;
+    uint64_t timecode_scale;
+    int64_t duration_ns;
+    const char* muxing_app;
+    const char* writing_app;
+    const char* title;
+
+    // From the EBML header
+    uint64_t ebml_version;
+    uint64_t ebml_read_version;
+    uint64_t ebml_max_id_length;
+    uint64_t ebml_max_size_length;
+    uint64_t doc_type_version;
+    uint64_t doc_type_read_version;
+  };
+
+  struct DemuxTrackInfo {
+    uint64_t number;
+    int32_t track_type;
+    const char* codec_id;
+    const uint8_t* codec_private;
+    size_t codec_private_len;
+    const char* name;
+    const char* language;
+    uint64_t codec_delay_ns;
+    uint64_t seek_pre_roll_ns;
+
+    // Video tracks only. Color values are -1 when not present, and mastering values are negative.
+    uint64_t width;
+    uint64_t height;
+    uint64_t display_width;
+    uint64_t display_height;
+    double frame_rate;
+    bool has_color;
+    int64_t color_bits;
+    int64_t color_sampling_horiz;
+    int64_t color_sampling_vert;
+    int64_t color_range;
+    int64_t color_matrix_coefficients;
+    int64_t color_transfer_characteristics;
+    int64_t color_primaries;
+    int64_t color_chroma_siting_horiz;
+    int64_t color_chroma_siting_vert;
+    int64_t color_max_cll;
+    int64_t color_max_fall;
+    bool has_mastering_metadata;
+    // Red, green, blue and white point chromaticity, as x and y pairs
+    float mastering_chromaticity[8];
+    float mastering_luminance_max;
+    float mastering_luminance_min;
+
+    // Audio tracks only
+    double sample_rate;
+    uint64_t channels;
+    uint64_t bit_depth;
+  };
+
+  struct DemuxFrameInfo {
+    uint64_t track_number;
+    int64_t timestamp_ns;
+    bool keyframe;
+    int64_t discard_padding_ns;
+    int64_t position;
+    int64_t length;
+  };
+
+  ResultCode demux_new_segment(DemuxReaderPtr reader, DemuxSegmentPtr* segment_out) {
+    if(reader == nullptr || segment_out == nullptr) { return ResultCode::BadParam; }
+
+    long long pos = 0;
+    DemuxSegment* demux_segment = new DemuxSegment;
+    mkvparser::EBMLHeader& header = demux_segment->header;
+    if(header.Parse(reader, pos) < 0) {
+      delete demux_segment;
+      return ResultCode::UnknownLibwebmError;
+    }
+
+    mkvparser::Segment* segment = nullptr;
+    if(mkvparser::Segment::CreateInstance(reader, pos, segment) != 0 || segment == nullptr) {
+      delete demux_segment;
+      return ResultCode::UnknownLibwebmError;
+    }
+    demux_segment->segment = segment;
+    if(segment->Load() < 0) {
+      delete demux_segment;
+      return ResultCode::UnknownLibwebmError;
+    }
+
+    demux_segment->doc_type = header.m_docType != nullptr ? header.m_docType : "";
+    *segment_out = demux_segment;
+    return ResultCode::Ok;
+  }
+  void demux_delete_segment(DemuxSegmentPtr segment) {
+    delete segment;
+  }
+
+  ResultCode demux_get_segment_info(DemuxSegmentPtr segment, DemuxSegmentInfo* info_out) {
+    if(segment == nullptr || info_out == nullptr) { return ResultCode::BadParam; }
+
+    const mkvparser::SegmentInfo* info = segment->segment->GetInfo();
+    if(info == nullptr) { return ResultCode::UnknownLibwebmError; }
+
+    info_out->doc_type = segment->doc_type.c_str();
+    info_out->timecode_scale = info->GetTimeCodeScale();
+    info_out->duration_ns = info->GetDuration();
+    info_out->muxing_app = info->GetMuxingAppAsUTF8();
+    info_out->writing_app = info->GetWritingAppAsUTF8();
+    info_out->title = info->GetTitleAsUTF8();
+
+    const mkvparser::EBMLHeader& header = segment->header;
+    info_out->ebml_version = header.m_version;
+    info_out->ebml_read_version = header.m_readVersion;
+    info_out->ebml_max_id_length = header.m_maxIdLength;
+    info_out->ebml_max_size_length = header.m_maxSizeLength;
+    info_out->doc_type_version = header.m_docTypeVersion;
+    info_out->doc_type_read_version = header.m_docTypeReadVersion;
+    return ResultCode::Ok;
+  }
+
+  uint64_t demux_get_track_count(DemuxSegmentPtr segment) {
+    if(segment == nullptr || segment->segment->GetTracks() == nullptr) { return 0; }
+
+    return segment->segment->GetTracks()->GetTracksCount();
+  }
+  ResultCode demux_get_track(DemuxSegmentPtr segment, uint64_t index, DemuxTrackInfo* track_out) {
+    if(segment == nullptr || track_out == nullptr) { return ResultCode::BadParam; }
+
+    const mkvparser::Tracks* tracks = segment->segment->GetTracks();
+    const mkvparser::Track* track = tracks != nullptr ? tracks->GetTrackByIndex(index) : nullptr;
+    if(track == nullptr) { return ResultCode::BadParam; }
+
+    *track_out = DemuxTrackInfo {};
+    track_out->number = track->GetNumber();
+    track_out->track_type = track->GetType();
+    track_out->codec_id = track->GetCodecId();
+    track_out->codec_private = track->GetCodecPrivate(track_out->codec_private_len);
+    track_out->name = track->GetNameAsUTF8();
+    track_out->language = track->GetLanguage();
+    track_out->codec_delay_ns = track->GetCodecDelay();
+    track_out->seek_pre_roll_ns = track->GetSeekPreRoll();
+    track_out->color_bits = -1;
+    track_out->color_sampling_horiz = -1;
+    track_out->color_sampling_vert = -1;
+    track_out->color_range = -1;
+    track_out->color_matrix_coefficients = -1;
+    track_out->color_transfer_characteristics = -1;
+    track_out->color_primaries = -1;
+    track_out->color_chroma_siting_horiz = -1;
+    track_out->color_chroma_siting_vert = -1;
+    track_out->color_max_cll = -1;
+    track_out->color_max_fall = -1;
+
+    if(track->GetType() == mkvparser::Track::kVideo) {
+      auto video = static_cast<const mkvparser::VideoTrack*>(track);
+      track_out->width = video->GetWidth();
+      track_out->height = video->GetHeight();
+      track_out->display_width = video->GetDisplayWidth();
+      track_out->display_height = video->GetDisplayHeight();
+      track_out->frame_rate = video->GetFrameRate();
+
+      const mkvparser::Colour* color = video->GetColour();
+      if(color != nullptr) {
+        auto value_or_missing = [](long long value) -> int64_t {
+          return value == mkvparser::Colour::kValueNotPresent ? -1 : value;
+        };
+        track_out->has_color = true;
+        track_out->color_bits = value_or_missing(color->bits_per_channel);
+        track_out->color_sampling_horiz = value_or_missing(color->chroma_subsampling_horz);
+        track_out->color_sampling_vert = value_or_missing(color->chroma_subsampling_vert);
+        track_out->color_range = value_or_missing(color->range);
+        track_out->color_matrix_coefficients = value_or_missing(color->matrix_coefficients);
+        track_out->color_transfer_characteristics = value_or_missing(color->transfer_characteristics);
+        track_out->color_primaries = value_or_missing(color->primaries);
+        track_out->color_chroma_siting_horiz = value_or_missing(color->chroma_siting_horz);
+        track_out->color_chroma_siting_vert = value_or_missing(color->chroma_siting_vert);
+        track_out->color_max_cll = value_or_missing(color->max_cll);
+        track_out->color_max_fall = value_or_missing(color->max_fall);
+
+        const mkvparser::MasteringMetadata* mastering = color->mastering_metadata;
+        if(mastering != nullptr) {
+          auto value_or_negative = [](float value) -> float {
+            return value == mkvparser::MasteringMetadata::kValueNotPresent ? -1.0f : value;
+          };
+          const mkvparser::PrimaryChromaticity* points[4] = {
+            mastering->r, mastering->g, mastering->b, mastering->white_point,
+          };
+          for(int i = 0; i < 4; i++) {
+            track_out->mastering_chromaticity[2 * i] = points[i] != nullptr ? points[i]->x : -1.0f;
+            track_out->mastering_chromaticity[2 * i + 1] = points[i] != nullptr ? points[i]->y : -1.0f;
+          }
+          track_out->has_mastering_metadata = true;
+          track_out->mastering_luminance_max = value_or_negative(mastering->luminance_max);
+          track_out->mastering_luminance_min = value_or_negative(mastering->luminance_min);
+        }
+      }
+    } else if(track->GetType() == mkvparser::Track::kAudio) {
+      auto audio = static_cast<const mkvparser::AudioTrack*>(track);
+      track_out->sample_rate = audio->GetSamplingRate();
+      track_out->channels = audio->GetChannels();
+      track_out->bit_depth = audio->GetBitDepth();
+    }
+    return ResultCode::Ok;
+  }
+
+  uint64_t demux_get_simple_tag_count(DemuxSegmentPtr segment) {
+    if(segment == nullptr) { return 0; }
+
+    const mkvparser::Tags* tags = segment->segment->GetTags();
+    if(tags == nullptr) { return 0; }
+
+    uint64_t count = 0;
+    for(int i = 0; i < tags->GetTagCount(); i++) {
+      count += tags->GetTag(i)->GetSimpleTagCount();
+    }
+    return count;
+  }
+  ResultCode demux_get_simple_tag(DemuxSegmentPtr segment, uint64_t index, const char** name_out, const char** value_out) {
+    if(segment == nullptr || name_out == nullptr || value_out == nullptr) { return ResultCode::BadParam; }
+
+    const mkvparser::Tags* tags = segment->segment->GetTags();
+    if(tags == nullptr) { return ResultCode::BadParam; }
+
+    // Tags are flattened into a single list of simple tags
+    for(int i = 0; i < tags->GetTagCount(); i++) {
+      const mkvparser::Tags::Tag* tag = tags->GetTag(i);
+      if(index < static_cast<uint64_t>(tag->GetSimpleTagCount())) {
+        const mkvparser::Tags::SimpleTag* simple_tag = tag->GetSimpleTag(static_cast<int>(index));
+        *name_out = simple_tag->GetTagName();
+        *value_out = simple_tag->GetTagString();
+        return ResultCode::Ok;
+      }
+      index -= tag->GetSimpleTagCount();
+    }
+    return ResultCode::BadParam;
+  }
+
+  uint64_t demux_get_chapter_count(DemuxSegmentPtr segment) {
+    if(segment == nullptr) { return 0; }
+
+    const mkvparser::Chapters* chapters = segment->segment->GetChapters();
+    if(!segment->chapters_loaded && chapters != nullptr) {
+      // Chapters of all editions are flattened into a single list
+      for(int e = 0; e < chapters->GetEditionCount(); e++) {
+        const mkvparser::Chapters::Edition* edition = chapters->GetEdition(e);
+        for(int a = 0; a < edition->GetAtomCount(); a++) {
+          const mkvparser::Chapters::Atom* atom = edition->GetAtom(a);
+          const mkvparser::Chapters::Display* display = atom->GetDisplayCount() > 0 ? atom->GetDisplay(0) : nullptr;
+
+          DemuxChapterInfo info {};
+          info.edition = static_cast<uint64_t>(e);
+          info.uid = atom->GetUID();
+          info.string_uid = atom->GetStringUID();
+          info.start_ns = atom->GetStartTime(chapters);
+          info.end_ns = atom->GetStopTimecode() < 0 ? -1 : atom->GetStopTime(chapters);
+          info.title = display != nullptr ? display->GetString() : nullptr;
+          info.language = display != nullptr ? display->GetLanguage() : nullptr;
+          segment->chapters.push_back(info);
+        }
+      }
+    }
+    segment->chapters_loaded = true;
+    return segment->chapters.size();
+  }
+  ResultCode demux_get_chapter(DemuxSegmentPtr segment, uint64_t index, DemuxChapterInfo* chapter_out) {
+    if(segment == nullptr || chapter_out == nullptr || index >= demux_get_chapter_count(segment)) {
+      return ResultCode::BadParam;
+    }
+
+    *chapter_out = segment->chapters[index];
+    return ResultCode::Ok;
+  }
+
+  uint64_t demux_get_cue_point_count(DemuxSegmentPtr segment) {
+    if(segment == nullptr) { return 0; }
+
+    const mkvparser::Cues* cues = segment->segment->GetCues();
+    const mkvparser::Tracks* tracks = segment->segment->GetTracks();
+    if(!segment->cue_points_loaded && cues != nullptr && tracks != nullptr) {
+      while(!cues->DoneParsing()) {
+        cues->LoadCuePoint();
+      }
+
+      // Cue points are flattened into one entry per track they have a position for
+      for(const mkvparser::CuePoint* cue_point = cues->GetFirst(); cue_point != nullptr;
+          cue_point = cues->GetNext(cue_point)) {
+        for(unsigned long i = 0; i < tracks->GetTracksCount(); i++) {
+          const mkvparser::Track* track = tracks->GetTrackByIndex(i);
+          const mkvparser::CuePoint::TrackPosition* position = track != nullptr ? cue_point->Find(track) : nullptr;
+          if(position == nullptr) { continue; }
+
+          DemuxCuePointInfo info {};
+          info.timestamp_ns = cue_point->GetTime(segment->segment);
+          info.track_number = track->GetNumber();
+          info.cluster_position = position->m_pos;
+          info.block_number = position->m_block;
+          segment->cue_points.push_back(info);
+        }
+      }
+    }
+    segment->cue_points_loaded = true;
+    return segment->cue_points.size();
+  }
+  ResultCode demux_get_cue_point(DemuxSegmentPtr segment, uint64_t index, DemuxCuePointInfo* cue_point_out) {
+    if(segment == nullptr || cue_point_out == nullptr || index >= demux_get_cue_point_count(segment)) {
+      return ResultCode::BadParam;
+    }
+
+    *cue_point_out = segment->cue_points[index];
+    return ResultCode::Ok;
+  }
+
+  ResultCode demux_next_frame(DemuxSegmentPtr segment, DemuxFrameInfo* frame_out, bool* end_of_stream) {
+    if(segment == nullptr || frame_out == nullptr || end_of_stream == nullptr) { return ResultCode::BadParam; }
+    *end_of_stream = segment->finished;
+
+    while(!segment->finished) {
+      if(segment->entry == nullptr) {
+        // Move on to the first block of the next cluster that has any
+        segment->cluster = segment->cluster == nullptr
+          ? segment->segment->GetFirst()
+          : segment->segment->GetNext(segment->cluster);
+        if(segment->cluster == nullptr || segment->cluster->EOS()) {
+          segment->finished = true;
+          *end_of_stream = true;
+          break;
+        }
+
+        if(segment->cluster->GetFirst(segment->entry) < 0) { return ResultCode::UnknownLibwebmError; }
+        segment->frame_index = 0;
+        continue;
+      }
+
+      const mkvparser::Block* block = segment->entry->GetBlock();
+      if(block != nullptr && segment->frame_index < block->GetFrameCount()) {
+        const mkvparser::Block::Frame& frame = block->GetFrame(segment->frame_index++);
+        frame_out->track_number = block->GetTrackNumber();
+        frame_out->timestamp_ns = block->GetTime(segment->cluster);
+        frame_out->keyframe = block->IsKey();
+        frame_out->discard_padding_ns = block->GetDiscardPadding();
+        frame_out->position = frame.pos;
+        frame_out->length = frame.len;
+        return ResultCode::Ok;
+      }
+
+      const mkvparser::BlockEntry* next = nullptr;
+      if(segment->cluster->GetNext(segment->entry, next) < 0) { return ResultCode::UnknownLibwebmError; }
+      segment->entry = next;
+      segment->frame_index = 0;
+    }
+    return ResultCode::Ok;
+  }
 }
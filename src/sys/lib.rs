@@ -33,12 +33,23 @@ pub mod mux {
     }
 
     // audio
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
     pub const OPUS_CODEC_ID: u32 = 0;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
     pub const VORBIS_CODEC_ID: u32 = 1;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
+    pub const FLAC_CODEC_ID: u32 = 2;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
+    pub const PCM_INT_LIT_CODEC_ID: u32 = 3;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
+    pub const PCM_FLOAT_CODEC_ID: u32 = 4;
 
     // video
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
     pub const VP8_CODEC_ID: u32 = 0;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
     pub const VP9_CODEC_ID: u32 = 1;
+    #[deprecated(note = "numeric codec IDs are replaced by codec ID strings")]
     pub const AV1_CODEC_ID: u32 = 2;
 
     #[repr(C)]
@@ -48,6 +59,26 @@ pub mod mux {
     pub type SegmentMutPtr = *mut Segment;
     pub type SegmentNonNullPtr = NonNull<Segment>;
 
+    /// The color space and HDR metadata of a video track. Integers that are not present are `u64::MAX`, and floats
+    /// that are not present are negative.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(C)]
+    pub struct ColorMetadata {
+        pub matrix_coefficients: u64,
+        pub transfer_characteristics: u64,
+        pub primaries: u64,
+        pub chroma_siting_horiz: u64,
+        pub chroma_siting_vert: u64,
+        pub max_cll: u64,
+        pub max_fall: u64,
+        pub has_mastering_metadata: bool,
+
+        /// Red, green, blue and white point chromaticity, as x and y pairs
+        pub mastering_chromaticity: [f32; 8],
+        pub mastering_luminance_max: f32,
+        pub mastering_luminance_min: f32,
+    }
+
     #[link(name = "webmadapter", kind = "static")]
     extern "C" {
         #[link_name = "mux_new_writer"]
@@ -74,13 +105,62 @@ pub mod mux {
             sampling_vert: u8,
             color_range: u8,
         ) -> ResultCode;
+        #[link_name = "mux_set_color_metadata"]
+        pub fn mux_set_color_metadata(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            metadata: *const ColorMetadata,
+        ) -> ResultCode;
+        #[link_name = "mux_set_display_size"]
+        pub fn mux_set_display_size(
+            segment: SegmentMutPtr,
+            video_track_num: TrackNum,
+            width: u64,
+            height: u64,
+        ) -> ResultCode;
+        #[link_name = "mux_set_frame_rate"]
+        pub fn mux_set_frame_rate(segment: SegmentMutPtr, video_track_num: TrackNum, frame_rate: f64) -> ResultCode;
+        #[link_name = "mux_set_audio_bit_depth"]
+        pub fn mux_set_audio_bit_depth(
+            segment: SegmentMutPtr,
+            audio_track_num: TrackNum,
+            bit_depth: u64,
+        ) -> ResultCode;
+        #[link_name = "mux_set_codec_delay"]
+        pub fn mux_set_codec_delay(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            codec_delay_ns: u64,
+            seek_pre_roll_ns: u64,
+        ) -> ResultCode;
         #[link_name = "mux_set_writing_app"]
         pub fn mux_set_writing_app(segment: SegmentMutPtr, name: *const c_char);
+        #[link_name = "mux_segment_add_simple_tag"]
+        pub fn segment_add_simple_tag(
+            segment: SegmentMutPtr,
+            name: *const c_char,
+            value: *const c_char,
+        ) -> ResultCode;
+        #[link_name = "mux_set_max_cluster_duration"]
+        pub fn mux_set_max_cluster_duration(segment: SegmentMutPtr, max_duration_ns: u64) -> ResultCode;
+        #[link_name = "mux_set_max_cluster_size"]
+        pub fn mux_set_max_cluster_size(segment: SegmentMutPtr, max_size: u64) -> ResultCode;
+        #[link_name = "mux_set_output_cues"]
+        pub fn mux_set_output_cues(segment: SegmentMutPtr, output_cues: bool) -> ResultCode;
+        #[link_name = "mux_set_track_name"]
+        pub fn mux_set_track_name(segment: SegmentMutPtr, track_num: TrackNum, name: *const c_char) -> ResultCode;
+        #[link_name = "mux_set_track_language"]
+        pub fn mux_set_track_language(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            language: *const c_char,
+        ) -> ResultCode;
         #[link_name = "mux_finalize_segment"]
         pub fn finalize_segment(segment: SegmentMutPtr, duration: u64) -> ResultCode;
         #[link_name = "mux_delete_segment"]
         pub fn delete_segment(segment: SegmentMutPtr);
 
+        #[deprecated(note = "use `segment_add_video_track_with_codec_id`")]
         #[link_name = "mux_segment_add_video_track"]
         pub fn segment_add_video_track(
             segment: SegmentMutPtr,
@@ -90,6 +170,7 @@ pub mod mux {
             codec_id: u32,
             track_num_out: *mut TrackNum,
         ) -> ResultCode;
+        #[deprecated(note = "use `segment_add_audio_track_with_codec_id`")]
         #[link_name = "mux_segment_add_audio_track"]
         pub fn segment_add_audio_track(
             segment: SegmentMutPtr,
@@ -99,6 +180,24 @@ pub mod mux {
             codec_id: u32,
             track_num_out: *mut TrackNum,
         ) -> ResultCode;
+        #[link_name = "mux_segment_add_video_track_with_codec_id"]
+        pub fn segment_add_video_track_with_codec_id(
+            segment: SegmentMutPtr,
+            width: i32,
+            height: i32,
+            number: i32,
+            codec_id: *const c_char,
+            track_num_out: *mut TrackNum,
+        ) -> ResultCode;
+        #[link_name = "mux_segment_add_audio_track_with_codec_id"]
+        pub fn segment_add_audio_track_with_codec_id(
+            segment: SegmentMutPtr,
+            sample_rate: i32,
+            channels: i32,
+            number: i32,
+            codec_id: *const c_char,
+            track_num_out: *mut TrackNum,
+        ) -> ResultCode;
         #[link_name = "mux_segment_add_frame"]
         pub fn segment_add_frame(
             segment: SegmentMutPtr,
@@ -108,6 +207,16 @@ pub mod mux {
             timestamp_ns: u64,
             keyframe: bool,
         ) -> ResultCode;
+        #[link_name = "mux_segment_add_frame_with_discard_padding"]
+        pub fn segment_add_frame_with_discard_padding(
+            segment: SegmentMutPtr,
+            track_num: TrackNum,
+            frame: *const u8,
+            length: usize,
+            timestamp_ns: u64,
+            keyframe: bool,
+            discard_padding_ns: i64,
+        ) -> ResultCode;
         #[link_name = "mux_segment_set_codec_private"]
         pub fn segment_set_codec_private(
             segment: SegmentMutPtr,
@@ -118,6 +227,180 @@ pub mod mux {
     }
 }
 
+pub mod demux {
+    use core::ffi::{c_char, c_void};
+    use core::ptr::NonNull;
+
+    pub use crate::mux::{ResultCode, TrackNum};
+
+    #[repr(C)]
+    pub struct IReader {
+        _opaque_c_aligned: *mut c_void,
+    }
+    pub type ReaderMutPtr = *mut IReader;
+    pub type ReaderNonNullPtr = NonNull<IReader>;
+
+    /// Reads the given number of bytes at the given position into the buffer, returning whether that succeeded.
+    pub type ReaderReadFn = extern "C" fn(*mut c_void, u64, usize, *mut u8) -> bool;
+
+    #[repr(C)]
+    pub struct Segment {
+        _opaque_c_aligned: *mut c_void,
+    }
+    pub type SegmentMutPtr = *mut Segment;
+    pub type SegmentNonNullPtr = NonNull<Segment>;
+
+    pub const VIDEO_TRACK_TYPE: i32 = 1;
+    pub const AUDIO_TRACK_TYPE: i32 = 2;
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct SegmentInfo {
+        pub doc_type: *const c_char,
+        pub timecode_scale: u64,
+
+        /// Negative if unknown
+        pub duration_ns: i64,
+        pub muxing_app: *const c_char,
+        pub writing_app: *const c_char,
+        pub title: *const c_char,
+
+        // From the EBML header
+        pub ebml_version: u64,
+        pub ebml_read_version: u64,
+        pub ebml_max_id_length: u64,
+        pub ebml_max_size_length: u64,
+        pub doc_type_version: u64,
+        pub doc_type_read_version: u64,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct TrackInfo {
+        pub number: TrackNum,
+        pub track_type: i32,
+        pub codec_id: *const c_char,
+        pub codec_private: *const u8,
+        pub codec_private_len: usize,
+        pub name: *const c_char,
+        pub language: *const c_char,
+        pub codec_delay_ns: u64,
+        pub seek_pre_roll_ns: u64,
+
+        // Video tracks only. Color and mastering values are negative when not present.
+        pub width: u64,
+        pub height: u64,
+        pub display_width: u64,
+        pub display_height: u64,
+        pub frame_rate: f64,
+        pub has_color: bool,
+        pub color_bits: i64,
+        pub color_sampling_horiz: i64,
+        pub color_sampling_vert: i64,
+        pub color_range: i64,
+        pub color_matrix_coefficients: i64,
+        pub color_transfer_characteristics: i64,
+        pub color_primaries: i64,
+        pub color_chroma_siting_horiz: i64,
+        pub color_chroma_siting_vert: i64,
+        pub color_max_cll: i64,
+        pub color_max_fall: i64,
+        pub has_mastering_metadata: bool,
+
+        /// Red, green, blue and white point chromaticity, as x and y pairs
+        pub mastering_chromaticity: [f32; 8],
+        pub mastering_luminance_max: f32,
+        pub mastering_luminance_min: f32,
+
+        // Audio tracks only
+        pub sample_rate: f64,
+        pub channels: u64,
+        pub bit_depth: u64,
+    }
+
+    #[derive(Debug, Clone, Copy, Default)]
+    #[repr(C)]
+    pub struct FrameInfo {
+        pub track_number: TrackNum,
+        pub timestamp_ns: i64,
+        pub keyframe: bool,
+        pub discard_padding_ns: i64,
+
+        /// Where the frame data is in the input, and how long it is
+        pub position: i64,
+        pub length: i64,
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    #[repr(C)]
+    pub struct ChapterInfo {
+        /// Index of the edition the chapter is in
+        pub edition: u64,
+        pub uid: u64,
+        pub string_uid: *const c_char,
+        pub start_ns: i64,
+
+        /// Negative if unknown
+        pub end_ns: i64,
+        pub title: *const c_char,
+        pub language: *const c_char,
+    }
+
+    #[derive(Debug, Clone, Copy, Default)]
+    #[repr(C)]
+    pub struct CuePointInfo {
+        pub timestamp_ns: i64,
+        pub track_number: TrackNum,
+
+        /// Relative to the start of the segment data
+        pub cluster_position: i64,
+        pub block_number: i64,
+    }
+
+    #[link(name = "webmadapter", kind = "static")]
+    extern "C" {
+        #[link_name = "demux_new_reader"]
+        pub fn new_reader(read: Option<ReaderReadFn>, length: u64, user_data: *mut c_void) -> ReaderMutPtr;
+        #[link_name = "demux_delete_reader"]
+        pub fn delete_reader(reader: ReaderMutPtr);
+
+        #[link_name = "demux_new_segment"]
+        pub fn new_segment(reader: ReaderMutPtr, segment_out: *mut SegmentMutPtr) -> ResultCode;
+        #[link_name = "demux_delete_segment"]
+        pub fn delete_segment(segment: SegmentMutPtr);
+        #[link_name = "demux_get_segment_info"]
+        pub fn get_segment_info(segment: SegmentMutPtr, info_out: *mut SegmentInfo) -> ResultCode;
+
+        #[link_name = "demux_get_track_count"]
+        pub fn get_track_count(segment: SegmentMutPtr) -> u64;
+        #[link_name = "demux_get_track"]
+        pub fn get_track(segment: SegmentMutPtr, index: u64, track_out: *mut TrackInfo) -> ResultCode;
+
+        #[link_name = "demux_get_simple_tag_count"]
+        pub fn get_simple_tag_count(segment: SegmentMutPtr) -> u64;
+        #[link_name = "demux_get_simple_tag"]
+        pub fn get_simple_tag(
+            segment: SegmentMutPtr,
+            index: u64,
+            name_out: *mut *const c_char,
+            value_out: *mut *const c_char,
+        ) -> ResultCode;
+
+        #[link_name = "demux_get_chapter_count"]
+        pub fn get_chapter_count(segment: SegmentMutPtr) -> u64;
+        #[link_name = "demux_get_chapter"]
+        pub fn get_chapter(segment: SegmentMutPtr, index: u64, chapter_out: *mut ChapterInfo) -> ResultCode;
+
+        #[link_name = "demux_get_cue_point_count"]
+        pub fn get_cue_point_count(segment: SegmentMutPtr) -> u64;
+        #[link_name = "demux_get_cue_point"]
+        pub fn get_cue_point(segment: SegmentMutPtr, index: u64, cue_point_out: *mut CuePointInfo) -> ResultCode;
+
+        #[link_name = "demux_next_frame"]
+        pub fn next_frame(segment: SegmentMutPtr, frame_out: *mut FrameInfo, end_of_stream: *mut bool) -> ResultCode;
+    }
+}
+
 #[test]
 fn smoke_test() {
     unsafe {
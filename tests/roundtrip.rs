@@ -0,0 +1,99 @@
+//! Muxes synthetic streams in many configurations and checks that libwebm's parser reads back exactly what was
+//! written. Run with `cargo test --features test-util`.
+
+use webm::test_util::{RoundTrip, SyntheticCodec, SyntheticTrack};
+
+const VIDEO_CODECS: [SyntheticCodec; 3] = [SyntheticCodec::Vp8, SyntheticCodec::Vp9, SyntheticCodec::Av1];
+
+fn video(codec: SyntheticCodec) -> SyntheticTrack {
+    // 25 fps with a keyframe every second
+    SyntheticTrack::new(codec, 40_000_000, 100).set_keyframe_interval(25)
+}
+
+fn opus() -> SyntheticTrack {
+    SyntheticTrack::new(SyntheticCodec::Opus, 20_000_000, 200)
+}
+
+#[test]
+fn single_tracks() {
+    for codec in VIDEO_CODECS {
+        RoundTrip::new().add_track(video(codec)).assert_round_trip();
+    }
+    RoundTrip::new().add_track(opus()).assert_round_trip();
+}
+
+#[test]
+fn video_with_audio() {
+    for codec in VIDEO_CODECS {
+        RoundTrip::new().add_track(video(codec)).add_track(opus()).assert_round_trip();
+
+        // Audio first, so its track number comes before the video's
+        RoundTrip::new().add_track(opus()).add_track(video(codec)).assert_round_trip();
+    }
+}
+
+#[test]
+fn several_video_tracks() {
+    RoundTrip::new()
+        .add_track(video(SyntheticCodec::Vp9))
+        .add_track(video(SyntheticCodec::Av1).set_keyframe_interval(10))
+        .add_track(opus())
+        .assert_round_trip();
+}
+
+#[test]
+fn live_writing() {
+    for codec in VIDEO_CODECS {
+        RoundTrip::new().add_track(video(codec)).add_track(opus()).set_seekable(false).assert_round_trip();
+    }
+}
+
+#[test]
+fn without_cues() {
+    RoundTrip::new().add_track(video(SyntheticCodec::Vp8)).add_track(opus()).set_cues(false).assert_round_trip();
+}
+
+#[test]
+fn cluster_limits() {
+    for duration_ns in [100_000_000, 1_000_000_000, 5_000_000_000] {
+        RoundTrip::new()
+            .add_track(video(SyntheticCodec::Vp9))
+            .add_track(opus())
+            .set_max_cluster_duration(duration_ns)
+            .assert_round_trip();
+    }
+    for size in [256, 4096] {
+        RoundTrip::new()
+            .add_track(video(SyntheticCodec::Vp8))
+            .add_track(opus())
+            .set_max_cluster_size(size)
+            .assert_round_trip();
+    }
+}
+
+#[test]
+fn keyframe_intervals() {
+    for interval in [0, 1, 7] {
+        let track = SyntheticTrack::new(SyntheticCodec::Vp9, 33_333_333, 90).set_keyframe_interval(interval);
+        RoundTrip::new().add_track(track).add_track(opus()).assert_round_trip();
+    }
+}
+
+#[test]
+fn long_gaps() {
+    // Frames further apart than a cluster can span with 16-bit relative timestamps
+    let track = SyntheticTrack::new(SyntheticCodec::Vp8, 40_000_000_000, 4).set_keyframe_interval(2);
+    RoundTrip::new().add_track(track).assert_round_trip();
+    RoundTrip::new().add_track(SyntheticTrack::new(SyntheticCodec::Opus, 35_000_000_000, 3)).assert_round_trip();
+}
+
+#[test]
+fn metadata() {
+    RoundTrip::new()
+        .add_track(video(SyntheticCodec::Av1).set_name("Camera").set_language("fin"))
+        .add_track(opus().set_name("Microphone").set_language("swe"))
+        .set_writing_app("Round trip")
+        .add_tag("TITLE", "Synthetic")
+        .add_tag("COMMENT", "Written by the round-trip tests")
+        .assert_round_trip();
+}